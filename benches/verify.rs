@@ -0,0 +1,132 @@
+//! Benchmarks cold and warm verification of the sample report, and verification
+//! of a batch of reports.
+//!
+//! Run with `cargo bench`. The embedded certificate chain is parsed once and
+//! cached, so warm verifications should beat the cold ones, which re-parse the
+//! PEM each time. With a chain cache, the chain's signatures are not verified
+//! again either, and a batch verifies the chain only once for all of its
+//! reports. The batch benchmark reports its throughput in verifications per
+//! second.
+
+use std::hint::black_box;
+use std::time::Duration;
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use sev::certs::snp::{ca, Certificate};
+use sev::firmware::guest::AttestationReport;
+use sev_attest_tool::chain_cache::ChainCache;
+use sev_attest_tool::crypto::{CertBackend, DefaultBackend};
+use sev_attest_tool::policy::VerifyPolicy;
+use sev_attest_tool::product::ProductLine;
+use sev_attest_tool::verify_attestation::{
+    get_cert_chain, verify_attestation_report_raw, verify_batch, verify_with_ca_chain, GENOA_PEM,
+    SAMPLE_ATTESTATION, SAMPLE_VCEK,
+};
+
+/// The number of reports verified together by the batch benchmark.
+const BATCH_SIZE: usize = 1000;
+
+/// Parses a PEM stack ordered (ask) -> (ark) into a chain, as an uncached
+/// `get_cert_chain` would on every call.
+///
+/// Unlike `ca_chain_from_pem`, this does not also verify the chain's signatures,
+/// so that the cold benchmark times only the parsing the cache saves.
+fn parse_ca_chain(pem: &[u8]) -> ca::Chain {
+    let certs = DefaultBackend::default().pem_to_der_stack(pem).unwrap();
+    let [ask, ark] = certs.as_slice() else {
+        panic!(
+            "expected an ASK and an ARK, found {} certificates",
+            certs.len()
+        );
+    };
+    ca::Chain {
+        ark: Certificate::from_der(ark).unwrap(),
+        ask: Certificate::from_der(ask).unwrap(),
+    }
+}
+
+fn sample() -> (AttestationReport, Certificate) {
+    let report = serde_json::from_str(SAMPLE_ATTESTATION).unwrap();
+    let vcek = Certificate::from_der(SAMPLE_VCEK).unwrap();
+    (report, vcek)
+}
+
+/// Verifications that parse the chain, or verify its signatures, every time.
+fn bench_cold(c: &mut Criterion) {
+    let (report, vcek) = sample();
+    let policy = VerifyPolicy::default();
+
+    let mut group = c.benchmark_group("cold");
+    group.bench_function("parse_chain", |b| {
+        b.iter(|| {
+            let chain = parse_ca_chain(GENOA_PEM);
+            verify_with_ca_chain(black_box(&report), &vcek, chain, &policy).unwrap();
+        })
+    });
+    group.bench_function("verify_chain", |b| {
+        b.iter(|| {
+            verify_attestation_report_raw(
+                black_box(report),
+                vcek.clone(),
+                ProductLine::Genoa,
+                &policy,
+            )
+            .unwrap();
+        })
+    });
+    group.finish();
+}
+
+/// Verifications with the cached chain, and with a chain cache.
+fn bench_warm(c: &mut Criterion) {
+    let (report, vcek) = sample();
+    let policy = VerifyPolicy::default();
+    let cached_policy = VerifyPolicy {
+        chain_cache: Some(ChainCache::default()),
+        ..Default::default()
+    };
+
+    let mut group = c.benchmark_group("warm");
+    group.bench_function("cached_chain", |b| {
+        b.iter(|| {
+            let chain = get_cert_chain(ProductLine::Genoa);
+            verify_with_ca_chain(black_box(&report), &vcek, chain, &policy).unwrap();
+        })
+    });
+    group.bench_function("chain_cache", |b| {
+        b.iter(|| {
+            verify_attestation_report_raw(
+                black_box(report),
+                vcek.clone(),
+                ProductLine::Genoa,
+                &cached_policy,
+            )
+            .unwrap();
+        })
+    });
+    group.finish();
+}
+
+/// A batch of reports verified together.
+fn bench_batch(c: &mut Criterion) {
+    let (report, vcek) = sample();
+    let policy = VerifyPolicy::default();
+    let reports = vec![report; BATCH_SIZE];
+
+    // Each iteration takes about a second, so take fewer samples
+    let mut group = c.benchmark_group("batch");
+    group
+        .sample_size(10)
+        .measurement_time(Duration::from_secs(15))
+        .throughput(Throughput::Elements(BATCH_SIZE as u64));
+    group.bench_function("verify_1000", |b| {
+        b.iter(|| {
+            let results = verify_batch(black_box(&reports), &vcek, ProductLine::Genoa, &policy);
+            assert!(results.iter().all(Result::is_ok));
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_cold, bench_warm, bench_batch);
+criterion_main!(benches);
@@ -0,0 +1,22 @@
+//! Feeds arbitrary strings to the JSON report parser, which must reject
+//! malformed JSON with an error rather than a panic.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use sev_attest_tool::policy::VerifyPolicy;
+use sev_attest_tool::product::ProductLine;
+use sev_attest_tool::report::{parse_report, ReportFormat};
+use sev_attest_tool::verify_attestation::{verify_attestation_report, SAMPLE_VCEK};
+
+fuzz_target!(|data: &[u8]| {
+    let _ = parse_report(data, ReportFormat::Json);
+
+    if let Ok(report_json) = std::str::from_utf8(data) {
+        let _ = verify_attestation_report(
+            report_json,
+            SAMPLE_VCEK,
+            ProductLine::Genoa,
+            &VerifyPolicy::default(),
+        );
+    }
+});
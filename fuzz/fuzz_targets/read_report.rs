@@ -0,0 +1,12 @@
+//! Feeds arbitrary input through format detection and every report parser, as
+//! the CLI does with a report whose format is not given.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use sev_attest_tool::report::{check_report_format, read_report};
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(report) = read_report(&mut &data[..], None) {
+        let _ = check_report_format(&report);
+    }
+});
@@ -0,0 +1,61 @@
+/*
+ * C interface to sev_attest_tool, built with the `ffi` feature.
+ *
+ * Link against the sev_attest_tool shared library (libsev_attest_tool.so).
+ */
+#ifndef SEV_ATTEST_TOOL_H
+#define SEV_ATTEST_TOOL_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* The report verified. */
+#define SEV_OK 0
+/* A required pointer argument was null. */
+#define SEV_ERR_NULL_ARGUMENT 1
+/* A string argument was not valid UTF-8, or named an unknown product line. */
+#define SEV_ERR_INVALID_ARGUMENT 2
+/* The attestation report could not be parsed. */
+#define SEV_ERR_REPORT_PARSE 3
+/* The VCEK could not be parsed. */
+#define SEV_ERR_VCEK_PARSE 4
+/* The VCEK or its certificate chain did not verify. */
+#define SEV_ERR_CERT_CHAIN 5
+/* The report was not signed by the VCEK. */
+#define SEV_ERR_REPORT_SIGNATURE 6
+/* The report is authentic, but was rejected by a verification check. */
+#define SEV_ERR_POLICY 7
+/* Verification failed for another reason. */
+#define SEV_ERR_OTHER 8
+/* Verification panicked. This is a bug. */
+#define SEV_ERR_PANIC 99
+
+/*
+ * Verifies a Genoa attestation report, given as JSON, against a DER-encoded VCEK.
+ * Returns SEV_OK if the report verified, and an error code otherwise.
+ */
+int sev_verify_report(const char *report_json, const uint8_t *vcek_der, size_t vcek_len);
+
+/*
+ * As sev_verify_report, for the named product line ("Milan", "Genoa" or "Turin").
+ * A null product means Genoa.
+ */
+int sev_verify_report_for_product(const char *report_json, const uint8_t *vcek_der,
+                                  size_t vcek_len, const char *product);
+
+/*
+ * The message for the last error on this thread, or null if the last call succeeded.
+ * The string is owned by the library, and is valid until the next call into it
+ * on the same thread.
+ */
+const char *sev_last_error_message(void);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* SEV_ATTEST_TOOL_H */
@@ -1,55 +1,1766 @@
 //! Create and verify AMD SEV-SNP attestation reports.
 //!
 //! Builds off of the the "SEV-SNP Platform Attestation Using VirTEE/SEV" whitepaper.
+//!
+//! The exit status is 0 on success, 1 if the report failed verification, 2 if
+//! verification could not be carried out (as on a network or I/O error), and 64
+//! if the command line is invalid.
+use std::fs::File;
+use std::io::{Error, ErrorKind, Read};
+use std::path::{Path, PathBuf};
+
 use clap::*;
+use serde::Serialize;
+use sev::{
+    certs::snp::Certificate, firmware::guest::AttestationReport, firmware::host::TcbVersion,
+};
 
+use sev_attest_tool::crl::Crl;
+use sev_attest_tool::crypto::is_pem_certificate;
+use sev_attest_tool::envelope::{parse_envelope, Envelope};
 use sev_attest_tool::generate_attestation::*;
+use sev_attest_tool::id_block::IdAuth;
+use sev_attest_tool::kds::{KdsClient, NetworkMode};
+use sev_attest_tool::measurement::{
+    compute_expected_measurement, parse_measurement, MeasurementInputs, VcpuType,
+};
+use sev_attest_tool::policy::{VerifyPolicy, NO_MIGRATION_AGENT};
+use sev_attest_tool::policy_file::{policy_from_json, policy_from_toml, validate_policy};
+use sev_attest_tool::product::{detect_product, ProductLine};
+use sev_attest_tool::report::{
+    describe_report_encoded, diff_reports, read_report, ByteEncoding, ReportFormat,
+};
+use sev_attest_tool::tcb::tcb_from_string;
+use sev_attest_tool::vcek::describe_vcek;
+use sev_attest_tool::vcek_cache::VcekCache;
+use sev_attest_tool::verification_report::VerificationReport;
+use sev_attest_tool::verifier::{verify_directory, Verifier, VerifyPlan};
 use sev_attest_tool::verify_attestation::*;
 
+/// Exit status: the report verified, or the command succeeded.
+const EXIT_OK: i32 = 0;
+
+/// Exit status: the report, or its VCEK, was rejected.
+const EXIT_VERIFICATION_FAILED: i32 = 1;
+
+/// Exit status: verification could not be carried out, as on a network or I/O
+/// error, so nothing is known about the report.
+const EXIT_OPERATIONAL_ERROR: i32 = 2;
+
+/// Exit status: the command line is invalid (EX_USAGE, from sysexits.h).
+const EXIT_USAGE: i32 = 64;
+
+const EXIT_STATUS_HELP: &str = "\
+Exit status:
+  0   the report verified, or the command succeeded
+  1   the report, or its VCEK, failed verification
+  2   verification could not be carried out, as on a network or I/O error
+  64  the command line is invalid";
+
+/// Bytes parsed from a hex argument (an alias, so that clap takes a single value).
+type HexBytes = Vec<u8>;
+
 #[derive(Parser, Debug)]
+#[command(after_help = EXIT_STATUS_HELP)]
 struct Cli {
     #[command(subcommand)]
     command: Commands,
 }
 
+// The command is parsed once, so the size of the verify arguments does not matter
+#[allow(clippy::large_enum_variant)]
 #[derive(Subcommand, Debug)]
 enum Commands {
+    /// Generate an attestation report from inside an SEV-SNP guest.
     GenerateAttestation {
         /// Data to attach to the attestation report.
         /// Must be a 64 byte hex string.
         data_to_attach: Option<String>,
     },
-    VerifyAttestation {
-        /// Path to the attestation report to verify.
-        /// The report must be a JSON file.
-        path: String,
 
-        /// Flag that modifies the attestation report, causing verification to fail.
+    /// Verify an attestation report.
+    Verify {
+        /// Path to the attestation report to verify, or `-` to read it from stdin.
+        /// A binary report may be followed by the certificate table of an extended
+        /// report request, holding its VCEK.
+        #[clap(long)]
+        report: PathBuf,
+
+        /// The format of the report: JSON, or the raw 1184-byte report layout as
+        /// base64, hex or binary. If not provided, it is detected from the report.
+        #[clap(long)]
+        input_format: Option<ReportFormat>,
+
+        /// The envelope the report is wrapped in: `raw` for a bare report, or
+        /// `azure` for the SEV-SNP evidence of Microsoft Azure Attestation, whose
+        /// bundled VCEK chain is then used unless `--vcek` is given.
+        #[clap(long, default_value_t = Envelope::Raw)]
+        envelope: Envelope,
+
+        /// Path to the "Versioned Chip Endorsement Key" (VCEK) to use for verification,
+        /// or `-` to read it from stdin. It may be DER or PEM encoded, or a PEM chain
+        /// of the VCEK, ASK and ARK.
+        /// If not provided, the VCEK from the report's certificate table is used, or
+        /// else it is requested from the AMD Key Distribution Service (KDS).
+        #[clap(long)]
+        vcek: Option<PathBuf>,
+
+        /// The AMD product line (Milan, Genoa or Turin) that produced the report.
+        /// If not provided, it is detected from the report (of version 3 or later)
+        /// and from the issuer of its VCEK, if one is given or in the report.
+        #[clap(short, long)]
+        product: Option<ProductLine>,
+
+        /// Path to a JSON policy file, or a TOML one if named *.toml, of the checks
+        /// to apply. Checks given on the command line replace the file's.
+        #[clap(long)]
+        policy: Option<PathBuf>,
+
+        /// The expected launch measurement of the guest, as a 48 byte hex string,
+        /// optionally prefixed by 0x and grouped by spaces or colons.
+        #[clap(long, value_parser = parse_measurement)]
+        expected_measurement: Option<[u8; 48]>,
+
+        /// Path to a file of allowed launch measurements, as 48 byte hex strings
+        /// one per line, one of which the guest's measurement must be. Blank lines
+        /// and lines starting with `#` are ignored.
+        #[clap(long)]
+        measurement_allowlist: Option<PathBuf>,
+
+        /// The expected report data (typically a challenge nonce), as a hex string
+        /// of at most 64 bytes. Only that many leading bytes of the report data are compared.
+        #[clap(long, value_parser = parse_nonce)]
+        nonce: Option<HexBytes>,
+
+        /// The expected host data provided by the hypervisor at launch, as a 32 byte hex string.
+        #[clap(long, value_parser = parse_host_data)]
+        expected_host_data: Option<[u8; 32]>,
+
+        /// The expected family ID set in the guest's ID block, as a 16 byte hex string.
+        #[clap(long, value_parser = parse_id)]
+        expected_family_id: Option<[u8; 16]>,
+
+        /// The expected image ID set in the guest's ID block, as a 16 byte hex string.
+        #[clap(long, value_parser = parse_id)]
+        expected_image_id: Option<[u8; 16]>,
+
+        /// The expected report ID the firmware assigned the guest, as a 32 byte hex string.
+        #[clap(long, value_parser = parse_host_data)]
+        expected_report_id: Option<[u8; 32]>,
+
+        /// Require the guest to have no migration agent (a REPORT_ID_MA of all ones).
+        #[clap(long)]
+        no_migration_agent: bool,
+
+        /// The VMPL the report must have been requested from, typically 0.
+        #[clap(long)]
+        require_vmpl: Option<u32>,
+
+        /// The SHA-256 fingerprint of the DER-encoded VCEK the report must be signed
+        /// by, as a 32 byte hex string. Any other VCEK is rejected, even if it chains to AMD.
+        #[clap(long, value_parser = parse_sha256)]
+        pin_vcek_sha256: Option<[u8; 32]>,
+
+        /// Path to the ID authentication information the guest was launched with
+        /// (as passed to QEMU's `id-auth`, base64 or raw), to check that the
+        /// guest's ID block is signed by its ID key and author key.
+        #[clap(long)]
+        id_auth: Option<PathBuf>,
+
+        /// The minimum TCB the report's reported TCB must meet, as "bootloader.tee.snp.microcode".
+        #[clap(long, value_parser = tcb_from_string)]
+        min_tcb: Option<TcbVersion>,
+
+        /// Directory in which VCEKs requested from KDS are cached.
+        #[clap(long)]
+        vcek_cache: Option<PathBuf>,
+
+        /// Path to a certificate revocation list (CRL), DER or PEM encoded, to
+        /// check the ASK and VCEK against.
+        #[clap(long)]
+        crl: Option<PathBuf>,
+
+        /// Check the ASK and VCEK against the CRL requested from KDS, unless `--crl` is given.
+        #[clap(long)]
+        check_revocation: bool,
+
+        /// Never make a network request. The VCEK must be provided with `--vcek`,
+        /// or already be in the `--vcek-cache`.
+        #[clap(long)]
+        offline: bool,
+
+        /// Base URL of the AMD Key Distribution Service (KDS), or of a mirror of it.
+        #[clap(long, default_value = KDS_CERT_SITE)]
+        kds_url: String,
+
+        /// The output format: human-readable text, or a JSON verification report.
+        #[clap(long, value_enum, default_value_t = OutputFormat::Text)]
+        output: OutputFormat,
+
+        /// Print nothing, leaving the exit status as the only result.
+        #[clap(short, long)]
+        quiet: bool,
+
+        /// Print the checks that would run, and which may make a network request,
+        /// instead of verifying the report. No network request is made.
+        #[clap(long)]
+        explain: bool,
+
+        /// Modify the report before verifying it, causing verification to fail.
+        #[clap(long, hide = true)]
+        tamper: bool,
+    },
+
+    /// Verify every attestation report in a directory, printing whether each
+    /// passed and a final count. Files that are not reports are skipped.
+    VerifyDir {
+        /// Path to the directory of reports. A binary report may be followed by
+        /// the certificate table of an extended report request, holding its VCEK;
+        /// otherwise the VCEK is requested from KDS.
+        #[clap(long)]
+        dir: PathBuf,
+
+        /// The AMD product line (Milan, Genoa or Turin) that produced the reports.
+        #[clap(short, long, default_value_t = ProductLine::Genoa)]
+        product: ProductLine,
+
+        /// Path to a JSON policy file, or a TOML one if named *.toml, of the checks
+        /// to apply to every report.
+        #[clap(long)]
+        policy: Option<PathBuf>,
+
+        /// Never make a network request, so only reports carrying their VCEK can pass.
+        #[clap(long)]
+        offline: bool,
+
+        /// Base URL of the AMD Key Distribution Service (KDS), or of a mirror of it.
+        #[clap(long, default_value = KDS_CERT_SITE)]
+        kds_url: String,
+    },
+
+    /// Print the decoded contents of an attestation report.
+    Inspect {
+        /// Path to the attestation report to inspect, in any input format, or `-`
+        /// to read it from stdin.
+        #[clap(long)]
+        report: PathBuf,
+
+        /// How byte fields are printed: lowercase hex, uppercase hex (HEX), or base64.
+        #[clap(long, default_value_t = ByteEncoding::Hex)]
+        encoding: ByteEncoding,
+    },
+
+    /// Print the fields that differ between two attestation reports, such as a
+    /// known-good report and one failing verification. The exit status is 1 if
+    /// any field differs.
+    Diff {
+        /// Path to the expected attestation report, in any input format, or `-`
+        /// to read it from stdin.
+        #[clap(long)]
+        expected: PathBuf,
+
+        /// Path to the attestation report to compare with it, in any input format.
+        #[clap(long)]
+        actual: PathBuf,
+    },
+
+    /// Print the issuer, validity and AMD extensions (hardware ID and TCB) of a VCEK.
+    InspectVcek {
+        /// Path to the VCEK to inspect, DER or PEM encoded, or `-` to read it from stdin.
+        #[clap(long)]
+        vcek: PathBuf,
+    },
+
+    /// Compute the expected launch measurement of a guest launched by QEMU, to
+    /// pass to `verify --expected-measurement`.
+    ComputeMeasurement {
+        /// Path to the OVMF firmware image (QEMU's `-bios`).
+        #[clap(long)]
+        ovmf: PathBuf,
+
+        /// Path to the kernel of a direct boot (QEMU's `-kernel`).
+        #[clap(long)]
+        kernel: Option<PathBuf>,
+
+        /// Path to the initrd of a direct boot (QEMU's `-initrd`).
+        #[clap(long, requires = "kernel")]
+        initrd: Option<PathBuf>,
+
+        /// The kernel command line of a direct boot (QEMU's `-append`).
+        #[clap(long, requires = "kernel")]
+        append: Option<String>,
+
+        /// The number of vCPUs (QEMU's `-smp`).
+        #[clap(long, default_value_t = 1)]
+        vcpus: u32,
+
+        /// The QEMU CPU model of the vCPUs, such as EPYC-v4 or EPYC-Milan.
+        #[clap(long, default_value = "EPYC-v4")]
+        vcpu_type: VcpuType,
+
+        /// The SEV features enabled for the guest (the VMSA's SEV_FEATURES), in
+        /// hex or decimal.
+        #[clap(long, default_value = "0x1", value_parser = parse_u64)]
+        guest_features: u64,
+    },
+
+    /// Request the VCEK for an attestation report from KDS.
+    FetchVcek {
+        /// Path to the attestation report, in any input format, or `-` to read
+        /// it from stdin. Its chip ID and reported TCB identify the VCEK.
+        #[clap(long)]
+        report: PathBuf,
+
+        /// The AMD product line (Milan, Genoa or Turin) that produced the report.
+        #[clap(short, long, default_value_t = ProductLine::Genoa)]
+        product: ProductLine,
+
+        /// Path to write the DER-encoded VCEK to.
         #[clap(short, long)]
-        fail_on_purpose: bool,
+        out: PathBuf,
+
+        /// Base URL of the AMD Key Distribution Service (KDS), or of a mirror of it.
+        #[clap(long, default_value = KDS_CERT_SITE)]
+        kds_url: String,
+    },
 
-        /// Path to the "Versioned Chip Endorsement Key" (VCEK) to use for verification.
-        /// If not provided, the VCEK will be requested from the AMD Key Distribution Service (KDS).
-        vcek_path: Option<String>,
+    /// Check that KDS is reachable, printing the latency of a request to it.
+    Health {
+        /// The AMD product line (Milan, Genoa or Turin) whose certificates are requested.
+        #[clap(short, long, default_value_t = ProductLine::Genoa)]
+        product: ProductLine,
+
+        /// Base URL of the AMD Key Distribution Service (KDS), or of a mirror of it.
+        #[clap(long, default_value = KDS_CERT_SITE)]
+        kds_url: String,
     },
+
+    /// Request the ARK and ASK certificates for a product line from KDS.
+    FetchCertChain {
+        /// The AMD product line (Milan, Genoa or Turin).
+        #[clap(short, long, default_value_t = ProductLine::Genoa)]
+        product: ProductLine,
+
+        /// Path to write the PEM-encoded ASK and ARK to.
+        /// If not provided, they are printed to stdout.
+        #[clap(short, long)]
+        out: Option<PathBuf>,
+
+        /// Base URL of the AMD Key Distribution Service (KDS), or of a mirror of it.
+        #[clap(long, default_value = KDS_CERT_SITE)]
+        kds_url: String,
+    },
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum OutputFormat {
+    Text,
+    Json,
+}
+
+/// The result of the verify command in JSON output mode.
+#[derive(Serialize, Debug)]
+struct JsonOutput {
+    /// Whether the report verified.
+    passed: bool,
+
+    /// Why the report could not be verified, if it could not be loaded.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+
+    /// Every verification step and its outcome, if the report was loaded.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    verification: Option<VerificationReport>,
+}
+
+impl JsonOutput {
+    fn new(result: Result<VerificationReport, VerifyError>) -> Self {
+        match result {
+            Ok(verification) => Self {
+                passed: verification.passed(),
+                error: None,
+                verification: Some(verification),
+            },
+            Err(e) => Self {
+                passed: false,
+                error: Some(e.to_string()),
+                verification: None,
+            },
+        }
+    }
+}
+
+/// The exit status for an error.
+///
+/// Errors in the report or VCEK being verified are verification failures, while
+/// errors in obtaining them or the tool's other inputs are operational errors.
+fn exit_code(error: &VerifyError) -> i32 {
+    match error {
+        VerifyError::ReportParse(_)
+        | VerifyError::ReportLength { .. }
+        | VerifyError::ReportDecode(_)
+        | VerifyError::ReportEncoding { .. }
+        | VerifyError::CertTableInvalid { .. }
+        | VerifyError::EnvelopeInvalid { .. }
+        | VerifyError::UnsupportedReportVersion { .. }
+        | VerifyError::UnsupportedSignatureAlgo { .. }
+        | VerifyError::NonZeroReserved { .. }
+        | VerifyError::VcekParse(_)
+        | VerifyError::ChainBuild(_)
+        | VerifyError::MalformedCaChain { .. }
+        | VerifyError::ChainVerify(_)
+        | VerifyError::UntrustedRoot
+        | VerifyError::VcekExpired { .. }
+        | VerifyError::VcekNotYetValid { .. }
+        | VerifyError::VcekPinMismatch { .. }
+        | VerifyError::VcekTcbMismatch { .. }
+        | VerifyError::VcekIssuerMismatch { .. }
+        | VerifyError::ChipIdMismatch { .. }
+        | VerifyError::CertificateRevoked { .. }
+        | VerifyError::ReportSignature(_)
+        | VerifyError::SigningKeyMismatch { .. }
+        | VerifyError::MeasurementMismatch { .. }
+        | VerifyError::MeasurementNotAllowed { .. }
+        | VerifyError::ReportDataMismatch { .. }
+        | VerifyError::HostDataMismatch { .. }
+        | VerifyError::FamilyIdMismatch { .. }
+        | VerifyError::ImageIdMismatch { .. }
+        | VerifyError::ReportIdMismatch { .. }
+        | VerifyError::UnexpectedMigrationAgent { .. }
+        | VerifyError::UnexpectedVmpl { .. }
+        | VerifyError::UnexpectedSigningKey { .. }
+        | VerifyError::IdKeyDigestMismatch { .. }
+        | VerifyError::IdAuthSignature { .. }
+        | VerifyError::ReportTooOld { .. }
+        | VerifyError::PolicyViolation { .. }
+        | VerifyError::TcbBelowMinimum { .. }
+        | VerifyError::TcbRollback { .. } => EXIT_VERIFICATION_FAILED,
+        VerifyError::Io(_)
+        | VerifyError::Vcek(_)
+        | VerifyError::IdAuthInvalid(_)
+        | VerifyError::CrlInvalid(_)
+        | VerifyError::CrlExpired { .. }
+        | VerifyError::CrlNotYetValid { .. } => EXIT_OPERATIONAL_ERROR,
+    }
+}
+
+/// The exit status for a command line that could not be parsed, which is
+/// [`EXIT_OK`] if help or the version was requested.
+fn usage_exit_code(error: &clap::Error) -> i32 {
+    if error.use_stderr() {
+        EXIT_USAGE
+    } else {
+        EXIT_OK
+    }
+}
+
+fn parse_hex(s: &str) -> Result<HexBytes, String> {
+    hex::decode(s).map_err(|e| format!("invalid hex: {e}"))
+}
+
+fn parse_nonce(s: &str) -> Result<HexBytes, String> {
+    let nonce = parse_hex(s)?;
+    match nonce.len() {
+        0 => Err("the nonce is empty".to_string()),
+        len if len > 64 => Err(format!("expected at most 64 bytes, got {len}")),
+        _ => Ok(nonce),
+    }
+}
+
+fn parse_host_data(s: &str) -> Result<[u8; 32], String> {
+    parse_hex(s)?
+        .try_into()
+        .map_err(|bytes: Vec<u8>| format!("expected 32 bytes, got {}", bytes.len()))
+}
+
+fn parse_sha256(s: &str) -> Result<[u8; 32], String> {
+    parse_hex(s)?
+        .try_into()
+        .map_err(|bytes: Vec<u8>| format!("expected a 32 byte digest, got {} bytes", bytes.len()))
+}
+
+fn parse_u64(s: &str) -> Result<u64, String> {
+    match s.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => s.parse(),
+    }
+    .map_err(|e| format!("invalid number: {e}"))
+}
+
+fn parse_id(s: &str) -> Result<[u8; 16], String> {
+    parse_hex(s)?
+        .try_into()
+        .map_err(|bytes: Vec<u8>| format!("expected 16 bytes, got {}", bytes.len()))
+}
+
+/// Whether the path names stdin.
+fn is_stdin(path: &Path) -> bool {
+    path == Path::new("-")
+}
+
+/// Opens a file, or stdin if the path is `-`.
+fn open_input(path: &Path) -> Result<Box<dyn Read>, VerifyError> {
+    if is_stdin(path) {
+        return Ok(Box::new(std::io::stdin().lock()));
+    }
+    let file = File::open(path).map_err(VerifyError::Io)?;
+    Ok(Box::new(file))
+}
+
+/// Reads the report from a file or stdin, detecting its format unless given.
+fn load_report(
+    path: &Path,
+    format: Option<ReportFormat>,
+) -> Result<AttestationReport, VerifyError> {
+    read_report(&mut open_input(path)?, format)
+}
+
+/// Reads the whole of a file, or of stdin if the path is `-`.
+fn read_input(path: &Path) -> Result<Vec<u8>, VerifyError> {
+    let mut data = Vec::new();
+    open_input(path)?
+        .read_to_end(&mut data)
+        .map_err(VerifyError::Io)?;
+    Ok(data)
+}
+
+/// Detects the product line that produced a report, from the report and from
+/// its VCEK: the one given, or else the one in the report's certificate table.
+///
+/// Neither the certificate table nor a bundled chain has been checked yet, as
+/// that needs the product, so each product in turn is tried to parse them.
+fn detect_input_product(
+    data: &[u8],
+    format: Option<ReportFormat>,
+    envelope: Envelope,
+    vcek_bytes: Option<&[u8]>,
+) -> Result<ProductLine, VerifyError> {
+    let extended = ProductLine::ALL
+        .map(|product| parse_envelope(data, envelope, format, product))
+        .into_iter()
+        .reduce(Result::or)
+        .expect("there is a product line")?;
+    let vcek = match vcek_bytes {
+        Some(vcek_bytes) => ProductLine::ALL
+            .map(|product| parse_vcek_bundle(vcek_bytes, product))
+            .into_iter()
+            .reduce(Result::or)
+            .expect("there is a product line")
+            .ok()
+            .map(|bundle| bundle.vcek),
+        None => extended.certs.map(|bundle| bundle.vcek),
+    };
+
+    detect_product(&extended.report, vcek.as_ref()).ok_or_else(|| {
+        VerifyError::Io(Error::new(
+            ErrorKind::InvalidInput,
+            "cannot detect the product line from the report or its VCEK; pass --product",
+        ))
+    })
+}
+
+/// Reads the CRL to check against from a file, or else (if requested) from KDS.
+fn load_crl(
+    crl_path: Option<&Path>,
+    check_revocation: bool,
+    kds: &KdsClient,
+    product: ProductLine,
+) -> Result<Option<Crl>, VerifyError> {
+    match crl_path {
+        Some(crl_path) => {
+            let data = std::fs::read(crl_path).map_err(VerifyError::Io)?;
+            let crl = if data.starts_with(b"-----BEGIN") {
+                Crl::from_pem(&data)?
+            } else {
+                Crl::from_der(data)?
+            };
+            Ok(Some(crl))
+        }
+        None if check_revocation => kds.get_crl(product).map(Some),
+        None => Ok(None),
+    }
+}
+
+/// Reads a measurement allowlist: hex measurements one per line, ignoring blank
+/// lines and `#` comments.
+fn load_measurement_allowlist(path: &Path) -> Result<Vec<[u8; 48]>, VerifyError> {
+    let text = std::fs::read_to_string(path).map_err(VerifyError::Io)?;
+    let mut allowed = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let measurement = parse_measurement(line).map_err(|e| {
+            VerifyError::Io(Error::new(
+                ErrorKind::InvalidData,
+                format!("{}:{}: {e}", path.display(), number + 1),
+            ))
+        })?;
+        allowed.push(measurement);
+    }
+    if allowed.is_empty() {
+        return Err(VerifyError::Io(Error::new(
+            ErrorKind::InvalidData,
+            format!("{}: no measurements are allowed", path.display()),
+        )));
+    }
+
+    Ok(allowed)
+}
+
+/// Adds to the policy the checks read from local files: the measurement
+/// allowlist and the ID authentication information, if given.
+fn load_local_checks(
+    policy: &mut VerifyPolicy,
+    measurement_allowlist: Option<&Path>,
+    id_auth: Option<&Path>,
+) -> Result<(), VerifyError> {
+    if let Some(path) = measurement_allowlist {
+        policy.allowed_measurements = Some(load_measurement_allowlist(path)?);
+        validate_policy(policy).map_err(|e| {
+            VerifyError::Io(Error::new(
+                ErrorKind::InvalidData,
+                format!("{}: {e}", path.display()),
+            ))
+        })?;
+    }
+    if let Some(path) = id_auth {
+        policy.id_auth = Some(load_id_auth(path)?);
+    }
+    Ok(())
+}
+
+/// Plans the verification of a report, reading it (but requesting nothing) to
+/// detect the product line if not given, and to find the VCEK it may carry.
+fn plan_verification(
+    report_path: &Path,
+    input_format: Option<ReportFormat>,
+    envelope: Envelope,
+    vcek_path: Option<&Path>,
+    product: Option<ProductLine>,
+    policy: &VerifyPolicy,
+    kds: &KdsClient,
+) -> Result<VerifyPlan, VerifyError> {
+    let data = read_input(report_path)?;
+    let vcek_file = vcek_path.map(read_input).transpose()?;
+    let product = match product {
+        Some(product) => product,
+        None => detect_input_product(&data, input_format, envelope, vcek_file.as_deref())?,
+    };
+    let bundled = parse_envelope(&data, envelope, input_format, product)?
+        .certs
+        .is_some();
+
+    let mut plan = Verifier::new(product)
+        .policy(policy.clone())
+        .kds_client(kds.clone())
+        .build()
+        .plan();
+    if vcek_file.is_some() || bundled {
+        if let Some(step) = plan.steps.iter_mut().find(|s| s.name == "vcek_request") {
+            step.enabled = false;
+        }
+    }
+    Ok(plan)
+}
+
+/// Reads a policy file, as TOML if its extension is `.toml` and as JSON
+/// otherwise, naming the file in any error.
+fn load_policy(path: &Path) -> Result<VerifyPolicy, VerifyError> {
+    let contents = std::fs::read_to_string(path).map_err(VerifyError::Io)?;
+    let policy = if path.extension().is_some_and(|ext| ext == "toml") {
+        policy_from_toml(&contents)
+    } else {
+        policy_from_json(&contents)
+    };
+    policy.map_err(|e| {
+        VerifyError::Io(Error::new(
+            ErrorKind::InvalidData,
+            format!("{}: {e}", path.display()),
+        ))
+    })
+}
+
+/// Reads ID authentication information from a file, raw or base64-encoded.
+fn load_id_auth(path: &Path) -> Result<IdAuth, VerifyError> {
+    let data = std::fs::read(path).map_err(VerifyError::Io)?;
+    if data.len() == IdAuth::SIZE {
+        return IdAuth::from_bytes(&data);
+    }
+    let text = String::from_utf8(data).map_err(|e| {
+        VerifyError::IdAuthInvalid(Error::new(ErrorKind::InvalidData, e.to_string()))
+    })?;
+    IdAuth::from_base64(&text)
+}
+
+/// Reads the report to verify and its VCEK, from a file or else from KDS, and
+/// returns them with the product line: the one given, or else the detected one.
+#[allow(clippy::too_many_arguments)]
+fn load_report_and_vcek(
+    report_path: &Path,
+    input_format: Option<ReportFormat>,
+    envelope: Envelope,
+    vcek_path: Option<&Path>,
+    vcek_cache: Option<&VcekCache>,
+    kds: &KdsClient,
+    product: Option<ProductLine>,
+    tamper: bool,
+) -> Result<(AttestationReport, Certificate, ProductLine), VerifyError> {
+    if is_stdin(report_path) && vcek_path.is_some_and(is_stdin) {
+        return Err(VerifyError::Io(Error::new(
+            ErrorKind::InvalidInput,
+            "the report and the VCEK cannot both be read from stdin",
+        )));
+    }
+
+    let data = read_input(report_path)?;
+    let vcek_file = vcek_path.map(read_input).transpose()?;
+    let product = match product {
+        Some(product) => product,
+        None => detect_input_product(&data, input_format, envelope, vcek_file.as_deref())?,
+    };
+
+    let extended = parse_envelope(&data, envelope, input_format, product)?;
+    let mut report = extended.report;
+    let vcek_bytes = match (vcek_file, extended.certs, vcek_cache) {
+        (Some(vcek_bytes), _, _) => vcek_bytes,
+        (None, Some(bundle), _) => bundle.vcek.to_der().map_err(VerifyError::VcekParse)?,
+        (None, None, Some(cache)) => {
+            cache.request_vcek(report.chip_id, report.reported_tcb, product)?
+        }
+        (None, None, None) => kds.request_vcek(report.chip_id, report.reported_tcb, product)?,
+    };
+    // A bundled ASK and ARK are checked to be AMD's, but the VCEK is verified
+    // against the embedded chain
+    let vcek = parse_vcek_bundle(&vcek_bytes, product)?.vcek;
+
+    if tamper {
+        tamper_report(&mut report);
+    }
+
+    Ok((report, vcek, product))
+}
+
+/// Compares two reports, returning the fields that differ to print, and the
+/// exit status: 1 if any field differs.
+fn diff_report_files(expected: &Path, actual: &Path) -> Result<(String, i32), VerifyError> {
+    let diffs = diff_reports(&load_report(expected, None)?, &load_report(actual, None)?);
+    let mut output = String::new();
+    for diff in &diffs {
+        output.push_str(&format!(
+            "{} (offset {:#x}):\n  expected: {}\n  actual:   {}\n",
+            diff.field, diff.offset, diff.a, diff.b
+        ));
+    }
+    if diffs.is_empty() {
+        output.push_str("The reports are identical\n");
+        Ok((output, EXIT_OK))
+    } else {
+        let count = match diffs.len() {
+            1 => "1 field differs".to_string(),
+            n => format!("{n} fields differ"),
+        };
+        output.push_str(&format!("{count}\n"));
+        Ok((output, EXIT_VERIFICATION_FAILED))
+    }
+}
+
+/// Reads a DER or PEM encoded VCEK from a file or stdin, and describes it.
+fn inspect_vcek(vcek_path: &Path) -> Result<String, VerifyError> {
+    let data = read_input(vcek_path)?;
+    let vcek = if is_pem_certificate(&data) {
+        Certificate::from_pem(&data)
+    } else {
+        Certificate::from_der(&data)
+    }
+    .map_err(VerifyError::VcekParse)?;
+
+    describe_vcek(&vcek)
+}
+
+/// Reads the files of a QEMU guest launch, and computes its measurement.
+fn compute_measurement(
+    ovmf: &Path,
+    kernel: Option<&Path>,
+    initrd: Option<&Path>,
+    cmdline: Option<&str>,
+    vcpus: u32,
+    vcpu_type: VcpuType,
+    guest_features: u64,
+) -> Result<[u8; 48], Box<dyn std::error::Error>> {
+    let ovmf = std::fs::read(ovmf)?;
+    let kernel = kernel.map(std::fs::read).transpose()?;
+    let initrd = initrd.map(std::fs::read).transpose()?;
+
+    let measurement = compute_expected_measurement(&MeasurementInputs {
+        ovmf: &ovmf,
+        kernel: kernel.as_deref(),
+        initrd: initrd.as_deref(),
+        cmdline,
+        vcpus,
+        vcpu_type,
+        guest_features,
+    })?;
+    Ok(measurement)
+}
+
+fn fetch_vcek(
+    report_path: &Path,
+    kds: &KdsClient,
+    product: ProductLine,
+    out: &Path,
+) -> Result<(), VerifyError> {
+    let report = load_report(report_path, None)?;
+    kds.save_vcek(report.chip_id, report.reported_tcb, product, out)
+}
+
+/// Verifies the reports in a directory, returning a line per report and a final
+/// count to print, and the exit status: the most severe of the failures, if any.
+fn verify_dir(dir: &Path, verifier: &Verifier) -> Result<(String, i32), VerifyError> {
+    let mut summary = String::new();
+    let (mut passed, mut failed, mut code) = (0, 0, EXIT_OK);
+    for (path, result) in verify_directory(dir, verifier)? {
+        let line = match result {
+            Ok(_) => {
+                passed += 1;
+                format!("PASS  {}\n", path.display())
+            }
+            Err(e) => {
+                failed += 1;
+                code = code.max(exit_code(&e));
+                format!("FAIL  {}: {e}\n", path.display())
+            }
+        };
+        summary.push_str(&line);
+    }
+    summary.push_str(&format!("{passed} passed, {failed} failed\n"));
+
+    Ok((summary, code))
+}
+
+fn fetch_cert_chain(
+    kds: &KdsClient,
+    product: ProductLine,
+    out: Option<&Path>,
+) -> Result<(), VerifyError> {
+    let cert_chain = kds.get_cert_chain(product)?;
+    let mut pem = cert_chain.ask.to_pem().map_err(VerifyError::Io)?;
+    pem.extend(cert_chain.ark.to_pem().map_err(VerifyError::Io)?);
+
+    match out {
+        Some(out) => std::fs::write(out, pem).map_err(VerifyError::Io),
+        None => {
+            print!("{}", String::from_utf8_lossy(&pem));
+            Ok(())
+        }
+    }
+}
+
+/// Prints an error and exits with its exit status.
+fn fail(error: VerifyError) -> ! {
+    eprintln!("error: {error}");
+    std::process::exit(exit_code(&error));
 }
 
 fn main() {
-    let args = Cli::parse();
+    let args = Cli::try_parse().unwrap_or_else(|e| {
+        let _ = e.print();
+        std::process::exit(usage_exit_code(&e));
+    });
 
     match args.command {
         Commands::GenerateAttestation { data_to_attach } => {
-            let output = generate_attestation_report(data_to_attach);
-            println!("{}", output);
+            match generate_attestation_report(data_to_attach) {
+                Ok(output) => println!("{output}"),
+                Err(e) => {
+                    eprintln!("error: {e}");
+                    std::process::exit(EXIT_OPERATIONAL_ERROR);
+                }
+            }
         }
-        Commands::VerifyAttestation {
-            path,
-            fail_on_purpose,
-            vcek_path,
+        Commands::Verify {
+            report,
+            input_format,
+            envelope,
+            vcek,
+            product,
+            policy: policy_file,
+            expected_measurement,
+            measurement_allowlist,
+            nonce,
+            expected_host_data,
+            expected_family_id,
+            expected_image_id,
+            expected_report_id,
+            no_migration_agent,
+            require_vmpl,
+            pin_vcek_sha256,
+            id_auth,
+            min_tcb,
+            vcek_cache,
+            crl,
+            check_revocation,
+            offline,
+            kds_url,
+            output,
+            quiet,
+            explain,
+            tamper,
         } => {
-            println!("Verifying attestation report...");
-            verify_attestation_report_cli(&path, vcek_path.as_deref(), fail_on_purpose);
-            println!("RESULT: PASS\nVerification successful!");
+            let mode = if offline {
+                NetworkMode::Offline
+            } else {
+                NetworkMode::Online
+            };
+            let kds = KdsClient::new(kds_url).with_mode(mode);
+            let vcek_cache = vcek_cache.map(|dir| match VcekCache::new(dir) {
+                Ok(cache) => cache.with_kds_client(kds.clone()),
+                Err(e) => fail(VerifyError::Io(e)),
+            });
+            let mut policy = match policy_file {
+                Some(path) => load_policy(&path).unwrap_or_else(|e| fail(e)),
+                None => VerifyPolicy::default(),
+            };
+            policy.expected_measurement = expected_measurement.or(policy.expected_measurement);
+            policy.expected_report_data = nonce.or(policy.expected_report_data);
+            policy.expected_host_data = expected_host_data.or(policy.expected_host_data);
+            policy.expected_family_id = expected_family_id.or(policy.expected_family_id);
+            policy.expected_image_id = expected_image_id.or(policy.expected_image_id);
+            policy.expected_report_id = expected_report_id.or(policy.expected_report_id);
+            if no_migration_agent {
+                policy.expected_report_id_ma = Some(NO_MIGRATION_AGENT);
+            }
+            policy.required_vmpl = require_vmpl.or(policy.required_vmpl);
+            policy.pinned_vcek_sha256 = pin_vcek_sha256.or(policy.pinned_vcek_sha256);
+            policy.min_tcb = min_tcb.or(policy.min_tcb);
+            if let Err(e) = validate_policy(&policy) {
+                eprintln!("error: {e}");
+                std::process::exit(EXIT_USAGE);
+            }
+
+            if explain {
+                let plan = load_local_checks(
+                    &mut policy,
+                    measurement_allowlist.as_deref(),
+                    id_auth.as_deref(),
+                )
+                .and_then(|()| {
+                    plan_verification(
+                        &report,
+                        input_format,
+                        envelope,
+                        vcek.as_deref(),
+                        product,
+                        &policy,
+                        &kds,
+                    )
+                });
+                match plan {
+                    Ok(mut plan) => {
+                        // The CRL is only loaded when verifying
+                        if let Some(step) = plan.steps.iter_mut().find(|s| s.name == "revocation") {
+                            step.enabled = crl.is_some() || check_revocation;
+                            step.network = crl.is_none() && check_revocation;
+                        }
+                        print!("{plan}");
+                        std::process::exit(EXIT_OK);
+                    }
+                    Err(e) => fail(e),
+                }
+            }
+
+            let input = load_report_and_vcek(
+                &report,
+                input_format,
+                envelope,
+                vcek.as_deref(),
+                vcek_cache.as_ref(),
+                &kds,
+                product,
+                tamper,
+            )
+            .and_then(|(report, vcek, product)| {
+                policy.crl = load_crl(crl.as_deref(), check_revocation, &kds, product)?;
+                load_local_checks(
+                    &mut policy,
+                    measurement_allowlist.as_deref(),
+                    id_auth.as_deref(),
+                )?;
+                Ok((report, vcek, product))
+            });
+
+            let code = match output {
+                OutputFormat::Text => {
+                    if !quiet {
+                        println!("Verifying attestation report...");
+                    }
+                    let result = input.and_then(|(report, vcek, product)| {
+                        verify_attestation_report_raw(report, vcek, product, &policy)
+                    });
+                    let code = result.as_ref().map_or_else(exit_code, |()| EXIT_OK);
+                    if !quiet {
+                        match result {
+                            Ok(()) => println!("RESULT: PASS\nVerification successful!"),
+                            Err(e) => println!("RESULT: FAIL\n{e}"),
+                        }
+                    }
+                    code
+                }
+                OutputFormat::Json => {
+                    let result = input.map(|(report, vcek, product)| {
+                        verify_detailed(&report, &vcek, product, &policy)
+                    });
+                    let code = match &result {
+                        Ok(verification) if verification.passed() => EXIT_OK,
+                        Ok(_) => EXIT_VERIFICATION_FAILED,
+                        Err(e) => exit_code(e),
+                    };
+                    if !quiet {
+                        let output = JsonOutput::new(result);
+                        println!(
+                            "{}",
+                            serde_json::to_string_pretty(&output).expect("output is serializable")
+                        );
+                    }
+                    code
+                }
+            };
+            std::process::exit(code);
+        }
+        Commands::VerifyDir {
+            dir,
+            product,
+            policy,
+            offline,
+            kds_url,
+        } => {
+            let policy = match policy {
+                Some(path) => load_policy(&path).unwrap_or_else(|e| fail(e)),
+                None => VerifyPolicy::default(),
+            };
+            let verifier = Verifier::new(product)
+                .policy(policy)
+                .kds_client(KdsClient::new(kds_url))
+                .offline(offline)
+                .build();
+            match verify_dir(&dir, &verifier) {
+                Ok((summary, code)) => {
+                    print!("{summary}");
+                    std::process::exit(code);
+                }
+                Err(e) => fail(e),
+            }
+        }
+        Commands::Inspect { report, encoding } => match load_report(&report, None) {
+            Ok(report) => print!("{}", describe_report_encoded(&report, encoding)),
+            Err(e) => fail(e),
+        },
+        Commands::Diff { expected, actual } => match diff_report_files(&expected, &actual) {
+            Ok((output, code)) => {
+                print!("{output}");
+                std::process::exit(code);
+            }
+            Err(e) => fail(e),
+        },
+        Commands::InspectVcek { vcek } => match inspect_vcek(&vcek) {
+            Ok(description) => print!("{description}"),
+            Err(e) => fail(e),
+        },
+        Commands::ComputeMeasurement {
+            ovmf,
+            kernel,
+            initrd,
+            append,
+            vcpus,
+            vcpu_type,
+            guest_features,
+        } => match compute_measurement(
+            &ovmf,
+            kernel.as_deref(),
+            initrd.as_deref(),
+            append.as_deref(),
+            vcpus,
+            vcpu_type,
+            guest_features,
+        ) {
+            Ok(measurement) => println!("{}", hex::encode(measurement)),
+            Err(e) => {
+                eprintln!("error: {e}");
+                std::process::exit(EXIT_OPERATIONAL_ERROR);
+            }
+        },
+        Commands::FetchVcek {
+            report,
+            product,
+            out,
+            kds_url,
+        } => {
+            if let Err(e) = fetch_vcek(&report, &KdsClient::new(kds_url), product, &out) {
+                fail(e);
+            }
+        }
+        Commands::Health { product, kds_url } => {
+            match KdsClient::new(kds_url.as_str()).health_check(product) {
+                Ok(latency) => println!("KDS at {kds_url} is reachable ({latency:.0?})"),
+                Err(e) => fail(e.into()),
+            }
+        }
+        Commands::FetchCertChain {
+            product,
+            out,
+            kds_url,
+        } => {
+            if let Err(e) = fetch_cert_chain(&KdsClient::new(kds_url), product, out.as_deref()) {
+                fail(e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const SAMPLE_REPORT_PATH: &str = "data/sample_attestation_report.json";
+    const SAMPLE_VCEK_PATH: &str = "data/sample_vcek.crt";
+
+    #[test]
+    fn test_cli_definition() {
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn test_parse_verify_args() {
+        let measurement = "ab".repeat(48);
+        let cli = Cli::try_parse_from([
+            "sev_attest_tool",
+            "verify",
+            "--report",
+            SAMPLE_REPORT_PATH,
+            "--product",
+            "milan",
+            "--expected-measurement",
+            &measurement,
+            "--nonce",
+            "0707",
+            "--input-format",
+            "base64",
+            "--min-tcb",
+            "7.0.11.62",
+        ])
+        .unwrap();
+        match cli.command {
+            Commands::Verify {
+                product,
+                expected_measurement,
+                nonce,
+                input_format,
+                min_tcb,
+                tamper,
+                ..
+            } => {
+                assert_eq!(min_tcb, Some(TcbVersion::new(7, 0, 11, 62)));
+                assert_eq!(product, Some(ProductLine::Milan));
+                assert_eq!(input_format, Some(ReportFormat::Base64));
+                assert_eq!(expected_measurement, Some([0xab; 48]));
+                assert_eq!(nonce, Some(vec![7, 7]));
+                assert!(!tamper);
+            }
+            command => panic!("unexpected command {command:?}"),
         }
+
+        let result = Cli::try_parse_from([
+            "sev_attest_tool",
+            "verify",
+            "--report",
+            SAMPLE_REPORT_PATH,
+            "--expected-measurement",
+            "abcd",
+        ]);
+        assert!(result.is_err());
+
+        // An empty nonce would match every report
+        let result = Cli::try_parse_from([
+            "sev_attest_tool",
+            "verify",
+            "--report",
+            SAMPLE_REPORT_PATH,
+            "--nonce",
+            "",
+        ]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_verify() {
+        let report_path = Path::new(SAMPLE_REPORT_PATH);
+        let vcek_path = Some(Path::new(SAMPLE_VCEK_PATH));
+        let policy = VerifyPolicy::default();
+        let kds = KdsClient::default();
+
+        let (report, vcek, _) = load_report_and_vcek(
+            report_path,
+            None,
+            Envelope::Raw,
+            vcek_path,
+            None,
+            &kds,
+            Some(ProductLine::Genoa),
+            false,
+        )
+        .unwrap();
+        assert!(verify_attestation_report_raw(report, vcek, ProductLine::Genoa, &policy).is_ok());
+
+        let (report, vcek, _) = load_report_and_vcek(
+            report_path,
+            None,
+            Envelope::Raw,
+            vcek_path,
+            None,
+            &kds,
+            Some(ProductLine::Genoa),
+            true,
+        )
+        .unwrap();
+        let result = verify_attestation_report_raw(report, vcek, ProductLine::Genoa, &policy);
+        assert!(matches!(result, Err(VerifyError::ReportSignature(_))));
+    }
+
+    #[test]
+    fn test_load_measurement_allowlist() {
+        let report = load_report(Path::new(SAMPLE_REPORT_PATH), None).unwrap();
+        let path = std::env::temp_dir().join(format!(
+            "sev_attest_tool_{}_measurements.txt",
+            std::process::id()
+        ));
+
+        let contents = format!(
+            "# approved images\n0x{}\n\n  {}\n",
+            "AB".repeat(48),
+            hex::encode(report.measurement)
+        );
+        std::fs::write(&path, contents).unwrap();
+        let allowed = load_measurement_allowlist(&path).unwrap();
+        assert_eq!(allowed, vec![[0xab; 48], report.measurement]);
+
+        std::fs::write(&path, "# nothing approved yet\n").unwrap();
+        assert!(matches!(
+            load_measurement_allowlist(&path),
+            Err(VerifyError::Io(e)) if e.kind() == ErrorKind::InvalidData
+        ));
+
+        // An expected measurement not on the allowlist contradicts it
+        std::fs::write(&path, format!("{}\n", hex::encode(report.measurement))).unwrap();
+        let mut policy = VerifyPolicy {
+            expected_measurement: Some([0xab; 48]),
+            ..Default::default()
+        };
+        let result = load_local_checks(&mut policy, Some(&path), None);
+        assert!(matches!(
+            result,
+            Err(VerifyError::Io(e)) if e.to_string().contains("contradictory policy")
+        ));
+
+        std::fs::write(&path, "abcd\n").unwrap();
+        let result = load_measurement_allowlist(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(result, Err(VerifyError::Io(e)) if e.to_string().contains(":1: ")));
+    }
+
+    #[test]
+    fn test_load_policy() {
+        let report = load_report(Path::new(SAMPLE_REPORT_PATH), None).unwrap();
+        let path = std::env::temp_dir().join(format!(
+            "sev_attest_tool_{}_policy.json",
+            std::process::id()
+        ));
+
+        let contents = format!(
+            r#"{{ "measurement": "{}", "vmpl": 1 }}"#,
+            hex::encode(report.measurement)
+        );
+        std::fs::write(&path, contents).unwrap();
+        let policy = load_policy(&path).unwrap();
+        assert_eq!(policy.required_vmpl, Some(1));
+        assert!(policy.check(&report).is_ok());
+
+        std::fs::write(&path, r#"{ "vmpl": "zero" }"#).unwrap();
+        let result = load_policy(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(
+            result,
+            Err(VerifyError::Io(e)) if e.kind() == ErrorKind::InvalidData
+                && e.to_string().starts_with(&path.display().to_string())
+        ));
+
+        // A .toml file is read as TOML
+        let path = path.with_extension("toml");
+        let contents = format!(
+            "measurement = \"{}\"\nvmpl = 1\n",
+            hex::encode(report.measurement)
+        );
+        std::fs::write(&path, contents).unwrap();
+        let policy = load_policy(&path);
+        std::fs::remove_file(&path).unwrap();
+        let policy = policy.unwrap();
+        assert_eq!(policy.required_vmpl, Some(1));
+        assert!(policy.check(&report).is_ok());
+    }
+
+    #[test]
+    fn test_verify_pem_vcek() {
+        let vcek = Certificate::from_der(&std::fs::read(SAMPLE_VCEK_PATH).unwrap()).unwrap();
+        let path =
+            std::env::temp_dir().join(format!("sev_attest_tool_{}_vcek.pem", std::process::id()));
+        std::fs::write(&path, vcek.to_pem().unwrap()).unwrap();
+
+        let result = load_report_and_vcek(
+            Path::new(SAMPLE_REPORT_PATH),
+            None,
+            Envelope::Raw,
+            Some(&path),
+            None,
+            &KdsClient::default(),
+            Some(ProductLine::Genoa),
+            false,
+        );
+        std::fs::remove_file(&path).unwrap();
+        let (report, vcek, _) = result.unwrap();
+        let policy = VerifyPolicy::default();
+        assert!(verify_attestation_report_raw(report, vcek, ProductLine::Genoa, &policy).is_ok());
+    }
+
+    #[test]
+    fn test_verify_azure_envelope_offline() {
+        use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+
+        let report = load_report(Path::new(SAMPLE_REPORT_PATH), None).unwrap();
+        let vcek = Certificate::from_der(&std::fs::read(SAMPLE_VCEK_PATH).unwrap()).unwrap();
+        let evidence = serde_json::json!({
+            "SnpReport": URL_SAFE_NO_PAD.encode(sev_attest_tool::report::report_to_bytes(&report)),
+            "VcekCertChain": URL_SAFE_NO_PAD.encode(vcek.to_pem().unwrap()),
+        });
+        let envelope =
+            serde_json::json!({ "report": URL_SAFE_NO_PAD.encode(evidence.to_string()) });
+        let path =
+            std::env::temp_dir().join(format!("sev_attest_tool_{}_azure.json", std::process::id()));
+        std::fs::write(&path, envelope.to_string()).unwrap();
+
+        // The VCEK comes from the envelope, and the product from its issuer
+        let result = load_report_and_vcek(
+            &path,
+            None,
+            Envelope::Azure,
+            None,
+            None,
+            &KdsClient::offline(),
+            None,
+            false,
+        );
+        std::fs::remove_file(&path).unwrap();
+        let (report, vcek, product) = result.unwrap();
+        assert_eq!(product, ProductLine::Genoa);
+        let policy = VerifyPolicy::default();
+        assert!(verify_attestation_report_raw(report, vcek, product, &policy).is_ok());
+    }
+
+    #[test]
+    fn test_verify_extended_report_offline() {
+        use sev_attest_tool::extended_report::{build_extended_report, VCEK_GUID};
+
+        let report = load_report(Path::new(SAMPLE_REPORT_PATH), None).unwrap();
+        let vcek = std::fs::read(SAMPLE_VCEK_PATH).unwrap();
+        let path =
+            std::env::temp_dir().join(format!("sev_attest_tool_{}_ext.bin", std::process::id()));
+        std::fs::write(&path, build_extended_report(&report, &[(VCEK_GUID, &vcek)])).unwrap();
+
+        // The VCEK comes from the certificate table, with no request to KDS
+        let result = load_report_and_vcek(
+            &path,
+            None,
+            Envelope::Raw,
+            None,
+            None,
+            &KdsClient::offline(),
+            Some(ProductLine::Genoa),
+            false,
+        );
+        std::fs::remove_file(&path).unwrap();
+        let (report, vcek, _) = result.unwrap();
+        let policy = VerifyPolicy::default();
+        assert!(verify_attestation_report_raw(report, vcek, ProductLine::Genoa, &policy).is_ok());
+    }
+
+    #[test]
+    fn test_verify_dir() {
+        let dir = std::env::temp_dir().join(format!("sev_attest_tool_{}_dir", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::copy(SAMPLE_REPORT_PATH, dir.join("report.json")).unwrap();
+        std::fs::write(dir.join("notes.txt"), "not a report\n").unwrap();
+
+        let verifier = Verifier::new(ProductLine::Genoa).offline(true).build();
+        let result = verify_dir(&dir, &verifier);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        // Offline, the report's VCEK cannot be requested
+        let (summary, code) = result.unwrap();
+        assert_eq!(code, EXIT_OPERATIONAL_ERROR);
+        let lines: Vec<_> = summary.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("FAIL  ") && lines[0].contains("report.json"));
+        assert_eq!(lines[1], "0 passed, 1 failed");
+    }
+
+    #[test]
+    fn test_diff_report_files() {
+        let report: AttestationReport = serde_json::from_str(SAMPLE_ATTESTATION).unwrap();
+        let (output, code) =
+            diff_report_files(Path::new(SAMPLE_REPORT_PATH), Path::new(SAMPLE_REPORT_PATH))
+                .unwrap();
+        assert_eq!(code, EXIT_OK);
+        assert_eq!(output, "The reports are identical\n");
+
+        // Formats are detected independently, so a binary report compares with JSON
+        let mut changed = report;
+        changed.guest_svn = 2;
+        let path =
+            std::env::temp_dir().join(format!("sev_attest_tool_{}_diff", std::process::id()));
+        std::fs::write(&path, sev_attest_tool::report::report_to_bytes(&changed)).unwrap();
+        let result = diff_report_files(Path::new(SAMPLE_REPORT_PATH), &path);
+        std::fs::remove_file(&path).unwrap();
+        let (output, code) = result.unwrap();
+        assert_eq!(code, EXIT_VERIFICATION_FAILED);
+        assert_eq!(
+            output,
+            "guest_svn (offset 0x4):\n  expected: 00000000\n  actual:   02000000\n1 field differs\n"
+        );
+    }
+
+    #[test]
+    fn test_compute_measurement() {
+        let kernel =
+            std::env::temp_dir().join(format!("sev_attest_tool_{}_kernel", std::process::id()));
+        std::fs::write(&kernel, b"").unwrap();
+        let result = compute_measurement(
+            Path::new("data/ovmf_amdsev_suffix.bin"),
+            Some(&kernel),
+            Some(&kernel),
+            Some("console=ttyS0 loglevel=7"),
+            1,
+            VcpuType::Epyc,
+            parse_u64("0x1").unwrap(),
+        );
+        std::fs::remove_file(&kernel).unwrap();
+        assert_eq!(
+            hex::encode(result.unwrap()),
+            "6d287813eb5222d770f75005c664e34c204f385ce832cc2ce7d0d6f354454362f390ef83a92046c042e706363b4b08fa"
+        );
+
+        assert_eq!(parse_u64("33"), Ok(0x21));
+        assert!(parse_u64("0xg").is_err());
+        assert!(compute_measurement(
+            Path::new("data/ovmf_x64_suffix.bin"),
+            Some(Path::new("data/ovmf_x64_suffix.bin")),
+            None,
+            None,
+            1,
+            VcpuType::Epyc,
+            1,
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_exit_codes() {
+        let report = load_report(Path::new(SAMPLE_REPORT_PATH), None).unwrap();
+        let vcek = Certificate::from_der(&std::fs::read(SAMPLE_VCEK_PATH).unwrap()).unwrap();
+        let policy = VerifyPolicy {
+            expected_measurement: Some([0; 48]),
+            ..Default::default()
+        };
+        let error =
+            verify_attestation_report_raw(report, vcek, ProductLine::Genoa, &policy).unwrap_err();
+        assert_eq!(exit_code(&error), EXIT_VERIFICATION_FAILED);
+
+        let error = load_report(Path::new("data/no_such_report.json"), None).unwrap_err();
+        assert_eq!(exit_code(&error), EXIT_OPERATIONAL_ERROR);
+        assert_eq!(
+            exit_code(&VerifyError::Vcek(VcekError::Offline)),
+            EXIT_OPERATIONAL_ERROR
+        );
+
+        let error = Cli::try_parse_from(["sev_attest_tool", "verify"]).unwrap_err();
+        assert_eq!(usage_exit_code(&error), EXIT_USAGE);
+        let error = Cli::try_parse_from(["sev_attest_tool", "--help"]).unwrap_err();
+        assert_eq!(usage_exit_code(&error), EXIT_OK);
+
+        let cli = Cli::try_parse_from([
+            "sev_attest_tool",
+            "verify",
+            "--report",
+            SAMPLE_REPORT_PATH,
+            "--quiet",
+        ])
+        .unwrap();
+        assert!(matches!(cli.command, Commands::Verify { quiet: true, .. }));
+    }
+
+    #[test]
+    fn test_detect_product_from_vcek() {
+        let report_path = Path::new(SAMPLE_REPORT_PATH);
+        let kds = KdsClient::offline();
+
+        let (_, _, product) = load_report_and_vcek(
+            report_path,
+            None,
+            Envelope::Raw,
+            Some(Path::new(SAMPLE_VCEK_PATH)),
+            None,
+            &kds,
+            None,
+            false,
+        )
+        .unwrap();
+        assert_eq!(product, ProductLine::Genoa);
+
+        // A version 2 report alone does not name its product line
+        let result = load_report_and_vcek(
+            report_path,
+            None,
+            Envelope::Raw,
+            None,
+            None,
+            &kds,
+            None,
+            false,
+        );
+        assert!(matches!(result, Err(VerifyError::Io(e)) if e.kind() == ErrorKind::InvalidInput));
+    }
+
+    #[test]
+    fn test_plan_verification() {
+        let report_path = Path::new(SAMPLE_REPORT_PATH);
+        let policy = VerifyPolicy {
+            min_tcb: Some(TcbVersion::new(7, 0, 11, 62)),
+            ..Default::default()
+        };
+        let kds = KdsClient::default();
+
+        let plan = plan_verification(
+            report_path,
+            None,
+            Envelope::Raw,
+            Some(Path::new(SAMPLE_VCEK_PATH)),
+            None,
+            &policy,
+            &kds,
+        )
+        .unwrap();
+        assert_eq!(plan.product, ProductLine::Genoa);
+        assert!(plan.step("tcb").unwrap().enabled);
+        assert!(!plan.needs_network());
+
+        let plan = plan_verification(
+            report_path,
+            None,
+            Envelope::Raw,
+            None,
+            Some(ProductLine::Genoa),
+            &policy,
+            &kds,
+        )
+        .unwrap();
+        assert!(plan.step("vcek_request").unwrap().enabled);
+    }
+
+    #[test]
+    fn test_verify_offline_requires_vcek() {
+        let report_path = Path::new(SAMPLE_REPORT_PATH);
+        let kds = KdsClient::offline();
+
+        let result = load_report_and_vcek(
+            report_path,
+            None,
+            Envelope::Raw,
+            None,
+            None,
+            &kds,
+            Some(ProductLine::Genoa),
+            false,
+        );
+        assert!(matches!(result, Err(VerifyError::Vcek(VcekError::Offline))));
+
+        let vcek_path = Some(Path::new(SAMPLE_VCEK_PATH));
+        let result = load_report_and_vcek(
+            report_path,
+            None,
+            Envelope::Raw,
+            vcek_path,
+            None,
+            &kds,
+            Some(ProductLine::Genoa),
+            false,
+        );
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn test_report_and_vcek_cannot_both_be_stdin() {
+        let stdin = Path::new("-");
+        let result = load_report_and_vcek(
+            stdin,
+            None,
+            Envelope::Raw,
+            Some(stdin),
+            None,
+            &KdsClient::offline(),
+            Some(ProductLine::Genoa),
+            false,
+        );
+        assert!(matches!(result, Err(VerifyError::Io(e)) if e.kind() == ErrorKind::InvalidInput));
+    }
+
+    #[test]
+    fn test_verify_hex_report() {
+        let report = load_report(Path::new(SAMPLE_REPORT_PATH), None).unwrap();
+        let path = std::env::temp_dir().join(format!("sev_attest_tool_{}.hex", std::process::id()));
+        std::fs::write(
+            &path,
+            hex::encode(sev_attest_tool::report::report_to_bytes(&report)),
+        )
+        .unwrap();
+
+        let kds = KdsClient::offline();
+        let vcek_path = Some(Path::new(SAMPLE_VCEK_PATH));
+        let result = load_report_and_vcek(
+            &path,
+            None,
+            Envelope::Raw,
+            vcek_path,
+            None,
+            &kds,
+            Some(ProductLine::Genoa),
+            false,
+        );
+        std::fs::remove_file(&path).unwrap();
+
+        let (report, vcek, _) = result.unwrap();
+        let policy = VerifyPolicy::default();
+        assert!(verify_attestation_report_raw(report, vcek, ProductLine::Genoa, &policy).is_ok());
+    }
+
+    #[test]
+    fn test_inspect_vcek() {
+        let description = inspect_vcek(Path::new(SAMPLE_VCEK_PATH)).unwrap();
+        assert!(description.contains("product_name:     Genoa\n"));
+
+        let pem = Certificate::from_der(&std::fs::read(SAMPLE_VCEK_PATH).unwrap())
+            .and_then(|vcek| vcek.to_pem())
+            .unwrap();
+        let path = std::env::temp_dir().join(format!("sev_attest_tool_{}.pem", std::process::id()));
+        std::fs::write(&path, pem).unwrap();
+        let result = inspect_vcek(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(result.unwrap(), description);
+
+        assert!(matches!(
+            inspect_vcek(Path::new(SAMPLE_REPORT_PATH)),
+            Err(VerifyError::VcekParse(_))
+        ));
+    }
+
+    #[test]
+    fn test_load_id_auth() {
+        use base64::{engine::general_purpose::STANDARD, Engine};
+
+        let raw = Path::new("data/test_id_auth.bin");
+        let id_auth = load_id_auth(raw).unwrap();
+
+        let path = std::env::temp_dir().join(format!("sev_attest_tool_{}.b64", std::process::id()));
+        std::fs::write(&path, format!("{}\n", STANDARD.encode(id_auth.to_bytes()))).unwrap();
+        let result = load_id_auth(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(result.unwrap(), id_auth);
+
+        assert!(matches!(
+            load_id_auth(Path::new(SAMPLE_VCEK_PATH)),
+            Err(VerifyError::IdAuthInvalid(_))
+        ));
+    }
+
+    #[test]
+    fn test_load_crl() {
+        let kds = KdsClient::offline();
+        let crl_path = Path::new("data/test_crl.der");
+
+        assert!(load_crl(None, false, &kds, ProductLine::Genoa)
+            .unwrap()
+            .is_none());
+        assert!(load_crl(Some(crl_path), true, &kds, ProductLine::Genoa)
+            .unwrap()
+            .is_some());
+        assert!(matches!(
+            load_crl(None, true, &kds, ProductLine::Genoa),
+            Err(VerifyError::Vcek(VcekError::Offline))
+        ));
+        assert!(matches!(
+            load_crl(
+                Some(Path::new(SAMPLE_VCEK_PATH)),
+                false,
+                &kds,
+                ProductLine::Genoa
+            ),
+            Err(VerifyError::CrlInvalid(_))
+        ));
+    }
+
+    #[test]
+    fn test_json_output() {
+        let report_path = Path::new(SAMPLE_REPORT_PATH);
+        let vcek_path = Some(Path::new(SAMPLE_VCEK_PATH));
+        let policy = VerifyPolicy::default();
+        let kds = KdsClient::default();
+
+        let (report, vcek, _) = load_report_and_vcek(
+            report_path,
+            None,
+            Envelope::Raw,
+            vcek_path,
+            None,
+            &kds,
+            Some(ProductLine::Genoa),
+            true,
+        )
+        .unwrap();
+        let output = JsonOutput::new(Ok(verify_detailed(
+            &report,
+            &vcek,
+            ProductLine::Genoa,
+            &policy,
+        )));
+        let json = serde_json::to_value(&output).unwrap();
+        assert_eq!(json["passed"], false);
+        assert!(json.get("error").is_none());
+        assert!(json["verification"]["steps"].is_array());
+
+        let missing = Path::new("data/missing.json");
+        let output = JsonOutput::new(
+            load_report_and_vcek(
+                missing,
+                None,
+                Envelope::Raw,
+                vcek_path,
+                None,
+                &kds,
+                Some(ProductLine::Genoa),
+                false,
+            )
+            .map(|_| unreachable!()),
+        );
+        let json = serde_json::to_value(&output).unwrap();
+        assert_eq!(json["passed"], false);
+        assert!(json["error"].is_string());
+        assert!(json.get("verification").is_none());
     }
 }
@@ -0,0 +1,138 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+use openssl::{hash::MessageDigest, x509::X509};
+use sev::certs::snp::{ca, Certificate};
+
+/// A VCEK and the ARK/ASK chain it was verified against, as the SHA-256
+/// fingerprints of the VCEK and of the two certificates' DER encodings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct ChainKey {
+    vcek: [u8; 32],
+    ca: [u8; 32],
+}
+
+impl ChainKey {
+    /// The key for a VCEK and its chain, or `None` if a certificate cannot be encoded.
+    pub(crate) fn new(cert_chain: &ca::Chain, vcek: &Certificate) -> Option<Self> {
+        let digest = |cert: &Certificate| X509::from(cert).digest(MessageDigest::sha256()).ok();
+
+        let mut ca = digest(&cert_chain.ark)?.to_vec();
+        ca.extend_from_slice(&digest(&cert_chain.ask)?);
+        Some(ChainKey {
+            vcek: digest(vcek)?.as_ref().try_into().ok()?,
+            ca: openssl::sha::sha256(&ca),
+        })
+    }
+}
+
+/// An in-memory cache of VCEKs whose VCEK -> ASK -> ARK chain has verified.
+///
+/// Reports from the same chip at the same TCB share a VCEK, so once its chain
+/// has verified, later reports need only their signature checked. Only the
+/// signatures of the chain are cached: the VCEK pin, revocation and validity
+/// period are still checked on every use, as they may change over time.
+///
+/// The least recently used VCEK is evicted once the cache is full. Clones share
+/// the same cache, so one can be set on several policies.
+#[derive(Debug, Clone)]
+pub struct ChainCache {
+    capacity: usize,
+    // Ordered from least to most recently used
+    entries: Arc<Mutex<VecDeque<ChainKey>>>,
+}
+
+impl ChainCache {
+    /// The number of VCEKs a [`ChainCache::default`] cache holds.
+    pub const DEFAULT_CAPACITY: usize = 256;
+
+    /// Creates an empty cache holding up to `capacity` VCEKs.
+    pub fn new(capacity: usize) -> Self {
+        ChainCache {
+            capacity,
+            entries: Arc::new(Mutex::new(VecDeque::with_capacity(capacity))),
+        }
+    }
+
+    /// The number of VCEKs in the cache.
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+
+    /// Whether the cache holds no VCEKs.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Forgets every VCEK, so each chain is verified again on next use.
+    pub fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+
+    /// Whether the chain has verified, marking it as recently used if so.
+    pub(crate) fn contains(&self, key: &ChainKey) -> bool {
+        let mut entries = self.entries.lock().unwrap();
+        match entries.iter().position(|entry| entry == key) {
+            Some(index) => {
+                let entry = entries.remove(index).expect("index is in bounds");
+                entries.push_back(entry);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Records that the chain has verified, evicting the least recently used if full.
+    pub(crate) fn insert(&self, key: ChainKey) {
+        if self.capacity == 0 || self.contains(&key) {
+            return;
+        }
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() == self.capacity {
+            entries.pop_front();
+        }
+        entries.push_back(key);
+    }
+}
+
+impl Default for ChainCache {
+    fn default() -> Self {
+        ChainCache::new(ChainCache::DEFAULT_CAPACITY)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::product::ProductLine;
+    use crate::verify_attestation::{get_cert_chain, SAMPLE_VCEK};
+
+    #[test]
+    fn test_chain_cache_evicts_least_recently_used() {
+        let vcek = Certificate::from_der(SAMPLE_VCEK).unwrap();
+        let genoa = ChainKey::new(&get_cert_chain(ProductLine::Genoa), &vcek).unwrap();
+        let milan = ChainKey::new(&get_cert_chain(ProductLine::Milan), &vcek).unwrap();
+        let turin = ChainKey::new(&get_cert_chain(ProductLine::Turin), &vcek).unwrap();
+        assert_ne!(genoa, milan);
+
+        let cache = ChainCache::new(2);
+        cache.insert(genoa);
+        cache.insert(milan);
+        assert!(cache.contains(&genoa));
+
+        // Milan is now the least recently used
+        cache.insert(turin);
+        assert_eq!(cache.len(), 2);
+        assert!(cache.contains(&genoa));
+        assert!(!cache.contains(&milan));
+        assert!(cache.contains(&turin));
+
+        cache.clone().clear();
+        assert!(cache.is_empty());
+
+        let cache = ChainCache::new(0);
+        cache.insert(genoa);
+        assert!(!cache.contains(&genoa));
+    }
+}
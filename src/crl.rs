@@ -0,0 +1,232 @@
+use std::io::{Error, ErrorKind};
+use std::time::SystemTime;
+
+use openssl::x509::{CrlStatus, X509Crl, X509};
+use sev::certs::snp::{ca, Certificate};
+
+use crate::vcek::{asn1_time, serial_hex};
+use crate::verify_attestation::VerifyError;
+
+/// A certificate revocation list (CRL), as published by AMD for a product line.
+///
+/// AMD's CRLs are signed by the ARK. [`Crl::check`] rejects a CRL that is not,
+/// so a CRL from an untrusted source can at worst fail verification. Request
+/// one from KDS with [`crate::kds::KdsClient::get_crl`].
+#[derive(Debug, Clone)]
+pub struct Crl {
+    der: Vec<u8>,
+}
+
+impl Crl {
+    /// Parses a DER-encoded CRL, as served by KDS.
+    pub fn from_der(der: impl Into<Vec<u8>>) -> Result<Self, VerifyError> {
+        let der = der.into();
+        X509Crl::from_der(&der).map_err(|e| VerifyError::CrlInvalid(e.into()))?;
+        Ok(Self { der })
+    }
+
+    /// Parses a PEM-encoded CRL.
+    pub fn from_pem(pem: &[u8]) -> Result<Self, VerifyError> {
+        let der = X509Crl::from_pem(pem)
+            .and_then(|crl| crl.to_der())
+            .map_err(|e| VerifyError::CrlInvalid(e.into()))?;
+        Ok(Self { der })
+    }
+
+    /// The DER encoding of the CRL.
+    pub fn to_der(&self) -> &[u8] {
+        &self.der
+    }
+
+    /// Checks that the CRL is signed by the chain's ARK and current at `as_of`,
+    /// and that neither the ASK nor the endorsement key is revoked by it.
+    ///
+    /// A CRL is current from when it was issued until its nextUpdate time, by
+    /// which AMD will have published a newer one.
+    pub fn check(
+        &self,
+        cert_chain: &ca::Chain,
+        key: &Certificate,
+        as_of: SystemTime,
+    ) -> Result<(), VerifyError> {
+        self.check_with_issuer(
+            &X509::from(&cert_chain.ark),
+            [("ASK", &cert_chain.ask), ("VCEK", key)],
+            as_of,
+        )
+    }
+
+    /// Checks that the CRL is signed by `issuer` and current at `as_of`, and
+    /// that none of the named certificates is revoked by it.
+    fn check_with_issuer(
+        &self,
+        issuer: &X509,
+        certs: [(&'static str, &Certificate); 2],
+        as_of: SystemTime,
+    ) -> Result<(), VerifyError> {
+        let invalid = |e: openssl::error::ErrorStack| VerifyError::CrlInvalid(e.into());
+
+        let crl = X509Crl::from_der(&self.der).map_err(invalid)?;
+        let issuer_key = issuer.public_key().map_err(invalid)?;
+        if !crl.verify(&issuer_key).map_err(invalid)? {
+            return Err(VerifyError::CrlInvalid(Error::new(
+                ErrorKind::InvalidData,
+                "CRL is not signed by the ARK",
+            )));
+        }
+
+        let as_of = asn1_time(as_of).map_err(invalid)?;
+        if crl.last_update() > as_of {
+            return Err(VerifyError::CrlNotYetValid {
+                last_update: crl.last_update().to_string(),
+            });
+        }
+        if let Some(next_update) = crl.next_update() {
+            if next_update < as_of {
+                return Err(VerifyError::CrlExpired {
+                    next_update: next_update.to_string(),
+                });
+            }
+        }
+
+        for (certificate, cert) in certs {
+            let cert = X509::from(cert);
+            let serial = cert.serial_number();
+            if let CrlStatus::Revoked(_) = crl.get_by_serial(serial) {
+                return Err(VerifyError::CertificateRevoked {
+                    certificate,
+                    serial: serial_hex(&cert).unwrap_or_default(),
+                });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use crate::product::ProductLine;
+    use crate::verify_attestation::{get_cert_chain, SAMPLE_VCEK};
+
+    /// A CRL, signed by [`TEST_CRL_ISSUER`], revoking the Genoa ASK. It was
+    /// issued on 2024-01-01, and its next update is due on 2124-01-01.
+    const TEST_CRL: &[u8] = include_bytes!("../data/test_crl.der");
+
+    /// An empty CRL issued on 2024-01-01 whose next update was due on 2024-02-01.
+    const TEST_CRL_EXPIRED: &[u8] = include_bytes!("../data/test_crl_expired.der");
+
+    /// An empty CRL issued on 2124-01-01.
+    const TEST_CRL_NOT_YET_VALID: &[u8] = include_bytes!("../data/test_crl_not_yet_valid.der");
+
+    const TEST_CRL_ISSUER: &[u8] = include_bytes!("../data/test_crl_issuer.pem");
+
+    #[test]
+    fn test_crl_revokes_ask() {
+        let crl = Crl::from_der(TEST_CRL).unwrap();
+        let issuer = X509::from_pem(TEST_CRL_ISSUER).unwrap();
+        let vcek = Certificate::from_der(SAMPLE_VCEK).unwrap();
+
+        let genoa = get_cert_chain(ProductLine::Genoa);
+        assert!(matches!(
+            crl.check_with_issuer(
+                &issuer,
+                [("ASK", &genoa.ask), ("VCEK", &vcek)],
+                SystemTime::now()
+            ),
+            Err(VerifyError::CertificateRevoked {
+                certificate: "ASK",
+                ..
+            })
+        ));
+
+        let milan = get_cert_chain(ProductLine::Milan);
+        assert!(crl
+            .check_with_issuer(
+                &issuer,
+                [("ASK", &milan.ask), ("VCEK", &vcek)],
+                SystemTime::now()
+            )
+            .is_ok());
+    }
+
+    #[test]
+    fn test_crl_validity() {
+        let issuer = X509::from_pem(TEST_CRL_ISSUER).unwrap();
+        let milan = get_cert_chain(ProductLine::Milan);
+        let vcek = Certificate::from_der(SAMPLE_VCEK).unwrap();
+        let certs = [("ASK", &milan.ask), ("VCEK", &vcek)];
+
+        let january_2024 = UNIX_EPOCH + Duration::from_secs(1_705_000_000);
+        let june_2124 = UNIX_EPOCH + Duration::from_secs(4_872_873_600);
+
+        let expired = Crl::from_der(TEST_CRL_EXPIRED).unwrap();
+        match expired.check_with_issuer(&issuer, certs, SystemTime::now()) {
+            Err(VerifyError::CrlExpired { next_update }) => {
+                assert_eq!(next_update, "Feb  1 00:00:00 2024 GMT")
+            }
+            other => panic!("unexpected result {other:?}"),
+        }
+        // Before its next update was due, the same CRL was current
+        assert!(expired
+            .check_with_issuer(&issuer, certs, january_2024)
+            .is_ok());
+
+        let not_yet_valid = Crl::from_der(TEST_CRL_NOT_YET_VALID).unwrap();
+        match not_yet_valid.check_with_issuer(&issuer, certs, SystemTime::now()) {
+            Err(VerifyError::CrlNotYetValid { last_update }) => {
+                assert_eq!(last_update, "Jan  1 00:00:00 2124 GMT")
+            }
+            other => panic!("unexpected result {other:?}"),
+        }
+        assert!(not_yet_valid
+            .check_with_issuer(&issuer, certs, june_2124)
+            .is_ok());
+
+        let crl = Crl::from_der(TEST_CRL).unwrap();
+        assert!(matches!(
+            crl.check_with_issuer(
+                &issuer,
+                certs,
+                june_2124 + Duration::from_secs(365 * 24 * 60 * 60)
+            ),
+            Err(VerifyError::CrlExpired { .. })
+        ));
+        assert!(matches!(
+            crl.check_with_issuer(&issuer, certs, UNIX_EPOCH),
+            Err(VerifyError::CrlNotYetValid { .. })
+        ));
+    }
+
+    #[test]
+    fn test_crl_must_be_signed_by_ark() {
+        let crl = Crl::from_der(TEST_CRL).unwrap();
+        let vcek = Certificate::from_der(SAMPLE_VCEK).unwrap();
+
+        assert!(matches!(
+            crl.check(
+                &get_cert_chain(ProductLine::Milan),
+                &vcek,
+                SystemTime::now()
+            ),
+            Err(VerifyError::CrlInvalid(_))
+        ));
+    }
+
+    #[test]
+    fn test_crl_parse() {
+        let crl = Crl::from_der(TEST_CRL).unwrap();
+        let pem = X509Crl::from_der(TEST_CRL).unwrap().to_pem().unwrap();
+        assert_eq!(Crl::from_pem(&pem).unwrap().to_der(), crl.to_der());
+
+        assert!(matches!(
+            Crl::from_der(&TEST_CRL[1..]),
+            Err(VerifyError::CrlInvalid(_))
+        ));
+        assert!(Crl::from_pem(b"not a crl").is_err());
+    }
+}
@@ -0,0 +1,152 @@
+use std::io::{Error, ErrorKind};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use subtle::ConstantTimeEq;
+
+/// Decodes certificates for the rest of the crate.
+///
+/// The openssl decoder (behind the `openssl-pem` feature) can be swapped for
+/// the pure-Rust [`RustBackend`]. This only covers PEM decoding: certificate
+/// chains and report signatures are still verified by the sev crate with
+/// openssl, so the crate does not build for WebAssembly targets either way.
+pub trait CertBackend {
+    /// Decodes a stack of PEM-encoded certificates into their DER encodings, in order.
+    fn pem_to_der_stack(&self, pem: &[u8]) -> Result<Vec<Vec<u8>>, Error>;
+}
+
+/// Whether the data is a PEM-encoded certificate (or a stack of them), rather
+/// than DER, judging by its first `-----BEGIN CERTIFICATE-----` header.
+pub fn is_pem_certificate(data: &[u8]) -> bool {
+    let start = data
+        .iter()
+        .position(|byte| !byte.is_ascii_whitespace())
+        .unwrap_or(data.len());
+    data[start..].starts_with(b"-----BEGIN CERTIFICATE-----")
+}
+
+/// A backend decoding certificates with openssl.
+#[cfg(feature = "openssl-pem")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct OpensslBackend;
+
+#[cfg(feature = "openssl-pem")]
+impl CertBackend for OpensslBackend {
+    fn pem_to_der_stack(&self, pem: &[u8]) -> Result<Vec<Vec<u8>>, Error> {
+        openssl::x509::X509::stack_from_pem(pem)?
+            .iter()
+            .map(|cert| cert.to_der().map_err(Error::from))
+            .collect()
+    }
+}
+
+/// A backend decoding certificates in pure Rust.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RustBackend;
+
+impl CertBackend for RustBackend {
+    fn pem_to_der_stack(&self, pem: &[u8]) -> Result<Vec<Vec<u8>>, Error> {
+        const BEGIN: &str = "-----BEGIN CERTIFICATE-----";
+        const END: &str = "-----END CERTIFICATE-----";
+
+        let pem = std::str::from_utf8(pem).map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+        let mut certs = Vec::new();
+        let mut body: Option<String> = None;
+        for line in pem.lines().map(str::trim) {
+            match (&mut body, line) {
+                (None, BEGIN) => body = Some(String::new()),
+                (Some(_), END) => {
+                    let der = STANDARD
+                        .decode(body.take().unwrap())
+                        .map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+                    certs.push(der);
+                }
+                (Some(body), line) => body.push_str(line),
+                // Text outside of a certificate block is ignored, as openssl does
+                (None, _) => {}
+            }
+        }
+
+        if body.is_some() {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "unterminated PEM certificate",
+            ));
+        }
+        Ok(certs)
+    }
+}
+
+/// Compares two byte slices in time independent of their contents.
+///
+/// Every byte is compared, wherever the first difference is, so the time taken
+/// does not reveal how much of an attacker-chosen value (such as a nonce) was
+/// right. The lengths are not treated as secret.
+pub fn ct_eq(a: &[u8], b: &[u8]) -> bool {
+    a.ct_eq(b).into()
+}
+
+/// The backend the crate uses: openssl if the `openssl-pem` feature is enabled,
+/// and pure Rust otherwise.
+#[cfg(feature = "openssl-pem")]
+pub type DefaultBackend = OpensslBackend;
+
+/// The backend the crate uses: openssl if the `openssl-pem` feature is enabled,
+/// and pure Rust otherwise.
+#[cfg(not(feature = "openssl-pem"))]
+pub type DefaultBackend = RustBackend;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::verify_attestation::{GENOA_PEM, MILAN_PEM, TURIN_PEM};
+
+    #[test]
+    fn test_rust_backend_decodes_embedded_chains() {
+        for pem in [GENOA_PEM, MILAN_PEM, TURIN_PEM] {
+            let certs = RustBackend.pem_to_der_stack(pem).unwrap();
+            assert_eq!(certs.len(), 2);
+
+            #[cfg(feature = "openssl-pem")]
+            assert_eq!(certs, OpensslBackend.pem_to_der_stack(pem).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_is_pem_certificate() {
+        assert!(is_pem_certificate(GENOA_PEM));
+        assert!(is_pem_certificate(b"\n  -----BEGIN CERTIFICATE-----\n"));
+        assert!(!is_pem_certificate(b"-----BEGIN X509 CRL-----\n"));
+        assert!(!is_pem_certificate(&[0x30, 0x82, 0x05, 0x4d]));
+        assert!(!is_pem_certificate(b""));
+    }
+
+    #[test]
+    fn test_ct_eq() {
+        assert!(ct_eq(&[], &[]));
+        assert!(ct_eq(&[7; 64], &[7; 64]));
+        assert!(!ct_eq(&[7; 64], &[7; 63]));
+
+        let mut first = [0; 64];
+        first[0] = 1;
+        let mut last = [0; 64];
+        last[63] = 2;
+        assert!(!ct_eq(&first, &[0; 64]));
+        assert!(!ct_eq(&last, &[0; 64]));
+        assert!(!ct_eq(&first, &last));
+    }
+
+    #[test]
+    fn test_rust_backend_rejects_bad_pem() {
+        assert!(RustBackend
+            .pem_to_der_stack(b"not a pem")
+            .unwrap()
+            .is_empty());
+        assert!(RustBackend
+            .pem_to_der_stack(b"-----BEGIN CERTIFICATE-----\nAAAA")
+            .is_err());
+        assert!(RustBackend
+            .pem_to_der_stack(b"-----BEGIN CERTIFICATE-----\n!!!!\n-----END CERTIFICATE-----")
+            .is_err());
+    }
+}
@@ -0,0 +1,240 @@
+//! Attestation reports wrapped in the JSON envelopes of cloud attestation services.
+//!
+//! A cloud provider's attestation API returns the SEV-SNP report embedded in a
+//! larger JSON document, with the report and its certificates as base64 fields,
+//! rather than as a bare report. The envelopes here are unwrapped to the report
+//! and the certificates bundled with it, which are then verified as usual.
+//!
+//! The supported envelope is Microsoft Azure Attestation's SEV-SNP evidence: a
+//! JSON object whose `report` field is the base64url encoding of a JSON object
+//! holding the report as `SnpReport` and the VCEK, ASK and ARK as a PEM chain in
+//! `VcekCertChain`, both base64url-encoded:
+//!
+//! ```json
+//! { "report": "eyJTbnBSZXBvcnQiOiJBZ0FBQUFBQ…" }
+//! ```
+
+use std::fmt;
+use std::str::FromStr;
+
+use base64::{
+    alphabet,
+    engine::{general_purpose::GeneralPurpose, DecodePaddingMode, GeneralPurposeConfig},
+    Engine,
+};
+use serde::Deserialize;
+
+use crate::extended_report::{parse_report_with_certs, ExtendedReport};
+use crate::product::ProductLine;
+use crate::report::{parse_report_bytes, ReportFormat};
+use crate::verify_attestation::{parse_vcek_bundle, VerifyError};
+
+/// Base64url, with or without padding, as the fields of cloud envelopes are written.
+const BASE64URL: GeneralPurpose = GeneralPurpose::new(
+    &alphabet::URL_SAFE,
+    GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent),
+);
+
+/// The envelope an attestation report is wrapped in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Envelope {
+    /// No envelope: the report itself, in any [`ReportFormat`].
+    #[default]
+    Raw,
+
+    /// Microsoft Azure Attestation's SEV-SNP evidence.
+    Azure,
+}
+
+impl Envelope {
+    /// All supported envelopes.
+    pub const ALL: [Envelope; 2] = [Envelope::Raw, Envelope::Azure];
+
+    /// The name of the envelope, as accepted by [`Envelope::from_str`].
+    pub fn name(&self) -> &'static str {
+        match self {
+            Envelope::Raw => "raw",
+            Envelope::Azure => "azure",
+        }
+    }
+}
+
+impl fmt::Display for Envelope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// An error returned when parsing an unknown envelope name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseEnvelopeError(String);
+
+impl fmt::Display for ParseEnvelopeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "unknown report envelope '{}' (expected one of raw, azure)",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for ParseEnvelopeError {}
+
+impl FromStr for Envelope {
+    type Err = ParseEnvelopeError;
+
+    /// Parses an envelope name, ignoring case.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Envelope::ALL
+            .into_iter()
+            .find(|envelope| envelope.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| ParseEnvelopeError(s.to_string()))
+    }
+}
+
+/// The outer object of an Azure envelope.
+#[derive(Debug, Deserialize)]
+struct AzureEnvelope {
+    report: String,
+}
+
+/// The evidence encoded in an Azure envelope's `report` field.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct AzureEvidence {
+    snp_report: String,
+    vcek_cert_chain: Option<String>,
+}
+
+/// Parses an attestation report wrapped in the given envelope, along with the
+/// certificates bundled with it.
+///
+/// Without an envelope, the report is parsed as [`parse_report_with_certs`]
+/// does, in the given format or whichever is detected. The format of a report
+/// in an envelope is set by the envelope, so `format` is then ignored.
+pub fn parse_envelope(
+    data: &[u8],
+    envelope: Envelope,
+    format: Option<ReportFormat>,
+    product: ProductLine,
+) -> Result<ExtendedReport, VerifyError> {
+    match envelope {
+        Envelope::Raw => parse_report_with_certs(data, format, product),
+        Envelope::Azure => parse_azure_envelope(data, product),
+    }
+}
+
+/// Parses the report and VCEK chain of an Azure envelope.
+///
+/// A bundled ASK and ARK must form a valid chain whose ARK is the embedded ARK
+/// for the product, as for [`parse_vcek_bundle`].
+fn parse_azure_envelope(data: &[u8], product: ProductLine) -> Result<ExtendedReport, VerifyError> {
+    let outer: AzureEnvelope =
+        serde_json::from_slice(data).map_err(|e| invalid(Envelope::Azure, e.to_string()))?;
+    let evidence = decode(Envelope::Azure, "report", &outer.report)?;
+    let evidence: AzureEvidence =
+        serde_json::from_slice(&evidence).map_err(|e| invalid(Envelope::Azure, e.to_string()))?;
+
+    let report = parse_report_bytes(&decode(Envelope::Azure, "SnpReport", &evidence.snp_report)?)?;
+    let certs = evidence
+        .vcek_cert_chain
+        .map(|chain| {
+            let chain = decode(Envelope::Azure, "VcekCertChain", &chain)?;
+            parse_vcek_bundle(&chain, product)
+        })
+        .transpose()?;
+
+    Ok(ExtendedReport { report, certs })
+}
+
+/// Decodes a base64url field of an envelope.
+fn decode(envelope: Envelope, field: &str, value: &str) -> Result<Vec<u8>, VerifyError> {
+    BASE64URL
+        .decode(value.trim())
+        .map_err(|e| invalid(envelope, format!("field '{field}' is not base64url: {e}")))
+}
+
+fn invalid(envelope: Envelope, reason: String) -> VerifyError {
+    VerifyError::EnvelopeInvalid { envelope, reason }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use sev::certs::snp::Certificate;
+    use sev::firmware::guest::AttestationReport;
+
+    use crate::report::report_to_bytes;
+    use crate::verify_attestation::{get_cert_chain, SAMPLE_ATTESTATION, SAMPLE_VCEK};
+
+    /// Wraps evidence in an Azure envelope.
+    fn azure_envelope(evidence: &serde_json::Value) -> Vec<u8> {
+        let report = BASE64URL.encode(evidence.to_string());
+        serde_json::json!({ "report": report })
+            .to_string()
+            .into_bytes()
+    }
+
+    #[test]
+    fn test_parse_azure_envelope() {
+        let report: AttestationReport = serde_json::from_str(SAMPLE_ATTESTATION).unwrap();
+        let genoa = get_cert_chain(ProductLine::Genoa);
+        let mut chain = Certificate::from_der(SAMPLE_VCEK)
+            .unwrap()
+            .to_pem()
+            .unwrap();
+        chain.extend(genoa.ask.to_pem().unwrap());
+        chain.extend(genoa.ark.to_pem().unwrap());
+
+        let data = azure_envelope(&serde_json::json!({
+            "SnpReport": BASE64URL.encode(report_to_bytes(&report)),
+            "VcekCertChain": BASE64URL.encode(&chain),
+            "Endorsements": "",
+        }));
+        let extended = parse_envelope(&data, Envelope::Azure, None, ProductLine::Genoa).unwrap();
+        assert_eq!(report_to_bytes(&extended.report), report_to_bytes(&report));
+        let certs = extended.certs.unwrap();
+        assert_eq!(certs.vcek.to_der().unwrap(), SAMPLE_VCEK);
+        assert!(certs.ca.is_some());
+
+        // The envelope cannot substitute another product's root of trust
+        assert!(matches!(
+            parse_envelope(&data, Envelope::Azure, None, ProductLine::Milan),
+            Err(VerifyError::UntrustedRoot)
+        ));
+
+        let data = azure_envelope(&serde_json::json!({
+            "SnpReport": BASE64URL.encode(report_to_bytes(&report)),
+        }));
+        let extended = parse_envelope(&data, Envelope::Azure, None, ProductLine::Genoa).unwrap();
+        assert!(extended.certs.is_none());
+    }
+
+    #[test]
+    fn test_invalid_azure_envelope() {
+        for data in [
+            br#"{ "quote": "" }"#.to_vec(),
+            br#"{ "report": "not base64!" }"#.to_vec(),
+            azure_envelope(&serde_json::json!({ "VcekCertChain": "" })),
+        ] {
+            assert!(matches!(
+                parse_envelope(&data, Envelope::Azure, None, ProductLine::Genoa),
+                Err(VerifyError::EnvelopeInvalid {
+                    envelope: Envelope::Azure,
+                    ..
+                })
+            ));
+        }
+    }
+
+    #[test]
+    fn test_envelope_round_trips() {
+        for envelope in Envelope::ALL {
+            assert_eq!(envelope.to_string().parse::<Envelope>(), Ok(envelope));
+        }
+        assert_eq!("Azure".parse::<Envelope>(), Ok(Envelope::Azure));
+        assert!("gcp".parse::<Envelope>().is_err());
+    }
+}
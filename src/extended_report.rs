@@ -0,0 +1,258 @@
+//! Attestation reports captured together with the certificates the host supplies.
+//!
+//! The extended report request of `/dev/sev-guest` (SNP_GET_EXT_REPORT) returns
+//! the report along with a certificate table the hypervisor provides, usually
+//! holding the chip's VCEK and its ASK and ARK. Saving the report followed by
+//! that table yields a single file from which the report can be verified with no
+//! request to KDS.
+//!
+//! The certificate table is laid out as in the GHCB specification: entries of a
+//! 16-byte GUID, a 4-byte offset and a 4-byte length, terminated by an all-zero
+//! entry, with offsets relative to the start of the table. Each certificate is
+//! DER-encoded.
+
+use sev::certs::snp::Certificate;
+use sev::firmware::guest::AttestationReport;
+
+use crate::product::ProductLine;
+use crate::report::{parse_report, parse_report_bytes, report_to_array, ReportFormat, REPORT_SIZE};
+use crate::verify_attestation::{
+    ca_chain_from_der, check_ark_is_embedded, VcekBundle, VerifyError,
+};
+
+/// The GUID of the VCEK in a certificate table.
+pub const VCEK_GUID: &str = "63da758d-e664-4564-adc5-f4b93be8accd";
+
+/// The GUID of the ASK in a certificate table.
+pub const ASK_GUID: &str = "4ab7b379-bbac-4fe4-a02f-05aef327c782";
+
+/// The GUID of the ARK in a certificate table.
+pub const ARK_GUID: &str = "c0b406a4-a803-4952-9743-3fb6014cd0ae";
+
+/// The size of a certificate table entry, in bytes.
+const ENTRY_SIZE: usize = 24;
+
+/// A certificate table entry's GUID, and the certificate it points to.
+type TableEntry<'a> = ([u8; 16], &'a [u8]);
+
+/// An attestation report, with the certificates the host supplied alongside it.
+#[derive(Debug, Clone)]
+pub struct ExtendedReport {
+    /// The attestation report.
+    pub report: AttestationReport,
+
+    /// The VCEK from the certificate table, with the ASK and ARK if the table
+    /// also held them, or `None` if there was no table or it held no VCEK.
+    pub certs: Option<VcekBundle>,
+}
+
+/// Parses an attestation report in its raw binary layout, optionally followed by
+/// the certificate table of an extended report request.
+///
+/// A table's ASK and ARK must form a valid chain and its ARK must be the
+/// embedded ARK for the product, so the host cannot substitute its own root of
+/// trust. Certificates of other types (such as a VLEK) are ignored.
+pub fn parse_extended_report(
+    data: &[u8],
+    product: ProductLine,
+) -> Result<ExtendedReport, VerifyError> {
+    if data.len() < REPORT_SIZE {
+        return Err(VerifyError::ReportLength {
+            expected: REPORT_SIZE,
+            actual: data.len(),
+        });
+    }
+    let (report, table) = data.split_at(REPORT_SIZE);
+    let report = parse_report_bytes(report)?;
+
+    let (mut vcek, mut ask, mut ark) = (None, None, None);
+    for (guid, cert) in parse_cert_table(table)? {
+        let slot = if guid_matches(&guid, VCEK_GUID) {
+            &mut vcek
+        } else if guid_matches(&guid, ASK_GUID) {
+            &mut ask
+        } else if guid_matches(&guid, ARK_GUID) {
+            &mut ark
+        } else {
+            continue;
+        };
+        if slot.replace(cert).is_some() {
+            return Err(invalid("a certificate appears more than once"));
+        }
+    }
+
+    let Some(vcek) = vcek else {
+        return Ok(ExtendedReport {
+            report,
+            certs: None,
+        });
+    };
+    let vcek = Certificate::from_der(vcek).map_err(VerifyError::VcekParse)?;
+    let ca = match (ask, ark) {
+        (Some(ask), Some(ark)) => {
+            let ca = ca_chain_from_der(ask, ark)?;
+            check_ark_is_embedded(product, &ca)?;
+            Some(ca)
+        }
+        _ => None,
+    };
+
+    Ok(ExtendedReport {
+        report,
+        certs: Some(VcekBundle { vcek, ca }),
+    })
+}
+
+/// Parses an attestation report in the given format (or, if `None`, whichever
+/// is detected), along with the certificate table following a binary report.
+pub fn parse_report_with_certs(
+    data: &[u8],
+    format: Option<ReportFormat>,
+    product: ProductLine,
+) -> Result<ExtendedReport, VerifyError> {
+    match format.unwrap_or_else(|| ReportFormat::detect(data)) {
+        ReportFormat::Binary if data.len() > REPORT_SIZE => parse_extended_report(data, product),
+        format => Ok(ExtendedReport {
+            report: parse_report(data, format)?,
+            certs: None,
+        }),
+    }
+}
+
+/// The GUIDs and certificates of a certificate table, which may be empty.
+fn parse_cert_table(table: &[u8]) -> Result<Vec<TableEntry<'_>>, VerifyError> {
+    let mut certs = Vec::new();
+    if table.is_empty() {
+        return Ok(certs);
+    }
+
+    for entry in table.chunks(ENTRY_SIZE) {
+        let Ok(entry) = <[u8; ENTRY_SIZE]>::try_from(entry) else {
+            return Err(invalid("the table is not terminated by an empty entry"));
+        };
+        let guid: [u8; 16] = entry[..16].try_into().unwrap();
+        let offset = u32::from_le_bytes(entry[16..20].try_into().unwrap()) as usize;
+        let length = u32::from_le_bytes(entry[20..24].try_into().unwrap()) as usize;
+        if guid == [0; 16] {
+            return Ok(certs);
+        }
+
+        let cert = offset
+            .checked_add(length)
+            .and_then(|end| table.get(offset..end))
+            .ok_or_else(|| invalid("a certificate extends past the end of the table"))?;
+        certs.push((guid, cert));
+    }
+
+    Err(invalid("the table is not terminated by an empty entry"))
+}
+
+/// Whether a table entry's GUID is the given one.
+///
+/// The GUID is accepted both in RFC 4122 byte order and in the mixed-endian
+/// order of EFI GUIDs, as tools writing tables disagree on which to use.
+fn guid_matches(bytes: &[u8; 16], guid: &str) -> bool {
+    let rfc = guid_bytes(guid);
+    let mut efi = rfc;
+    efi[..4].reverse();
+    efi[4..6].reverse();
+    efi[6..8].reverse();
+
+    *bytes == rfc || *bytes == efi
+}
+
+/// The RFC 4122 bytes of a GUID written as hex groups.
+fn guid_bytes(guid: &str) -> [u8; 16] {
+    let hex: String = guid.chars().filter(|&c| c != '-').collect();
+    let mut bytes = [0; 16];
+    hex::decode_to_slice(hex, &mut bytes).expect("GUID constants are valid");
+    bytes
+}
+
+fn invalid(reason: &str) -> VerifyError {
+    VerifyError::CertTableInvalid {
+        reason: reason.to_string(),
+    }
+}
+
+/// Serializes a report followed by a certificate table of the given GUIDs and
+/// DER-encoded certificates, as [`parse_extended_report`] reads them.
+pub fn build_extended_report(report: &AttestationReport, certs: &[(&str, &[u8])]) -> Vec<u8> {
+    let mut table = Vec::new();
+    let mut offset = (certs.len() + 1) * ENTRY_SIZE;
+    for (guid, cert) in certs {
+        table.extend_from_slice(&guid_bytes(guid));
+        table.extend_from_slice(&(offset as u32).to_le_bytes());
+        table.extend_from_slice(&(cert.len() as u32).to_le_bytes());
+        offset += cert.len();
+    }
+    table.extend_from_slice(&[0; ENTRY_SIZE]);
+    for (_, cert) in certs {
+        table.extend_from_slice(cert);
+    }
+
+    [&report_to_array(report)[..], &table].concat()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::report::report_to_bytes;
+    use crate::verify_attestation::{get_cert_chain, SAMPLE_ATTESTATION, SAMPLE_VCEK};
+
+    #[test]
+    fn test_parse_extended_report() {
+        let report: AttestationReport = serde_json::from_str(SAMPLE_ATTESTATION).unwrap();
+        let report_bytes = report_to_bytes(&report);
+        let genoa = get_cert_chain(ProductLine::Genoa);
+        let (ask, ark) = (genoa.ask.to_der().unwrap(), genoa.ark.to_der().unwrap());
+
+        let data = build_extended_report(
+            &report,
+            &[(VCEK_GUID, SAMPLE_VCEK), (ASK_GUID, &ask), (ARK_GUID, &ark)],
+        );
+        let extended = parse_extended_report(&data, ProductLine::Genoa).unwrap();
+        assert_eq!(report_to_bytes(&extended.report), report_bytes);
+        let certs = extended.certs.unwrap();
+        assert_eq!(certs.vcek.to_der().unwrap(), SAMPLE_VCEK);
+        assert!(certs.ca.is_some());
+
+        // The host cannot substitute another product's root of trust
+        assert!(matches!(
+            parse_extended_report(&data, ProductLine::Milan),
+            Err(VerifyError::UntrustedRoot)
+        ));
+
+        // A bare report, or an empty table, has no certificates
+        for data in [report_bytes.clone(), build_extended_report(&report, &[])] {
+            let extended = parse_extended_report(&data, ProductLine::Genoa).unwrap();
+            assert!(extended.certs.is_none());
+        }
+    }
+
+    #[test]
+    fn test_invalid_cert_table() {
+        let report: AttestationReport = serde_json::from_str(SAMPLE_ATTESTATION).unwrap();
+
+        let data = build_extended_report(&report, &[(VCEK_GUID, SAMPLE_VCEK)]);
+        for truncated in [
+            &data[..data.len() - 1],
+            &data[..REPORT_SIZE + ENTRY_SIZE + 4],
+        ] {
+            assert!(matches!(
+                parse_extended_report(truncated, ProductLine::Genoa),
+                Err(VerifyError::CertTableInvalid { .. })
+            ));
+        }
+
+        let data = build_extended_report(
+            &report,
+            &[(VCEK_GUID, SAMPLE_VCEK), (VCEK_GUID, SAMPLE_VCEK)],
+        );
+        assert!(matches!(
+            parse_extended_report(&data, ProductLine::Genoa),
+            Err(VerifyError::CertTableInvalid { .. })
+        ));
+    }
+}
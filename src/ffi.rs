@@ -0,0 +1,260 @@
+use std::cell::RefCell;
+use std::ffi::{c_char, c_int, CStr, CString};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+use crate::policy::VerifyPolicy;
+use crate::product::ProductLine;
+use crate::verify_attestation::{verify_attestation_report, VerifyError};
+
+/// The report verified.
+pub const SEV_OK: c_int = 0;
+
+/// A required pointer argument was null.
+pub const SEV_ERR_NULL_ARGUMENT: c_int = 1;
+
+/// A string argument was not valid UTF-8, or named an unknown product line.
+pub const SEV_ERR_INVALID_ARGUMENT: c_int = 2;
+
+/// The attestation report could not be parsed.
+pub const SEV_ERR_REPORT_PARSE: c_int = 3;
+
+/// The VCEK could not be parsed.
+pub const SEV_ERR_VCEK_PARSE: c_int = 4;
+
+/// The VCEK or its certificate chain did not verify.
+pub const SEV_ERR_CERT_CHAIN: c_int = 5;
+
+/// The report was not signed by the VCEK.
+pub const SEV_ERR_REPORT_SIGNATURE: c_int = 6;
+
+/// The report is authentic, but was rejected by a verification check.
+pub const SEV_ERR_POLICY: c_int = 7;
+
+/// Verification failed for another reason.
+pub const SEV_ERR_OTHER: c_int = 8;
+
+/// Verification panicked. This is a bug.
+pub const SEV_ERR_PANIC: c_int = 99;
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(message: String) {
+    // Interior NULs cannot be represented in a C string
+    let message = CString::new(message.replace('\0', " ")).unwrap_or_default();
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(message));
+}
+
+/// The error code for a verification error.
+fn error_code(error: &VerifyError) -> c_int {
+    match error {
+        VerifyError::ReportParse(_)
+        | VerifyError::ReportLength { .. }
+        | VerifyError::ReportDecode(_)
+        | VerifyError::ReportEncoding { .. }
+        | VerifyError::CertTableInvalid { .. }
+        | VerifyError::EnvelopeInvalid { .. }
+        | VerifyError::UnsupportedReportVersion { .. }
+        | VerifyError::UnsupportedSignatureAlgo { .. }
+        | VerifyError::NonZeroReserved { .. } => SEV_ERR_REPORT_PARSE,
+        VerifyError::VcekParse(_) => SEV_ERR_VCEK_PARSE,
+        VerifyError::ChainBuild(_)
+        | VerifyError::MalformedCaChain { .. }
+        | VerifyError::ChainVerify(_)
+        | VerifyError::UntrustedRoot
+        | VerifyError::VcekExpired { .. }
+        | VerifyError::VcekNotYetValid { .. }
+        | VerifyError::VcekPinMismatch { .. }
+        | VerifyError::VcekTcbMismatch { .. }
+        | VerifyError::VcekIssuerMismatch { .. }
+        | VerifyError::ChipIdMismatch { .. }
+        | VerifyError::CrlInvalid(_)
+        | VerifyError::CrlExpired { .. }
+        | VerifyError::CrlNotYetValid { .. }
+        | VerifyError::CertificateRevoked { .. } => SEV_ERR_CERT_CHAIN,
+        VerifyError::ReportSignature(_) | VerifyError::SigningKeyMismatch { .. } => {
+            SEV_ERR_REPORT_SIGNATURE
+        }
+        VerifyError::MeasurementMismatch { .. }
+        | VerifyError::MeasurementNotAllowed { .. }
+        | VerifyError::ReportDataMismatch { .. }
+        | VerifyError::HostDataMismatch { .. }
+        | VerifyError::FamilyIdMismatch { .. }
+        | VerifyError::ImageIdMismatch { .. }
+        | VerifyError::ReportIdMismatch { .. }
+        | VerifyError::UnexpectedMigrationAgent { .. }
+        | VerifyError::UnexpectedVmpl { .. }
+        | VerifyError::UnexpectedSigningKey { .. }
+        | VerifyError::IdKeyDigestMismatch { .. }
+        | VerifyError::IdAuthSignature { .. }
+        | VerifyError::ReportTooOld { .. }
+        | VerifyError::PolicyViolation { .. }
+        | VerifyError::TcbBelowMinimum { .. }
+        | VerifyError::TcbRollback { .. } => SEV_ERR_POLICY,
+        VerifyError::Io(_) | VerifyError::Vcek(_) | VerifyError::IdAuthInvalid(_) => SEV_ERR_OTHER,
+    }
+}
+
+/// Runs `f`, recording any error (or panic) as the last error.
+fn ffi_result(f: impl FnOnce() -> Result<(), (c_int, String)>) -> c_int {
+    let result = catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|panic| {
+        let message = panic
+            .downcast_ref::<&str>()
+            .map(|s| s.to_string())
+            .or_else(|| panic.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "unknown panic".to_string());
+        Err((SEV_ERR_PANIC, format!("verification panicked: {message}")))
+    });
+
+    match result {
+        Ok(()) => {
+            LAST_ERROR.with(|last| *last.borrow_mut() = None);
+            SEV_OK
+        }
+        Err((code, message)) => {
+            set_last_error(message);
+            code
+        }
+    }
+}
+
+/// Reads a NUL-terminated UTF-8 string argument.
+///
+/// # Safety
+///
+/// `s` must be null or point to a NUL-terminated string.
+unsafe fn str_arg<'a>(s: *const c_char, name: &str) -> Result<&'a str, (c_int, String)> {
+    if s.is_null() {
+        return Err((SEV_ERR_NULL_ARGUMENT, format!("{name} is null")));
+    }
+    CStr::from_ptr(s).to_str().map_err(|e| {
+        (
+            SEV_ERR_INVALID_ARGUMENT,
+            format!("{name} is not UTF-8: {e}"),
+        )
+    })
+}
+
+/// Verifies a Genoa attestation report, given as JSON, against a DER-encoded VCEK.
+///
+/// Returns [`SEV_OK`] if the report verified, and an error code otherwise; see
+/// [`sev_last_error_message`] for details.
+///
+/// # Safety
+///
+/// `report_json` must point to a NUL-terminated string, and `vcek_der` to
+/// `vcek_len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn sev_verify_report(
+    report_json: *const c_char,
+    vcek_der: *const u8,
+    vcek_len: usize,
+) -> c_int {
+    sev_verify_report_for_product(report_json, vcek_der, vcek_len, std::ptr::null())
+}
+
+/// Verifies an attestation report, given as JSON, against a DER-encoded VCEK,
+/// for the named product line ("Milan", "Genoa" or "Turin"; Genoa if null).
+///
+/// # Safety
+///
+/// `report_json` must point to a NUL-terminated string, `vcek_der` to `vcek_len`
+/// readable bytes, and `product` must be null or point to a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn sev_verify_report_for_product(
+    report_json: *const c_char,
+    vcek_der: *const u8,
+    vcek_len: usize,
+    product: *const c_char,
+) -> c_int {
+    ffi_result(|| {
+        let report_json = str_arg(report_json, "report_json")?;
+        if vcek_der.is_null() {
+            return Err((SEV_ERR_NULL_ARGUMENT, "vcek_der is null".to_string()));
+        }
+        let vcek_der = std::slice::from_raw_parts(vcek_der, vcek_len);
+        let product = if product.is_null() {
+            ProductLine::default()
+        } else {
+            str_arg(product, "product")?
+                .parse::<ProductLine>()
+                .map_err(|e| (SEV_ERR_INVALID_ARGUMENT, e.to_string()))?
+        };
+
+        verify_attestation_report(report_json, vcek_der, product, &VerifyPolicy::default())
+            .map_err(|e| (error_code(&e), e.to_string()))
+    })
+}
+
+/// The message for the last error on this thread, or null if the last call succeeded.
+///
+/// The string is owned by the library, and is valid until the next call into
+/// it on the same thread.
+#[no_mangle]
+pub extern "C" fn sev_last_error_message() -> *const c_char {
+    LAST_ERROR.with(|last| {
+        last.borrow()
+            .as_ref()
+            .map_or(std::ptr::null(), |message| message.as_ptr())
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::verify_attestation::{SAMPLE_ATTESTATION, SAMPLE_VCEK};
+
+    fn last_error() -> Option<String> {
+        let message = sev_last_error_message();
+        (!message.is_null()).then(|| {
+            unsafe { CStr::from_ptr(message) }
+                .to_string_lossy()
+                .into_owned()
+        })
+    }
+
+    #[test]
+    fn test_sev_verify_report() {
+        let report = CString::new(SAMPLE_ATTESTATION).unwrap();
+        let code =
+            unsafe { sev_verify_report(report.as_ptr(), SAMPLE_VCEK.as_ptr(), SAMPLE_VCEK.len()) };
+        assert_eq!(code, SEV_OK);
+        assert_eq!(last_error(), None);
+
+        let code = unsafe { sev_verify_report(report.as_ptr(), SAMPLE_VCEK.as_ptr(), 10) };
+        assert_eq!(code, SEV_ERR_VCEK_PARSE);
+        assert!(last_error().is_some());
+
+        let milan = CString::new("Milan").unwrap();
+        let code = unsafe {
+            sev_verify_report_for_product(
+                report.as_ptr(),
+                SAMPLE_VCEK.as_ptr(),
+                SAMPLE_VCEK.len(),
+                milan.as_ptr(),
+            )
+        };
+        assert_eq!(code, SEV_ERR_CERT_CHAIN);
+    }
+
+    #[test]
+    fn test_sev_verify_report_bad_arguments() {
+        let code = unsafe { sev_verify_report(std::ptr::null(), SAMPLE_VCEK.as_ptr(), 0) };
+        assert_eq!(code, SEV_ERR_NULL_ARGUMENT);
+        assert_eq!(last_error().as_deref(), Some("report_json is null"));
+
+        let report = CString::new("{}").unwrap();
+        let code =
+            unsafe { sev_verify_report(report.as_ptr(), SAMPLE_VCEK.as_ptr(), SAMPLE_VCEK.len()) };
+        assert_eq!(code, SEV_ERR_REPORT_PARSE);
+    }
+
+    #[test]
+    fn test_panics_do_not_unwind() {
+        let code = ffi_result(|| panic!("boom"));
+        assert_eq!(code, SEV_ERR_PANIC);
+        assert_eq!(last_error().as_deref(), Some("verification panicked: boom"));
+    }
+}
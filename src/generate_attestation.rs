@@ -1,12 +1,20 @@
+use std::fmt;
+
+#[cfg(not(feature = "skip-generation"))]
 use serde::Serialize;
 
+#[cfg(not(feature = "skip-generation"))]
 use sev::firmware::guest::*;
 
 #[cfg(not(feature = "skip-generation"))]
+use crate::guest::{request_report, GuestError};
+#[cfg(not(feature = "skip-generation"))]
+use crate::product::ProductLine;
 use crate::verify_attestation::*;
 #[cfg(not(feature = "skip-generation"))]
 use base64::{engine::general_purpose, Engine as _};
 
+#[cfg(not(feature = "skip-generation"))]
 #[derive(Debug, Serialize)]
 struct AugementedReport {
     #[serde(flatten)]
@@ -15,45 +23,91 @@ struct AugementedReport {
     vcek: String,
 }
 
+/// An error encountered while generating an attestation report.
+#[derive(Debug)]
+pub enum GenerateError {
+    /// The data to attach is not 64 bytes of hex.
+    InvalidData(String),
+
+    /// The report could not be requested from `/dev/sev-guest`.
+    #[cfg(not(feature = "skip-generation"))]
+    Guest(GuestError),
+
+    /// The VCEK for the report could not be requested.
+    Vcek(VcekError),
+
+    /// Report generation is not compiled in, as the `skip-generation` feature is enabled.
+    Disabled,
+}
+
+impl fmt::Display for GenerateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GenerateError::InvalidData(reason) => write!(f, "invalid data to attach: {reason}"),
+            #[cfg(not(feature = "skip-generation"))]
+            GenerateError::Guest(e) => write!(f, "{e}"),
+            GenerateError::Vcek(e) => write!(f, "could not request VCEK: {e}"),
+            GenerateError::Disabled => write!(
+                f,
+                "cannot generate attestation report when the 'skip-generation' feature is enabled"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for GenerateError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            #[cfg(not(feature = "skip-generation"))]
+            GenerateError::Guest(e) => Some(e),
+            GenerateError::Vcek(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
 #[cfg(feature = "skip-generation")]
-pub fn generate_attestation_report(_data_to_attach: Option<String>) -> String {
-    panic!("Cannot generate attestation report when the 'skip-generation' feature is enabled");
+pub fn generate_attestation_report(
+    _data_to_attach: Option<String>,
+) -> Result<String, GenerateError> {
+    Err(GenerateError::Disabled)
 }
 
 /// Requests an AMD-SEV attestation from the CPU, and returns the report
 /// as a JSON string.
+///
+/// The report is requested through [`crate::guest::request_report`].
 #[cfg(not(feature = "skip-generation"))]
-pub fn generate_attestation_report(data_to_attach: Option<String>) -> String {
+pub fn generate_attestation_report(
+    data_to_attach: Option<String>,
+) -> Result<String, GenerateError> {
     // This is the data we are attaching to the attestation request.
     // It is typically a public key that we want to prove was generated
     // in the enclave.
-    let unique_data: [u8; 64] = data_to_attach.map_or([7; 64], |s| {
-        hex::decode(s)
-            .expect("data_to_attach was not a valid hex string")
+    let unique_data: [u8; 64] = match data_to_attach {
+        Some(s) => hex::decode(s)
+            .map_err(|e| GenerateError::InvalidData(e.to_string()))?
             .try_into()
-            .expect("data_to_attach was not 64 bytes")
-    });
+            .map_err(|data: Vec<u8>| {
+                GenerateError::InvalidData(format!("expected 64 bytes, found {}", data.len()))
+            })?,
+        None => [7; 64],
+    };
 
-    // Open a connection to the firmware and request a standard attestation report
-    let mut fw: Firmware = Firmware::open().expect("could not open /dev/sev-guest");
-    let attestation_report = fw
-        .get_report(None, Some(unique_data), None)
-        .expect("could not generate attestation");
+    let attestation_report = request_report(unique_data).map_err(GenerateError::Guest)?;
 
     // Add the VCEK certificate directly to the report
     let vcek_bytes = request_vcek(
         attestation_report.chip_id,
         attestation_report.reported_tcb,
-        SEV_PROD_NAME,
-    );
+        ProductLine::Genoa,
+    )
+    .map_err(GenerateError::Vcek)?;
     let attestation_report = AugementedReport {
         report: attestation_report,
-        vcek: general_purpose::STANDARD_NO_PAD.encode(&vcek_bytes),
+        vcek: general_purpose::STANDARD_NO_PAD.encode(vcek_bytes),
     };
 
     // Serialize the report to JSON
-    let report_json = serde_json::to_string(&attestation_report)
-        .expect("could not serialize attestation to JSON");
-
-    report_json
+    Ok(serde_json::to_string(&attestation_report).expect("reports are serializable"))
 }
@@ -0,0 +1,55 @@
+//! Requesting attestation reports from inside an SEV-SNP guest.
+//!
+//! The reports are signed by the AMD Secure Processor, through the guest's
+//! `/dev/sev-guest` device, so this only works in a confidential VM. It is
+//! compiled only on Linux, with the `guest` feature or with report generation
+//! enabled (without `skip-generation`), and lets a guest verify its own report,
+//! or a test run the whole round trip from request to verification.
+//! [`crate::generate_attestation`] requests its reports here too.
+
+use std::fmt;
+
+use sev::error::UserApiError;
+use sev::firmware::guest::{AttestationReport, Firmware};
+
+/// An error encountered while requesting a report from the guest device.
+#[derive(Debug)]
+pub enum GuestError {
+    /// `/dev/sev-guest` could not be opened, as when not running in an SEV-SNP
+    /// guest or without permission to the device.
+    Open(std::io::Error),
+
+    /// The Secure Processor did not return a report.
+    Request(UserApiError),
+}
+
+impl fmt::Display for GuestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GuestError::Open(e) => write!(f, "could not open /dev/sev-guest: {e}"),
+            GuestError::Request(e) => write!(f, "could not request attestation report: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for GuestError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            GuestError::Open(e) => Some(e),
+            GuestError::Request(e) => Some(e),
+        }
+    }
+}
+
+/// Requests a freshly signed attestation report carrying the given report data,
+/// typically a nonce from the verifier.
+///
+/// The report is requested for VMPL 0, the level of a guest OS not running
+/// below an SVSM or paravisor. Verify it with a [`crate::verifier::Verifier`]
+/// that requires the same nonce.
+pub fn request_report(nonce: [u8; 64]) -> Result<AttestationReport, GuestError> {
+    let mut firmware = Firmware::open().map_err(GuestError::Open)?;
+    firmware
+        .get_report(None, Some(nonce), None)
+        .map_err(GuestError::Request)
+}
@@ -0,0 +1,430 @@
+use std::io::{Error, ErrorKind};
+
+use openssl::{
+    bn::{BigNum, BigNumContext},
+    ec::{EcGroup, EcKey, EcKeyRef},
+    ecdsa::EcdsaSig,
+    nid::Nid,
+    pkey::Public,
+    sha::sha384,
+};
+use sev::firmware::guest::AttestationReport;
+
+use crate::crypto::ct_eq;
+use crate::policy::{policy_bits, SigningKeyDigest};
+use crate::report::report_to_array;
+use crate::verify_attestation::VerifyError;
+
+/// The ID_KEY_ALGO (and AUTHOR_KEY_ALGO) of ECDSA P-384 with SHA-384, the only
+/// algorithm the SNP firmware supports.
+const ECDSA_P384_SHA384: u32 = 1;
+
+/// The CURVE of a P-384 key in the SNP public key format.
+const CURVE_P384: u32 = 2;
+
+/// The size of each coordinate (and of each signature component) in the SNP
+/// formats, which leave room for P-521.
+const COMPONENT_SIZE: usize = 72;
+
+/// The size of a public key in the SNP format.
+const PUBLIC_KEY_SIZE: usize = 0x404;
+
+/// The size of a signature in the SNP format.
+const SIGNATURE_SIZE: usize = 0x200;
+
+/// The version of the ID block layout.
+const ID_BLOCK_VERSION: u32 = 1;
+
+/// The ID authentication information structure (ID_AUTH_INFO) a guest was
+/// launched with, holding the keys that vouch for its ID block.
+///
+/// This is the 4096-byte structure passed to QEMU as the base64 `id-auth`
+/// option of the `sev-snp-guest` object. Its ID key signs the guest's ID block
+/// (its measurement, family and image IDs, SVN and policy), and (if the guest
+/// owner uses one) its author key signs the ID key. The report carries only
+/// the digests of the keys, so a guest owner holding the ID_AUTH_INFO can
+/// prove the guest was launched under their keys with [`verify_id_auth`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IdAuth {
+    data: Vec<u8>,
+}
+
+impl IdAuth {
+    /// The size of the structure.
+    pub const SIZE: usize = 0x1000;
+
+    /// Parses the raw 4096-byte structure.
+    pub fn from_bytes(data: &[u8]) -> Result<Self, VerifyError> {
+        if data.len() != Self::SIZE {
+            return Err(invalid(format!(
+                "expected {} bytes, got {}",
+                Self::SIZE,
+                data.len()
+            )));
+        }
+        let id_auth = Self {
+            data: data.to_vec(),
+        };
+        if id_auth.u32_at(0) != ECDSA_P384_SHA384 {
+            return Err(invalid(format!(
+                "unsupported ID key algorithm {}",
+                id_auth.u32_at(0)
+            )));
+        }
+        Ok(id_auth)
+    }
+
+    /// Parses the structure as given to QEMU, base64-encoded.
+    pub fn from_base64(s: &str) -> Result<Self, VerifyError> {
+        use base64::{engine::general_purpose::STANDARD, Engine};
+
+        let data = STANDARD
+            .decode(s.trim())
+            .map_err(|e| invalid(e.to_string()))?;
+        Self::from_bytes(&data)
+    }
+
+    /// The raw 4096-byte structure.
+    pub fn to_bytes(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// The ID key, which signs the ID block.
+    pub fn id_key(&self) -> Result<EcKey<Public>, VerifyError> {
+        parse_public_key(self.id_key_bytes())
+    }
+
+    /// The author key, which signs the ID key.
+    pub fn author_key(&self) -> Result<EcKey<Public>, VerifyError> {
+        if self.u32_at(4) != ECDSA_P384_SHA384 {
+            return Err(invalid(format!(
+                "unsupported author key algorithm {}",
+                self.u32_at(4)
+            )));
+        }
+        parse_public_key(self.author_key_bytes())
+    }
+
+    fn id_block_sig(&self) -> &[u8] {
+        &self.data[0x40..0x40 + SIGNATURE_SIZE]
+    }
+
+    fn id_key_bytes(&self) -> &[u8] {
+        &self.data[0x240..0x240 + PUBLIC_KEY_SIZE]
+    }
+
+    fn id_key_sig(&self) -> &[u8] {
+        &self.data[0x680..0x680 + SIGNATURE_SIZE]
+    }
+
+    fn author_key_bytes(&self) -> &[u8] {
+        &self.data[0x880..0x880 + PUBLIC_KEY_SIZE]
+    }
+
+    fn u32_at(&self, offset: usize) -> u32 {
+        u32::from_le_bytes(self.data[offset..offset + 4].try_into().unwrap())
+    }
+}
+
+/// Verifies that a guest was launched with the given ID authentication information.
+///
+/// This checks that the report's ID key digest is that of the ID key, and that
+/// the ID key signs the ID block the report describes. If the report says an
+/// author key was used, it also checks the author key digest, and that the
+/// author key signs the ID key.
+pub fn verify_id_auth(report: &AttestationReport, id_auth: &IdAuth) -> Result<(), VerifyError> {
+    check_key_digest(
+        SigningKeyDigest::IdKey,
+        &report.id_key_digest,
+        id_auth.id_key_bytes(),
+    )?;
+    let id_key = id_auth.id_key()?;
+    verify_signature(
+        SigningKeyDigest::IdKey,
+        &id_key,
+        &id_block(report),
+        id_auth.id_block_sig(),
+    )?;
+
+    if author_key_enabled(report) {
+        check_key_digest(
+            SigningKeyDigest::AuthorKey,
+            &report.author_key_digest,
+            id_auth.author_key_bytes(),
+        )?;
+        let author_key = id_auth.author_key()?;
+        verify_signature(
+            SigningKeyDigest::AuthorKey,
+            &author_key,
+            id_auth.id_key_bytes(),
+            id_auth.id_key_sig(),
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Checks that the report's ID key (or author key) digest is the digest of the given key.
+///
+/// Unlike [`verify_id_auth`], this does not check any signature: it establishes
+/// which key the guest owner launched the guest with, not that the key signed
+/// this guest's ID block.
+pub fn check_id_key(
+    report: &AttestationReport,
+    which: SigningKeyDigest,
+    key: &EcKeyRef<Public>,
+) -> Result<(), VerifyError> {
+    let expected = match which {
+        SigningKeyDigest::IdKey => &report.id_key_digest,
+        SigningKeyDigest::AuthorKey => &report.author_key_digest,
+    };
+    check_key_digest(which, expected, &public_key_bytes(key)?)
+}
+
+/// The SHA-384 digest of a P-384 public key, as the report's ID_KEY_DIGEST
+/// and AUTHOR_KEY_DIGEST fields hold it.
+pub fn key_digest(key: &EcKeyRef<Public>) -> Result<[u8; 48], VerifyError> {
+    Ok(sha384(&public_key_bytes(key)?))
+}
+
+/// The ID block a report's guest was launched with, as its ID key signs it.
+///
+/// Every field of the ID block is reflected in the report, so it can be rebuilt
+/// rather than supplied.
+pub fn id_block(report: &AttestationReport) -> [u8; 0x60] {
+    let mut block = [0; 0x60];
+    block[..0x30].copy_from_slice(&report.measurement);
+    block[0x30..0x40].copy_from_slice(&report.family_id);
+    block[0x40..0x50].copy_from_slice(&report.image_id);
+    block[0x50..0x54].copy_from_slice(&ID_BLOCK_VERSION.to_le_bytes());
+    block[0x54..0x58].copy_from_slice(&report.guest_svn.to_le_bytes());
+    block[0x58..].copy_from_slice(&policy_bits(&report.policy).to_le_bytes());
+    block
+}
+
+/// Whether the guest was launched with an author key, from the AUTHOR_KEY_EN
+/// bit of the report, which the sev crate keeps private.
+pub fn author_key_enabled(report: &AttestationReport) -> bool {
+    report_to_array(report)[0x48] & 1 == 1
+}
+
+fn check_key_digest(
+    which: SigningKeyDigest,
+    expected: &[u8; 48],
+    key_bytes: &[u8],
+) -> Result<(), VerifyError> {
+    let actual = sha384(key_bytes);
+    if !ct_eq(&actual, expected) {
+        return Err(VerifyError::IdKeyDigestMismatch {
+            key: which,
+            expected: *expected,
+            actual,
+        });
+    }
+
+    Ok(())
+}
+
+/// Verifies an SNP-format signature (r and s, little-endian) over the SHA-384 digest of `data`.
+fn verify_signature(
+    signer: SigningKeyDigest,
+    key: &EcKeyRef<Public>,
+    data: &[u8],
+    signature: &[u8],
+) -> Result<(), VerifyError> {
+    let component = |bytes: &[u8]| {
+        let mut be = bytes.to_vec();
+        be.reverse();
+        BigNum::from_slice(&be)
+    };
+    let sig = component(&signature[..COMPONENT_SIZE])
+        .and_then(|r| {
+            let s = component(&signature[COMPONENT_SIZE..2 * COMPONENT_SIZE])?;
+            EcdsaSig::from_private_components(r, s)
+        })
+        .map_err(|e| invalid(e.to_string()))?;
+
+    // A malformed signature fails to verify, rather than being an error
+    if !sig.verify(&sha384(data), key).unwrap_or(false) {
+        return Err(VerifyError::IdAuthSignature { signer });
+    }
+
+    Ok(())
+}
+
+/// Parses a public key in the SNP format: the curve, then the little-endian coordinates.
+fn parse_public_key(bytes: &[u8]) -> Result<EcKey<Public>, VerifyError> {
+    let curve = u32::from_le_bytes(bytes[..4].try_into().unwrap());
+    if curve != CURVE_P384 {
+        return Err(invalid(format!("unsupported curve {curve}")));
+    }
+    let coordinate = |offset: usize| {
+        let mut be = bytes[offset..offset + COMPONENT_SIZE].to_vec();
+        be.reverse();
+        BigNum::from_slice(&be)
+    };
+
+    let key = || {
+        let group = EcGroup::from_curve_name(Nid::SECP384R1)?;
+        let x = coordinate(4)?;
+        let y = coordinate(4 + COMPONENT_SIZE)?;
+        EcKey::from_public_key_affine_coordinates(&group, &x, &y)
+    };
+    key().map_err(|e| invalid(format!("invalid public key: {e}")))
+}
+
+/// Encodes a P-384 public key in the SNP format.
+fn public_key_bytes(key: &EcKeyRef<Public>) -> Result<Vec<u8>, VerifyError> {
+    if key.group().curve_name() != Some(Nid::SECP384R1) {
+        return Err(invalid("key is not a P-384 key".to_string()));
+    }
+
+    let encode = || -> Result<Vec<u8>, openssl::error::ErrorStack> {
+        let mut x = BigNum::new()?;
+        let mut y = BigNum::new()?;
+        let mut ctx = BigNumContext::new()?;
+        key.public_key()
+            .affine_coordinates(key.group(), &mut x, &mut y, &mut ctx)?;
+
+        let mut bytes = vec![0; PUBLIC_KEY_SIZE];
+        bytes[..4].copy_from_slice(&CURVE_P384.to_le_bytes());
+        for (offset, coordinate) in [(4, x), (4 + COMPONENT_SIZE, y)] {
+            let mut le = coordinate.to_vec_padded(COMPONENT_SIZE as i32)?;
+            le.reverse();
+            bytes[offset..offset + COMPONENT_SIZE].copy_from_slice(&le);
+        }
+        Ok(bytes)
+    };
+    encode().map_err(|e| invalid(e.to_string()))
+}
+
+fn invalid(message: String) -> VerifyError {
+    VerifyError::IdAuthInvalid(Error::new(ErrorKind::InvalidData, message))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::report::parse_report_bytes;
+    use crate::verify_attestation::SAMPLE_ATTESTATION;
+
+    /// An ID_AUTH_INFO, from the test suite of the virtee `sev` crate, signing
+    /// an ID block for [`TEST_MEASUREMENT`] with the sample report's (all-zero)
+    /// family and image IDs, SVN and policy.
+    const TEST_ID_AUTH: &[u8] = include_bytes!("../data/test_id_auth.bin");
+
+    const TEST_ID_KEY: &[u8] = include_bytes!("../data/test_id_key.pem");
+    const TEST_AUTHOR_KEY: &[u8] = include_bytes!("../data/test_author_key.pem");
+
+    const TEST_MEASUREMENT: &str = "a14d638341e5674628fe1e02fbedebe5e5ab4faf1dd72d8966068e615bb99efd13d214b35c231715b38e263dc6059745";
+
+    /// The sample report, altered to describe a guest launched with [`TEST_ID_AUTH`].
+    fn launched_report(author_key: bool) -> AttestationReport {
+        let report: AttestationReport = serde_json::from_str(SAMPLE_ATTESTATION).unwrap();
+        let mut bytes = report_to_array(&report);
+        bytes[0x48] |= author_key as u8;
+        let mut report = parse_report_bytes(&bytes).unwrap();
+
+        report.measurement = hex::decode(TEST_MEASUREMENT).unwrap().try_into().unwrap();
+        let id_key = EcKey::public_key_from_pem(TEST_ID_KEY).unwrap();
+        report.id_key_digest = key_digest(&id_key).unwrap();
+        if author_key {
+            let author_key = EcKey::public_key_from_pem(TEST_AUTHOR_KEY).unwrap();
+            report.author_key_digest = key_digest(&author_key).unwrap();
+        }
+        report
+    }
+
+    #[test]
+    fn test_key_digest() {
+        let id_key = EcKey::public_key_from_pem(TEST_ID_KEY).unwrap();
+        assert_eq!(
+            hex::encode(key_digest(&id_key).unwrap()),
+            "59fb2c686dfa4fef49f38339aa775c90051c28d57383ddc3baf3b5eb4b1496df3b0dbbdcbae5fe27677a644820773fe2"
+        );
+
+        let id_auth = IdAuth::from_bytes(TEST_ID_AUTH).unwrap();
+        assert_eq!(
+            public_key_bytes(&id_auth.id_key().unwrap()).unwrap(),
+            id_auth.id_key_bytes()
+        );
+    }
+
+    #[test]
+    fn test_verify_id_auth() {
+        let id_auth = IdAuth::from_bytes(TEST_ID_AUTH).unwrap();
+        assert!(verify_id_auth(&launched_report(false), &id_auth).is_ok());
+        assert!(verify_id_auth(&launched_report(true), &id_auth).is_ok());
+
+        // The ID block signature covers the measurement
+        let mut report = launched_report(true);
+        report.measurement[0] ^= 1;
+        assert!(matches!(
+            verify_id_auth(&report, &id_auth),
+            Err(VerifyError::IdAuthSignature {
+                signer: SigningKeyDigest::IdKey
+            })
+        ));
+
+        let mut report = launched_report(true);
+        report.author_key_digest = [0; 48];
+        assert!(matches!(
+            verify_id_auth(&report, &id_auth),
+            Err(VerifyError::IdKeyDigestMismatch {
+                key: SigningKeyDigest::AuthorKey,
+                ..
+            })
+        ));
+
+        // The author key must sign the ID key
+        let mut data = TEST_ID_AUTH.to_vec();
+        data[0x680] ^= 1;
+        let id_auth = IdAuth::from_bytes(&data).unwrap();
+        assert!(verify_id_auth(&launched_report(false), &id_auth).is_ok());
+        assert!(matches!(
+            verify_id_auth(&launched_report(true), &id_auth),
+            Err(VerifyError::IdAuthSignature {
+                signer: SigningKeyDigest::AuthorKey
+            })
+        ));
+
+        // The sample report was launched without an ID block
+        let report: AttestationReport = serde_json::from_str(SAMPLE_ATTESTATION).unwrap();
+        assert!(matches!(
+            verify_id_auth(&report, &id_auth),
+            Err(VerifyError::IdKeyDigestMismatch {
+                key: SigningKeyDigest::IdKey,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_check_id_key() {
+        let report = launched_report(true);
+        let id_key = EcKey::public_key_from_pem(TEST_ID_KEY).unwrap();
+        let author_key = EcKey::public_key_from_pem(TEST_AUTHOR_KEY).unwrap();
+
+        assert!(check_id_key(&report, SigningKeyDigest::IdKey, &id_key).is_ok());
+        assert!(check_id_key(&report, SigningKeyDigest::AuthorKey, &author_key).is_ok());
+        assert!(matches!(
+            check_id_key(&report, SigningKeyDigest::IdKey, &author_key),
+            Err(VerifyError::IdKeyDigestMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_parse_id_auth() {
+        use base64::{engine::general_purpose::STANDARD, Engine};
+
+        let id_auth = IdAuth::from_base64(&STANDARD.encode(TEST_ID_AUTH)).unwrap();
+        assert_eq!(id_auth.to_bytes(), TEST_ID_AUTH);
+        assert!(matches!(
+            IdAuth::from_bytes(&TEST_ID_AUTH[1..]),
+            Err(VerifyError::IdAuthInvalid(_))
+        ));
+        assert!(IdAuth::from_base64("not base64!").is_err());
+        assert!(IdAuth::from_bytes(&[0; IdAuth::SIZE]).is_err());
+    }
+}
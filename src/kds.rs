@@ -0,0 +1,1480 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::path::Path;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+use miniz_oxide::inflate::stream::{inflate as inflate_stream, InflateState};
+use miniz_oxide::{DataFormat, MZError, MZFlush, MZStatus};
+use reqwest::{
+    header::{ACCEPT_ENCODING, CONTENT_ENCODING},
+    StatusCode,
+};
+use sev::{
+    certs::snp::{ca, Certificate},
+    firmware::host::TcbVersion,
+};
+
+use crate::crl::Crl;
+use crate::crypto::is_pem_certificate;
+use crate::product::ProductLine;
+use crate::tcb::tcb_to_kds_query;
+use crate::vcek::{is_der_sequence, TcbSpl};
+use crate::verify_attestation::{
+    ca_chain_from_remote_pem, parse_vcek_bundle, verify_vcek, verify_vcek_with_ca, VcekBundle,
+    VcekError, VerifyError, KDS_CERT_CHAIN, KDS_CERT_SITE, KDS_CRL, KDS_VCEK, KDS_VLEK,
+};
+
+/// Whether a [`KdsClient`] may make network requests.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NetworkMode {
+    /// Requests are made to KDS.
+    #[default]
+    Online,
+
+    /// No request is ever made; every request fails with [`VcekError::Offline`].
+    ///
+    /// This guarantees that verification (of a report, say, in an air-gapped
+    /// deployment) uses only the certificates it is given, along with those
+    /// embedded in this crate and those already in a [`crate::vcek_cache::VcekCache`].
+    Offline,
+}
+
+/// The proxy a [`KdsClient`] sends its requests through.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum KdsProxy {
+    /// The proxy configured by the environment: `HTTPS_PROXY` (or `HTTP_PROXY`
+    /// for plain HTTP URLs), except for the hosts in `NO_PROXY`.
+    #[default]
+    System,
+
+    /// The proxy at the given URL, such as "http://proxy.internal:3128",
+    /// whatever the environment.
+    Url(String),
+
+    /// No proxy, whatever the environment.
+    Disabled,
+}
+
+/// Builds the KDS URL of the VCEK for the specified chip and TCB, as requested by
+/// [`KdsClient::request_vcek`].
+///
+/// `base` is the URL of KDS or a mirror of it, such as [`KDS_CERT_SITE`]. This
+/// lets requests be proxied or pre-signed without duplicating the formatting of
+/// the TCB's security patch levels.
+///
+/// From Turin on, KDS identifies a chip by its 8-byte hwID (the start of the
+/// chip ID) and the TCB also carries an FMC SPL, decoded as by [`TcbSpl::from_tcb`].
+pub fn build_vcek_url(
+    base: &str,
+    product: ProductLine,
+    chip_id: [u8; 64],
+    tcb: TcbVersion,
+) -> String {
+    let turin = product == ProductLine::Turin;
+    let hwid = if turin { &chip_id[..8] } else { &chip_id[..] };
+    format!(
+        "{}{KDS_VCEK}/{product}/{}?{}",
+        base.trim_end_matches('/'),
+        hex::encode(hwid),
+        tcb_to_kds_query(&TcbSpl::from_tcb(&tcb, turin)),
+    )
+}
+
+/// Builds the KDS URL of the ARK/ASK certificate chain for the product, as
+/// requested by [`KdsClient::get_cert_chain`].
+pub fn build_cert_chain_url(base: &str, product: ProductLine) -> String {
+    format!(
+        "{}{KDS_VCEK}/{product}/{KDS_CERT_CHAIN}",
+        base.trim_end_matches('/')
+    )
+}
+
+/// Builds the KDS URL of the ARK/ASVK certificate chain that signs VLEKs for the
+/// product, as requested by [`KdsClient::get_vlek_cert_chain`].
+pub fn build_vlek_cert_chain_url(base: &str, product: ProductLine) -> String {
+    format!(
+        "{}{KDS_VLEK}/{product}/{KDS_CERT_CHAIN}",
+        base.trim_end_matches('/')
+    )
+}
+
+/// Builds the KDS URL of the certificate revocation list for the product, as
+/// requested by [`KdsClient::get_crl`].
+pub fn build_crl_url(base: &str, product: ProductLine) -> String {
+    format!(
+        "{}{KDS_VCEK}/{product}/{KDS_CRL}",
+        base.trim_end_matches('/')
+    )
+}
+
+/// The content encodings a [`KdsClient`] accepts, and decompresses itself.
+const ACCEPTED_ENCODINGS: &str = "gzip, deflate";
+
+/// The magic bytes starting a gzip stream.
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// A client for the AMD Key Distribution Service (KDS), or a mirror of it.
+///
+/// The default client talks to AMD's KDS at [`KDS_CERT_SITE`]. Deployments
+/// without access to it (say, air-gapped ones) can point a client at an internal
+/// mirror that serves the same paths.
+///
+/// KDS generates a VCEK on its first request, and often responds with HTTP 429
+/// (Too Many Requests) until it is ready. Requests that fail with 429 or a 5xx
+/// status are retried with exponential backoff, up to [`KdsClient::retries`] times.
+///
+/// Responses compressed with gzip or deflate, as by a proxy in front of KDS, are
+/// decompressed before they are parsed.
+#[derive(Debug, Clone)]
+pub struct KdsClient {
+    base_url: String,
+    mode: NetworkMode,
+    retries: u32,
+    base_delay: Duration,
+    timeout: Duration,
+    proxy: KdsProxy,
+    max_response_size: usize,
+    http_client: Option<reqwest::blocking::Client>,
+    // Built on the first request from the timeout and proxy, and reset when they change
+    default_client: OnceLock<reqwest::blocking::Client>,
+    #[cfg(feature = "async")]
+    default_async_client: OnceLock<reqwest::Client>,
+}
+
+impl Default for KdsClient {
+    fn default() -> Self {
+        Self::new(KDS_CERT_SITE)
+    }
+}
+
+impl KdsClient {
+    /// The default number of times a request is retried.
+    pub const DEFAULT_RETRIES: u32 = 4;
+
+    /// The default delay before the first retry, which doubles on each later retry.
+    pub const DEFAULT_BASE_DELAY: Duration = Duration::from_millis(500);
+
+    /// The default time a single request may take, from connecting to reading the body.
+    pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+    /// The default maximum size of a response body. Certificates and CRLs are a
+    /// few KiB, so this is only reached by a misbehaving server.
+    pub const DEFAULT_MAX_RESPONSE_SIZE: usize = 4 * 1024 * 1024;
+
+    /// Creates a client for the KDS at the given base URL, such as "https://kdsintf.amd.com".
+    pub fn new(base_url: impl Into<String>) -> Self {
+        let base_url = base_url.into().trim_end_matches('/').to_string();
+        Self {
+            base_url,
+            mode: NetworkMode::Online,
+            retries: Self::DEFAULT_RETRIES,
+            base_delay: Self::DEFAULT_BASE_DELAY,
+            timeout: Self::DEFAULT_TIMEOUT,
+            proxy: KdsProxy::System,
+            max_response_size: Self::DEFAULT_MAX_RESPONSE_SIZE,
+            http_client: None,
+            default_client: OnceLock::new(),
+            #[cfg(feature = "async")]
+            default_async_client: OnceLock::new(),
+        }
+    }
+
+    /// Creates a client that never makes a network request.
+    pub fn offline() -> Self {
+        Self::default().with_mode(NetworkMode::Offline)
+    }
+
+    /// Sets whether the client may make network requests.
+    pub fn with_mode(mut self, mode: NetworkMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Sets how many times a request that fails with a retryable status is retried.
+    pub fn with_retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// Sets the delay before the first retry, which doubles on each later retry.
+    pub fn with_base_delay(mut self, base_delay: Duration) -> Self {
+        self.base_delay = base_delay;
+        self
+    }
+
+    /// Sets the time a single request may take before failing with [`VcekError::Timeout`].
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self.reset_default_clients();
+        self
+    }
+
+    /// Sets the proxy requests are sent through.
+    pub fn with_proxy(mut self, proxy: KdsProxy) -> Self {
+        self.proxy = proxy;
+        self.reset_default_clients();
+        self
+    }
+
+    /// Sets the largest response body, in bytes, that is read before the request
+    /// fails with [`VcekError::ResponseTooLarge`].
+    pub fn with_max_response_size(mut self, max_response_size: usize) -> Self {
+        self.max_response_size = max_response_size;
+        self
+    }
+
+    /// Sends blocking requests through the given HTTP client, such as one with a
+    /// custom CA bundle, client certificates or connection pool.
+    ///
+    /// The timeout and proxy set by [`KdsClient::with_timeout`] and
+    /// [`KdsClient::with_proxy`] are then ignored for blocking requests, which
+    /// use the client's own. Without a custom client, one is built from those
+    /// settings on the first request and reused for later ones.
+    pub fn with_http_client(mut self, http_client: reqwest::blocking::Client) -> Self {
+        self.http_client = Some(http_client);
+        self
+    }
+
+    /// The base URL of the KDS this client talks to.
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    /// Whether the client may make network requests.
+    pub fn mode(&self) -> NetworkMode {
+        self.mode
+    }
+
+    /// How many times a request that fails with a retryable status is retried.
+    pub fn retries(&self) -> u32 {
+        self.retries
+    }
+
+    /// The delay before the first retry.
+    pub fn base_delay(&self) -> Duration {
+        self.base_delay
+    }
+
+    /// The time a single request may take.
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// The proxy requests are sent through.
+    pub fn proxy(&self) -> &KdsProxy {
+        &self.proxy
+    }
+
+    /// The largest response body, in bytes, that is read.
+    pub fn max_response_size(&self) -> usize {
+        self.max_response_size
+    }
+
+    /// The HTTP client blocking requests are sent through, if one was given.
+    pub fn http_client(&self) -> Option<&reqwest::blocking::Client> {
+        self.http_client.as_ref()
+    }
+
+    /// Requests the VCEK for the specified chip and TCB, as a DER-encoded X509 certificate.
+    ///
+    /// See [`crate::verify_attestation::request_vcek`].
+    pub fn request_vcek(
+        &self,
+        chip_id: [u8; 64],
+        reported_tcb: TcbVersion,
+        product: ProductLine,
+    ) -> Result<Vec<u8>, VcekError> {
+        self.get(&build_vcek_url(
+            &self.base_url,
+            product,
+            chip_id,
+            reported_tcb,
+        ))
+        .and_then(check_vcek_body)
+    }
+
+    /// Requests the VCEK for the specified chip and TCB together with the ARK/ASK
+    /// chain that issued it, checking that the VCEK chains to it.
+    ///
+    /// A server that answers the VCEK request with a PEM bundle of the VCEK, ASK and
+    /// ARK (see [`parse_vcek_bundle`]) needs only the one request. AMD's KDS answers
+    /// with the VCEK alone, so the chain is then requested separately.
+    pub fn request_vcek_bundle(
+        &self,
+        chip_id: [u8; 64],
+        reported_tcb: TcbVersion,
+        product: ProductLine,
+    ) -> Result<VcekBundle, VerifyError> {
+        let rsp_bytes = self.get(&build_vcek_url(
+            &self.base_url,
+            product,
+            chip_id,
+            reported_tcb,
+        ))?;
+        let bundle = if is_pem_certificate(&rsp_bytes) {
+            parse_vcek_bundle(&rsp_bytes, product)?
+        } else {
+            let der = check_vcek_body(rsp_bytes)?;
+            let vcek = Certificate::from_der(&der).map_err(VerifyError::VcekParse)?;
+            VcekBundle { vcek, ca: None }
+        };
+
+        let ca = match bundle.ca {
+            Some(ca) => ca,
+            None => self.get_cert_chain(product)?,
+        };
+        verify_vcek_with_ca(&bundle.vcek, &ca)?;
+
+        Ok(VcekBundle {
+            vcek: bundle.vcek,
+            ca: Some(ca),
+        })
+    }
+
+    /// Requests the VCEK for the specified chip and TCB, and writes it to `out`
+    /// as a DER-encoded X509 certificate.
+    ///
+    /// See [`crate::verify_attestation::save_vcek`].
+    pub fn save_vcek(
+        &self,
+        chip_id: [u8; 64],
+        reported_tcb: TcbVersion,
+        product: ProductLine,
+        out: &Path,
+    ) -> Result<(), VerifyError> {
+        let vcek_bytes = self.request_vcek(chip_id, reported_tcb, product)?;
+        let vcek = Certificate::from_der(&vcek_bytes).map_err(VerifyError::VcekParse)?;
+        verify_vcek(&vcek, product)?;
+
+        std::fs::write(out, vcek_bytes).map_err(VerifyError::Io)
+    }
+
+    /// Requests the VCEK for the specified chip and TCB, without blocking.
+    #[cfg(feature = "async")]
+    pub async fn request_vcek_async(
+        &self,
+        chip_id: [u8; 64],
+        reported_tcb: TcbVersion,
+        product: ProductLine,
+    ) -> Result<Vec<u8>, VcekError> {
+        self.get_async(&build_vcek_url(
+            &self.base_url,
+            product,
+            chip_id,
+            reported_tcb,
+        ))
+        .await
+        .and_then(check_vcek_body)
+    }
+
+    /// Requests the ARK/ASK certificate chain for the product.
+    ///
+    /// See [`crate::verify_attestation::get_cert_chain_remote`].
+    pub fn get_cert_chain(&self, product: ProductLine) -> Result<ca::Chain, VerifyError> {
+        let url = build_cert_chain_url(&self.base_url, product);
+        let pem = self.get(&url)?;
+
+        ca_chain_from_remote_pem(product, &pem)
+    }
+
+    /// Requests the ARK/ASVK certificate chain that signs VLEKs for the product.
+    ///
+    /// See [`crate::verify_attestation::get_vlek_cert_chain_remote`].
+    pub fn get_vlek_cert_chain(&self, product: ProductLine) -> Result<ca::Chain, VerifyError> {
+        let url = build_vlek_cert_chain_url(&self.base_url, product);
+        let pem = self.get(&url)?;
+
+        ca_chain_from_remote_pem(product, &pem)
+    }
+
+    /// Requests the certificate revocation list (CRL) for the product.
+    ///
+    /// The CRL is not checked against the ARK here; see [`Crl::check`].
+    pub fn get_crl(&self, product: ProductLine) -> Result<Crl, VerifyError> {
+        let url = build_crl_url(&self.base_url, product);
+        let der = self.get(&url)?;
+
+        Crl::from_der(der)
+    }
+
+    /// Checks that KDS is reachable, by requesting the product's ARK/ASK chain,
+    /// and returns how long the request took.
+    ///
+    /// The request is made once, without retrying, so that an unhealthy KDS is
+    /// reported promptly. See [`crate::verify_attestation::kds_health_check`].
+    pub fn health_check(&self, product: ProductLine) -> Result<Duration, VcekError> {
+        if self.mode == NetworkMode::Offline {
+            return Err(VcekError::Offline);
+        }
+
+        let url = build_cert_chain_url(&self.base_url, product);
+        let start = Instant::now();
+        self.get_once(&url)?;
+        let latency = start.elapsed();
+        info!(url, ?latency, "KDS is reachable");
+
+        Ok(latency)
+    }
+
+    /// Fetches the given KDS URL, retrying on a retryable status.
+    fn get(&self, url: &str) -> Result<Vec<u8>, VcekError> {
+        if self.mode == NetworkMode::Offline {
+            debug!(url, "not requesting from KDS while offline");
+            return Err(VcekError::Offline);
+        }
+
+        info!(url, "requesting from KDS");
+        let mut attempts = 0;
+        loop {
+            #[cfg(feature = "metrics")]
+            let start = Instant::now();
+            let result = self.get_once(url);
+            #[cfg(feature = "metrics")]
+            crate::metrics::record_kds_fetch(start.elapsed(), result.is_ok());
+            match result {
+                Err(VcekError::Status { status, .. }) if is_retryable(status) => {
+                    attempts += 1;
+                    if attempts > self.retries {
+                        error!(url, %status, attempts, "KDS request retries exhausted");
+                        return Err(VcekError::Exhausted { attempts, status });
+                    }
+                    let delay = self.backoff(attempts);
+                    warn!(url, %status, ?delay, "KDS request failed, retrying");
+                    std::thread::sleep(delay);
+                }
+                Err(e) => {
+                    error!(url, error = %e, "KDS request failed");
+                    return Err(e);
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Fetches the given KDS URL without blocking, retrying on a retryable status.
+    #[cfg(feature = "async")]
+    async fn get_async(&self, url: &str) -> Result<Vec<u8>, VcekError> {
+        if self.mode == NetworkMode::Offline {
+            debug!(url, "not requesting from KDS while offline");
+            return Err(VcekError::Offline);
+        }
+
+        info!(url, "requesting from KDS");
+        let mut attempts = 0;
+        loop {
+            #[cfg(feature = "metrics")]
+            let start = Instant::now();
+            let result = self.get_once_async(url).await;
+            #[cfg(feature = "metrics")]
+            crate::metrics::record_kds_fetch(start.elapsed(), result.is_ok());
+            match result {
+                Err(VcekError::Status { status, .. }) if is_retryable(status) => {
+                    attempts += 1;
+                    if attempts > self.retries {
+                        error!(url, %status, attempts, "KDS request retries exhausted");
+                        return Err(VcekError::Exhausted { attempts, status });
+                    }
+                    let delay = self.backoff(attempts);
+                    warn!(url, %status, ?delay, "KDS request failed, retrying");
+                    tokio::time::sleep(delay).await;
+                }
+                Err(e) => {
+                    error!(url, error = %e, "KDS request failed");
+                    return Err(e);
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Fetches a certificate, or chain of certificates, from the given KDS URL.
+    fn get_once(&self, url: &str) -> Result<Vec<u8>, VcekError> {
+        let mut rsp = self
+            .blocking_client()?
+            .get(url)
+            .header(ACCEPT_ENCODING, ACCEPTED_ENCODINGS)
+            .send()
+            .map_err(|e| self.http_error(e))?;
+
+        let status = rsp.status();
+        let encoding = content_encoding(rsp.headers());
+        let mut body = LimitedBuffer::new(self.max_response_size, rsp.content_length());
+        let read = rsp.copy_to(&mut body);
+        if !status.is_success() {
+            let body = String::from_utf8_lossy(&body.data).into_owned();
+            return Err(VcekError::Status { status, body });
+        }
+
+        read.map_err(|e| body.error().unwrap_or_else(|| self.http_error(e)))?;
+        check_kds_body(decode_body(
+            body.data,
+            encoding.as_deref(),
+            self.max_response_size,
+        )?)
+    }
+
+    /// The HTTP client blocking requests are sent through: the custom one if
+    /// given, and otherwise one with the configured timeout and proxy.
+    fn blocking_client(&self) -> Result<&reqwest::blocking::Client, VcekError> {
+        if let Some(client) = self.http_client.as_ref().or(self.default_client.get()) {
+            return Ok(client);
+        }
+
+        let mut builder = reqwest::blocking::Client::builder().timeout(self.timeout);
+        builder = match &self.proxy {
+            KdsProxy::System => builder,
+            KdsProxy::Url(url) => builder.proxy(reqwest::Proxy::all(url).map_err(VcekError::Http)?),
+            KdsProxy::Disabled => builder.no_proxy(),
+        };
+        let client = builder.build().map_err(VcekError::Http)?;
+        Ok(self.default_client.get_or_init(|| client))
+    }
+
+    /// The HTTP client requests without blocking are sent through, with the
+    /// configured timeout and proxy.
+    #[cfg(feature = "async")]
+    fn async_client(&self) -> Result<&reqwest::Client, VcekError> {
+        if let Some(client) = self.default_async_client.get() {
+            return Ok(client);
+        }
+
+        let mut builder = reqwest::Client::builder().timeout(self.timeout);
+        builder = match &self.proxy {
+            KdsProxy::System => builder,
+            KdsProxy::Url(url) => builder.proxy(reqwest::Proxy::all(url).map_err(VcekError::Http)?),
+            KdsProxy::Disabled => builder.no_proxy(),
+        };
+        let client = builder.build().map_err(VcekError::Http)?;
+        Ok(self.default_async_client.get_or_init(|| client))
+    }
+
+    /// Drops the clients built from the timeout and proxy, after either changes.
+    fn reset_default_clients(&mut self) {
+        self.default_client = OnceLock::new();
+        #[cfg(feature = "async")]
+        {
+            self.default_async_client = OnceLock::new();
+        }
+    }
+
+    /// Fetches a certificate, or chain of certificates, from the given KDS URL, without blocking.
+    #[cfg(feature = "async")]
+    async fn get_once_async(&self, url: &str) -> Result<Vec<u8>, VcekError> {
+        let mut rsp = self
+            .async_client()?
+            .get(url)
+            .header(ACCEPT_ENCODING, ACCEPTED_ENCODINGS)
+            .send()
+            .await
+            .map_err(|e| self.http_error(e))?;
+
+        let status = rsp.status();
+        let encoding = content_encoding(rsp.headers());
+        let mut body = LimitedBuffer::new(self.max_response_size, rsp.content_length());
+        let mut read = Ok(());
+        while body.error().is_none() {
+            match rsp.chunk().await {
+                Ok(Some(chunk)) if body.push(&chunk).is_ok() => {}
+                Ok(_) => break,
+                Err(e) => {
+                    read = Err(self.http_error(e));
+                    break;
+                }
+            }
+        }
+        if !status.is_success() {
+            let body = String::from_utf8_lossy(&body.data).into_owned();
+            return Err(VcekError::Status { status, body });
+        }
+
+        read?;
+        if let Some(e) = body.error() {
+            return Err(e);
+        }
+        check_kds_body(decode_body(
+            body.data,
+            encoding.as_deref(),
+            self.max_response_size,
+        )?)
+    }
+
+    /// The error for a failed HTTP request.
+    fn http_error(&self, e: reqwest::Error) -> VcekError {
+        if e.is_timeout() {
+            VcekError::Timeout {
+                timeout: self.timeout,
+            }
+        } else {
+            VcekError::Http(e)
+        }
+    }
+
+    /// The delay before the given retry (counting from 1).
+    ///
+    /// The delay doubles with each retry, and is jittered to between half and
+    /// all of that, so that many clients retrying at once spread out.
+    fn backoff(&self, retry: u32) -> Duration {
+        let delay = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(retry - 1));
+        let half = delay / 2;
+        let jitter = RandomState::new().build_hasher().finish() % (half.as_nanos() as u64 + 1);
+        half + Duration::from_nanos(jitter)
+    }
+}
+
+/// A response body, read up to a maximum size.
+///
+/// Writes past the maximum fail, so that a server cannot exhaust memory with an
+/// endless body. A body is also refused upfront if its declared length is too large.
+struct LimitedBuffer {
+    data: Vec<u8>,
+    limit: usize,
+    exceeded: bool,
+}
+
+impl LimitedBuffer {
+    fn new(limit: usize, content_length: Option<u64>) -> Self {
+        Self {
+            data: Vec::new(),
+            limit,
+            exceeded: content_length.is_some_and(|length| length > limit as u64),
+        }
+    }
+
+    /// Appends a chunk of the body, unless that would exceed the maximum size.
+    fn push(&mut self, chunk: &[u8]) -> std::io::Result<()> {
+        if self.exceeded || self.data.len() + chunk.len() > self.limit {
+            self.exceeded = true;
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                "response too large",
+            ));
+        }
+        self.data.extend_from_slice(chunk);
+        Ok(())
+    }
+
+    /// The error for a body that exceeded the maximum size, if it did.
+    fn error(&self) -> Option<VcekError> {
+        self.exceeded
+            .then_some(VcekError::ResponseTooLarge { limit: self.limit })
+    }
+}
+
+impl std::io::Write for LimitedBuffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.push(buf).map(|()| buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Whether a request that failed with the given status may succeed if retried.
+fn is_retryable(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+}
+
+/// The lowercased `Content-Encoding` of a response, if it has one.
+fn content_encoding(headers: &reqwest::header::HeaderMap) -> Option<String> {
+    headers
+        .get(CONTENT_ENCODING)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.trim().to_ascii_lowercase())
+}
+
+/// Decompresses a response body compressed with gzip or deflate, up to `limit` bytes.
+///
+/// A body is decompressed if its `Content-Encoding` says so, and a gzip body is
+/// also recognized by its magic bytes, as some proxies compress a response
+/// without saying so. Neither a DER certificate nor a PEM one starts with them.
+///
+/// Only what KDS and the proxies in front of it send is supported:
+///
+/// - A gzip body must be a single gzip member. A multi-member body, or one with
+///   any other data after the first member, is rejected rather than truncated.
+/// - A deflate body must be a single zlib or raw deflate stream, with nothing
+///   after it.
+/// - A body in any other encoding, or in several (such as `gzip, br`), is passed
+///   through as is, and then fails to parse as a certificate. The client only
+///   accepts gzip and deflate, so a server honoring `Accept-Encoding` sends
+///   neither.
+fn decode_body(body: Vec<u8>, encoding: Option<&str>, limit: usize) -> Result<Vec<u8>, VcekError> {
+    match encoding {
+        Some("gzip" | "x-gzip") => gunzip(&body, limit),
+        Some("deflate") => inflate(&body, limit),
+        _ if body.starts_with(&GZIP_MAGIC) => gunzip(&body, limit),
+        _ => Ok(body),
+    }
+}
+
+/// Decompresses a single-member gzip stream (RFC 1952), checking its header
+/// CRC if present, and its length and CRC.
+fn gunzip(data: &[u8], limit: usize) -> Result<Vec<u8>, VcekError> {
+    const FHCRC: u8 = 0x02;
+    const FEXTRA: u8 = 0x04;
+    const FNAME: u8 = 0x08;
+    const FCOMMENT: u8 = 0x10;
+
+    let error = |reason: &str| VcekError::Decompress {
+        encoding: "gzip",
+        reason: reason.to_string(),
+    };
+    if data.len() < 10 || !data.starts_with(&GZIP_MAGIC) || data[2] != 8 {
+        return Err(error("not a gzip stream"));
+    }
+
+    // Skip the optional header fields the flags announce
+    let flags = data[3];
+    let mut pos = 10;
+    if flags & FEXTRA != 0 {
+        let extra = data
+            .get(pos..pos + 2)
+            .ok_or_else(|| error("truncated header"))?;
+        pos += 2 + u16::from_le_bytes([extra[0], extra[1]]) as usize;
+    }
+    for flag in [FNAME, FCOMMENT] {
+        if flags & flag != 0 {
+            let end = data
+                .get(pos..)
+                .and_then(|rest| rest.iter().position(|&b| b == 0))
+                .ok_or_else(|| error("truncated header"))?;
+            pos += end + 1;
+        }
+    }
+    if flags & FHCRC != 0 {
+        let header_crc = data
+            .get(pos..pos + 2)
+            .ok_or_else(|| error("truncated header"))?;
+        if u16::from_le_bytes([header_crc[0], header_crc[1]]) != crc32(&data[..pos]) as u16 {
+            return Err(error("header CRC mismatch"));
+        }
+        pos += 2;
+    }
+    let deflated = data.get(pos..).ok_or_else(|| error("truncated header"))?;
+
+    let (decompressed, consumed) = decompress(deflated, DataFormat::Raw, "gzip", limit)?;
+    let trailer = &deflated[consumed..];
+    if trailer.len() < 8 {
+        return Err(error("truncated trailer"));
+    }
+    if trailer.len() > 8 {
+        return Err(error("trailing data after the gzip member"));
+    }
+    if crc32(&decompressed) != u32::from_le_bytes(trailer[..4].try_into().unwrap()) {
+        return Err(error("CRC mismatch"));
+    }
+    // ISIZE is the length modulo 2^32
+    if decompressed.len() as u32 != u32::from_le_bytes(trailer[4..].try_into().unwrap()) {
+        return Err(error("length mismatch"));
+    }
+
+    Ok(decompressed)
+}
+
+/// Decompresses a deflate body: a zlib stream (RFC 1950), as HTTP specifies, or
+/// the raw deflate stream some servers send instead.
+fn inflate(data: &[u8], limit: usize) -> Result<Vec<u8>, VcekError> {
+    // A zlib header names the deflate method, and is a multiple of 31. A raw
+    // deflate stream only starts like one if it opens with a stored block whose
+    // padding bits are set, which encoders do not write.
+    let is_zlib =
+        data.len() >= 2 && data[0] & 0x0f == 8 && u16::from_be_bytes([data[0], data[1]]) % 31 == 0;
+    let format = if is_zlib {
+        DataFormat::Zlib
+    } else {
+        DataFormat::Raw
+    };
+
+    let (decompressed, consumed) = decompress(data, format, "deflate", limit)?;
+    if consumed != data.len() {
+        return Err(VcekError::Decompress {
+            encoding: "deflate",
+            reason: "trailing data after the deflate stream".to_string(),
+        });
+    }
+    Ok(decompressed)
+}
+
+/// Decompresses the stream at the start of `data`, up to `limit` bytes,
+/// returning the decompressed data and the length of the stream.
+fn decompress(
+    data: &[u8],
+    format: DataFormat,
+    encoding: &'static str,
+    limit: usize,
+) -> Result<(Vec<u8>, usize), VcekError> {
+    let error = |reason: &str| VcekError::Decompress {
+        encoding,
+        reason: reason.to_string(),
+    };
+
+    let mut state = InflateState::new_boxed(format);
+    let mut decompressed = Vec::new();
+    let mut buf = [0; 16 * 1024];
+    let mut pos = 0;
+    loop {
+        let result = inflate_stream(&mut state, &data[pos..], &mut buf, MZFlush::None);
+        pos += result.bytes_consumed;
+        if decompressed.len() + result.bytes_written > limit {
+            return Err(VcekError::ResponseTooLarge { limit });
+        }
+        decompressed.extend_from_slice(&buf[..result.bytes_written]);
+
+        match result.status {
+            Ok(MZStatus::StreamEnd) => return Ok((decompressed, pos)),
+            Ok(_) if result.bytes_consumed > 0 || result.bytes_written > 0 => {}
+            // No progress can be made without more input
+            Ok(_) | Err(MZError::Buf) => return Err(error("truncated stream")),
+            Err(MZError::Data) => return Err(error("invalid compressed data")),
+            Err(e) => return Err(error(&format!("{e:?}"))),
+        }
+    }
+}
+
+/// The CRC-32 (IEEE) of the data, as in a gzip trailer.
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xedb8_8320 & (crc & 1).wrapping_neg());
+        }
+    }
+    !crc
+}
+
+/// Checks the body of a successful KDS response.
+fn check_kds_body(rsp_bytes: Vec<u8>) -> Result<Vec<u8>, VcekError> {
+    if rsp_bytes.is_empty() {
+        return Err(VcekError::EmptyBody);
+    }
+
+    Ok(rsp_bytes)
+}
+
+/// Checks that a successful VCEK response is a DER-encoded certificate.
+///
+/// KDS (or a proxy in front of it) sometimes answers an error with a success
+/// status and an HTML page or a short message, which would otherwise surface as
+/// an obscure certificate parsing error.
+fn check_vcek_body(rsp_bytes: Vec<u8>) -> Result<Vec<u8>, VcekError> {
+    const SNIPPET_LEN: usize = 200;
+
+    if !is_der_sequence(&rsp_bytes) {
+        let text = String::from_utf8_lossy(&rsp_bytes);
+        let text = text.trim();
+        let mut snippet: String = text.chars().take(SNIPPET_LEN).collect();
+        if snippet.len() < text.len() {
+            snippet.push_str("...");
+        }
+        return Err(VcekError::UnexpectedResponse { snippet });
+    }
+
+    Ok(rsp_bytes)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::io::Write;
+    use std::net::TcpListener;
+
+    use sev::firmware::guest::AttestationReport;
+
+    use crate::mock_kds::{read_request, serve, serve_once, MockKds};
+    use crate::report::parse_report_bytes;
+    use crate::tcb::tcb_from_u64;
+    use crate::verify_attestation::{
+        GENOA_PEM, SAMPLE_ATTESTATION, SAMPLE_VCEK, TEST_TURIN_REPORT,
+    };
+
+    #[test]
+    fn test_save_vcek() {
+        let report: AttestationReport = serde_json::from_str(SAMPLE_ATTESTATION).unwrap();
+        let out = std::env::temp_dir().join(format!("sev_attest_tool_{}.crt", std::process::id()));
+
+        let (base_url, handle) = serve_once("200 OK", SAMPLE_VCEK);
+        KdsClient::new(base_url)
+            .save_vcek(
+                report.chip_id,
+                report.reported_tcb,
+                ProductLine::Genoa,
+                &out,
+            )
+            .unwrap();
+        handle.join().unwrap();
+        assert_eq!(std::fs::read(&out).unwrap(), SAMPLE_VCEK);
+        std::fs::remove_file(&out).unwrap();
+
+        // A VCEK that does not chain to the product's ARK is not saved
+        let (base_url, handle) = serve_once("200 OK", SAMPLE_VCEK);
+        let result = KdsClient::new(base_url).save_vcek(
+            report.chip_id,
+            report.reported_tcb,
+            ProductLine::Milan,
+            &out,
+        );
+        handle.join().unwrap();
+        assert!(matches!(
+            result,
+            Err(VerifyError::VcekIssuerMismatch { .. })
+        ));
+        assert!(!out.exists());
+    }
+
+    #[test]
+    fn test_default_base_url() {
+        assert_eq!(KdsClient::default().base_url(), KDS_CERT_SITE);
+        assert_eq!(
+            KdsClient::new("http://mirror.internal/").base_url(),
+            "http://mirror.internal"
+        );
+    }
+
+    #[test]
+    fn test_build_urls() {
+        let report: AttestationReport = serde_json::from_str(SAMPLE_ATTESTATION).unwrap();
+        let hw_id = hex::encode(report.chip_id);
+        assert_eq!(
+            build_vcek_url(
+                KDS_CERT_SITE,
+                ProductLine::Genoa,
+                report.chip_id,
+                report.reported_tcb
+            ),
+            format!("https://kdsintf.amd.com/vcek/v1/Genoa/{hw_id}?blSPL=07&teeSPL=00&snpSPL=11&ucodeSPL=62")
+        );
+
+        // Turin VCEKs are requested by the 8-byte hardware ID, with the FMC SPL
+        let report = parse_report_bytes(TEST_TURIN_REPORT).unwrap();
+        assert_eq!(
+            build_vcek_url(
+                KDS_CERT_SITE,
+                ProductLine::Turin,
+                report.chip_id,
+                report.reported_tcb
+            ),
+            "https://kdsintf.amd.com/vcek/v1/Turin/5e177a1c0d2b934f?fmcSPL=01&blSPL=02&teeSPL=01&snpSPL=24&ucodeSPL=72"
+        );
+        let mut chip_id = [0; 64];
+        chip_id[..8].copy_from_slice(&hex::decode("1e550a8ee5cf9f4d").unwrap());
+        assert_eq!(
+            build_vcek_url(
+                KDS_CERT_SITE,
+                ProductLine::Turin,
+                chip_id,
+                tcb_from_u64(0x0900_0000_0000_0000)
+            ),
+            "https://kdsintf.amd.com/vcek/v1/Turin/1e550a8ee5cf9f4d?fmcSPL=00&blSPL=00&teeSPL=00&snpSPL=00&ucodeSPL=09"
+        );
+        assert_eq!(
+            build_cert_chain_url("http://mirror.internal/", ProductLine::Milan),
+            "http://mirror.internal/vcek/v1/Milan/cert_chain"
+        );
+        assert_eq!(
+            build_vlek_cert_chain_url(KDS_CERT_SITE, ProductLine::Turin),
+            "https://kdsintf.amd.com/vlek/v1/Turin/cert_chain"
+        );
+        assert_eq!(
+            build_crl_url(KDS_CERT_SITE, ProductLine::Genoa),
+            "https://kdsintf.amd.com/vcek/v1/Genoa/crl"
+        );
+    }
+
+    #[test]
+    fn test_request_vcek_from_mirror() {
+        let report: AttestationReport = serde_json::from_str(SAMPLE_ATTESTATION).unwrap();
+        let (base_url, handle) = serve_once("200 OK", SAMPLE_VCEK);
+
+        let client = KdsClient::new(base_url);
+        let vcek = client
+            .request_vcek(report.chip_id, report.reported_tcb, ProductLine::Genoa)
+            .unwrap();
+        assert_eq!(vcek, SAMPLE_VCEK);
+
+        let path = handle.join().unwrap();
+        let hw_id = hex::encode(report.chip_id);
+        assert_eq!(
+            path,
+            format!("/vcek/v1/Genoa/{hw_id}?blSPL=07&teeSPL=00&snpSPL=11&ucodeSPL=62")
+        );
+    }
+
+    #[test]
+    fn test_request_vcek_bundle() {
+        let report: AttestationReport = serde_json::from_str(SAMPLE_ATTESTATION).unwrap();
+        let request = |base_url: String| {
+            KdsClient::new(base_url).request_vcek_bundle(
+                report.chip_id,
+                report.reported_tcb,
+                ProductLine::Genoa,
+            )
+        };
+
+        // A DER VCEK needs a second request for its chain
+        let (base_url, handle) = serve(vec![("200 OK", SAMPLE_VCEK), ("200 OK", GENOA_PEM)]);
+        let bundle = request(base_url).unwrap();
+        assert_eq!(bundle.vcek.to_der().unwrap(), SAMPLE_VCEK);
+        assert!(bundle.ca.is_some());
+        let paths = handle.join().unwrap();
+        assert_eq!(paths.len(), 2);
+        assert_eq!(paths[1], "/vcek/v1/Genoa/cert_chain");
+
+        // A bundled VCEK needs only the one request
+        let mut pem = Certificate::from_der(SAMPLE_VCEK)
+            .unwrap()
+            .to_pem()
+            .unwrap();
+        pem.extend_from_slice(GENOA_PEM);
+        let (base_url, handle) = serve_once("200 OK", Box::leak(pem.into_boxed_slice()));
+        assert!(request(base_url).unwrap().ca.is_some());
+        handle.join().unwrap();
+
+        // A VCEK that does not chain to the product's ARK is rejected
+        let (base_url, handle) = serve(vec![
+            ("200 OK", SAMPLE_VCEK),
+            ("200 OK", include_bytes!("../data/Milan.pem")),
+        ]);
+        let result = KdsClient::new(base_url).request_vcek_bundle(
+            report.chip_id,
+            report.reported_tcb,
+            ProductLine::Milan,
+        );
+        assert!(matches!(
+            result,
+            Err(VerifyError::VcekIssuerMismatch { .. })
+        ));
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_get_cert_chain_from_mirror() {
+        let (base_url, handle) = serve_once("200 OK", GENOA_PEM);
+
+        assert!(KdsClient::new(base_url)
+            .get_cert_chain(ProductLine::Genoa)
+            .is_ok());
+        assert_eq!(handle.join().unwrap(), "/vcek/v1/Genoa/cert_chain");
+    }
+
+    #[test]
+    fn test_health_check() {
+        let (base_url, handle) = serve_once("200 OK", GENOA_PEM);
+        assert!(KdsClient::new(base_url)
+            .health_check(ProductLine::Genoa)
+            .is_ok());
+        assert_eq!(handle.join().unwrap(), "/vcek/v1/Genoa/cert_chain");
+
+        // An unhealthy KDS is not retried
+        let (base_url, handle) = serve_once("503 Service Unavailable", b"");
+        assert!(matches!(
+            KdsClient::new(base_url).health_check(ProductLine::Genoa),
+            Err(VcekError::Status { status, .. }) if status == StatusCode::SERVICE_UNAVAILABLE
+        ));
+        handle.join().unwrap();
+
+        assert!(matches!(
+            KdsClient::offline().health_check(ProductLine::Genoa),
+            Err(VcekError::Offline)
+        ));
+    }
+
+    #[test]
+    fn test_get_crl_from_mirror() {
+        let (base_url, handle) = serve_once("200 OK", include_bytes!("../data/test_crl.der"));
+
+        assert!(KdsClient::new(base_url).get_crl(ProductLine::Genoa).is_ok());
+        assert_eq!(handle.join().unwrap(), "/vcek/v1/Genoa/crl");
+    }
+
+    #[test]
+    fn test_kds_error_status() {
+        let report: AttestationReport = serde_json::from_str(SAMPLE_ATTESTATION).unwrap();
+        let (base_url, handle) = serve_once("404 Not Found", b"not found");
+
+        let result = KdsClient::new(base_url).request_vcek(
+            report.chip_id,
+            report.reported_tcb,
+            ProductLine::Genoa,
+        );
+        assert!(matches!(
+            result,
+            Err(VcekError::Status { status, .. }) if status == reqwest::StatusCode::NOT_FOUND
+        ));
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_kds_html_response() {
+        let report: AttestationReport = serde_json::from_str(SAMPLE_ATTESTATION).unwrap();
+        let (base_url, handle) = serve_once(
+            "200 OK",
+            b"<html><body>Service temporarily unavailable</body></html>\n",
+        );
+
+        let result = KdsClient::new(base_url).request_vcek(
+            report.chip_id,
+            report.reported_tcb,
+            ProductLine::Genoa,
+        );
+        assert!(matches!(
+            result,
+            Err(VcekError::UnexpectedResponse { snippet })
+                if snippet == "<html><body>Service temporarily unavailable</body></html>"
+        ));
+        handle.join().unwrap();
+
+        // A truncated certificate is not mistaken for one
+        assert!(check_vcek_body(SAMPLE_VCEK[..100].to_vec()).is_err());
+        let long = vec![b'x'; 1000];
+        assert!(matches!(
+            check_vcek_body(long),
+            Err(VcekError::UnexpectedResponse { snippet }) if snippet.len() == 203
+        ));
+    }
+
+    #[test]
+    fn test_retry_on_too_many_requests() {
+        let report: AttestationReport = serde_json::from_str(SAMPLE_ATTESTATION).unwrap();
+        let (base_url, handle) = serve(vec![
+            ("429 Too Many Requests", b""),
+            ("503 Service Unavailable", b""),
+            ("200 OK", SAMPLE_VCEK),
+        ]);
+
+        let client = KdsClient::new(base_url).with_base_delay(Duration::from_millis(1));
+        let vcek = client
+            .request_vcek(report.chip_id, report.reported_tcb, ProductLine::Genoa)
+            .unwrap();
+        assert_eq!(vcek, SAMPLE_VCEK);
+        assert_eq!(handle.join().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_retries_exhausted() {
+        let report: AttestationReport = serde_json::from_str(SAMPLE_ATTESTATION).unwrap();
+        let (base_url, handle) = serve(vec![("429 Too Many Requests", b""); 3]);
+
+        let client = KdsClient::new(base_url)
+            .with_retries(2)
+            .with_base_delay(Duration::from_millis(1));
+        let result = client.request_vcek(report.chip_id, report.reported_tcb, ProductLine::Genoa);
+        assert!(matches!(
+            result,
+            Err(VcekError::Exhausted {
+                attempts: 3,
+                status: StatusCode::TOO_MANY_REQUESTS
+            })
+        ));
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_backoff() {
+        let client = KdsClient::default().with_base_delay(Duration::from_millis(100));
+        for retry in 1..=4 {
+            let delay = client.backoff(retry);
+            let full = Duration::from_millis(100 * 2u64.pow(retry - 1));
+            assert!(delay >= full / 2 && delay <= full);
+        }
+    }
+
+    #[test]
+    fn test_offline_client_makes_no_request() {
+        let report: AttestationReport = serde_json::from_str(SAMPLE_ATTESTATION).unwrap();
+        // Nothing listens on this port, so any request would fail with an HTTP error
+        let client = KdsClient::new("http://127.0.0.1:1").with_mode(NetworkMode::Offline);
+
+        let result = client.request_vcek(report.chip_id, report.reported_tcb, ProductLine::Genoa);
+        assert!(matches!(result, Err(VcekError::Offline)));
+        assert!(matches!(
+            client.get_cert_chain(ProductLine::Genoa),
+            Err(VerifyError::Vcek(VcekError::Offline))
+        ));
+        assert_eq!(KdsClient::offline().mode(), NetworkMode::Offline);
+    }
+
+    #[test]
+    fn test_requests_use_proxy() {
+        // Plain HTTP requests through a proxy name the whole URL
+        let (proxy_url, handle) = serve_once("200 OK", GENOA_PEM);
+        let proxy = KdsProxy::Url(proxy_url);
+
+        let client = KdsClient::new("http://kds.invalid").with_proxy(proxy.clone());
+        assert_eq!(client.proxy(), &proxy);
+        assert!(client.get_cert_chain(ProductLine::Genoa).is_ok());
+        assert_eq!(
+            handle.join().unwrap(),
+            "http://kds.invalid/vcek/v1/Genoa/cert_chain"
+        );
+    }
+
+    #[test]
+    fn test_disabled_proxy() {
+        let (base_url, handle) = serve_once("200 OK", GENOA_PEM);
+
+        // Requests go directly to the mirror, even if the environment configures a proxy
+        let client = KdsClient::new(base_url).with_proxy(KdsProxy::Disabled);
+        assert!(client.get_cert_chain(ProductLine::Genoa).is_ok());
+        assert_eq!(handle.join().unwrap(), "/vcek/v1/Genoa/cert_chain");
+
+        // A request through an unreachable proxy fails
+        let client = KdsClient::new("http://kds.invalid")
+            .with_proxy(KdsProxy::Url("http://127.0.0.1:1".to_string()));
+        assert!(matches!(
+            client.get_cert_chain(ProductLine::Genoa),
+            Err(VerifyError::Vcek(VcekError::Http(_)))
+        ));
+    }
+
+    #[test]
+    fn test_custom_http_client() {
+        let report: AttestationReport = serde_json::from_str(SAMPLE_ATTESTATION).unwrap();
+        let kds = MockKds::start_requiring_header("x-mirror-token", "secret");
+
+        // The mirror forbids requests from the default client, which lacks the header
+        let default = KdsClient::new(kds.base_url());
+        assert!(default.http_client().is_none());
+        assert!(matches!(
+            default.get_cert_chain(ProductLine::Genoa),
+            Err(VerifyError::Vcek(VcekError::Status { status, .. })) if status == StatusCode::FORBIDDEN
+        ));
+        // The default client is kept for later requests, until its settings change
+        assert!(default.default_client.get().is_some());
+        assert!(default
+            .with_timeout(Duration::from_secs(1))
+            .default_client
+            .get()
+            .is_none());
+
+        let mut headers = reqwest::header::HeaderMap::new();
+        headers.insert(
+            "x-mirror-token",
+            reqwest::header::HeaderValue::from_static("secret"),
+        );
+        let http_client = reqwest::blocking::Client::builder()
+            .default_headers(headers)
+            .build()
+            .unwrap();
+
+        // Requests go through the caller's client, whose lack of a timeout
+        // overrides the configured one
+        let client = KdsClient::new(kds.base_url())
+            .with_timeout(Duration::from_nanos(1))
+            .with_http_client(http_client);
+        assert!(client.http_client().is_some());
+        assert!(client.get_cert_chain(ProductLine::Genoa).is_ok());
+        assert_eq!(
+            client
+                .request_vcek(report.chip_id, report.reported_tcb, ProductLine::Genoa)
+                .unwrap(),
+            SAMPLE_VCEK
+        );
+        assert_eq!(kds.paths().len(), 3);
+    }
+
+    /// Compresses data as a gzip stream with no optional header fields.
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut stream = vec![0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 0, 0xff];
+        stream.extend(miniz_oxide::deflate::compress_to_vec(data, 6));
+        stream.extend(crc32(data).to_le_bytes());
+        stream.extend((data.len() as u32).to_le_bytes());
+        stream
+    }
+
+    #[test]
+    fn test_decode_body() {
+        let limit = KdsClient::DEFAULT_MAX_RESPONSE_SIZE;
+        let gzipped = gzip(SAMPLE_VCEK);
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+
+        // A gzip body is decompressed whether or not the response says it is
+        for encoding in [Some("gzip"), None] {
+            assert_eq!(
+                decode_body(gzipped.clone(), encoding, limit).unwrap(),
+                SAMPLE_VCEK
+            );
+        }
+        let zlib = miniz_oxide::deflate::compress_to_vec_zlib(SAMPLE_VCEK, 6);
+        let raw = miniz_oxide::deflate::compress_to_vec(SAMPLE_VCEK, 6);
+        for deflated in [zlib, raw] {
+            assert_eq!(
+                decode_body(deflated, Some("deflate"), limit).unwrap(),
+                SAMPLE_VCEK
+            );
+        }
+        assert_eq!(
+            decode_body(SAMPLE_VCEK.to_vec(), None, limit).unwrap(),
+            SAMPLE_VCEK
+        );
+
+        assert!(matches!(
+            decode_body(gzipped, None, 1024),
+            Err(VcekError::ResponseTooLarge { limit: 1024 })
+        ));
+    }
+
+    /// The reason decompressing a gzip body failed.
+    fn gunzip_error(data: &[u8]) -> String {
+        match gunzip(data, KdsClient::DEFAULT_MAX_RESPONSE_SIZE) {
+            Err(VcekError::Decompress {
+                encoding: "gzip",
+                reason,
+            }) => reason,
+            other => panic!("unexpected result {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_gunzip() {
+        let limit = KdsClient::DEFAULT_MAX_RESPONSE_SIZE;
+        let gzipped = gzip(SAMPLE_VCEK);
+        let (header, rest) = gzipped.split_at(10);
+
+        // Optional header fields are skipped, and the header CRC checked
+        let mut with_fields = header.to_vec();
+        with_fields[3] = 0x04 | 0x08 | 0x10 | 0x02;
+        with_fields.extend([3, 0, b'a', b'b', b'c']);
+        with_fields.extend(b"vcek.der\0");
+        with_fields.extend(b"a comment\0");
+        let header_crc = crc32(&with_fields) as u16;
+        with_fields.extend(header_crc.to_le_bytes());
+        with_fields.extend(rest);
+        assert_eq!(gunzip(&with_fields, limit).unwrap(), SAMPLE_VCEK);
+
+        let header_end = with_fields.len() - rest.len();
+        with_fields[header_end - 1] ^= 1;
+        assert_eq!(gunzip_error(&with_fields), "header CRC mismatch");
+        assert_eq!(gunzip_error(&with_fields[..20]), "truncated header");
+
+        // The trailer must be complete and match the data
+        assert_eq!(
+            gunzip_error(&gzipped[..gzipped.len() - 3]),
+            "truncated trailer"
+        );
+        assert_eq!(
+            gunzip_error(&gzipped[..gzipped.len() / 2]),
+            "truncated stream"
+        );
+        let mut corrupted = gzipped.clone();
+        let crc = corrupted.len() - 8;
+        corrupted[crc] ^= 1;
+        assert_eq!(gunzip_error(&corrupted), "CRC mismatch");
+        let mut corrupted = gzipped.clone();
+        let size = corrupted.len() - 4;
+        corrupted[size] ^= 1;
+        assert_eq!(gunzip_error(&corrupted), "length mismatch");
+
+        // Nothing may follow the first member, not even another member
+        let mut multi_member = gzipped.clone();
+        multi_member.extend(gzip(b"more"));
+        assert_eq!(
+            gunzip_error(&multi_member),
+            "trailing data after the gzip member"
+        );
+        let mut trailing = gzipped.clone();
+        trailing.push(0);
+        assert_eq!(
+            gunzip_error(&trailing),
+            "trailing data after the gzip member"
+        );
+
+        let mut deflated = miniz_oxide::deflate::compress_to_vec_zlib(SAMPLE_VCEK, 6);
+        deflated.push(0);
+        assert!(matches!(
+            decode_body(deflated, Some("deflate"), limit),
+            Err(VcekError::Decompress {
+                encoding: "deflate",
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_request_gzipped_vcek() {
+        let report: AttestationReport = serde_json::from_str(SAMPLE_ATTESTATION).unwrap();
+        let gzipped: &'static [u8] = Box::leak(gzip(SAMPLE_VCEK).into_boxed_slice());
+        let (base_url, handle) = serve_once("200 OK", gzipped);
+        let vcek = KdsClient::new(base_url)
+            .request_vcek(report.chip_id, report.reported_tcb, ProductLine::Genoa)
+            .unwrap();
+        assert_eq!(vcek, SAMPLE_VCEK);
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_get_unreachable() {
+        let client = KdsClient::new("http://127.0.0.1:1");
+        let result = client.get_once("http://127.0.0.1:1/vcek/v1/Genoa/00");
+        assert!(matches!(result, Err(VcekError::Http(_))));
+    }
+
+    #[test]
+    fn test_response_too_large() {
+        let report: AttestationReport = serde_json::from_str(SAMPLE_ATTESTATION).unwrap();
+        let (base_url, handle) = serve_once("200 OK", SAMPLE_VCEK);
+        let client = KdsClient::new(base_url).with_max_response_size(1024);
+        assert_eq!(client.max_response_size(), 1024);
+        assert!(matches!(
+            client.request_vcek(report.chip_id, report.reported_tcb, ProductLine::Genoa),
+            Err(VcekError::ResponseTooLarge { limit: 1024 })
+        ));
+        handle.join().unwrap();
+
+        // A body of undeclared length is cut off once it exceeds the limit
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        let handle = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            read_request(&stream);
+            let _ = stream.write_all(b"HTTP/1.1 200 OK\r\nConnection: close\r\n\r\n");
+            let _ = stream.write_all(&[0x30; 64 * 1024]);
+        });
+        let client = KdsClient::new(base_url).with_max_response_size(1024);
+        assert!(matches!(
+            client.get_crl(ProductLine::Genoa),
+            Err(VerifyError::Vcek(VcekError::ResponseTooLarge {
+                limit: 1024
+            }))
+        ));
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_get_timeout() {
+        // Accept the connection, but never respond
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        let handle = std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            std::thread::sleep(Duration::from_secs(1));
+            drop(stream);
+        });
+
+        let timeout = Duration::from_millis(200);
+        let client = KdsClient::new(base_url).with_timeout(timeout);
+        assert_eq!(client.timeout(), timeout);
+        assert!(matches!(
+            client.get_cert_chain(ProductLine::Genoa),
+            Err(VerifyError::Vcek(VcekError::Timeout { timeout: t })) if t == timeout
+        ));
+        handle.join().unwrap();
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn test_get_async_unreachable() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        let client = KdsClient::new("http://127.0.0.1:1");
+        let result = runtime.block_on(client.get_once_async("http://127.0.0.1:1/vcek/v1/Genoa/00"));
+        assert!(matches!(result, Err(VcekError::Http(_))));
+    }
+}
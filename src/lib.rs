@@ -1,5 +1,39 @@
+#[macro_use]
+mod trace;
+
+pub mod chain_cache;
+pub mod crl;
+pub mod crypto;
+pub mod envelope;
+pub mod extended_report;
 pub mod generate_attestation;
+#[cfg(all(
+    any(feature = "guest", not(feature = "skip-generation")),
+    target_os = "linux"
+))]
+pub mod guest;
+pub mod id_block;
+pub mod kds;
+pub mod measurement;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+pub mod policy;
+pub mod policy_file;
+pub mod product;
+pub mod report;
+pub mod report_data;
+pub mod tcb;
+pub mod vcek;
+pub mod vcek_cache;
+pub mod verification_report;
+pub mod verifier;
 pub mod verify_attestation;
 
+#[cfg(test)]
+mod mock_kds;
+
+#[cfg(feature = "ffi")]
+pub mod ffi;
+
 #[cfg(feature = "python")]
 pub mod python;
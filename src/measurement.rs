@@ -0,0 +1,796 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+use openssl::sha::{sha256, sha384};
+
+/// The size of a guest page, the unit in which the launch digest is extended.
+const PAGE_SIZE: usize = 4096;
+
+/// The guest physical address recorded for VMSA pages.
+const VMSA_GPA: u64 = 0xffff_ffff_f000;
+
+/// The instruction pointer of the bootstrap processor at reset.
+const BSP_EIP: u64 = 0xffff_fff0;
+
+/// The GUID of the table at the end of the OVMF image.
+const OVMF_TABLE_FOOTER_GUID: &str = "96b582de-1fb2-45f7-baea-a366c55a082d";
+
+/// The GUID of the OVMF table entry locating the kernel hashes table.
+const SEV_HASH_TABLE_RV_GUID: &str = "7255371f-3a3b-4b04-927b-1da6efa8d454";
+
+/// The GUID of the OVMF table entry giving the application processors' reset address.
+const SEV_ES_RESET_BLOCK_GUID: &str = "00f771de-1a7e-4fcb-890e-68c77e2fb44e";
+
+/// The GUID of the OVMF table entry locating the SEV metadata.
+const OVMF_SEV_METADATA_GUID: &str = "dc886566-984a-4798-a75e-5585a7bf67cc";
+
+const SEV_HASH_TABLE_HEADER_GUID: &str = "9438d606-4f22-4cc9-b479-a793d411fd21";
+const SEV_KERNEL_ENTRY_GUID: &str = "4de79437-abd2-427f-b835-d5b172d2045b";
+const SEV_INITRD_ENTRY_GUID: &str = "44baf731-3a2f-4bd7-9af1-41e29169781d";
+const SEV_CMDLINE_ENTRY_GUID: &str = "97d02dd8-bd20-4c94-aa78-e7714d36ab2a";
+
+/// The guest features (the VMSA's SEV_FEATURES) of a plain SNP guest: SNP
+/// active and nothing else.
+pub const SNP_ACTIVE: u64 = 0x1;
+
+/// An error encountered while computing an expected measurement.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MeasurementError {
+    /// The OVMF image could not be parsed.
+    InvalidOvmf(String),
+
+    /// A kernel was given, but the OVMF image has no section for its hashes,
+    /// so it does not support measured direct boot.
+    NoKernelHashes,
+
+    /// The vCPU type is not a known QEMU CPU model.
+    UnknownVcpuType(String),
+}
+
+impl fmt::Display for MeasurementError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MeasurementError::InvalidOvmf(reason) => write!(f, "invalid OVMF image: {reason}"),
+            MeasurementError::NoKernelHashes => write!(
+                f,
+                "a kernel was given, but the OVMF image does not support kernel hashes"
+            ),
+            MeasurementError::UnknownVcpuType(name) => write!(f, "unknown vCPU type '{name}'"),
+        }
+    }
+}
+
+impl std::error::Error for MeasurementError {}
+
+/// The size of a launch measurement, in bytes.
+pub const MEASUREMENT_SIZE: usize = 48;
+
+/// An error returned when a launch measurement cannot be parsed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseMeasurementError {
+    /// A character is neither a hex digit nor a separator.
+    InvalidCharacter(char),
+
+    /// The measurement has the wrong number of hex digits.
+    Length { digits: usize },
+}
+
+impl fmt::Display for ParseMeasurementError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseMeasurementError::InvalidCharacter(c) => {
+                write!(f, "invalid character {c:?} in measurement")
+            }
+            ParseMeasurementError::Length { digits } => write!(
+                f,
+                "expected {} hex digits ({MEASUREMENT_SIZE} bytes), found {digits}",
+                MEASUREMENT_SIZE * 2
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ParseMeasurementError {}
+
+/// Parses a launch measurement written as hex, in any of the forms tools print it.
+///
+/// The digits may be upper or lower case, prefixed by `0x`, and grouped by
+/// whitespace, `:`, `-` or `_` (as in `8d:21:42:…` or `8d2142 a0…`), as long as
+/// there are exactly 96 of them.
+pub fn parse_measurement(s: &str) -> Result<[u8; MEASUREMENT_SIZE], ParseMeasurementError> {
+    let s = s.trim();
+    let s = s
+        .strip_prefix("0x")
+        .or_else(|| s.strip_prefix("0X"))
+        .unwrap_or(s);
+
+    let mut digits = Vec::with_capacity(MEASUREMENT_SIZE * 2);
+    for c in s.chars() {
+        match c {
+            c if c.is_ascii_hexdigit() => digits.push(c as u8),
+            c if c.is_whitespace() || matches!(c, ':' | '-' | '_') => {}
+            c => return Err(ParseMeasurementError::InvalidCharacter(c)),
+        }
+    }
+    let mut measurement = [0; MEASUREMENT_SIZE];
+    hex::decode_to_slice(&digits, &mut measurement).map_err(|_| ParseMeasurementError::Length {
+        digits: digits.len(),
+    })?;
+
+    Ok(measurement)
+}
+
+/// The QEMU CPU model of the guest's vCPUs, which sets the CPUID signature each
+/// vCPU starts with.
+///
+/// Versioned QEMU models (such as `EPYC-v4` or `EPYC-Milan-v2`) have the same
+/// signature as their base model.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VcpuType {
+    /// `EPYC`, including `EPYC-IBPB` and `EPYC-v1` to `EPYC-v4`.
+    Epyc,
+
+    /// `EPYC-Rome`.
+    EpycRome,
+
+    /// `EPYC-Milan`.
+    EpycMilan,
+
+    /// `EPYC-Genoa`.
+    EpycGenoa,
+
+    /// `EPYC-Turin`.
+    EpycTurin,
+}
+
+impl VcpuType {
+    /// The QEMU name of the base model.
+    pub fn name(&self) -> &'static str {
+        match self {
+            VcpuType::Epyc => "EPYC",
+            VcpuType::EpycRome => "EPYC-Rome",
+            VcpuType::EpycMilan => "EPYC-Milan",
+            VcpuType::EpycGenoa => "EPYC-Genoa",
+            VcpuType::EpycTurin => "EPYC-Turin",
+        }
+    }
+
+    /// The CPUID family/model/stepping signature, as QEMU places it in RDX.
+    fn signature(&self) -> u64 {
+        let (family, model, stepping) = match self {
+            VcpuType::Epyc => (23, 1, 2),
+            VcpuType::EpycRome => (23, 49, 0),
+            VcpuType::EpycMilan => (25, 1, 1),
+            VcpuType::EpycGenoa => (25, 17, 0),
+            VcpuType::EpycTurin => (26, 0, 0),
+        };
+        let (family_low, family_high) = if family > 0xf {
+            (0xf, (family - 0xf) & 0xff)
+        } else {
+            (family, 0)
+        };
+
+        (family_high << 20)
+            | ((model >> 4) << 16)
+            | (family_low << 8)
+            | ((model & 0xf) << 4)
+            | stepping
+    }
+}
+
+impl fmt::Display for VcpuType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for VcpuType {
+    type Err = MeasurementError;
+
+    /// Parses a QEMU CPU model name, ignoring case and any version suffix.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let lower = s.to_ascii_lowercase();
+        let base = match lower.rsplit_once('-') {
+            Some((base, suffix))
+                if suffix == "ibpb"
+                    || (suffix.starts_with('v') && suffix[1..].parse::<u32>().is_ok()) =>
+            {
+                base
+            }
+            _ => &lower,
+        };
+
+        [
+            VcpuType::Epyc,
+            VcpuType::EpycRome,
+            VcpuType::EpycMilan,
+            VcpuType::EpycGenoa,
+            VcpuType::EpycTurin,
+        ]
+        .into_iter()
+        .find(|vcpu_type| vcpu_type.name().eq_ignore_ascii_case(base))
+        .ok_or_else(|| MeasurementError::UnknownVcpuType(s.to_string()))
+    }
+}
+
+/// The inputs of a QEMU guest launch that determine its measurement.
+#[derive(Debug, Clone)]
+pub struct MeasurementInputs<'a> {
+    /// The OVMF firmware image.
+    pub ovmf: &'a [u8],
+
+    /// The kernel, for a direct boot (QEMU's `-kernel`), or `None` to boot from disk.
+    pub kernel: Option<&'a [u8]>,
+
+    /// The initrd of a direct boot.
+    pub initrd: Option<&'a [u8]>,
+
+    /// The kernel command line of a direct boot (QEMU's `-append`).
+    pub cmdline: Option<&'a str>,
+
+    /// The number of vCPUs.
+    pub vcpus: u32,
+
+    /// The QEMU CPU model of the vCPUs.
+    pub vcpu_type: VcpuType,
+
+    /// The SEV features enabled for the guest, as in the VMSA's SEV_FEATURES
+    /// field. Usually [`SNP_ACTIVE`].
+    pub guest_features: u64,
+}
+
+/// Computes the launch measurement of an SNP guest launched by QEMU.
+///
+/// This replays the pages QEMU adds to the guest at launch, as described by
+/// the OVMF image's SEV metadata, through the launch digest algorithm of the
+/// AMD SEV-SNP ABI specification. For a direct boot, OVMF checks the kernel,
+/// initrd and command line against hashes QEMU places in a measured page, so
+/// they are covered by the measurement too.
+///
+/// The result can be passed to [`crate::verifier::VerifierBuilder::expect_measurement`].
+pub fn compute_expected_measurement(
+    inputs: &MeasurementInputs,
+) -> Result<[u8; 48], MeasurementError> {
+    let ovmf = Ovmf::parse(inputs.ovmf)?;
+    let mut digest = LaunchDigest([0; 48]);
+
+    digest.update_normal(ovmf.gpa(), inputs.ovmf);
+
+    let hashes_page = inputs
+        .kernel
+        .map(|kernel| {
+            kernel_hashes_page(
+                kernel,
+                inputs.initrd.unwrap_or_default(),
+                inputs.cmdline,
+                ovmf.hashes_table_gpa()?,
+            )
+        })
+        .transpose()?;
+    let mut has_hashes_section = false;
+    for section in &ovmf.sections {
+        match section.kind {
+            SectionKind::SecMemory | SectionKind::SvsmCaa => {
+                digest.update_zero(section.gpa, section.size)
+            }
+            SectionKind::Secrets => digest.update(PageType::Secrets, section.gpa, &[0; 48]),
+            SectionKind::Cpuid => digest.update(PageType::Cpuid, section.gpa, &[0; 48]),
+            SectionKind::KernelHashes => {
+                has_hashes_section = true;
+                match &hashes_page {
+                    Some(page) if section.size as usize == PAGE_SIZE => {
+                        digest.update_normal(section.gpa, page)
+                    }
+                    Some(_) => {
+                        return Err(MeasurementError::InvalidOvmf(
+                            "kernel hashes section is not a single page".to_string(),
+                        ))
+                    }
+                    None => digest.update_zero(section.gpa, section.size),
+                }
+            }
+        }
+    }
+    if hashes_page.is_some() && !has_hashes_section {
+        return Err(MeasurementError::NoKernelHashes);
+    }
+
+    // The first vCPU starts at the reset vector, and the others where OVMF says
+    let ap_eip = ovmf.ap_reset_eip()?;
+    for vcpu in 0..inputs.vcpus {
+        let eip = if vcpu == 0 { BSP_EIP } else { ap_eip };
+        if eip == 0 {
+            continue;
+        }
+        let vmsa = vmsa_page(eip, inputs.vcpu_type, inputs.guest_features);
+        digest.update(PageType::Vmsa, VMSA_GPA, &sha384(&vmsa));
+    }
+
+    Ok(digest.0)
+}
+
+/// Builds the page QEMU fills with the SHA-256 hashes of a direct boot's kernel,
+/// initrd and command line, placed where the OVMF image expects it.
+///
+/// OVMF checks the kernel, initrd and command line it is passed against this
+/// page, which QEMU adds to the guest as a measured page, so the measurement of
+/// the guest covers them. [`compute_expected_measurement`] builds the page when
+/// [`MeasurementInputs::kernel`] is set.
+pub fn build_kernel_hashes_page(
+    ovmf: &[u8],
+    kernel: &[u8],
+    initrd: Option<&[u8]>,
+    cmdline: Option<&str>,
+) -> Result<Vec<u8>, MeasurementError> {
+    let ovmf = Ovmf::parse(ovmf)?;
+    kernel_hashes_page(
+        kernel,
+        initrd.unwrap_or_default(),
+        cmdline,
+        ovmf.hashes_table_gpa()?,
+    )
+}
+
+/// The types of page the launch digest records, from the SNP_LAUNCH_UPDATE command.
+#[derive(Debug, Clone, Copy)]
+enum PageType {
+    Normal = 1,
+    Vmsa = 2,
+    Zero = 3,
+    Secrets = 5,
+    Cpuid = 6,
+}
+
+/// A launch digest, as the PSP extends it with each page added to the guest.
+struct LaunchDigest([u8; 48]);
+
+impl LaunchDigest {
+    /// Extends the digest with a page's PAGE_INFO structure.
+    fn update(&mut self, page_type: PageType, gpa: u64, contents: &[u8; 48]) {
+        let mut page_info = Vec::with_capacity(0x70);
+        page_info.extend_from_slice(&self.0);
+        page_info.extend_from_slice(contents);
+        page_info.extend_from_slice(&0x70u16.to_le_bytes());
+        page_info.push(page_type as u8);
+        // IMI_PAGE, then the VMPL3, VMPL2 and VMPL1 permissions, then reserved
+        page_info.extend_from_slice(&[0; 5]);
+        page_info.extend_from_slice(&gpa.to_le_bytes());
+
+        self.0 = sha384(&page_info);
+    }
+
+    /// Extends the digest with each page of `data`, which is page-aligned.
+    fn update_normal(&mut self, gpa: u64, data: &[u8]) {
+        for (i, page) in data.chunks(PAGE_SIZE).enumerate() {
+            let gpa = gpa + (i * PAGE_SIZE) as u64;
+            self.update(PageType::Normal, gpa, &sha384(page));
+        }
+    }
+
+    /// Extends the digest with `size` bytes of zero pages.
+    fn update_zero(&mut self, gpa: u64, size: u32) {
+        for offset in (0..size as u64).step_by(PAGE_SIZE) {
+            self.update(PageType::Zero, gpa + offset, &[0; 48]);
+        }
+    }
+}
+
+/// The kinds of section in OVMF's SEV metadata, from `OvmfSevMetadata.asm` in edk2.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SectionKind {
+    SecMemory,
+    Secrets,
+    Cpuid,
+    SvsmCaa,
+    KernelHashes,
+}
+
+/// A region of guest memory that QEMU adds to the guest at launch.
+#[derive(Debug, Clone, Copy)]
+struct Section {
+    gpa: u64,
+    size: u32,
+    kind: SectionKind,
+}
+
+/// The parts of an OVMF image that determine the measurement.
+struct Ovmf<'a> {
+    data: &'a [u8],
+    table: HashMap<[u8; 16], &'a [u8]>,
+    sections: Vec<Section>,
+}
+
+impl<'a> Ovmf<'a> {
+    /// Parses the GUIDed table at the end of the image, and the SEV metadata it locates.
+    fn parse(data: &'a [u8]) -> Result<Self, MeasurementError> {
+        let invalid = |reason: &str| MeasurementError::InvalidOvmf(reason.to_string());
+        // Each table entry ends with its 2-byte size and its GUID
+        const HEADER: usize = 18;
+
+        if data.is_empty() || data.len() % PAGE_SIZE != 0 {
+            return Err(invalid("image is not a whole number of pages"));
+        }
+
+        // The footer entry ends 32 bytes before the end of the image
+        let footer_start = data.len() - 32 - HEADER;
+        let footer = &data[footer_start..footer_start + HEADER];
+        if footer[2..] != guid_le(OVMF_TABLE_FOOTER_GUID) {
+            return Err(invalid("no GUIDed table footer"));
+        }
+        let table_size = (u16_at(footer, 0) as usize)
+            .checked_sub(HEADER)
+            .filter(|&size| size <= footer_start)
+            .ok_or_else(|| invalid("bad table size"))?;
+        let table_bytes = &data[footer_start - table_size..footer_start];
+
+        let mut table = HashMap::new();
+        let mut end = table_size;
+        while end >= HEADER {
+            let header = &table_bytes[end - HEADER..end];
+            let size = u16_at(header, 0) as usize;
+            if size < HEADER || size > end {
+                return Err(invalid("bad table entry size"));
+            }
+            let guid: [u8; 16] = header[2..].try_into().unwrap();
+            table.insert(guid, &table_bytes[end - size..end - HEADER]);
+            end -= size;
+        }
+
+        let mut ovmf = Ovmf {
+            data,
+            table,
+            sections: Vec::new(),
+        };
+        ovmf.sections = ovmf.parse_sev_metadata()?;
+        Ok(ovmf)
+    }
+
+    /// Parses the SEV metadata: an `ASEV` header, then 12-byte section descriptors.
+    fn parse_sev_metadata(&self) -> Result<Vec<Section>, MeasurementError> {
+        let invalid = |reason: &str| MeasurementError::InvalidOvmf(reason.to_string());
+
+        let offset_from_end = self.table_u32(OVMF_SEV_METADATA_GUID)? as usize;
+        let start = self
+            .data
+            .len()
+            .checked_sub(offset_from_end)
+            .ok_or_else(|| invalid("bad SEV metadata offset"))?;
+        let header = self
+            .data
+            .get(start..start + 16)
+            .ok_or_else(|| invalid("bad SEV metadata offset"))?;
+        if &header[..4] != b"ASEV" || u32_at(header, 8) != 1 {
+            return Err(invalid("bad SEV metadata header"));
+        }
+
+        let num_items = u32_at(header, 12) as usize;
+        let items = self
+            .data
+            .get(start + 16..start + 16 + 12 * num_items)
+            .ok_or_else(|| invalid("SEV metadata is truncated"))?;
+        items
+            .chunks(12)
+            .map(|item| {
+                let kind = match u32_at(item, 8) {
+                    1 => SectionKind::SecMemory,
+                    2 => SectionKind::Secrets,
+                    3 => SectionKind::Cpuid,
+                    4 => SectionKind::SvsmCaa,
+                    0x10 => SectionKind::KernelHashes,
+                    kind => return Err(invalid(&format!("unknown SEV metadata section {kind}"))),
+                };
+                Ok(Section {
+                    gpa: u32_at(item, 0).into(),
+                    size: u32_at(item, 4),
+                    kind,
+                })
+            })
+            .collect()
+    }
+
+    /// The address the image is loaded at, so that it ends at 4 GiB.
+    fn gpa(&self) -> u64 {
+        0x1_0000_0000 - self.data.len() as u64
+    }
+
+    /// The reset address of the application processors.
+    fn ap_reset_eip(&self) -> Result<u64, MeasurementError> {
+        self.table_u32(SEV_ES_RESET_BLOCK_GUID).map(u64::from)
+    }
+
+    /// The address of the kernel hashes table.
+    fn hashes_table_gpa(&self) -> Result<u64, MeasurementError> {
+        match self.table_u32(SEV_HASH_TABLE_RV_GUID) {
+            Ok(0) | Err(_) => Err(MeasurementError::NoKernelHashes),
+            Ok(gpa) => Ok(gpa.into()),
+        }
+    }
+
+    /// The 32-bit value that starts the table entry with the given GUID.
+    fn table_u32(&self, guid: &str) -> Result<u32, MeasurementError> {
+        self.table
+            .get(&guid_le(guid))
+            .filter(|entry| entry.len() >= 4)
+            .map(|entry| u32_at(entry, 0))
+            .ok_or_else(|| MeasurementError::InvalidOvmf(format!("no table entry {guid}")))
+    }
+}
+
+/// The page QEMU fills with the hashes of the kernel, initrd and command line,
+/// with the table at the given address's offset into the page.
+fn kernel_hashes_page(
+    kernel: &[u8],
+    initrd: &[u8],
+    cmdline: Option<&str>,
+    table_gpa: u64,
+) -> Result<Vec<u8>, MeasurementError> {
+    // The command line is hashed with its terminating NUL
+    let mut cmdline = cmdline.unwrap_or_default().trim().as_bytes().to_vec();
+    cmdline.push(0);
+
+    let entry = |guid: &str, hash: [u8; 32]| {
+        let mut entry = guid_le(guid).to_vec();
+        entry.extend_from_slice(&50u16.to_le_bytes());
+        entry.extend_from_slice(&hash);
+        entry
+    };
+    let mut table = guid_le(SEV_HASH_TABLE_HEADER_GUID).to_vec();
+    table.extend_from_slice(&168u16.to_le_bytes());
+    table.extend(entry(SEV_CMDLINE_ENTRY_GUID, sha256(&cmdline)));
+    table.extend(entry(SEV_INITRD_ENTRY_GUID, sha256(initrd)));
+    table.extend(entry(SEV_KERNEL_ENTRY_GUID, sha256(kernel)));
+
+    let offset = (table_gpa & 0xfff) as usize;
+    // The table is padded to 16 bytes, which must fit in the page
+    if offset + 176 > PAGE_SIZE {
+        return Err(MeasurementError::InvalidOvmf(
+            "kernel hashes table crosses a page boundary".to_string(),
+        ));
+    }
+    let mut page = vec![0; PAGE_SIZE];
+    page[offset..offset + table.len()].copy_from_slice(&table);
+    Ok(page)
+}
+
+/// The initial VMSA (register state) QEMU gives a vCPU starting at `eip`.
+fn vmsa_page(eip: u64, vcpu_type: VcpuType, guest_features: u64) -> Vec<u8> {
+    let mut vmsa = vec![0; PAGE_SIZE];
+    let mut put = |offset: usize, bytes: &[u8]| {
+        vmsa[offset..offset + bytes.len()].copy_from_slice(bytes);
+    };
+
+    // Segment registers: selector, attributes, limit and base
+    let segments: [(usize, u16, u16, u64); 10] = [
+        (0x00, 0, 0x93, 0),                  // ES
+        (0x10, 0xf000, 0x9b, eip & !0xffff), // CS
+        (0x20, 0, 0x93, 0),                  // SS
+        (0x30, 0, 0x93, 0),                  // DS
+        (0x40, 0, 0x93, 0),                  // FS
+        (0x50, 0, 0x93, 0),                  // GS
+        (0x60, 0, 0, 0),                     // GDTR
+        (0x70, 0, 0x82, 0),                  // LDTR
+        (0x80, 0, 0, 0),                     // IDTR
+        (0x90, 0, 0x8b, 0),                  // TR
+    ];
+    for (offset, selector, attrib, base) in segments {
+        put(offset, &selector.to_le_bytes());
+        put(offset + 2, &attrib.to_le_bytes());
+        put(offset + 4, &0xffffu32.to_le_bytes());
+        put(offset + 8, &base.to_le_bytes());
+    }
+
+    let registers: [(usize, u64); 11] = [
+        (0xd0, 0x1000),                 // EFER
+        (0x148, 0x40),                  // CR4
+        (0x158, 0x10),                  // CR0
+        (0x160, 0x400),                 // DR7
+        (0x168, 0xffff_0ff0),           // DR6
+        (0x170, 0x2),                   // RFLAGS
+        (0x178, eip & 0xffff),          // RIP
+        (0x268, 0x0007_0406_0007_0406), // G_PAT
+        (0x310, vcpu_type.signature()), // RDX
+        (0x3b0, guest_features),        // SEV_FEATURES
+        (0x3e8, 0x1),                   // XCR0
+    ];
+    for (offset, value) in registers {
+        put(offset, &value.to_le_bytes());
+    }
+    put(0x408, &0x1f80u32.to_le_bytes()); // MXCSR
+    put(0x410, &0x37fu16.to_le_bytes()); // X87_FCW
+
+    vmsa
+}
+
+/// The little-endian (EFI) encoding of a GUID.
+fn guid_le(guid: &str) -> [u8; 16] {
+    let bytes = hex::decode(guid.replace('-', "")).expect("GUID is valid");
+    let mut le: [u8; 16] = bytes.try_into().expect("GUID is 16 bytes");
+    le[..4].reverse();
+    le[4..6].reverse();
+    le[6..8].reverse();
+    le
+}
+
+fn u16_at(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes(data[offset..offset + 2].try_into().unwrap())
+}
+
+fn u32_at(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// The last page of an OVMF image built with `AmdSevX64.dsc`, which
+    /// supports direct boot, and of one built with `OvmfPkgX64.dsc`, which does not.
+    ///
+    /// These images, and the digests below, are from the test suite of the
+    /// virtee `sev` crate, whose measurement support this matches.
+    const OVMF_AMDSEV: &[u8] = include_bytes!("../data/ovmf_amdsev_suffix.bin");
+    const OVMF_X64: &[u8] = include_bytes!("../data/ovmf_x64_suffix.bin");
+
+    fn inputs(ovmf: &[u8]) -> MeasurementInputs<'_> {
+        MeasurementInputs {
+            ovmf,
+            kernel: None,
+            initrd: None,
+            cmdline: None,
+            vcpus: 1,
+            vcpu_type: VcpuType::Epyc,
+            guest_features: SNP_ACTIVE,
+        }
+    }
+
+    fn measure(inputs: &MeasurementInputs) -> String {
+        hex::encode(compute_expected_measurement(inputs).unwrap())
+    }
+
+    #[test]
+    fn test_parse_measurement() {
+        let measurement: [u8; 48] = std::array::from_fn(|i| i as u8 * 5);
+        let hex = hex::encode(measurement);
+        let colons = measurement
+            .iter()
+            .map(|b| format!("{b:02X}"))
+            .collect::<Vec<_>>()
+            .join(":");
+        for s in [
+            hex.clone(),
+            hex.to_uppercase(),
+            format!("0x{hex}"),
+            format!("  {hex}\n"),
+            colons,
+            format!("{} {}", &hex[..48], &hex[48..]),
+        ] {
+            assert_eq!(parse_measurement(&s), Ok(measurement), "{s}");
+        }
+
+        assert_eq!(
+            parse_measurement(&hex[..94]),
+            Err(ParseMeasurementError::Length { digits: 94 })
+        );
+        assert_eq!(
+            parse_measurement(&format!("{hex}0"))
+                .unwrap_err()
+                .to_string(),
+            "expected 96 hex digits (48 bytes), found 97"
+        );
+        assert_eq!(
+            parse_measurement(&format!("{}g", &hex[..95])),
+            Err(ParseMeasurementError::InvalidCharacter('g'))
+        );
+    }
+
+    #[test]
+    fn test_measurement_without_kernel() {
+        assert_eq!(
+            measure(&inputs(OVMF_AMDSEV)),
+            "19358ba9a7615534a9a1e2f0dfc29384dcd4dcb7062ff9c6013b26869a5fc6ecabe033c48dd6f6db5d6d76e7c5df632d"
+        );
+        assert_eq!(
+            measure(&MeasurementInputs {
+                guest_features: 0x21,
+                ..inputs(OVMF_AMDSEV)
+            }),
+            "e1e1ca029dd7973ab9513295be68198472dcd4fc834bd9af9b63f6e8a1674dbf281a9278a4a2ebe0eed9f22adbcd0e2b"
+        );
+        assert_eq!(
+            measure(&inputs(OVMF_X64)),
+            "da0296de8193586a5512078dcd719eccecbd87e2b825ad4148c44f665dc87df21e5b49e21523a9ad993afdb6a30b4005"
+        );
+    }
+
+    #[test]
+    fn test_measurement_with_kernel() {
+        let direct_boot = MeasurementInputs {
+            kernel: Some(b""),
+            initrd: Some(b""),
+            cmdline: Some("console=ttyS0 loglevel=7"),
+            ..inputs(OVMF_AMDSEV)
+        };
+        assert_eq!(
+            measure(&direct_boot),
+            "6d287813eb5222d770f75005c664e34c204f385ce832cc2ce7d0d6f354454362f390ef83a92046c042e706363b4b08fa"
+        );
+        assert_eq!(
+            measure(&MeasurementInputs {
+                guest_features: 0x21,
+                ..direct_boot.clone()
+            }),
+            "803f691094946e42068aaa3a8f9e26a5c89f36f7b73ecfb28c653360fe4b3aba7e534442e7e1e17895dfe778d0228977"
+        );
+        assert_eq!(
+            measure(&MeasurementInputs {
+                vcpus: 4,
+                cmdline: None,
+                ..direct_boot.clone()
+            }),
+            "5061fffb019493a903613d56d54b94912a1a2f9e4502385f5c194616753720a92441310ba6c4933de877c36e23046ad5"
+        );
+
+        assert_eq!(
+            compute_expected_measurement(&MeasurementInputs {
+                ovmf: OVMF_X64,
+                ..direct_boot
+            }),
+            Err(MeasurementError::NoKernelHashes)
+        );
+    }
+
+    #[test]
+    fn test_build_kernel_hashes_page() {
+        let page = build_kernel_hashes_page(OVMF_AMDSEV, b"", None, Some("console=ttyS0")).unwrap();
+        assert_eq!(page.len(), PAGE_SIZE);
+
+        // The table starts with its header, then the command line, initrd and kernel entries
+        let offset = page
+            .windows(16)
+            .position(|window| window == guid_le(SEV_HASH_TABLE_HEADER_GUID))
+            .unwrap();
+        let table = &page[offset..offset + 168];
+        assert_eq!(u16_at(table, 16), 168);
+        assert_eq!(&table[18..34], guid_le(SEV_CMDLINE_ENTRY_GUID));
+        assert_eq!(&table[36..68], sha256(b"console=ttyS0\0"));
+        assert_eq!(&table[118..134], guid_le(SEV_KERNEL_ENTRY_GUID));
+        assert_eq!(&table[136..168], sha256(b""));
+        assert!(page[offset + 168..].iter().all(|&byte| byte == 0));
+
+        assert_eq!(
+            build_kernel_hashes_page(OVMF_X64, b"", None, None),
+            Err(MeasurementError::NoKernelHashes)
+        );
+    }
+
+    #[test]
+    fn test_measurement_rejects_bad_ovmf() {
+        assert!(matches!(
+            compute_expected_measurement(&inputs(&[0; PAGE_SIZE])),
+            Err(MeasurementError::InvalidOvmf(_))
+        ));
+        assert!(matches!(
+            compute_expected_measurement(&inputs(&OVMF_AMDSEV[1..])),
+            Err(MeasurementError::InvalidOvmf(_))
+        ));
+    }
+
+    #[test]
+    fn test_vcpu_type() {
+        assert_eq!("EPYC-v4".parse::<VcpuType>().unwrap(), VcpuType::Epyc);
+        assert_eq!("epyc-ibpb".parse::<VcpuType>().unwrap(), VcpuType::Epyc);
+        assert_eq!(
+            "EPYC-Milan-v2".parse::<VcpuType>().unwrap(),
+            VcpuType::EpycMilan
+        );
+        assert_eq!(
+            "EPYC-Genoa".parse::<VcpuType>().unwrap(),
+            VcpuType::EpycGenoa
+        );
+        assert!("EPYC-Vintage".parse::<VcpuType>().is_err());
+
+        assert_eq!(VcpuType::Epyc.signature(), 0x800f12);
+        assert_eq!(VcpuType::EpycGenoa.signature(), 0xa10f10);
+    }
+}
@@ -0,0 +1,157 @@
+//! Metrics on verification outcomes and KDS requests, for production dashboards.
+//!
+//! Measurements are emitted through the [`metrics`](::metrics) crate's facade,
+//! to whichever recorder the application installs (an exporter for Prometheus
+//! or OpenTelemetry, say). Until a recorder is installed, nothing is recorded.
+//!
+//! Counters and histograms are named as in OpenTelemetry, and their labels
+//! take a small, fixed set of values, so the metrics stay cheap to aggregate:
+//!
+//! | Metric                         | Kind      | Labels                                   |
+//! |--------------------------------|-----------|------------------------------------------|
+//! | [`VERIFICATIONS_ATTEMPTED`]    | counter   |                                          |
+//! | [`VERIFICATIONS_SUCCEEDED`]    | counter   |                                          |
+//! | [`VERIFICATIONS_FAILED`]       | counter   | `reason`: the name of the failed step    |
+//! | [`VERIFICATION_STEPS`]         | counter   | `step`, `outcome` (passed/failed/skipped)|
+//! | [`KDS_FETCH_DURATION`]         | histogram | `outcome` (success/failure), in seconds  |
+
+use std::time::Duration;
+
+use ::metrics::{counter, histogram};
+
+/// The number of reports whose verification was attempted.
+pub const VERIFICATIONS_ATTEMPTED: &str = "sev_attest.verifications.attempted";
+
+/// The number of reports that passed verification.
+pub const VERIFICATIONS_SUCCEEDED: &str = "sev_attest.verifications.succeeded";
+
+/// The number of reports that failed verification, by the step that failed.
+pub const VERIFICATIONS_FAILED: &str = "sev_attest.verifications.failed";
+
+/// The number of verification steps run or skipped, by step and outcome.
+pub const VERIFICATION_STEPS: &str = "sev_attest.verification.steps";
+
+/// The time each KDS request took, including reading the response, in seconds.
+///
+/// Every attempt of a retried request is recorded separately.
+pub const KDS_FETCH_DURATION: &str = "sev_attest.kds.fetch.duration";
+
+/// Counts a finished verification, and the step that failed it if any.
+pub(crate) fn record_verification(failed_step: Option<&'static str>) {
+    counter!(VERIFICATIONS_ATTEMPTED).increment(1);
+    match failed_step {
+        None => counter!(VERIFICATIONS_SUCCEEDED).increment(1),
+        Some(step) => counter!(VERIFICATIONS_FAILED, "reason" => step).increment(1),
+    }
+}
+
+/// Counts a verification step with its outcome.
+pub(crate) fn record_step(step: &'static str, outcome: &'static str) {
+    counter!(VERIFICATION_STEPS, "step" => step, "outcome" => outcome).increment(1);
+}
+
+/// Records how long a KDS request took, and whether it succeeded.
+pub(crate) fn record_kds_fetch(duration: Duration, success: bool) {
+    let outcome = if success { "success" } else { "failure" };
+    histogram!(KDS_FETCH_DURATION, "outcome" => outcome).record(duration.as_secs_f64());
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use ::metrics::with_local_recorder;
+    use metrics_util::debugging::{DebugValue, DebuggingRecorder, Snapshotter};
+    use metrics_util::MetricKind;
+    use sev::certs::snp::Certificate;
+    use sev::firmware::guest::AttestationReport;
+
+    use crate::kds::KdsClient;
+    use crate::mock_kds::MockKds;
+    use crate::policy::VerifyPolicy;
+    use crate::product::ProductLine;
+    use crate::verify_attestation::{
+        tamper_report, verify_report, SAMPLE_ATTESTATION, SAMPLE_VCEK,
+    };
+
+    /// The count of the counter of the given name and labels, or the number of
+    /// values recorded in the histogram since the last snapshot.
+    fn recorded(snapshotter: &Snapshotter, name: &str, labels: &[(&str, &str)]) -> usize {
+        snapshotter
+            .snapshot()
+            .into_vec()
+            .into_iter()
+            .filter(|(key, _, _, _)| {
+                let key = key.key();
+                key.name() == name
+                    && key
+                        .labels()
+                        .map(|label| (label.key(), label.value()))
+                        .eq(labels.iter().copied())
+            })
+            .map(|(key, _, _, value)| match value {
+                DebugValue::Counter(count) => {
+                    assert_eq!(key.kind(), MetricKind::Counter);
+                    count as usize
+                }
+                DebugValue::Histogram(values) => {
+                    assert!(values.iter().all(|value| value.into_inner() >= 0.0));
+                    values.len()
+                }
+                DebugValue::Gauge(_) => panic!("unexpected gauge {name}"),
+            })
+            .sum()
+    }
+
+    #[test]
+    fn test_metrics() {
+        let recorder = DebuggingRecorder::new();
+        let snapshotter = recorder.snapshotter();
+
+        // The recorder only sees this thread's measurements, not other tests'
+        with_local_recorder(&recorder, || {
+            let mut report: AttestationReport = serde_json::from_str(SAMPLE_ATTESTATION).unwrap();
+            let vcek = Certificate::from_der(SAMPLE_VCEK).unwrap();
+            let policy = VerifyPolicy::default();
+            assert!(verify_report(&report, &vcek, ProductLine::Genoa, &policy).is_ok());
+            assert_eq!(recorded(&snapshotter, VERIFICATIONS_ATTEMPTED, &[]), 1);
+            assert_eq!(recorded(&snapshotter, VERIFICATIONS_SUCCEEDED, &[]), 1);
+            assert_eq!(
+                recorded(
+                    &snapshotter,
+                    VERIFICATION_STEPS,
+                    &[("step", "report_signature"), ("outcome", "passed")]
+                ),
+                1
+            );
+
+            tamper_report(&mut report);
+            assert!(verify_report(&report, &vcek, ProductLine::Genoa, &policy).is_err());
+            assert_eq!(recorded(&snapshotter, VERIFICATIONS_ATTEMPTED, &[]), 2);
+            assert_eq!(
+                recorded(
+                    &snapshotter,
+                    VERIFICATIONS_FAILED,
+                    &[("reason", "report_signature")]
+                ),
+                1
+            );
+            assert_eq!(
+                recorded(
+                    &snapshotter,
+                    VERIFICATION_STEPS,
+                    &[("step", "report_signature"), ("outcome", "failed")]
+                ),
+                1
+            );
+
+            let kds = MockKds::start();
+            let client = KdsClient::new(kds.base_url());
+            // Each snapshot takes the histogram values recorded since the last one
+            assert!(client.get_cert_chain(ProductLine::Genoa).is_ok());
+            assert!(recorded(&snapshotter, KDS_FETCH_DURATION, &[("outcome", "success")]) > 0);
+            assert!(client.get_cert_chain(ProductLine::Milan).is_err());
+            assert!(recorded(&snapshotter, KDS_FETCH_DURATION, &[("outcome", "failure")]) > 0);
+        });
+    }
+}
@@ -0,0 +1,139 @@
+//! Local HTTP servers standing in for KDS in tests.
+
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+
+use crate::verify_attestation::{GENOA_PEM, KDS_CERT_CHAIN, KDS_VCEK, SAMPLE_VCEK};
+
+/// Reads a request from the stream, returning the requested path.
+pub(crate) fn read_request(stream: &TcpStream) -> String {
+    read_request_with_headers(stream).0
+}
+
+/// Reads a request from the stream, returning the requested path and the
+/// request's headers, with lowercased names.
+fn read_request_with_headers(stream: &TcpStream) -> (String, Vec<(String, String)>) {
+    let mut reader = BufReader::new(stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line).unwrap();
+    let mut headers = Vec::new();
+    loop {
+        let mut header = String::new();
+        reader.read_line(&mut header).unwrap();
+        match header.trim().split_once(':') {
+            Some((name, value)) => {
+                headers.push((name.to_ascii_lowercase(), value.trim().to_string()))
+            }
+            None => break,
+        }
+    }
+
+    let path = request_line.split_whitespace().nth(1).unwrap().to_string();
+    (path, headers)
+}
+
+fn respond(mut stream: TcpStream, status: &str, body: &[u8]) {
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        body.len()
+    )
+    .unwrap();
+    stream.write_all(body).unwrap();
+}
+
+/// Serves HTTP responses on a local port, one per connection, returning the
+/// base URL to request and a handle that yields the paths that were requested.
+pub(crate) fn serve(
+    responses: Vec<(&'static str, &'static [u8])>,
+) -> (String, JoinHandle<Vec<String>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let base_url = format!("http://{}", listener.local_addr().unwrap());
+
+    let handle = std::thread::spawn(move || {
+        let mut paths = Vec::new();
+        for (status, body) in responses {
+            let (stream, _) = listener.accept().unwrap();
+            paths.push(read_request(&stream));
+            respond(stream, status, body);
+        }
+        paths
+    });
+
+    (base_url, handle)
+}
+
+/// Serves a single HTTP response, returning a handle that yields the requested path.
+pub(crate) fn serve_once(
+    status: &'static str,
+    body: &'static [u8],
+) -> (String, JoinHandle<String>) {
+    let (base_url, handle) = serve(vec![(status, body)]);
+    let handle = std::thread::spawn(move || handle.join().unwrap().remove(0));
+    (base_url, handle)
+}
+
+/// A mock KDS serving the sample VCEK and the Genoa certificate chain.
+///
+/// Any VCEK request is answered with the sample VCEK, whatever its chip ID and
+/// TCB; any other path is not found. A mock started with
+/// [`MockKds::start_requiring_header`] forbids requests without the header.
+pub(crate) struct MockKds {
+    base_url: String,
+    paths: Arc<Mutex<Vec<String>>>,
+}
+
+impl MockKds {
+    /// Starts serving on a local port, until the test process exits.
+    pub(crate) fn start() -> Self {
+        Self::start_with(None)
+    }
+
+    /// Starts serving on a local port, answering requests without the given
+    /// header (whose name must be lowercase) with 403 Forbidden.
+    pub(crate) fn start_requiring_header(name: &'static str, value: &'static str) -> Self {
+        Self::start_with(Some((name, value)))
+    }
+
+    fn start_with(required_header: Option<(&'static str, &'static str)>) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        let paths = Arc::new(Mutex::new(Vec::new()));
+
+        let requested = paths.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let stream = stream.unwrap();
+                let (path, headers) = read_request_with_headers(&stream);
+                let forbidden = required_header.is_some_and(|(name, value)| {
+                    !headers.iter().any(|(n, v)| n == name && v == value)
+                });
+                let (status, body) = if forbidden {
+                    ("403 Forbidden", &b"forbidden"[..])
+                } else if path == format!("{KDS_VCEK}/Genoa/{KDS_CERT_CHAIN}") {
+                    ("200 OK", GENOA_PEM)
+                } else if path.starts_with(&format!("{KDS_VCEK}/Genoa/")) {
+                    ("200 OK", SAMPLE_VCEK)
+                } else {
+                    ("404 Not Found", &b"not found"[..])
+                };
+                requested.lock().unwrap().push(path);
+                respond(stream, status, body);
+            }
+        });
+
+        Self { base_url, paths }
+    }
+
+    /// The base URL to point a [`crate::kds::KdsClient`] at.
+    pub(crate) fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    /// The paths requested so far, in order.
+    pub(crate) fn paths(&self) -> Vec<String> {
+        self.paths.lock().unwrap().clone()
+    }
+}
@@ -0,0 +1,942 @@
+use std::fmt;
+use std::time::{Duration, SystemTime};
+
+use serde::Serialize;
+use sev::firmware::{
+    guest::{AttestationReport, GuestPolicy},
+    host::TcbVersion,
+};
+
+use crate::chain_cache::ChainCache;
+use crate::crl::Crl;
+use crate::crypto::ct_eq;
+use crate::id_block::{verify_id_auth, IdAuth};
+use crate::tcb::check_tcb;
+use crate::verify_attestation::VerifyError;
+
+/// Checks applied to an attestation report after its signature has been verified.
+///
+/// A valid signature proves the report is authentic; these checks establish
+/// that the guest is the one the relying party expects. Every check is optional,
+/// and the default policy checks nothing.
+///
+/// The policy also sets the time at which the VCEK must be valid.
+#[derive(Debug, Clone, Default)]
+pub struct VerifyPolicy {
+    /// The expected launch measurement of the guest.
+    pub expected_measurement: Option<[u8; 48]>,
+
+    /// The launch measurements the guest may have, such as those of every image
+    /// version approved during a rolling upgrade.
+    pub allowed_measurements: Option<Vec<[u8; 48]>>,
+
+    /// The expected guest-provided report data, typically a challenge nonce.
+    ///
+    /// Only the first `expected_report_data.len()` bytes of the 64-byte field are
+    /// compared, so a caller who embeds a 32-byte hash (with trailing zeros)
+    /// can pass just the hash. Data longer than 64 bytes never matches.
+    pub expected_report_data: Option<Vec<u8>>,
+
+    /// The expected hypervisor-provided host data, typically a hash of the
+    /// launch configuration.
+    pub expected_host_data: Option<[u8; 32]>,
+
+    /// The expected family ID, which the guest owner sets in the ID block at
+    /// launch to tag a family of VMs.
+    pub expected_family_id: Option<[u8; 16]>,
+
+    /// The expected image ID, which the guest owner sets in the ID block at
+    /// launch to tag a VM image.
+    ///
+    /// Along with the family ID, this confirms a report came from the intended
+    /// image lineage where pinning the measurement of every build is impractical.
+    /// The IDs are only trustworthy if the ID block's signing key is also checked
+    /// (see [`VerifyPolicy::allowed_signing_keys`]).
+    pub expected_image_id: Option<[u8; 16]>,
+
+    /// The expected report ID, which the firmware assigns the guest at launch.
+    ///
+    /// The report ID identifies a guest across its reports, so this ties the
+    /// report to one previously attested guest.
+    pub expected_report_id: Option<[u8; 32]>,
+
+    /// The expected report ID of the guest's migration agent, or
+    /// [`NO_MIGRATION_AGENT`] to require that the guest has none.
+    pub expected_report_id_ma: Option<[u8; 32]>,
+
+    /// The Virtual Machine Privilege Level (VMPL) the report must have been
+    /// requested from.
+    ///
+    /// A guest split into privilege levels, such as one running an SVSM or a
+    /// paravisor at VMPL 0 below the guest OS, measures only its launch image:
+    /// code at a less privileged level is not part of the measurement, and can
+    /// request reports carrying any report data it likes. Requiring VMPL 0 (or
+    /// the level of the component the relying party trusts) ensures the report
+    /// data was chosen by the measured code.
+    pub required_vmpl: Option<u32>,
+
+    /// The ID block signing keys the guest may have been launched with.
+    pub allowed_signing_keys: Option<SigningKeyAllowlist>,
+
+    /// The ID authentication information the guest must have been launched
+    /// with, proving the guest owner's ID key (and author key) vouched for the
+    /// guest (see [`verify_id_auth`]).
+    pub id_auth: Option<IdAuth>,
+
+    /// Requirements on the guest policy the guest was launched with.
+    pub guest_policy: PolicyRequirements,
+
+    /// The minimum TCB the report's reported TCB must meet (see [`check_tcb`]).
+    pub min_tcb: Option<TcbVersion>,
+
+    /// How recent the report must be.
+    pub freshness: Option<Freshness>,
+
+    /// The SHA-256 fingerprint of the DER-encoded VCEK the report must be signed
+    /// by, rejecting any other VCEK even if it chains to the ARK.
+    pub pinned_vcek_sha256: Option<[u8; 32]>,
+
+    /// The certificate revocation list the ASK and VCEK are checked against, or
+    /// `None` to skip revocation checking.
+    ///
+    /// Use [`crate::kds::KdsClient::get_crl`] to request it from KDS, or
+    /// [`Crl::from_der`] to load one supplied out of band (say, in an offline
+    /// deployment). It must be current at [`VerifyPolicy::as_of`].
+    pub crl: Option<Crl>,
+
+    /// A cache of VCEKs whose certificate chain has already verified, or `None`
+    /// to verify the chain for every report.
+    pub chain_cache: Option<ChainCache>,
+
+    /// The time at which the VCEK must be valid, or `None` for the current time.
+    ///
+    /// Setting this allows past reports to be verified deterministically.
+    pub as_of: Option<SystemTime>,
+
+    /// How reports of a version newer than this crate supports are treated.
+    pub unknown_version: UnknownVersionPolicy,
+}
+
+impl VerifyPolicy {
+    /// Checks an (already signature-verified) attestation report against this policy.
+    pub fn check(&self, report: &AttestationReport) -> Result<(), VerifyError> {
+        self.check_steps(report)
+            .into_iter()
+            .filter_map(|(_, result)| result)
+            .collect()
+    }
+
+    /// Runs each check of this policy, naming it and giving its result, or `None`
+    /// if the policy does not configure that check.
+    pub(crate) fn check_steps(
+        &self,
+        report: &AttestationReport,
+    ) -> Vec<(&'static str, Option<Result<(), VerifyError>>)> {
+        let guest_policy = self.guest_policy;
+        vec![
+            (
+                "measurement",
+                self.expected_measurement
+                    .map(|expected| check_measurement(report, &expected)),
+            ),
+            (
+                "measurement_allowlist",
+                self.allowed_measurements
+                    .as_ref()
+                    .map(|allowed| check_measurement_allowlist(report, allowed)),
+            ),
+            (
+                "report_data",
+                self.expected_report_data
+                    .as_ref()
+                    .map(|expected| check_report_data(report, expected)),
+            ),
+            (
+                "host_data",
+                self.expected_host_data
+                    .map(|expected| check_host_data(report, &expected)),
+            ),
+            (
+                "family_id",
+                self.expected_family_id
+                    .map(|expected| check_family_id(report, &expected)),
+            ),
+            (
+                "image_id",
+                self.expected_image_id
+                    .map(|expected| check_image_id(report, &expected)),
+            ),
+            (
+                "report_id",
+                self.expected_report_id
+                    .map(|expected| check_report_id(report, &expected)),
+            ),
+            (
+                "report_id_ma",
+                self.expected_report_id_ma
+                    .map(|expected| check_report_id_ma(report, &expected)),
+            ),
+            (
+                "vmpl",
+                self.required_vmpl
+                    .map(|expected| check_vmpl(report, expected)),
+            ),
+            (
+                "signing_key",
+                self.allowed_signing_keys
+                    .as_ref()
+                    .map(|allowlist| check_signing_key(report, allowlist)),
+            ),
+            (
+                "id_auth",
+                self.id_auth
+                    .as_ref()
+                    .map(|id_auth| verify_id_auth(report, id_auth)),
+            ),
+            (
+                "freshness",
+                self.freshness
+                    .map(|freshness| freshness.check(self.as_of.unwrap_or_else(SystemTime::now))),
+            ),
+            (
+                "tcb",
+                self.min_tcb
+                    .as_ref()
+                    .map(|minimum| check_tcb(report, minimum)),
+            ),
+            (
+                "guest_policy",
+                (guest_policy != PolicyRequirements::default())
+                    .then(|| check_policy(report, &guest_policy)),
+            ),
+        ]
+    }
+
+    /// Names each check of this policy, in the order of [`VerifyPolicy::check_steps`],
+    /// and whether the policy configures it.
+    pub(crate) fn configured_checks(&self) -> [(&'static str, bool); 14] {
+        [
+            ("measurement", self.expected_measurement.is_some()),
+            ("measurement_allowlist", self.allowed_measurements.is_some()),
+            ("report_data", self.expected_report_data.is_some()),
+            ("host_data", self.expected_host_data.is_some()),
+            ("family_id", self.expected_family_id.is_some()),
+            ("image_id", self.expected_image_id.is_some()),
+            ("report_id", self.expected_report_id.is_some()),
+            ("report_id_ma", self.expected_report_id_ma.is_some()),
+            ("vmpl", self.required_vmpl.is_some()),
+            ("signing_key", self.allowed_signing_keys.is_some()),
+            ("id_auth", self.id_auth.is_some()),
+            ("freshness", self.freshness.is_some()),
+            ("tcb", self.min_tcb.is_some()),
+            (
+                "guest_policy",
+                self.guest_policy != PolicyRequirements::default(),
+            ),
+        ]
+    }
+}
+
+/// Checks that the report's launch measurement is the expected one.
+pub fn check_measurement(
+    report: &AttestationReport,
+    expected: &[u8; 48],
+) -> Result<(), VerifyError> {
+    if !ct_eq(&report.measurement, expected) {
+        return Err(VerifyError::MeasurementMismatch {
+            expected: *expected,
+            actual: report.measurement,
+        });
+    }
+
+    Ok(())
+}
+
+/// Checks that the report's launch measurement is one of the allowed ones.
+pub fn check_measurement_allowlist(
+    report: &AttestationReport,
+    allowed: &[[u8; 48]],
+) -> Result<(), VerifyError> {
+    // Compare against every allowed measurement, so the time taken does not reveal which matched
+    let found = allowed.iter().fold(false, |found, allowed| {
+        found | ct_eq(allowed, &report.measurement)
+    });
+    if !found {
+        return Err(VerifyError::MeasurementNotAllowed {
+            actual: report.measurement,
+        });
+    }
+
+    Ok(())
+}
+
+/// Checks that the report's report data starts with the expected bytes.
+///
+/// Empty expected report data is a prefix of every report's, so it never matches.
+pub fn check_report_data(report: &AttestationReport, expected: &[u8]) -> Result<(), VerifyError> {
+    let matches = !expected.is_empty()
+        && expected.len() <= report.report_data.len()
+        && ct_eq(expected, &report.report_data[..expected.len()]);
+    if !matches {
+        return Err(VerifyError::ReportDataMismatch {
+            expected: expected.to_vec(),
+            actual: report.report_data,
+        });
+    }
+
+    Ok(())
+}
+
+/// Checks that the report's host data is the expected one.
+///
+/// The host data is set by the hypervisor at launch, so it is only trustworthy
+/// once the report's signature has been verified.
+pub fn check_host_data(report: &AttestationReport, expected: &[u8; 32]) -> Result<(), VerifyError> {
+    if !ct_eq(&report.host_data, expected) {
+        return Err(VerifyError::HostDataMismatch {
+            expected: *expected,
+            actual: report.host_data,
+        });
+    }
+
+    Ok(())
+}
+
+/// Checks that the report's family ID is the expected one.
+pub fn check_family_id(report: &AttestationReport, expected: &[u8; 16]) -> Result<(), VerifyError> {
+    if !ct_eq(&report.family_id, expected) {
+        return Err(VerifyError::FamilyIdMismatch {
+            expected: *expected,
+            actual: report.family_id,
+        });
+    }
+
+    Ok(())
+}
+
+/// The REPORT_ID_MA of a guest with no migration agent: all ones.
+///
+/// The firmware sets the field to this value when the guest was launched
+/// without a migration agent, and otherwise to the migration agent's report ID.
+pub const NO_MIGRATION_AGENT: [u8; 32] = [0xff; 32];
+
+/// Checks that the report's report ID is the expected one.
+pub fn check_report_id(report: &AttestationReport, expected: &[u8; 32]) -> Result<(), VerifyError> {
+    if !ct_eq(&report.report_id, expected) {
+        return Err(VerifyError::ReportIdMismatch {
+            expected: *expected,
+            actual: report.report_id,
+        });
+    }
+
+    Ok(())
+}
+
+/// Checks that the report's migration agent report ID is the expected one, or,
+/// if `expected` is [`NO_MIGRATION_AGENT`], that the guest has no migration agent.
+pub fn check_report_id_ma(
+    report: &AttestationReport,
+    expected: &[u8; 32],
+) -> Result<(), VerifyError> {
+    if !ct_eq(&report.report_id_ma, expected) {
+        return Err(VerifyError::UnexpectedMigrationAgent {
+            expected: *expected,
+            actual: report.report_id_ma,
+        });
+    }
+
+    Ok(())
+}
+
+/// Checks that the report's image ID is the expected one.
+pub fn check_image_id(report: &AttestationReport, expected: &[u8; 16]) -> Result<(), VerifyError> {
+    if !ct_eq(&report.image_id, expected) {
+        return Err(VerifyError::ImageIdMismatch {
+            expected: *expected,
+            actual: report.image_id,
+        });
+    }
+
+    Ok(())
+}
+
+/// Checks that the report was requested from the expected VMPL.
+pub fn check_vmpl(report: &AttestationReport, expected: u32) -> Result<(), VerifyError> {
+    if report.vmpl != expected {
+        return Err(VerifyError::UnexpectedVmpl {
+            expected,
+            actual: report.vmpl,
+        });
+    }
+
+    Ok(())
+}
+
+/// A bound on the age of a report, to reject replays of old reports.
+///
+/// Reports carry no trustworthy timestamp, so a report's age is measured from
+/// when the relying party issued the nonce the report carries in its report
+/// data. This is only meaningful along with checking that nonce (see
+/// [`VerifyPolicy::expected_report_data`]).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Freshness {
+    /// When the nonce in the report was issued.
+    pub issued_at: SystemTime,
+
+    /// How long after `issued_at` the report is accepted.
+    pub max_age: Duration,
+}
+
+impl Freshness {
+    /// Checks that, at time `now`, the report is no older than the maximum age.
+    pub fn check(&self, now: SystemTime) -> Result<(), VerifyError> {
+        // A nonce issued in the future (by a skewed clock) is as fresh as can be
+        let age = now.duration_since(self.issued_at).unwrap_or_default();
+        if age > self.max_age {
+            return Err(VerifyError::ReportTooOld {
+                age,
+                max_age: self.max_age,
+            });
+        }
+
+        Ok(())
+    }
+}
+
+/// How a report whose version is newer than [`crate::report::SUPPORTED_REPORT_VERSIONS`]
+/// is treated, as when new firmware bumps the report format.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum UnknownVersionPolicy {
+    /// The report fails verification.
+    #[default]
+    Reject,
+
+    /// The report is verified on the fields shared with the supported versions,
+    /// including its signature, but the checks of version-specific fields (the
+    /// reserved fields) are skipped, and the verification records a warning.
+    ///
+    /// Later versions have so far only assigned fields in reserved space, but a
+    /// future version could change the layout, so this is a best effort.
+    BestEffort,
+}
+
+/// A digest of a key that signed the ID block a guest was launched with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SigningKeyDigest {
+    /// The digest of the key that signed the ID block (ID_KEY_DIGEST).
+    IdKey,
+
+    /// The digest of the key that signed the ID key (AUTHOR_KEY_DIGEST).
+    ///
+    /// This is all zeros unless the ID block was accompanied by an author key.
+    AuthorKey,
+}
+
+impl fmt::Display for SigningKeyDigest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            SigningKeyDigest::IdKey => "ID key",
+            SigningKeyDigest::AuthorKey => "author key",
+        })
+    }
+}
+
+/// The signing keys a guest's ID block may have been signed with.
+///
+/// A guest launched without an ID block has an all-zero digest, which matches
+/// only if all zeros is on the allowlist.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SigningKeyAllowlist {
+    /// Which of the report's digests is checked.
+    pub digest: SigningKeyDigest,
+
+    /// The SHA-384 digests of the allowed keys.
+    pub allowed: Vec<[u8; 48]>,
+}
+
+/// Checks that the report's ID key (or author key) digest is on the allowlist.
+pub fn check_signing_key(
+    report: &AttestationReport,
+    allowlist: &SigningKeyAllowlist,
+) -> Result<(), VerifyError> {
+    let digest = match allowlist.digest {
+        SigningKeyDigest::IdKey => report.id_key_digest,
+        SigningKeyDigest::AuthorKey => report.author_key_digest,
+    };
+    // Compare against every allowed key, so the time taken does not reveal which matched
+    let allowed = allowlist
+        .allowed
+        .iter()
+        .fold(false, |found, allowed| found | ct_eq(allowed, &digest));
+    if !allowed {
+        return Err(VerifyError::UnexpectedSigningKey {
+            key: allowlist.digest,
+            digest,
+        });
+    }
+
+    Ok(())
+}
+
+/// A flag in the guest policy that the guest owner sets at launch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PolicyFlag {
+    /// Whether the host may use SMT while the guest runs.
+    Smt,
+
+    /// Whether the guest may be associated with a migration agent.
+    MigrateMa,
+
+    /// Whether the guest may be debugged by the host.
+    Debug,
+
+    /// Whether the guest may only be activated on a single socket.
+    SingleSocket,
+}
+
+impl fmt::Display for PolicyFlag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            PolicyFlag::Smt => "SMT allowed",
+            PolicyFlag::MigrateMa => "migration agent allowed",
+            PolicyFlag::Debug => "debug allowed",
+            PolicyFlag::SingleSocket => "single socket required",
+        })
+    }
+}
+
+/// Required values for the flags of a report's guest policy.
+///
+/// Each field is the value the flag must have, or `None` if it is not checked.
+/// For example, `debug: Some(false)` requires that debugging is disabled.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PolicyRequirements {
+    /// Required value of the SMT_ALLOWED bit.
+    pub smt: Option<bool>,
+
+    /// Required value of the MIGRATE_MA_ALLOWED bit.
+    pub migrate_ma: Option<bool>,
+
+    /// Required value of the DEBUG_ALLOWED bit.
+    pub debug: Option<bool>,
+
+    /// Required value of the SINGLE_SOCKET_REQUIRED bit.
+    pub single_socket: Option<bool>,
+}
+
+/// The decoded fields of a report's guest policy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct DecodedPolicy {
+    /// The minimum ABI major version required for the guest to run.
+    pub abi_major: u8,
+
+    /// The minimum ABI minor version required for the guest to run.
+    pub abi_minor: u8,
+
+    /// Whether the host may use SMT while the guest runs.
+    pub smt_allowed: bool,
+
+    /// Whether the guest may be associated with a migration agent.
+    pub migrate_ma_allowed: bool,
+
+    /// Whether the guest may be debugged by the host.
+    pub debug_allowed: bool,
+
+    /// Whether the guest may only be activated on a single socket.
+    pub single_socket_required: bool,
+
+    /// Whether CXL may be populated with devices or memory.
+    pub cxl_allowed: bool,
+
+    /// Whether AES-256-XTS is required for memory encryption.
+    pub mem_aes_256_xts_required: bool,
+
+    /// Whether Running Average Power Limit (RAPL) must be disabled.
+    pub rapl_disabled_required: bool,
+
+    /// Whether ciphertext hiding must be enabled.
+    pub ciphertext_hiding_required: bool,
+}
+
+impl DecodedPolicy {
+    /// Decodes the raw 64-bit guest policy, as laid out in the AMD SEV-SNP ABI
+    /// specification.
+    pub fn from_bits(bits: u64) -> Self {
+        let bit = |n: u32| bits & (1 << n) != 0;
+        Self {
+            abi_minor: bits as u8,
+            abi_major: (bits >> 8) as u8,
+            smt_allowed: bit(16),
+            migrate_ma_allowed: bit(18),
+            debug_allowed: bit(19),
+            single_socket_required: bit(20),
+            cxl_allowed: bit(21),
+            mem_aes_256_xts_required: bit(22),
+            rapl_disabled_required: bit(23),
+            ciphertext_hiding_required: bit(24),
+        }
+    }
+}
+
+impl From<GuestPolicy> for DecodedPolicy {
+    fn from(policy: GuestPolicy) -> Self {
+        Self::from_bits(policy_bits(&policy))
+    }
+}
+
+/// The raw 64-bit value of a guest policy.
+///
+/// The sev crate only decodes some of its flags.
+pub fn policy_bits(policy: &GuestPolicy) -> u64 {
+    let bytes = bincode::serialize(policy).expect("guest policy is serializable");
+    u64::from_le_bytes(bytes.try_into().expect("guest policy is 8 bytes"))
+}
+
+/// Checks the guest policy of a report against the required flag values.
+pub fn check_policy(
+    report: &AttestationReport,
+    required: &PolicyRequirements,
+) -> Result<(), VerifyError> {
+    let policy = DecodedPolicy::from(report.policy);
+    let checks = [
+        (PolicyFlag::Smt, required.smt, policy.smt_allowed),
+        (
+            PolicyFlag::MigrateMa,
+            required.migrate_ma,
+            policy.migrate_ma_allowed,
+        ),
+        (PolicyFlag::Debug, required.debug, policy.debug_allowed),
+        (
+            PolicyFlag::SingleSocket,
+            required.single_socket,
+            policy.single_socket_required,
+        ),
+    ];
+
+    for (flag, required, actual) in checks {
+        if let Some(required) = required {
+            if required != actual {
+                return Err(VerifyError::PolicyViolation {
+                    flag,
+                    required,
+                    actual,
+                });
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::verify_attestation::SAMPLE_ATTESTATION;
+
+    #[test]
+    fn test_expected_measurement() {
+        let report: AttestationReport = serde_json::from_str(SAMPLE_ATTESTATION).unwrap();
+        assert!(VerifyPolicy::default().check(&report).is_ok());
+
+        let policy = VerifyPolicy {
+            expected_measurement: Some(report.measurement),
+            ..Default::default()
+        };
+        assert!(policy.check(&report).is_ok());
+
+        let policy = VerifyPolicy {
+            expected_measurement: Some([0; 48]),
+            ..Default::default()
+        };
+        assert!(matches!(
+            policy.check(&report),
+            Err(VerifyError::MeasurementMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_expected_report_data() {
+        let report: AttestationReport = serde_json::from_str(SAMPLE_ATTESTATION).unwrap();
+
+        for (expected, ok) in [
+            (vec![7; 64], true),
+            (vec![7; 32], true),
+            (vec![], false),
+            (vec![7; 65], false),
+            (vec![0; 32], false),
+        ] {
+            let policy = VerifyPolicy {
+                expected_report_data: Some(expected),
+                ..Default::default()
+            };
+            match policy.check(&report) {
+                Ok(()) => assert!(ok),
+                Err(e) => assert!(!ok && matches!(e, VerifyError::ReportDataMismatch { .. })),
+            }
+        }
+    }
+
+    #[test]
+    fn test_expected_host_data() {
+        // The sample report has all-zero host data
+        let report: AttestationReport = serde_json::from_str(SAMPLE_ATTESTATION).unwrap();
+
+        let policy = VerifyPolicy {
+            expected_host_data: Some([0; 32]),
+            ..Default::default()
+        };
+        assert!(policy.check(&report).is_ok());
+
+        let policy = VerifyPolicy {
+            expected_host_data: Some([1; 32]),
+            ..Default::default()
+        };
+        match policy.check(&report) {
+            Err(VerifyError::HostDataMismatch { expected, actual }) => {
+                assert_eq!(expected, [1; 32]);
+                assert_eq!(actual, report.host_data);
+            }
+            result => panic!("unexpected result {result:?}"),
+        }
+    }
+
+    #[test]
+    fn test_expected_family_and_image_id() {
+        // The sample report has all-zero family and image IDs
+        let report: AttestationReport = serde_json::from_str(SAMPLE_ATTESTATION).unwrap();
+
+        let policy = VerifyPolicy {
+            expected_family_id: Some([0; 16]),
+            expected_image_id: Some([0; 16]),
+            ..Default::default()
+        };
+        assert!(policy.check(&report).is_ok());
+
+        let policy = VerifyPolicy {
+            expected_family_id: Some([1; 16]),
+            ..Default::default()
+        };
+        assert!(matches!(
+            policy.check(&report),
+            Err(VerifyError::FamilyIdMismatch { expected, .. }) if expected == [1; 16]
+        ));
+
+        let policy = VerifyPolicy {
+            expected_image_id: Some([2; 16]),
+            ..Default::default()
+        };
+        assert!(matches!(
+            policy.check(&report),
+            Err(VerifyError::ImageIdMismatch { expected, .. }) if expected == [2; 16]
+        ));
+    }
+
+    #[test]
+    fn test_report_id_and_migration_agent() {
+        // The sample report's guest has no migration agent
+        let report: AttestationReport = serde_json::from_str(SAMPLE_ATTESTATION).unwrap();
+        let policy = VerifyPolicy {
+            expected_report_id: Some(report.report_id),
+            expected_report_id_ma: Some(NO_MIGRATION_AGENT),
+            ..Default::default()
+        };
+        assert!(policy.check(&report).is_ok());
+
+        let policy = VerifyPolicy {
+            expected_report_id: Some([1; 32]),
+            ..Default::default()
+        };
+        let e = policy.check(&report).unwrap_err();
+        assert!(matches!(e, VerifyError::ReportIdMismatch { expected, .. } if expected == [1; 32]));
+        assert!(e.to_string().contains(&hex::encode(report.report_id)));
+
+        let policy = VerifyPolicy {
+            expected_report_id_ma: Some([2; 32]),
+            ..Default::default()
+        };
+        let e = policy.check(&report).unwrap_err();
+        assert!(matches!(e, VerifyError::UnexpectedMigrationAgent { .. }));
+        assert!(e.to_string().starts_with("guest has no migration agent"));
+
+        let mut migratable = report;
+        migratable.report_id_ma = [3; 32];
+        let e = check_report_id_ma(&migratable, &NO_MIGRATION_AGENT).unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            format!(
+                "guest has migration agent {}, but none is allowed",
+                hex::encode([3; 32])
+            )
+        );
+    }
+
+    #[test]
+    fn test_required_vmpl() {
+        // The sample report was requested from VMPL 1
+        let report: AttestationReport = serde_json::from_str(SAMPLE_ATTESTATION).unwrap();
+
+        let policy = VerifyPolicy {
+            required_vmpl: Some(1),
+            ..Default::default()
+        };
+        assert!(policy.check(&report).is_ok());
+
+        let policy = VerifyPolicy {
+            required_vmpl: Some(0),
+            ..Default::default()
+        };
+        assert!(matches!(
+            policy.check(&report),
+            Err(VerifyError::UnexpectedVmpl {
+                expected: 0,
+                actual: 1
+            })
+        ));
+    }
+
+    #[test]
+    fn test_freshness() {
+        let report: AttestationReport = serde_json::from_str(SAMPLE_ATTESTATION).unwrap();
+        let issued_at = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let freshness = Freshness {
+            issued_at,
+            max_age: Duration::from_secs(60),
+        };
+
+        assert!(freshness.check(issued_at).is_ok());
+        assert!(freshness.check(issued_at + Duration::from_secs(60)).is_ok());
+        assert!(freshness.check(issued_at - Duration::from_secs(60)).is_ok());
+        assert!(matches!(
+            freshness.check(issued_at + Duration::from_secs(61)),
+            Err(VerifyError::ReportTooOld { age, .. }) if age == Duration::from_secs(61)
+        ));
+
+        let policy = VerifyPolicy {
+            freshness: Some(freshness),
+            as_of: Some(issued_at + Duration::from_secs(3600)),
+            ..Default::default()
+        };
+        assert!(matches!(
+            policy.check(&report),
+            Err(VerifyError::ReportTooOld { .. })
+        ));
+    }
+
+    #[test]
+    fn test_check_measurement_allowlist() {
+        let report: AttestationReport = serde_json::from_str(SAMPLE_ATTESTATION).unwrap();
+
+        assert!(check_measurement_allowlist(&report, &[[1; 48], report.measurement]).is_ok());
+        assert!(matches!(
+            check_measurement_allowlist(&report, &[[1; 48]]),
+            Err(VerifyError::MeasurementNotAllowed { actual }) if actual == report.measurement
+        ));
+        assert!(check_measurement_allowlist(&report, &[]).is_err());
+    }
+
+    #[test]
+    fn test_check_signing_key() {
+        // The sample report was launched without an ID block
+        let report: AttestationReport = serde_json::from_str(SAMPLE_ATTESTATION).unwrap();
+
+        let mut allowlist = SigningKeyAllowlist {
+            digest: SigningKeyDigest::IdKey,
+            allowed: vec![[1; 48]],
+        };
+        assert!(matches!(
+            check_signing_key(&report, &allowlist),
+            Err(VerifyError::UnexpectedSigningKey {
+                key: SigningKeyDigest::IdKey,
+                ..
+            })
+        ));
+
+        allowlist.allowed.push(report.id_key_digest);
+        assert!(check_signing_key(&report, &allowlist).is_ok());
+
+        let allowlist = SigningKeyAllowlist {
+            digest: SigningKeyDigest::AuthorKey,
+            allowed: vec![],
+        };
+        let policy = VerifyPolicy {
+            allowed_signing_keys: Some(allowlist),
+            ..Default::default()
+        };
+        assert!(matches!(
+            policy.check(&report),
+            Err(VerifyError::UnexpectedSigningKey {
+                key: SigningKeyDigest::AuthorKey,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_decoded_policy_bits() {
+        // The sample report's policy is ABI 0.0 with SMT allowed and the
+        // must-be-one bit 17 set
+        let report: AttestationReport = serde_json::from_str(SAMPLE_ATTESTATION).unwrap();
+        assert_eq!(policy_bits(&report.policy), 0x30000);
+        let policy = DecodedPolicy::from(report.policy);
+        assert!(policy.smt_allowed && !policy.debug_allowed && !policy.migrate_ma_allowed);
+
+        let none = DecodedPolicy::from_bits(0);
+        assert_eq!(
+            DecodedPolicy::from_bits(0x0000_0305),
+            DecodedPolicy {
+                abi_major: 3,
+                abi_minor: 5,
+                ..none
+            }
+        );
+        type Flag = fn(&DecodedPolicy) -> bool;
+        let flags: [(u32, Flag); 8] = [
+            (16, |p| p.smt_allowed),
+            (18, |p| p.migrate_ma_allowed),
+            (19, |p| p.debug_allowed),
+            (20, |p| p.single_socket_required),
+            (21, |p| p.cxl_allowed),
+            (22, |p| p.mem_aes_256_xts_required),
+            (23, |p| p.rapl_disabled_required),
+            (24, |p| p.ciphertext_hiding_required),
+        ];
+        for (bit, flag) in flags {
+            let policy = DecodedPolicy::from_bits(1 << bit);
+            assert!(flag(&policy), "bit {bit}");
+            // No other flag is set
+            for (other, flag) in flags {
+                assert_eq!(flag(&policy), other == bit, "bit {bit} sets bit {other}");
+            }
+        }
+        assert_eq!(DecodedPolicy::from_bits(1 << 17), none);
+    }
+
+    #[test]
+    fn test_check_policy() {
+        // The sample report allows SMT, and disallows debugging and migration agents
+        let report: AttestationReport = serde_json::from_str(SAMPLE_ATTESTATION).unwrap();
+        assert!(check_policy(&report, &PolicyRequirements::default()).is_ok());
+
+        let required = PolicyRequirements {
+            debug: Some(false),
+            migrate_ma: Some(false),
+            ..Default::default()
+        };
+        assert!(check_policy(&report, &required).is_ok());
+
+        let required = PolicyRequirements {
+            debug: Some(false),
+            smt: Some(false),
+            ..Default::default()
+        };
+        assert!(matches!(
+            check_policy(&report, &required),
+            Err(VerifyError::PolicyViolation {
+                flag: PolicyFlag::Smt,
+                required: false,
+                actual: true,
+            })
+        ));
+    }
+}
@@ -0,0 +1,504 @@
+//! Verification policies loaded from a JSON or TOML file, so that a team can
+//! keep its attestation policy under version control.
+//!
+//! Byte strings are hex encoded and TCB versions are written as
+//! "bootloader.tee.snp.microcode". Every field is optional:
+//!
+//! ```json
+//! {
+//!     "allowed_measurements": ["8d2142…", "3f41a0…"],
+//!     "report_data": "07070707",
+//!     "vmpl": 0,
+//!     "signing_keys": { "digest": "id_key", "allowed": ["9a5f21…"] },
+//!     "min_tcb": "7.0.11.62",
+//!     "guest_policy": { "require": ["single_socket"], "forbid": ["debug", "migrate_ma"] }
+//! }
+//! ```
+//!
+//! The same policy in TOML:
+//!
+//! ```toml
+//! allowed_measurements = ["8d2142…", "3f41a0…"]
+//! report_data = "07070707"
+//! vmpl = 0
+//! min_tcb = "7.0.11.62"
+//!
+//! [signing_keys]
+//! digest = "id_key"
+//! allowed = ["9a5f21…"]
+//!
+//! [guest_policy]
+//! require = ["single_socket"]
+//! forbid = ["debug", "migrate_ma"]
+//! ```
+
+use std::fmt;
+
+use serde::Deserialize;
+
+use crate::measurement::parse_measurement;
+use crate::policy::{
+    PolicyFlag, PolicyRequirements, SigningKeyAllowlist, SigningKeyDigest, VerifyPolicy,
+};
+use crate::tcb::tcb_from_string;
+
+/// An error returned when a policy file is malformed or contradictory.
+#[derive(Debug)]
+pub enum PolicyFileError {
+    /// The file is not valid JSON, or has an unknown or mistyped field.
+    Parse(serde_json::Error),
+
+    /// The file is not valid TOML, or has an unknown or mistyped field.
+    ParseToml(toml::de::Error),
+
+    /// A field has an invalid value.
+    InvalidField { field: &'static str, reason: String },
+
+    /// The policy's checks contradict each other, so no report could pass.
+    Contradiction(String),
+}
+
+impl fmt::Display for PolicyFileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PolicyFileError::Parse(e) => write!(f, "invalid policy file: {e}"),
+            PolicyFileError::ParseToml(e) => write!(f, "invalid policy file: {e}"),
+            PolicyFileError::InvalidField { field, reason } => {
+                write!(f, "invalid policy field '{field}': {reason}")
+            }
+            PolicyFileError::Contradiction(reason) => write!(f, "contradictory policy: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for PolicyFileError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            PolicyFileError::Parse(e) => Some(e),
+            PolicyFileError::ParseToml(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// Which of the report's signing key digests a [`SigningKeysFile`] checks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum DigestName {
+    IdKey,
+    AuthorKey,
+}
+
+/// A guest policy flag, as named in a policy file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum FlagName {
+    Smt,
+    MigrateMa,
+    Debug,
+    SingleSocket,
+}
+
+impl FlagName {
+    /// The name of the flag, as written in a policy file.
+    fn name(self) -> &'static str {
+        match self {
+            FlagName::Smt => "smt",
+            FlagName::MigrateMa => "migrate_ma",
+            FlagName::Debug => "debug",
+            FlagName::SingleSocket => "single_socket",
+        }
+    }
+}
+
+impl From<FlagName> for PolicyFlag {
+    fn from(name: FlagName) -> Self {
+        match name {
+            FlagName::Smt => PolicyFlag::Smt,
+            FlagName::MigrateMa => PolicyFlag::MigrateMa,
+            FlagName::Debug => PolicyFlag::Debug,
+            FlagName::SingleSocket => PolicyFlag::SingleSocket,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SigningKeysFile {
+    digest: DigestName,
+    allowed: Vec<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct GuestPolicyFile {
+    /// Flags that must be set.
+    #[serde(default)]
+    require: Vec<FlagName>,
+
+    /// Flags that must be clear.
+    #[serde(default)]
+    forbid: Vec<FlagName>,
+}
+
+/// The checks of a [`VerifyPolicy`], as written in a policy file.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PolicyFile {
+    measurement: Option<String>,
+    allowed_measurements: Option<Vec<String>>,
+    report_data: Option<String>,
+    host_data: Option<String>,
+    family_id: Option<String>,
+    image_id: Option<String>,
+    report_id: Option<String>,
+    report_id_ma: Option<String>,
+    vmpl: Option<u32>,
+    signing_keys: Option<SigningKeysFile>,
+    min_tcb: Option<String>,
+    pinned_vcek_sha256: Option<String>,
+    #[serde(default)]
+    guest_policy: GuestPolicyFile,
+}
+
+/// Parses a verification policy from the JSON contents of a policy file.
+///
+/// Unknown fields are rejected, so a misspelled check is not silently skipped,
+/// as are policies whose checks no report could pass, such as a flag that is both
+/// required and forbidden. Checks that cannot be written in a file (freshness,
+/// ID authentication, revocation) can be set on the returned policy.
+pub fn policy_from_json(json: &str) -> Result<VerifyPolicy, PolicyFileError> {
+    policy_from_file(serde_json::from_str(json).map_err(PolicyFileError::Parse)?)
+}
+
+/// Parses a verification policy from the TOML contents of a policy file, as
+/// [`policy_from_json`] does from JSON.
+pub fn policy_from_toml(toml: &str) -> Result<VerifyPolicy, PolicyFileError> {
+    policy_from_file(toml::from_str(toml).map_err(PolicyFileError::ParseToml)?)
+}
+
+/// Checks that no report could fail a policy's checks just because they
+/// contradict each other, such as an expected measurement that is not on the
+/// allowlist.
+///
+/// Policies parsed from a file are already checked. This is for policies
+/// changed since, as by command-line options replacing a file's checks.
+pub fn validate_policy(policy: &VerifyPolicy) -> Result<(), PolicyFileError> {
+    match (&policy.expected_measurement, &policy.allowed_measurements) {
+        (_, Some(allowed)) if allowed.is_empty() => {
+            return Err(PolicyFileError::Contradiction(
+                "no measurements are allowed".to_string(),
+            ))
+        }
+        (Some(expected), Some(allowed)) if !allowed.contains(expected) => {
+            return Err(PolicyFileError::Contradiction(
+                "the expected measurement is not on the allowlist".to_string(),
+            ))
+        }
+        _ => {}
+    }
+    if policy
+        .allowed_signing_keys
+        .as_ref()
+        .is_some_and(|keys| keys.allowed.is_empty())
+    {
+        return Err(PolicyFileError::Contradiction(
+            "no signing keys are allowed".to_string(),
+        ));
+    }
+
+    Ok(())
+}
+
+fn policy_from_file(file: PolicyFile) -> Result<VerifyPolicy, PolicyFileError> {
+    let expected_measurement = file
+        .measurement
+        .as_deref()
+        .map(|hex| decode_measurement("measurement", hex))
+        .transpose()?;
+    let allowed_measurements = file
+        .allowed_measurements
+        .map(|allowed| {
+            allowed
+                .iter()
+                .map(|hex| decode_measurement("allowed_measurements", hex))
+                .collect::<Result<Vec<_>, _>>()
+        })
+        .transpose()?;
+
+    let expected_report_data = file
+        .report_data
+        .as_deref()
+        .map(|hex| decode("report_data", hex))
+        .transpose()?;
+    match expected_report_data.as_ref().map(Vec::len) {
+        Some(0) => return Err(invalid("report_data", "empty".to_string())),
+        Some(len) if len > 64 => {
+            return Err(invalid("report_data", "longer than 64 bytes".to_string()))
+        }
+        _ => {}
+    }
+
+    if file.vmpl.is_some_and(|vmpl| vmpl > 3) {
+        return Err(invalid("vmpl", "must be between 0 and 3".to_string()));
+    }
+
+    let allowed_signing_keys = file
+        .signing_keys
+        .map(|keys| {
+            let allowed = keys
+                .allowed
+                .iter()
+                .map(|hex| decode_array("signing_keys", hex))
+                .collect::<Result<Vec<_>, _>>()?;
+            let digest = match keys.digest {
+                DigestName::IdKey => SigningKeyDigest::IdKey,
+                DigestName::AuthorKey => SigningKeyDigest::AuthorKey,
+            };
+            Ok(SigningKeyAllowlist { digest, allowed })
+        })
+        .transpose()?;
+
+    let min_tcb = file
+        .min_tcb
+        .as_deref()
+        .map(|tcb| tcb_from_string(tcb).map_err(|e| invalid("min_tcb", e.to_string())))
+        .transpose()?;
+
+    let policy = VerifyPolicy {
+        expected_measurement,
+        allowed_measurements,
+        expected_report_data,
+        expected_host_data: decode_optional("host_data", file.host_data)?,
+        expected_family_id: decode_optional("family_id", file.family_id)?,
+        expected_image_id: decode_optional("image_id", file.image_id)?,
+        expected_report_id: decode_optional("report_id", file.report_id)?,
+        expected_report_id_ma: decode_optional("report_id_ma", file.report_id_ma)?,
+        required_vmpl: file.vmpl,
+        allowed_signing_keys,
+        guest_policy: guest_policy_requirements(&file.guest_policy)?,
+        min_tcb,
+        pinned_vcek_sha256: decode_optional("pinned_vcek_sha256", file.pinned_vcek_sha256)?,
+        ..Default::default()
+    };
+    validate_policy(&policy)?;
+    Ok(policy)
+}
+
+/// The requirements on the guest policy flags a file requires and forbids.
+fn guest_policy_requirements(
+    file: &GuestPolicyFile,
+) -> Result<PolicyRequirements, PolicyFileError> {
+    let mut requirements = PolicyRequirements::default();
+    let required = file.require.iter().map(|&flag| (flag, true));
+    let forbidden = file.forbid.iter().map(|&flag| (flag, false));
+    for (flag, value) in required.chain(forbidden) {
+        let requirement = match flag {
+            FlagName::Smt => &mut requirements.smt,
+            FlagName::MigrateMa => &mut requirements.migrate_ma,
+            FlagName::Debug => &mut requirements.debug,
+            FlagName::SingleSocket => &mut requirements.single_socket,
+        };
+        if requirement.is_some_and(|other| other != value) {
+            return Err(PolicyFileError::Contradiction(format!(
+                "'{}' is in both guest_policy.require and guest_policy.forbid",
+                flag.name()
+            )));
+        }
+        *requirement = Some(value);
+    }
+
+    Ok(requirements)
+}
+
+fn invalid(field: &'static str, reason: String) -> PolicyFileError {
+    PolicyFileError::InvalidField { field, reason }
+}
+
+fn decode(field: &'static str, hex: &str) -> Result<Vec<u8>, PolicyFileError> {
+    hex::decode(hex.strip_prefix("0x").unwrap_or(hex)).map_err(|e| invalid(field, e.to_string()))
+}
+
+fn decode_array<const N: usize>(
+    field: &'static str,
+    hex: &str,
+) -> Result<[u8; N], PolicyFileError> {
+    let bytes = decode(field, hex)?;
+    bytes
+        .as_slice()
+        .try_into()
+        .map_err(|_| invalid(field, format!("expected {N} bytes, found {}", bytes.len())))
+}
+
+fn decode_measurement(field: &'static str, hex: &str) -> Result<[u8; 48], PolicyFileError> {
+    parse_measurement(hex).map_err(|e| invalid(field, e.to_string()))
+}
+
+fn decode_optional<const N: usize>(
+    field: &'static str,
+    hex: Option<String>,
+) -> Result<Option<[u8; N]>, PolicyFileError> {
+    hex.map(|hex| decode_array(field, &hex)).transpose()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use sev::firmware::guest::AttestationReport;
+
+    use crate::verify_attestation::SAMPLE_ATTESTATION;
+
+    #[test]
+    fn test_policy_from_json() {
+        let report: AttestationReport = serde_json::from_str(SAMPLE_ATTESTATION).unwrap();
+        let json = format!(
+            r#"{{
+                "allowed_measurements": ["{}", "{}"],
+                "report_data": "0707070707070707",
+                "vmpl": 1,
+                "min_tcb": "7.0.11.62",
+                "guest_policy": {{ "forbid": ["debug", "migrate_ma"] }}
+            }}"#,
+            hex::encode([0; 48]),
+            hex::encode(report.measurement),
+        );
+
+        let policy = policy_from_json(&json).unwrap();
+        assert_eq!(policy.allowed_measurements.as_ref().unwrap().len(), 2);
+        assert_eq!(policy.expected_report_data, Some(vec![7; 8]));
+        assert_eq!(policy.guest_policy.debug, Some(false));
+        assert_eq!(policy.guest_policy.smt, None);
+        assert!(policy.check(&report).is_ok());
+
+        assert!(policy_from_json("{}").unwrap().check(&report).is_ok());
+    }
+
+    #[test]
+    fn test_policy_from_toml() {
+        let report: AttestationReport = serde_json::from_str(SAMPLE_ATTESTATION).unwrap();
+        let toml = format!(
+            r#"
+                allowed_measurements = ["{}", "{}"]
+                report_data = "0707070707070707"
+                vmpl = 1
+                min_tcb = "7.0.11.62"
+
+                [guest_policy]
+                forbid = ["debug", "migrate_ma"]
+            "#,
+            hex::encode([0; 48]),
+            hex::encode(report.measurement),
+        );
+
+        let policy = policy_from_toml(&toml).unwrap();
+        assert_eq!(policy.allowed_measurements.as_ref().unwrap().len(), 2);
+        assert_eq!(policy.expected_report_data, Some(vec![7; 8]));
+        assert_eq!(policy.guest_policy.debug, Some(false));
+        assert!(policy.check(&report).is_ok());
+
+        assert!(policy_from_toml("").unwrap().check(&report).is_ok());
+        let result = policy_from_toml(r#"measurment = "00""#);
+        assert!(matches!(result, Err(PolicyFileError::ParseToml(_))));
+        assert!(result.unwrap_err().to_string().contains("measurment"));
+        assert!(matches!(
+            policy_from_toml("[guest_policy]\nrequire = [\"debug\"]\nforbid = [\"debug\"]"),
+            Err(PolicyFileError::Contradiction(_))
+        ));
+    }
+
+    #[test]
+    fn test_validate_policy() {
+        let report: AttestationReport = serde_json::from_str(SAMPLE_ATTESTATION).unwrap();
+        let mut policy = VerifyPolicy {
+            allowed_measurements: Some(vec![report.measurement]),
+            ..Default::default()
+        };
+        assert!(validate_policy(&policy).is_ok());
+
+        // As when a command-line option replaces the file's expected measurement
+        policy.expected_measurement = Some([0; 48]);
+        assert!(matches!(
+            validate_policy(&policy),
+            Err(PolicyFileError::Contradiction(_))
+        ));
+
+        policy.expected_measurement = None;
+        policy.allowed_signing_keys = Some(SigningKeyAllowlist {
+            digest: SigningKeyDigest::IdKey,
+            allowed: vec![],
+        });
+        assert!(matches!(
+            validate_policy(&policy),
+            Err(PolicyFileError::Contradiction(_))
+        ));
+    }
+
+    #[test]
+    fn test_invalid_policy_file() {
+        // Misspelled checks are not silently ignored
+        let result = policy_from_json(r#"{ "measurment": "00" }"#);
+        assert!(matches!(result, Err(PolicyFileError::Parse(_))));
+        let e = result.unwrap_err();
+        assert!(e.to_string().contains("measurment"));
+        assert!(std::error::Error::source(&e).is_some());
+        assert!(matches!(
+            policy_from_json(r#"{ "host_data": "0011" }"#),
+            Err(PolicyFileError::InvalidField {
+                field: "host_data",
+                ..
+            })
+        ));
+        assert!(matches!(
+            policy_from_json(r#"{ "min_tcb": "7.0.11" }"#),
+            Err(PolicyFileError::InvalidField {
+                field: "min_tcb",
+                ..
+            })
+        ));
+        assert!(matches!(
+            policy_from_json(r#"{ "vmpl": 4 }"#),
+            Err(PolicyFileError::InvalidField { field: "vmpl", .. })
+        ));
+
+        let result = policy_from_json(
+            r#"{ "guest_policy": { "require": ["debug"], "forbid": ["smt", "debug"] } }"#,
+        );
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "contradictory policy: 'debug' is in both guest_policy.require and guest_policy.forbid"
+        );
+
+        assert!(matches!(
+            policy_from_json(r#"{ "report_data": "" }"#),
+            Err(PolicyFileError::InvalidField {
+                field: "report_data",
+                ..
+            })
+        ));
+
+        // A single 0x prefix is accepted, but not a repeated one
+        assert!(policy_from_json(r#"{ "report_data": "0xab" }"#).is_ok());
+        assert!(matches!(
+            policy_from_json(r#"{ "report_data": "0x0x0xab" }"#),
+            Err(PolicyFileError::InvalidField {
+                field: "report_data",
+                ..
+            })
+        ));
+
+        let json = format!(
+            r#"{{ "measurement": "{}", "allowed_measurements": ["{}"] }}"#,
+            hex::encode([0; 48]),
+            hex::encode([1; 48]),
+        );
+        assert!(matches!(
+            policy_from_json(&json),
+            Err(PolicyFileError::Contradiction(_))
+        ));
+        assert!(matches!(
+            policy_from_json(r#"{ "allowed_measurements": [] }"#),
+            Err(PolicyFileError::Contradiction(_))
+        ));
+    }
+}
@@ -0,0 +1,208 @@
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Serialize, Serializer};
+use sev::certs::snp::Certificate;
+use sev::firmware::guest::AttestationReport;
+
+use crate::report::report_to_array;
+use crate::vcek::vcek_product;
+use crate::verify_attestation::{GENOA_PEM, MILAN_PEM, TURIN_PEM};
+
+/// The SHA-256 fingerprint of the Milan ARK's DER encoding.
+const MILAN_ARK_SHA256: [u8; 32] = [
+    0x69, 0xd0, 0x63, 0xb4, 0x53, 0x44, 0xd2, 0x6a, 0x2e, 0x94, 0xe1, 0xf4, 0x21, 0x0d, 0xe4, 0x9e,
+    0xf5, 0x55, 0x30, 0x82, 0x87, 0xd4, 0xc1, 0x74, 0x44, 0x5c, 0x95, 0x63, 0x9a, 0x54, 0x0b, 0xcd,
+];
+
+/// The SHA-256 fingerprint of the Genoa ARK's DER encoding.
+const GENOA_ARK_SHA256: [u8; 32] = [
+    0x4c, 0x65, 0x98, 0xd1, 0x9c, 0x18, 0x71, 0x9c, 0x5d, 0xfd, 0x4a, 0x7d, 0x33, 0x5f, 0x67, 0x4e,
+    0x5b, 0xfe, 0x1d, 0x8f, 0x80, 0x0c, 0xea, 0x2c, 0xf2, 0x70, 0xc1, 0x0d, 0x10, 0x3d, 0xb2, 0xf1,
+];
+
+/// The SHA-256 fingerprint of the Turin ARK's DER encoding.
+const TURIN_ARK_SHA256: [u8; 32] = [
+    0x1f, 0x08, 0x41, 0x61, 0xa4, 0x4b, 0xb6, 0xd9, 0x37, 0x78, 0xa9, 0x04, 0x87, 0x7d, 0x48, 0x19,
+    0xca, 0xfa, 0x5d, 0x05, 0xef, 0x41, 0x93, 0xb2, 0xde, 0xd9, 0xdd, 0x9c, 0x73, 0xdd, 0x3f, 0x6a,
+];
+
+/// An AMD SEV-SNP product line, as named by the AMD Key Distribution Service (KDS).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum ProductLine {
+    /// 3rd generation EPYC.
+    Milan,
+
+    /// 4th generation EPYC.
+    #[default]
+    Genoa,
+
+    /// 5th generation EPYC.
+    ///
+    /// No report from Turin hardware is public, so Turin report verification is
+    /// tested against a report signed by a test chain rather than AMD's.
+    Turin,
+}
+
+impl ProductLine {
+    /// All supported product lines.
+    pub const ALL: [ProductLine; 3] = [ProductLine::Milan, ProductLine::Genoa, ProductLine::Turin];
+
+    /// The product name, as used in KDS URLs.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ProductLine::Milan => "Milan",
+            ProductLine::Genoa => "Genoa",
+            ProductLine::Turin => "Turin",
+        }
+    }
+
+    /// The SHA-256 fingerprint of the DER-encoded AMD Root Key (ARK) for this
+    /// product line.
+    ///
+    /// The embedded ARK (see [`ProductLine::embedded_pem`]) must have this
+    /// fingerprint, so the root of trust is pinned in source code rather than
+    /// to whichever certificate file the crate was built with.
+    pub fn ark_sha256(&self) -> [u8; 32] {
+        match self {
+            ProductLine::Milan => MILAN_ARK_SHA256,
+            ProductLine::Genoa => GENOA_ARK_SHA256,
+            ProductLine::Turin => TURIN_ARK_SHA256,
+        }
+    }
+
+    /// The embedded ARK and ASK certificates for this product line, as a PEM stack.
+    pub fn embedded_pem(&self) -> &'static [u8] {
+        match self {
+            ProductLine::Milan => MILAN_PEM,
+            ProductLine::Genoa => GENOA_PEM,
+            ProductLine::Turin => TURIN_PEM,
+        }
+    }
+
+    /// The product line of a CPU with the given CPUID family and model.
+    ///
+    /// Bergamo and Siena (family 19h, models A0h to AFh) share Genoa's keys.
+    fn from_cpuid(family: u8, model: u8) -> Option<Self> {
+        match (family, model) {
+            (0x19, 0x00..=0x0f) => Some(ProductLine::Milan),
+            (0x19, 0x10..=0x1f | 0xa0..=0xaf) => Some(ProductLine::Genoa),
+            (0x1a, 0x00..=0x1f) => Some(ProductLine::Turin),
+            _ => None,
+        }
+    }
+}
+
+/// Detects the product line of the chip that produced a report, so that it can
+/// be verified without knowing the hardware generation in advance.
+///
+/// The product line is read from the CPUID family and model in the report (from
+/// report version 3), and from the issuer of the VCEK, if given. Returns `None`
+/// if neither identifies a known product line, or if they disagree.
+pub fn detect_product(
+    report: &AttestationReport,
+    vcek: Option<&Certificate>,
+) -> Option<ProductLine> {
+    let from_report = match report.version {
+        3.. => {
+            let bytes = report_to_array(report);
+            ProductLine::from_cpuid(bytes[0x188], bytes[0x189])
+        }
+        _ => None,
+    };
+    let from_vcek = vcek.and_then(vcek_product);
+
+    match (from_report, from_vcek) {
+        (Some(a), Some(b)) if a != b => None,
+        (a, b) => a.or(b),
+    }
+}
+
+impl fmt::Display for ProductLine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl Serialize for ProductLine {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.name())
+    }
+}
+
+/// An error returned when parsing an unknown product name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseProductLineError(String);
+
+impl fmt::Display for ParseProductLineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "unknown product line '{}' (expected one of Milan, Genoa, Turin)",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for ParseProductLineError {}
+
+impl FromStr for ProductLine {
+    type Err = ParseProductLineError;
+
+    /// Parses a product name, ignoring case.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ProductLine::ALL
+            .into_iter()
+            .find(|product| product.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| ParseProductLineError(s.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::report::{parse_report_bytes, SAMPLE_ATTESTATION_MILAN, SAMPLE_VCEK_MILAN};
+    use crate::verify_attestation::{SAMPLE_ATTESTATION, SAMPLE_VCEK};
+
+    #[test]
+    fn test_product_line_round_trips() {
+        for product in ProductLine::ALL {
+            assert_eq!(product.to_string().parse::<ProductLine>(), Ok(product));
+        }
+        assert_eq!("genoa".parse::<ProductLine>(), Ok(ProductLine::Genoa));
+        assert!("Naples".parse::<ProductLine>().is_err());
+    }
+
+    #[test]
+    fn test_detect_product() {
+        let report = parse_report_bytes(SAMPLE_ATTESTATION_MILAN).unwrap();
+        let milan = Certificate::from_der(SAMPLE_VCEK_MILAN).unwrap();
+        let genoa = Certificate::from_der(SAMPLE_VCEK).unwrap();
+        assert_eq!(
+            detect_product(&report, Some(&milan)),
+            Some(ProductLine::Milan)
+        );
+
+        // A version 2 report carries no CPUID bytes
+        assert_eq!(detect_product(&report, None), None);
+
+        // A version 3 report names its CPU, which must agree with the VCEK
+        let mut bytes = report_to_array(&report);
+        bytes[0..4].copy_from_slice(&3u32.to_le_bytes());
+        bytes[0x188..0x18b].copy_from_slice(&[0x19, 0x11, 0x01]);
+        let report = parse_report_bytes(&bytes).unwrap();
+        assert_eq!(detect_product(&report, None), Some(ProductLine::Genoa));
+        assert_eq!(
+            detect_product(&report, Some(&genoa)),
+            Some(ProductLine::Genoa)
+        );
+        assert_eq!(detect_product(&report, Some(&milan)), None);
+
+        let report: AttestationReport = serde_json::from_str(SAMPLE_ATTESTATION).unwrap();
+        assert_eq!(
+            detect_product(&report, Some(&genoa)),
+            Some(ProductLine::Genoa)
+        );
+    }
+}
@@ -1,17 +1,39 @@
+use pyo3::exceptions::PyValueError;
 use pyo3::prelude::*;
+use sev::{certs::snp::Certificate, firmware::guest::AttestationReport};
 
-use crate::verify_attestation::verify_attestation_report as verify_attestation_report_raw;
-use crate::verify_attestation::{SAMPLE_ATTESTATION, SAMPLE_VCEK};
+use crate::policy::VerifyPolicy;
+use crate::product::ProductLine;
+use crate::verify_attestation::{tamper_report, verify_report, SAMPLE_ATTESTATION, SAMPLE_VCEK};
 
 /// Verifies an attestation report, using the provided report JSON string and VCEK bytes.
+///
+/// The product line ("Milan", "Genoa" or "Turin") defaults to Genoa.
+/// Verification intentionally fails if `fail_on_purpose` is true.
 #[pyfunction]
 pub fn verify_attestation_report(
     report_json: &str,
     vcek_bytes: &[u8],
     fail_on_purpose: Option<bool>,
+    product: Option<&str>,
 ) -> PyResult<()> {
-    verify_attestation_report_raw(report_json, vcek_bytes, fail_on_purpose.unwrap_or(false));
-    Ok(())
+    let product = match product {
+        Some(name) => name
+            .parse::<ProductLine>()
+            .map_err(|e| PyValueError::new_err(e.to_string()))?,
+        None => ProductLine::default(),
+    };
+
+    let mut report: AttestationReport =
+        serde_json::from_str(report_json).map_err(|e| PyValueError::new_err(e.to_string()))?;
+    let vcek =
+        Certificate::from_der(vcek_bytes).map_err(|e| PyValueError::new_err(e.to_string()))?;
+    if fail_on_purpose.unwrap_or(false) {
+        tamper_report(&mut report);
+    }
+
+    verify_report(&report, &vcek, product, &VerifyPolicy::default())
+        .map_err(|e| PyValueError::new_err(e.to_string()))
 }
 
 #[pymodule]
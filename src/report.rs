@@ -0,0 +1,1030 @@
+use std::fmt::{self, Write};
+use std::io::Read;
+use std::ops::{Range, RangeInclusive};
+use std::str::FromStr;
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::Serialize;
+use sev::firmware::guest::AttestationReport;
+
+use crate::policy::DecodedPolicy;
+use crate::report_data::ReportData;
+use crate::tcb::{report_tcb_components, tcb_to_string, SerializableTcb};
+use crate::verify_attestation::VerifyError;
+
+/// The size of an attestation report in its raw binary layout, in bytes.
+pub const REPORT_SIZE: usize = 0x4a0;
+
+/// The report versions whose layout this crate understands.
+///
+/// Versions after 2 only add fields in space that was reserved before, so the
+/// signed layout is the same.
+pub const SUPPORTED_REPORT_VERSIONS: RangeInclusive<u32> = 2..=5;
+
+/// The SIGNATURE_ALGO of a report signed with ECDSA P-384 with SHA-384, the only
+/// algorithm SEV-SNP firmware uses.
+pub const SIG_ALGO_ECDSA_P384_SHA384: u32 = 1;
+
+/// The byte range of a report's raw binary layout covered by its signature.
+///
+/// This is every field before the signature: offsets 0x000 to 0x29f, from VERSION
+/// through the reserved bytes following LAUNCH_TCB. The signature itself, at
+/// 0x2a0 to 0x49f, is not covered.
+pub const SIGNED_REGION: Range<usize> = 0x000..0x2a0;
+
+/// A sample Milan attestation report, in its raw binary layout.
+pub const SAMPLE_ATTESTATION_MILAN: &[u8] =
+    include_bytes!("../data/sample_attestation_report_milan.bin");
+
+/// The VCEK for [`SAMPLE_ATTESTATION_MILAN`], as bytes for a DER-encoded X509 certificate.
+pub const SAMPLE_VCEK_MILAN: &[u8] = include_bytes!("../data/sample_vcek_milan.crt");
+
+/// Parses an attestation report from its raw 1184-byte binary layout.
+///
+/// This is the layout returned by the `/dev/sev-guest` ioctl, with all fields
+/// little-endian and in the order of the AMD SEV-SNP ABI specification.
+pub fn parse_report_bytes(data: &[u8]) -> Result<AttestationReport, VerifyError> {
+    if data.len() != REPORT_SIZE {
+        return Err(VerifyError::ReportLength {
+            expected: REPORT_SIZE,
+            actual: data.len(),
+        });
+    }
+
+    bincode::deserialize(data).map_err(VerifyError::ReportDecode)
+}
+
+/// An encoding of an attestation report.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ReportFormat {
+    /// The JSON serialization of the sev crate's `AttestationReport`.
+    #[default]
+    Json,
+
+    /// The raw binary layout, base64-encoded.
+    Base64,
+
+    /// The raw binary layout, hex-encoded.
+    Hex,
+
+    /// The raw binary layout.
+    Binary,
+}
+
+impl ReportFormat {
+    /// All supported report formats.
+    pub const ALL: [ReportFormat; 4] = [
+        ReportFormat::Json,
+        ReportFormat::Base64,
+        ReportFormat::Hex,
+        ReportFormat::Binary,
+    ];
+
+    /// The name of the format, as accepted by [`ReportFormat::from_str`].
+    pub fn name(&self) -> &'static str {
+        match self {
+            ReportFormat::Json => "json",
+            ReportFormat::Base64 => "base64",
+            ReportFormat::Hex => "hex",
+            ReportFormat::Binary => "binary",
+        }
+    }
+
+    /// Guesses the format of an encoded report from its length and contents.
+    ///
+    /// Data exactly the size of a report is taken to be binary, as is data that
+    /// is not UTF-8, and text starting with `{` to be JSON. Other text is hex if
+    /// it is all hex digits of the right length, and otherwise base64.
+    pub fn detect(data: &[u8]) -> Self {
+        let text = match std::str::from_utf8(data) {
+            Ok(text) if data.len() != REPORT_SIZE => text.trim(),
+            _ => return ReportFormat::Binary,
+        };
+
+        if text.starts_with('{') {
+            ReportFormat::Json
+        } else if text.len() == 2 * REPORT_SIZE && text.bytes().all(|b| b.is_ascii_hexdigit()) {
+            ReportFormat::Hex
+        } else {
+            ReportFormat::Base64
+        }
+    }
+}
+
+impl fmt::Display for ReportFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// An error returned when parsing an unknown report format name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseReportFormatError(String);
+
+impl fmt::Display for ParseReportFormatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "unknown report format '{}' (expected one of json, base64, hex, binary)",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for ParseReportFormatError {}
+
+impl FromStr for ReportFormat {
+    type Err = ParseReportFormatError;
+
+    /// Parses a format name, ignoring case.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ReportFormat::ALL
+            .into_iter()
+            .find(|format| format.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| ParseReportFormatError(s.to_string()))
+    }
+}
+
+/// How byte fields are rendered in a report description.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ByteEncoding {
+    /// Lowercase hex, as KDS uses for hardware IDs.
+    #[default]
+    Hex,
+
+    /// Uppercase hex.
+    UpperHex,
+
+    /// Standard base64, with padding.
+    Base64,
+}
+
+impl ByteEncoding {
+    /// All supported byte encodings.
+    pub const ALL: [ByteEncoding; 3] = [
+        ByteEncoding::Hex,
+        ByteEncoding::UpperHex,
+        ByteEncoding::Base64,
+    ];
+
+    /// The name of the encoding, as accepted by [`ByteEncoding::from_str`].
+    pub fn name(&self) -> &'static str {
+        match self {
+            ByteEncoding::Hex => "hex",
+            ByteEncoding::UpperHex => "HEX",
+            ByteEncoding::Base64 => "base64",
+        }
+    }
+
+    /// Encodes bytes in this encoding.
+    pub fn encode(&self, data: &[u8]) -> String {
+        match self {
+            ByteEncoding::Hex => hex::encode(data),
+            ByteEncoding::UpperHex => hex::encode_upper(data),
+            ByteEncoding::Base64 => STANDARD.encode(data),
+        }
+    }
+}
+
+impl fmt::Display for ByteEncoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// An error returned when parsing an unknown byte encoding name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseByteEncodingError(String);
+
+impl fmt::Display for ParseByteEncodingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "unknown byte encoding '{}' (expected one of hex, HEX, base64)",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for ParseByteEncodingError {}
+
+impl FromStr for ByteEncoding {
+    type Err = ParseByteEncodingError;
+
+    /// Parses an encoding name. The case of `hex` picks the case of the digits,
+    /// and `base64` may be in any case.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "hex" => Ok(ByteEncoding::Hex),
+            "HEX" => Ok(ByteEncoding::UpperHex),
+            _ if s.eq_ignore_ascii_case("base64") => Ok(ByteEncoding::Base64),
+            _ => Err(ParseByteEncodingError(s.to_string())),
+        }
+    }
+}
+
+/// Parses an attestation report in the given format.
+///
+/// Text formats may be surrounded by whitespace, such as a trailing newline.
+pub fn parse_report(data: &[u8], format: ReportFormat) -> Result<AttestationReport, VerifyError> {
+    let text = || {
+        std::str::from_utf8(data).map_err(|e| VerifyError::ReportEncoding {
+            format,
+            reason: e.to_string(),
+        })
+    };
+
+    match format {
+        ReportFormat::Json => serde_json::from_slice(data).map_err(VerifyError::ReportParse),
+        ReportFormat::Base64 => parse_report_base64(text()?),
+        ReportFormat::Hex => parse_report_hex(text()?),
+        ReportFormat::Binary => parse_report_bytes(data),
+    }
+}
+
+/// Reads an attestation report in the given format, or (if `None`) in the
+/// format detected by [`ReportFormat::detect`].
+pub fn read_report(
+    reader: &mut dyn Read,
+    format: Option<ReportFormat>,
+) -> Result<AttestationReport, VerifyError> {
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data).map_err(VerifyError::Io)?;
+    parse_report(&data, format.unwrap_or_else(|| ReportFormat::detect(&data)))
+}
+
+/// Parses an attestation report from its raw binary layout, base64-encoded.
+pub fn parse_report_base64(s: &str) -> Result<AttestationReport, VerifyError> {
+    let data = STANDARD
+        .decode(s.trim())
+        .map_err(|e| VerifyError::ReportEncoding {
+            format: ReportFormat::Base64,
+            reason: e.to_string(),
+        })?;
+    parse_report_bytes(&data)
+}
+
+/// Parses an attestation report from its raw binary layout, hex-encoded.
+pub fn parse_report_hex(s: &str) -> Result<AttestationReport, VerifyError> {
+    let data = hex::decode(s.trim()).map_err(|e| VerifyError::ReportEncoding {
+        format: ReportFormat::Hex,
+        reason: e.to_string(),
+    })?;
+    parse_report_bytes(&data)
+}
+
+/// Serializes an attestation report to its raw 1184-byte binary layout.
+pub fn report_to_bytes(report: &AttestationReport) -> Vec<u8> {
+    report_to_array(report).to_vec()
+}
+
+/// Serializes an attestation report to its raw binary layout, without allocating.
+pub fn report_to_array(report: &AttestationReport) -> [u8; REPORT_SIZE] {
+    let mut bytes = [0; REPORT_SIZE];
+    bincode::serialize_into(&mut bytes[..], report).expect("attestation report is serializable");
+    bytes
+}
+
+/// The bytes of a report that its VCEK signs: the first 0x2a0 bytes of its raw
+/// binary layout (see [`SIGNED_REGION`]).
+///
+/// The signature is ECDSA P-384 over the SHA-384 digest of these bytes, so a
+/// report can be checked with any ECDSA implementation, such as an HSM's, given
+/// the VCEK's public key and the signature's R and S (each stored little-endian
+/// in the report).
+pub fn signed_region(report: &AttestationReport) -> Vec<u8> {
+    report_to_array(report)[SIGNED_REGION].to_vec()
+}
+
+/// The canonical JSON form of a report (see [`report_to_canonical_json`]).
+#[derive(Serialize)]
+struct CanonicalReport {
+    version: u32,
+    guest_svn: u32,
+    policy: u64,
+    family_id: String,
+    image_id: String,
+    vmpl: u32,
+    sig_algo: u32,
+    current_tcb: SerializableTcb,
+    plat_info: u64,
+    author_key_en: bool,
+    signing_key: u32,
+    report_data: String,
+    measurement: String,
+    host_data: String,
+    id_key_digest: String,
+    author_key_digest: String,
+    report_id: String,
+    report_id_ma: String,
+    reported_tcb: SerializableTcb,
+    chip_id: String,
+    committed_tcb: SerializableTcb,
+    current_build: u8,
+    current_minor: u8,
+    current_major: u8,
+    committed_build: u8,
+    committed_minor: u8,
+    committed_major: u8,
+    launch_tcb: SerializableTcb,
+    signature_r: String,
+    signature_s: String,
+}
+
+/// Serializes a report to JSON in a stable form, for logging, storing or diffing reports.
+///
+/// Unlike the sev crate's serialization, which may change between versions, the
+/// fields are always in the order of the report layout, byte arrays are
+/// lowercase hex, and TCB versions are [`SerializableTcb`]s. The KEY_INFO bits
+/// the sev crate keeps private are included as `author_key_en` and
+/// `signing_key`, while reserved fields, which must be zero, are left out.
+pub fn report_to_canonical_json(report: &AttestationReport) -> String {
+    let data = decode_report_data(report);
+
+    let canonical = CanonicalReport {
+        version: data.version,
+        guest_svn: data.guest_svn,
+        policy: data.policy,
+        family_id: hex::encode(data.family_id),
+        image_id: hex::encode(data.image_id),
+        vmpl: data.vmpl,
+        sig_algo: data.sig_algo,
+        current_tcb: data.current_tcb,
+        plat_info: data.plat_info,
+        author_key_en: data.author_key_en,
+        signing_key: data.signing_key,
+        report_data: hex::encode(data.report_data),
+        measurement: hex::encode(data.measurement),
+        host_data: hex::encode(data.host_data),
+        id_key_digest: hex::encode(data.id_key_digest),
+        author_key_digest: hex::encode(data.author_key_digest),
+        report_id: hex::encode(data.report_id),
+        report_id_ma: hex::encode(data.report_id_ma),
+        reported_tcb: data.reported_tcb,
+        chip_id: hex::encode(data.chip_id),
+        committed_tcb: data.committed_tcb,
+        current_build: data.current_build,
+        current_minor: data.current_minor,
+        current_major: data.current_major,
+        committed_build: data.committed_build,
+        committed_minor: data.committed_minor,
+        committed_major: data.committed_major,
+        launch_tcb: data.launch_tcb,
+        signature_r: hex::encode(data.signature_r),
+        signature_s: hex::encode(data.signature_s),
+    };
+    serde_json::to_string(&canonical).expect("canonical report is serializable")
+}
+
+/// Copies the fields of a report into a [`ReportData`].
+///
+/// This backs both [`report_to_canonical_json`] and, with the `report-data`
+/// feature, `ReportData`'s conversion from a report.
+pub(crate) fn decode_report_data(report: &AttestationReport) -> ReportData {
+    let bytes = report_to_array(report);
+    let u64_at = |offset: usize| u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap());
+
+    ReportData {
+        version: report.version,
+        guest_svn: report.guest_svn,
+        policy: u64_at(0x08),
+        family_id: report.family_id,
+        image_id: report.image_id,
+        vmpl: report.vmpl,
+        sig_algo: report.sig_algo,
+        current_tcb: report.current_tcb.into(),
+        plat_info: u64_at(0x40),
+        author_key_en: bytes[0x48] & 1 == 1,
+        signing_key: report_signing_key_id(report),
+        report_data: report.report_data,
+        measurement: report.measurement,
+        host_data: report.host_data,
+        id_key_digest: report.id_key_digest,
+        author_key_digest: report.author_key_digest,
+        report_id: report.report_id,
+        report_id_ma: report.report_id_ma,
+        reported_tcb: report.reported_tcb.into(),
+        chip_id: report.chip_id,
+        committed_tcb: report.committed_tcb.into(),
+        current_build: report.current_build,
+        current_minor: report.current_minor,
+        current_major: report.current_major,
+        committed_build: report.committed_build,
+        committed_minor: report.committed_minor,
+        committed_major: report.committed_major,
+        launch_tcb: report.launch_tcb.into(),
+        signature_r: *report.signature.r(),
+        signature_s: *report.signature.s(),
+    }
+}
+
+/// Checks that a report has a supported version and signature algorithm.
+///
+/// A report in an unknown layout could otherwise be misparsed, failing later
+/// checks in confusing ways (or, worse, passing them).
+pub fn check_report_format(report: &AttestationReport) -> Result<(), VerifyError> {
+    if !SUPPORTED_REPORT_VERSIONS.contains(&report.version) {
+        return Err(VerifyError::UnsupportedReportVersion {
+            version: report.version,
+        });
+    }
+    check_signature_algo(report)
+}
+
+/// Checks that a report is signed with ECDSA P-384 with SHA-384.
+pub(crate) fn check_signature_algo(report: &AttestationReport) -> Result<(), VerifyError> {
+    if report.sig_algo != SIG_ALGO_ECDSA_P384_SHA384 {
+        return Err(VerifyError::UnsupportedSignatureAlgo {
+            algo: report.sig_algo,
+        });
+    }
+
+    Ok(())
+}
+
+/// Whether a report's version is newer than any in [`SUPPORTED_REPORT_VERSIONS`].
+pub fn is_future_report_version(report: &AttestationReport) -> bool {
+    report.version > *SUPPORTED_REPORT_VERSIONS.end()
+}
+
+/// The byte ranges of a report that are reserved, and must be zero, in every
+/// supported version.
+const RESERVED_RANGES: [Range<usize>; 7] = [
+    0x4c..0x50,
+    0x18b..0x1a0,
+    0x1eb..0x1ec,
+    0x1ef..0x1f0,
+    0x208..0x2a0,
+    // The signature's R and S are 48 bytes each, zero-extended to 72
+    0x2d0..0x2e8,
+    0x318..0x4a0,
+];
+
+/// Bits 31:5 of KEY_INFO at offset 0x48, which are reserved.
+const KEY_INFO_RESERVED: u32 = !0x1f;
+
+/// Checks that the reserved fields of a report are zero.
+///
+/// Firmware zeroes them, so a nonzero byte means the report is corrupt or was
+/// crafted. This matters most past the signed region (from the signature on),
+/// where nothing else would notice data smuggled into the report. Fields that a
+/// later report version assigns (the CPUID bytes at 0x188, added in version 3,
+/// and the 16 bytes at 0x1f8) are required to be zero only in reports of
+/// version 2 and, for the latter, 3.
+pub fn check_reserved_fields(report: &AttestationReport) -> Result<(), VerifyError> {
+    let bytes = report_to_array(report);
+
+    let key_info = u32::from_le_bytes(bytes[0x48..0x4c].try_into().unwrap());
+    if key_info & KEY_INFO_RESERVED != 0 {
+        return Err(VerifyError::NonZeroReserved { offset: 0x48 });
+    }
+
+    let mut ranges = RESERVED_RANGES.to_vec();
+    if report.version < 3 {
+        ranges.push(0x188..0x18b);
+    }
+    if report.version < 4 {
+        ranges.push(0x1f8..0x208);
+    }
+    ranges.sort_by_key(|range| range.start);
+    for range in ranges {
+        if let Some(index) = bytes[range.clone()].iter().position(|&b| b != 0) {
+            return Err(VerifyError::NonZeroReserved {
+                offset: range.start + index,
+            });
+        }
+    }
+
+    Ok(())
+}
+
+/// The fields of a report's raw binary layout, by name and byte range, in order.
+///
+/// The names are those of the report's JSON form. Fields that later report
+/// versions assign in reserved space are covered by the reserved ranges.
+const FIELDS: [(&str, Range<usize>); 33] = [
+    ("version", 0x00..0x04),
+    ("guest_svn", 0x04..0x08),
+    ("policy", 0x08..0x10),
+    ("family_id", 0x10..0x20),
+    ("image_id", 0x20..0x30),
+    ("vmpl", 0x30..0x34),
+    ("sig_algo", 0x34..0x38),
+    ("current_tcb", 0x38..0x40),
+    ("plat_info", 0x40..0x48),
+    ("author_key_en", 0x48..0x4c),
+    ("reserved_0", 0x4c..0x50),
+    ("report_data", 0x50..0x90),
+    ("measurement", 0x90..0xc0),
+    ("host_data", 0xc0..0xe0),
+    ("id_key_digest", 0xe0..0x110),
+    ("author_key_digest", 0x110..0x140),
+    ("report_id", 0x140..0x160),
+    ("report_id_ma", 0x160..0x180),
+    ("reported_tcb", 0x180..0x188),
+    ("reserved_1", 0x188..0x1a0),
+    ("chip_id", 0x1a0..0x1e0),
+    ("committed_tcb", 0x1e0..0x1e8),
+    ("current_build", 0x1e8..0x1e9),
+    ("current_minor", 0x1e9..0x1ea),
+    ("current_major", 0x1ea..0x1eb),
+    ("reserved_2", 0x1eb..0x1ec),
+    ("committed_build", 0x1ec..0x1ed),
+    ("committed_minor", 0x1ed..0x1ee),
+    ("committed_major", 0x1ee..0x1ef),
+    ("reserved_3", 0x1ef..0x1f0),
+    ("launch_tcb", 0x1f0..0x1f8),
+    ("reserved_4", 0x1f8..0x2a0),
+    ("signature", 0x2a0..0x4a0),
+];
+
+/// A field that differs between two reports.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FieldDiff {
+    /// The name of the field, as in the report's JSON form.
+    pub field: &'static str,
+
+    /// The offset of the field in the report's raw binary layout.
+    pub offset: usize,
+
+    /// The field's bytes in the first report, as lowercase hex.
+    pub a: String,
+
+    /// The field's bytes in the second report, as lowercase hex.
+    pub b: String,
+}
+
+/// Compares two reports field by field, returning the fields that differ in
+/// layout order.
+///
+/// Every byte of the report is compared, including the reserved fields and the
+/// signature. Values are the field's bytes as laid out in the report, so
+/// integers are little-endian.
+pub fn diff_reports(a: &AttestationReport, b: &AttestationReport) -> Vec<FieldDiff> {
+    let (a, b) = (report_to_array(a), report_to_array(b));
+    FIELDS
+        .iter()
+        .filter(|(_, range)| a[range.clone()] != b[range.clone()])
+        .map(|(field, range)| FieldDiff {
+            field,
+            offset: range.start,
+            a: hex::encode(&a[range.clone()]),
+            b: hex::encode(&b[range.clone()]),
+        })
+        .collect()
+}
+
+/// The SIGNING_KEY field of a report, identifying the kind of key that signed it.
+///
+/// This is bits 4:2 of the 32-bit word at offset 0x48 of the report, which the
+/// sev crate keeps private: 0 for a VCEK, 1 for a VLEK, and 7 for no key.
+pub(crate) fn report_signing_key_id(report: &AttestationReport) -> u32 {
+    let bytes = report_to_array(report);
+    let word = u32::from_le_bytes(bytes[0x48..0x4c].try_into().unwrap());
+    (word >> 2) & 0b111
+}
+
+/// The decoded PLATFORM_INFO field of a report, describing the host platform.
+///
+/// The bits follow the AMD SEV-SNP ABI specification. sev 2.0.2 swaps the SMT
+/// and TSME bits in its own `PlatformInfo` getters, so this decodes the raw value.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct PlatformInfoFlags {
+    /// Whether SMT is enabled on the host.
+    pub smt_enabled: bool,
+
+    /// Whether Transparent SME is enabled on the host.
+    pub tsme_enabled: bool,
+
+    /// Whether the platform uses error-correcting memory.
+    pub ecc_enabled: bool,
+
+    /// Whether Running Average Power Limit (RAPL) is disabled.
+    pub rapl_disabled: bool,
+
+    /// Whether ciphertext hiding is enabled.
+    pub ciphertext_hiding_enabled: bool,
+
+    /// Whether alias checking has completed since the last firmware update.
+    pub alias_check_complete: bool,
+}
+
+impl PlatformInfoFlags {
+    /// Decodes the raw 64-bit PLATFORM_INFO value.
+    pub fn from_bits(bits: u64) -> Self {
+        let bit = |n: u32| bits & (1 << n) != 0;
+        Self {
+            smt_enabled: bit(0),
+            tsme_enabled: bit(1),
+            ecc_enabled: bit(2),
+            rapl_disabled: bit(3),
+            ciphertext_hiding_enabled: bit(4),
+            alias_check_complete: bit(5),
+        }
+    }
+}
+
+/// Decodes the PLATFORM_INFO field of a report, at offset 0x40.
+pub fn decode_plat_info(report: &AttestationReport) -> PlatformInfoFlags {
+    let bytes = report_to_array(report);
+    PlatformInfoFlags::from_bits(u64::from_le_bytes(bytes[0x40..0x48].try_into().unwrap()))
+}
+
+/// Decodes the guest policy of a report into its named flags.
+pub fn decode_policy(report: &AttestationReport) -> DecodedPolicy {
+    DecodedPolicy::from(report.policy)
+}
+
+/// Renders the fields of a report an operator typically reviews as a readable block.
+///
+/// Byte fields are rendered as lowercase hex, and the guest policy is decoded
+/// into its flags.
+pub fn describe_report(report: &AttestationReport) -> String {
+    describe_report_encoded(report, ByteEncoding::Hex)
+}
+
+/// Renders a report as [`describe_report`] does, with byte fields in the given encoding.
+pub fn describe_report_encoded(report: &AttestationReport, encoding: ByteEncoding) -> String {
+    let policy = decode_policy(report);
+    let mut out = String::new();
+
+    // Writing to a String cannot fail
+    let mut line = |name: &str, value: &dyn std::fmt::Display| {
+        writeln!(out, "{name:<18}{value}").unwrap();
+    };
+    line("version:", &report.version);
+    line("guest_svn:", &report.guest_svn);
+    line("vmpl:", &report.vmpl);
+    line(
+        "policy:",
+        &format_args!("ABI {}.{}", policy.abi_major, policy.abi_minor),
+    );
+    line("  smt:", &allowed(policy.smt_allowed));
+    line("  migrate_ma:", &allowed(policy.migrate_ma_allowed));
+    line("  debug:", &allowed(policy.debug_allowed));
+    line(
+        "  single_socket:",
+        &if policy.single_socket_required {
+            "required"
+        } else {
+            "not required"
+        },
+    );
+    line("reported_tcb:", &tcb_to_string(&report.reported_tcb));
+    for (component, svn) in report_tcb_components(report, &report.reported_tcb) {
+        line(&format!("  {component}:"), &svn);
+    }
+    line("current_tcb:", &tcb_to_string(&report.current_tcb));
+    line("committed_tcb:", &tcb_to_string(&report.committed_tcb));
+    line("launch_tcb:", &tcb_to_string(&report.launch_tcb));
+    line("measurement:", &encoding.encode(&report.measurement));
+    line("report_data:", &encoding.encode(&report.report_data));
+    line("host_data:", &encoding.encode(&report.host_data));
+    line("report_id:", &encoding.encode(&report.report_id));
+    line("chip_id:", &encoding.encode(&report.chip_id));
+
+    out
+}
+
+fn allowed(allowed: bool) -> &'static str {
+    if allowed {
+        "allowed"
+    } else {
+        "not allowed"
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::verify_attestation::SAMPLE_ATTESTATION;
+
+    #[test]
+    fn test_report_bytes_round_trip() {
+        let report: AttestationReport = serde_json::from_str(SAMPLE_ATTESTATION).unwrap();
+        let bytes = report_to_bytes(&report);
+        assert_eq!(bytes.len(), REPORT_SIZE);
+
+        let parsed = parse_report_bytes(&bytes).unwrap();
+        assert_eq!(report_to_bytes(&parsed), bytes);
+        assert_eq!(parsed.measurement, report.measurement);
+    }
+
+    #[test]
+    fn test_signed_region() {
+        let report: AttestationReport = serde_json::from_str(SAMPLE_ATTESTATION).unwrap();
+        let signed = signed_region(&report);
+        assert_eq!(signed.len(), 0x2a0);
+        assert_eq!(signed, report_to_bytes(&report)[..0x2a0]);
+
+        // The region ends where the signature starts
+        let mut resigned = report;
+        resigned.signature = Default::default();
+        assert_eq!(signed_region(&resigned), signed);
+        resigned.launch_tcb.microcode ^= 1;
+        assert_ne!(signed_region(&resigned), signed);
+    }
+
+    #[test]
+    fn test_report_to_canonical_json() {
+        let report: AttestationReport = serde_json::from_str(SAMPLE_ATTESTATION).unwrap();
+        let json = report_to_canonical_json(&report);
+        assert!(json.starts_with(r#"{"version":2,"guest_svn":0,"policy":196608,"#));
+        assert!(json.contains(&format!(r#""report_data":"{}""#, "07".repeat(64))));
+        assert!(json.contains(&format!(
+            r#""measurement":"{}""#,
+            hex::encode(report.measurement)
+        )));
+        assert!(json.contains(r#""reported_tcb":{"bootloader":7,"tee":0,"snp":11,"microcode":62}"#));
+        assert!(!json.contains("reserved"));
+
+        // The same report always serializes the same way, however it was parsed
+        let parsed = parse_report_bytes(&report_to_bytes(&report)).unwrap();
+        assert_eq!(report_to_canonical_json(&parsed), json);
+
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value["signing_key"], 0);
+        assert_eq!(value["signature_r"].as_str().unwrap().len(), 144);
+    }
+
+    #[test]
+    fn test_parse_report_bytes_rejects_bad_length() {
+        let result = parse_report_bytes(&SAMPLE_ATTESTATION_MILAN[..REPORT_SIZE - 1]);
+        assert!(matches!(
+            result,
+            Err(VerifyError::ReportLength {
+                expected: REPORT_SIZE,
+                actual: 1183
+            })
+        ));
+        assert!(parse_report_bytes(&[]).is_err());
+    }
+
+    #[test]
+    fn test_parse_sample_milan_report() {
+        let report = parse_report_bytes(SAMPLE_ATTESTATION_MILAN).unwrap();
+        assert_eq!(report.version, 2);
+        assert_eq!(report_signing_key_id(&report), 0);
+    }
+
+    #[test]
+    fn test_parse_report_formats() {
+        let report = parse_report_bytes(SAMPLE_ATTESTATION_MILAN).unwrap();
+        let base64 = format!("{}\n", STANDARD.encode(SAMPLE_ATTESTATION_MILAN));
+        let hex = hex::encode(SAMPLE_ATTESTATION_MILAN);
+
+        for (data, format) in [
+            (SAMPLE_ATTESTATION_MILAN, ReportFormat::Binary),
+            (base64.as_bytes(), ReportFormat::Base64),
+            (hex.as_bytes(), ReportFormat::Hex),
+        ] {
+            let parsed = parse_report(data, format).unwrap();
+            assert_eq!(report_to_bytes(&parsed), report_to_bytes(&report));
+        }
+
+        let parsed = parse_report(SAMPLE_ATTESTATION.as_bytes(), ReportFormat::Json).unwrap();
+        assert_eq!(parsed.version, 2);
+    }
+
+    #[test]
+    fn test_detect_report_format() {
+        let base64 = format!("{}\n", STANDARD.encode(SAMPLE_ATTESTATION_MILAN));
+        let hex = format!("{}\n", hex::encode(SAMPLE_ATTESTATION_MILAN));
+        let json = format!("\n{SAMPLE_ATTESTATION}");
+
+        for (data, format) in [
+            (SAMPLE_ATTESTATION_MILAN, ReportFormat::Binary),
+            (base64.as_bytes(), ReportFormat::Base64),
+            (hex.as_bytes(), ReportFormat::Hex),
+            (json.as_bytes(), ReportFormat::Json),
+        ] {
+            assert_eq!(ReportFormat::detect(data), format);
+            let parsed = read_report(&mut &data[..], None).unwrap();
+            let expected = parse_report(data, format).unwrap();
+            assert_eq!(report_to_bytes(&parsed), report_to_bytes(&expected));
+        }
+
+        // A truncated binary report is still detected as binary
+        assert_eq!(
+            ReportFormat::detect(&[0xff; REPORT_SIZE - 1]),
+            ReportFormat::Binary
+        );
+        assert!(matches!(
+            read_report(&mut &SAMPLE_ATTESTATION_MILAN[..], Some(ReportFormat::Json)),
+            Err(VerifyError::ReportParse(_))
+        ));
+    }
+
+    #[test]
+    fn test_malformed_reports_are_errors() {
+        // Truncated reports, in every format, fail without panicking
+        for len in [0, 1, 100, REPORT_SIZE - 1] {
+            let data = &SAMPLE_ATTESTATION_MILAN[..len];
+            assert!(read_report(&mut &data[..], None).is_err());
+            assert!(parse_report(data, ReportFormat::Json).is_err());
+            assert!(
+                parse_report(&SAMPLE_ATTESTATION.as_bytes()[..len], ReportFormat::Json).is_err()
+            );
+        }
+
+        // Any bytes of the right length decode, and round-trip
+        for byte in [0x00, 0x80, 0xff] {
+            let data = [byte; REPORT_SIZE];
+            let report = parse_report_bytes(&data).unwrap();
+            assert_eq!(report_to_bytes(&report), data);
+        }
+
+        assert!(parse_report(b"{\"version\": -1}", ReportFormat::Json).is_err());
+        assert!(parse_report(&[0xff; 10], ReportFormat::Json).is_err());
+    }
+
+    #[test]
+    fn test_parse_report_formats_reject_bad_input() {
+        assert!(matches!(
+            parse_report_base64("not base64!"),
+            Err(VerifyError::ReportEncoding {
+                format: ReportFormat::Base64,
+                ..
+            })
+        ));
+        assert!(matches!(
+            parse_report_hex("abc"),
+            Err(VerifyError::ReportEncoding {
+                format: ReportFormat::Hex,
+                ..
+            })
+        ));
+        // Decoded input must still be a whole report
+        assert!(matches!(
+            parse_report_hex("abcd"),
+            Err(VerifyError::ReportLength { actual: 2, .. })
+        ));
+        assert!(matches!(
+            parse_report(&[0xff], ReportFormat::Base64),
+            Err(VerifyError::ReportEncoding { .. })
+        ));
+
+        assert_eq!("HEX".parse::<ReportFormat>(), Ok(ReportFormat::Hex));
+        assert!("yaml".parse::<ReportFormat>().is_err());
+    }
+
+    #[test]
+    fn test_check_report_format() {
+        let report: AttestationReport = serde_json::from_str(SAMPLE_ATTESTATION).unwrap();
+        assert!(check_report_format(&report).is_ok());
+        assert!(
+            check_report_format(&parse_report_bytes(SAMPLE_ATTESTATION_MILAN).unwrap()).is_ok()
+        );
+
+        let mut future = report;
+        future.version = 6;
+        assert!(matches!(
+            check_report_format(&future),
+            Err(VerifyError::UnsupportedReportVersion { version: 6 })
+        ));
+
+        let mut unsigned = report;
+        unsigned.sig_algo = 0;
+        assert!(matches!(
+            check_report_format(&unsigned),
+            Err(VerifyError::UnsupportedSignatureAlgo { algo: 0 })
+        ));
+    }
+
+    #[test]
+    fn test_check_reserved_fields() {
+        let report: AttestationReport = serde_json::from_str(SAMPLE_ATTESTATION).unwrap();
+        assert!(check_reserved_fields(&report).is_ok());
+        assert!(
+            check_reserved_fields(&parse_report_bytes(SAMPLE_ATTESTATION_MILAN).unwrap()).is_ok()
+        );
+
+        // Past the signature, data is not covered by the signature
+        for offset in [0x4c, 0x19f, 0x2d0, 0x400, 0x49f] {
+            let mut bytes = report_to_array(&report);
+            bytes[offset] = 1;
+            assert!(matches!(
+                check_reserved_fields(&parse_report_bytes(&bytes).unwrap()),
+                Err(VerifyError::NonZeroReserved { offset: o }) if o == offset
+            ));
+        }
+
+        let mut bytes = report_to_array(&report);
+        bytes[0x48] |= 0x20;
+        assert!(matches!(
+            check_reserved_fields(&parse_report_bytes(&bytes).unwrap()),
+            Err(VerifyError::NonZeroReserved { offset: 0x48 })
+        ));
+
+        // The CPUID bytes are only reserved before version 3
+        let mut bytes = report_to_array(&report);
+        bytes[0x188] = 0x19;
+        let v2 = parse_report_bytes(&bytes).unwrap();
+        assert!(check_reserved_fields(&v2).is_err());
+        let mut v3 = v2;
+        v3.version = 3;
+        assert!(check_reserved_fields(&v3).is_ok());
+    }
+
+    #[test]
+    fn test_diff_reports() {
+        let report: AttestationReport = serde_json::from_str(SAMPLE_ATTESTATION).unwrap();
+        assert!(diff_reports(&report, &report).is_empty());
+
+        // The fields tile the layout, with no gaps or overlaps
+        let mut end = 0;
+        for (_, range) in &FIELDS {
+            assert_eq!(range.start, end);
+            end = range.end;
+        }
+        assert_eq!(end, REPORT_SIZE);
+
+        let mut changed = report;
+        changed.measurement[47] ^= 1;
+        changed.vmpl = 3;
+        let diffs = diff_reports(&report, &changed);
+        assert_eq!(diffs.len(), 2);
+        assert_eq!(
+            diffs[0],
+            FieldDiff {
+                field: "vmpl",
+                offset: 0x30,
+                a: "01000000".to_string(),
+                b: "03000000".to_string(),
+            }
+        );
+        assert_eq!(diffs[1].field, "measurement");
+        assert_eq!(diffs[1].a, hex::encode(report.measurement));
+        assert_eq!(diffs[1].b, hex::encode(changed.measurement));
+    }
+
+    #[test]
+    fn test_decode_plat_info() {
+        // The sample report's platform has SMT enabled
+        let report: AttestationReport = serde_json::from_str(SAMPLE_ATTESTATION).unwrap();
+        assert_eq!(
+            decode_plat_info(&report),
+            PlatformInfoFlags {
+                smt_enabled: true,
+                ..Default::default()
+            }
+        );
+        assert_eq!(decode_policy(&report), DecodedPolicy::from_bits(0x30000));
+
+        let flags: [fn(&PlatformInfoFlags) -> bool; 6] = [
+            |p| p.smt_enabled,
+            |p| p.tsme_enabled,
+            |p| p.ecc_enabled,
+            |p| p.rapl_disabled,
+            |p| p.ciphertext_hiding_enabled,
+            |p| p.alias_check_complete,
+        ];
+        for (bit, flag) in flags.iter().enumerate() {
+            let info = PlatformInfoFlags::from_bits(1 << bit);
+            for (other, flag) in flags.iter().enumerate() {
+                assert_eq!(flag(&info), other == bit, "bit {bit} sets bit {other}");
+            }
+            assert!(flag(&info));
+        }
+        assert_eq!(PlatformInfoFlags::from_bits(1 << 6), Default::default());
+    }
+
+    #[test]
+    fn test_describe_report() {
+        let report: AttestationReport = serde_json::from_str(SAMPLE_ATTESTATION).unwrap();
+        let description = describe_report(&report);
+
+        assert!(description.contains("version:          2\n"));
+        assert!(description.contains("reported_tcb:     7.0.11.62\n"));
+        assert!(description.contains("  microcode:      62\n"));
+        assert!(description.contains("  debug:          not allowed\n"));
+        assert!(description.contains(&format!(
+            "measurement:      {}\n",
+            hex::encode(report.measurement)
+        )));
+        assert!(description.contains(&format!("report_data:      {}\n", "07".repeat(64))));
+    }
+
+    #[test]
+    fn test_describe_report_encoded() {
+        let report: AttestationReport = serde_json::from_str(SAMPLE_ATTESTATION).unwrap();
+
+        let upper = describe_report_encoded(&report, "HEX".parse().unwrap());
+        assert!(upper.contains(&format!(
+            "chip_id:          {}\n",
+            hex::encode_upper(report.chip_id)
+        )));
+        let base64 = describe_report_encoded(&report, "base64".parse().unwrap());
+        assert!(base64.contains(&format!("report_data:      {}\n", STANDARD.encode([7; 64]))));
+        assert_eq!(
+            describe_report_encoded(&report, ByteEncoding::default()),
+            describe_report(&report)
+        );
+
+        for encoding in ByteEncoding::ALL {
+            assert_eq!(encoding.name().parse(), Ok(encoding));
+        }
+        assert!("Hex".parse::<ByteEncoding>().is_err());
+    }
+}
@@ -0,0 +1,164 @@
+//! The fields of an attestation report as plain data, independent of the sev crate.
+//!
+//! [`ReportData`] holds owned copies of every field of a report, using only
+//! standard types and [`SerializableTcb`], so code that stores or passes report
+//! fields around need not name sev's types nor follow changes to them. The
+//! KEY_INFO bits that sev keeps private are decoded into `author_key_en` and
+//! `signing_key`; the reserved fields, which must be zero, are left out.
+//!
+//! Converting from sev's [`AttestationReport`] needs the `report-data` feature.
+//!
+//! [`AttestationReport`]: sev::firmware::guest::AttestationReport
+
+#[cfg(feature = "report-data")]
+use sev::firmware::guest::AttestationReport;
+
+#[cfg(feature = "report-data")]
+use crate::report::{decode_report_data, parse_report_bytes};
+use crate::tcb::SerializableTcb;
+#[cfg(feature = "report-data")]
+use crate::verify_attestation::VerifyError;
+
+/// The fields of an attestation report, in the order of the report layout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ReportData {
+    /// The version of the report format.
+    pub version: u32,
+
+    /// The guest's security version number.
+    pub guest_svn: u32,
+
+    /// The guest policy, as its raw 64-bit value.
+    pub policy: u64,
+
+    /// The family ID set in the guest's ID block.
+    pub family_id: [u8; 16],
+
+    /// The image ID set in the guest's ID block.
+    pub image_id: [u8; 16],
+
+    /// The VMPL the report was requested from.
+    pub vmpl: u32,
+
+    /// The algorithm the report is signed with.
+    pub sig_algo: u32,
+
+    /// The platform's current TCB.
+    pub current_tcb: SerializableTcb,
+
+    /// The platform information, as its raw 64-bit value.
+    pub plat_info: u64,
+
+    /// Whether the digest of the author key is included in the report.
+    pub author_key_en: bool,
+
+    /// The kind of key that signed the report: 0 for a VCEK, 1 for a VLEK, and 7
+    /// for no key.
+    pub signing_key: u32,
+
+    /// The guest-provided report data, typically a nonce.
+    pub report_data: [u8; 64],
+
+    /// The launch measurement of the guest.
+    pub measurement: [u8; 48],
+
+    /// The hypervisor-provided host data.
+    pub host_data: [u8; 32],
+
+    /// The SHA-384 digest of the ID key that signed the ID block.
+    pub id_key_digest: [u8; 48],
+
+    /// The SHA-384 digest of the author key that signed the ID key.
+    pub author_key_digest: [u8; 48],
+
+    /// The report ID of the guest.
+    pub report_id: [u8; 32],
+
+    /// The report ID of the guest's migration agent.
+    pub report_id_ma: [u8; 32],
+
+    /// The TCB the report was signed under.
+    pub reported_tcb: SerializableTcb,
+
+    /// The ID of the chip that produced the report.
+    pub chip_id: [u8; 64],
+
+    /// The TCB the platform has committed to.
+    pub committed_tcb: SerializableTcb,
+
+    /// The build number of the current firmware.
+    pub current_build: u8,
+
+    /// The minor version of the current firmware.
+    pub current_minor: u8,
+
+    /// The major version of the current firmware.
+    pub current_major: u8,
+
+    /// The build number of the committed firmware.
+    pub committed_build: u8,
+
+    /// The minor version of the committed firmware.
+    pub committed_minor: u8,
+
+    /// The major version of the committed firmware.
+    pub committed_major: u8,
+
+    /// The TCB of the platform when the guest was launched.
+    pub launch_tcb: SerializableTcb,
+
+    /// The R component of the signature, little-endian and zero-extended.
+    pub signature_r: [u8; 72],
+
+    /// The S component of the signature, little-endian and zero-extended.
+    pub signature_s: [u8; 72],
+}
+
+#[cfg(feature = "report-data")]
+impl ReportData {
+    /// Decodes the fields of a report in its raw 1184-byte binary layout.
+    pub fn from_bytes(data: &[u8]) -> Result<Self, VerifyError> {
+        parse_report_bytes(data).map(|report| Self::from(&report))
+    }
+}
+
+#[cfg(feature = "report-data")]
+impl From<&AttestationReport> for ReportData {
+    fn from(report: &AttestationReport) -> Self {
+        decode_report_data(report)
+    }
+}
+
+#[cfg(all(test, feature = "report-data"))]
+mod test {
+    use super::*;
+
+    use crate::report::report_to_bytes;
+    use crate::verify_attestation::SAMPLE_ATTESTATION;
+
+    #[test]
+    fn test_report_data_from_report() {
+        let report: AttestationReport = serde_json::from_str(SAMPLE_ATTESTATION).unwrap();
+        let data = ReportData::from(&report);
+        assert_eq!(data.version, 2);
+        assert_eq!(data.policy, 0x30000);
+        assert_eq!(data.vmpl, report.vmpl);
+        assert_eq!(data.measurement, report.measurement);
+        assert_eq!(data.chip_id, report.chip_id);
+        assert_eq!(
+            data.reported_tcb,
+            SerializableTcb::from(report.reported_tcb)
+        );
+        assert_eq!(data.signing_key, 0);
+        assert_eq!(&data.signature_r, report.signature.r());
+
+        assert_eq!(
+            ReportData::from_bytes(&report_to_bytes(&report)).unwrap(),
+            data
+        );
+        assert!(matches!(
+            ReportData::from_bytes(&[0; 16]),
+            Err(VerifyError::ReportLength { .. })
+        ));
+    }
+}
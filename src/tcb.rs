@@ -0,0 +1,352 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+use sev::firmware::{guest::AttestationReport, host::TcbVersion};
+
+use crate::product::{detect_product, ProductLine};
+use crate::vcek::TcbSpl;
+use crate::verify_attestation::VerifyError;
+
+/// A component of a TCB version, each of which carries its own security version number.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TcbComponent {
+    /// The PSP bootloader.
+    Bootloader,
+
+    /// The PSP operating system.
+    Tee,
+
+    /// The SNP firmware.
+    Snp,
+
+    /// The lowest patch level of all the cores.
+    Microcode,
+
+    /// The firmware mask ROM, from Turin on.
+    Fmc,
+}
+
+impl fmt::Display for TcbComponent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            TcbComponent::Bootloader => "bootloader",
+            TcbComponent::Tee => "tee",
+            TcbComponent::Snp => "snp",
+            TcbComponent::Microcode => "microcode",
+            TcbComponent::Fmc => "fmc",
+        })
+    }
+}
+
+/// The components of a TCB version, paired with their security version numbers.
+pub fn tcb_components(tcb: &TcbVersion) -> [(TcbComponent, u8); 4] {
+    [
+        (TcbComponent::Bootloader, tcb.bootloader),
+        (TcbComponent::Tee, tcb.tee),
+        (TcbComponent::Snp, tcb.snp),
+        (TcbComponent::Microcode, tcb.microcode),
+    ]
+}
+
+/// Decodes one of a report's TCB versions, in the layout of the product line
+/// the report is from: from Turin, it carries an FMC SPL and its fields move.
+pub fn report_tcb_spl(report: &AttestationReport, tcb: &TcbVersion) -> TcbSpl {
+    let turin = detect_product(report, None) == Some(ProductLine::Turin);
+    TcbSpl::from_tcb(tcb, turin)
+}
+
+/// The components of one of a report's TCB versions, decoded as by
+/// [`report_tcb_spl`], paired with their security version numbers.
+///
+/// The components are in the order of [`tcb_components`], followed by the FMC
+/// from Turin on.
+pub fn report_tcb_components(
+    report: &AttestationReport,
+    tcb: &TcbVersion,
+) -> Vec<(TcbComponent, u8)> {
+    let spl = report_tcb_spl(report, tcb);
+    let mut components = vec![
+        (TcbComponent::Bootloader, spl.bootloader),
+        (TcbComponent::Tee, spl.tee),
+        (TcbComponent::Snp, spl.snp),
+        (TcbComponent::Microcode, spl.microcode),
+    ];
+    components.extend(spl.fmc.map(|fmc| (TcbComponent::Fmc, fmc)));
+    components
+}
+
+/// Formats a TCB version compactly, as "bootloader.tee.snp.microcode".
+pub fn tcb_to_string(tcb: &TcbVersion) -> String {
+    format!(
+        "{}.{}.{}.{}",
+        tcb.bootloader, tcb.tee, tcb.snp, tcb.microcode
+    )
+}
+
+/// An error returned when parsing a malformed TCB version.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseTcbError(String);
+
+impl fmt::Display for ParseTcbError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid TCB version '{}' (expected bootloader.tee.snp.microcode)",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for ParseTcbError {}
+
+/// Parses a TCB version formatted as by [`tcb_to_string`], such as "7.0.11.62".
+pub fn tcb_from_string(s: &str) -> Result<TcbVersion, ParseTcbError> {
+    let error = || ParseTcbError(s.to_string());
+
+    let mut svns = s
+        .split('.')
+        .map(|svn| svn.parse::<u8>().map_err(|_| error()));
+    let mut next = || svns.next().unwrap_or_else(|| Err(error()));
+    let tcb = TcbVersion::new(next()?, next()?, next()?, next()?);
+    if svns.next().is_some() {
+        return Err(error());
+    }
+
+    Ok(tcb)
+}
+
+/// Packs a TCB version into the 64-bit value firmware uses, as in the
+/// REPORTED_TCB field of a report.
+///
+/// This is the layout before Turin: the bootloader SVN in the lowest byte, then
+/// the TEE SVN, four reserved bytes, the SNP SVN and the microcode SVN.
+pub fn tcb_to_u64(tcb: &TcbVersion) -> u64 {
+    let bytes = bincode::serialize(tcb).expect("TCB version is serializable");
+    u64::from_le_bytes(bytes.try_into().expect("TCB version is 8 bytes"))
+}
+
+/// Unpacks a TCB version from the 64-bit value firmware uses (see [`tcb_to_u64`]).
+pub fn tcb_from_u64(raw: u64) -> TcbVersion {
+    bincode::deserialize(&raw.to_le_bytes()).expect("any 8 bytes are a TCB version")
+}
+
+/// A TCB version with a stable serialized form, independent of the sev crate's.
+///
+/// This serializes as `{"bootloader":7,"tee":0,"snp":11,"microcode":62}`, so it
+/// suits TCB versions stored apart from their report, as in a cache index.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub struct SerializableTcb {
+    /// The SVN of the PSP bootloader.
+    pub bootloader: u8,
+
+    /// The SVN of the PSP operating system.
+    pub tee: u8,
+
+    /// The SVN of the SNP firmware.
+    pub snp: u8,
+
+    /// The lowest microcode patch level of all the cores.
+    pub microcode: u8,
+}
+
+impl From<TcbVersion> for SerializableTcb {
+    fn from(tcb: TcbVersion) -> Self {
+        SerializableTcb {
+            bootloader: tcb.bootloader,
+            tee: tcb.tee,
+            snp: tcb.snp,
+            microcode: tcb.microcode,
+        }
+    }
+}
+
+impl From<SerializableTcb> for TcbVersion {
+    fn from(tcb: SerializableTcb) -> Self {
+        TcbVersion::new(tcb.bootloader, tcb.tee, tcb.snp, tcb.microcode)
+    }
+}
+
+/// Formats the SPL query parameters identifying a TCB version to KDS, as in
+/// "blSPL=07&teeSPL=00&snpSPL=11&ucodeSPL=62", with a leading fmcSPL from Turin on.
+pub(crate) fn tcb_to_kds_query(spl: &TcbSpl) -> String {
+    let fmc = spl
+        .fmc
+        .map(|fmc| format!("fmcSPL={fmc:02}&"))
+        .unwrap_or_default();
+    format!(
+        "{fmc}blSPL={:02}&teeSPL={:02}&snpSPL={:02}&ucodeSPL={:02}",
+        spl.bootloader, spl.tee, spl.snp, spl.microcode,
+    )
+}
+
+/// Checks that the report's TCB meets a minimum patch baseline.
+///
+/// Each component of `report.reported_tcb` must meet or exceed the same
+/// component of `minimum`, both decoded as by [`report_tcb_spl`]. A report whose reported
+/// TCB is lower than its committed TCB is rejected as a rollback (see
+/// [`check_tcb_rollback`]), since the VCEK it is signed with may belong to
+/// vulnerable firmware.
+pub fn check_tcb(report: &AttestationReport, minimum: &TcbVersion) -> Result<(), VerifyError> {
+    check_tcb_rollback(report)?;
+
+    let minimum = report_tcb_components(report, minimum);
+    let actual = report_tcb_components(report, &report.reported_tcb);
+    for ((component, minimum), (_, actual)) in minimum.into_iter().zip(actual) {
+        if actual < minimum {
+            return Err(VerifyError::TcbBelowMinimum {
+                component,
+                minimum,
+                actual,
+            });
+        }
+    }
+
+    Ok(())
+}
+
+/// Checks that no component of the report's reported TCB is lower than its committed TCB.
+///
+/// The committed TCB is the lowest TCB the firmware can be rolled back to, so a
+/// reported TCB below it indicates a rollback to older, possibly vulnerable, firmware.
+pub fn check_tcb_rollback(report: &AttestationReport) -> Result<(), VerifyError> {
+    let reported = report_tcb_components(report, &report.reported_tcb);
+    let committed = report_tcb_components(report, &report.committed_tcb);
+
+    if reported
+        .iter()
+        .zip(committed.iter())
+        .any(|((_, reported), (_, committed))| reported < committed)
+    {
+        return Err(VerifyError::TcbRollback {
+            reported: report.reported_tcb,
+            committed: report.committed_tcb,
+        });
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::report::parse_report_bytes;
+    use crate::verify_attestation::{SAMPLE_ATTESTATION, TEST_TURIN_REPORT};
+
+    #[test]
+    fn test_tcb_string_round_trip() {
+        let tcb = TcbVersion::new(7, 0, 11, 62);
+        assert_eq!(tcb_to_string(&tcb), "7.0.11.62");
+        assert_eq!(tcb_from_string("7.0.11.62"), Ok(tcb));
+
+        for s in [
+            "",
+            "7.0.11",
+            "7.0.11.62.1",
+            "7.0.11.256",
+            "7.0.x.62",
+            "7..11.62",
+        ] {
+            assert_eq!(tcb_from_string(s), Err(ParseTcbError(s.to_string())));
+        }
+    }
+
+    #[test]
+    fn test_tcb_u64_round_trip() {
+        let report: AttestationReport = serde_json::from_str(SAMPLE_ATTESTATION).unwrap();
+        let raw = tcb_to_u64(&report.reported_tcb);
+        assert_eq!(raw, 0x3e0b_0000_0000_0007);
+        assert_eq!(tcb_from_u64(raw), report.reported_tcb);
+        assert_eq!(
+            tcb_from_u64(0x0102_0000_0000_0304),
+            TcbVersion::new(4, 3, 2, 1)
+        );
+    }
+
+    #[test]
+    fn test_serializable_tcb_round_trip() {
+        let tcb = TcbVersion::new(7, 0, 11, 62);
+        let json = serde_json::to_string(&SerializableTcb::from(tcb)).unwrap();
+        assert_eq!(json, r#"{"bootloader":7,"tee":0,"snp":11,"microcode":62}"#);
+
+        let parsed: SerializableTcb = serde_json::from_str(&json).unwrap();
+        assert_eq!(TcbVersion::from(parsed), tcb);
+        assert!(serde_json::from_str::<SerializableTcb>(r#"{"bootloader":7}"#).is_err());
+    }
+
+    #[test]
+    fn test_tcb_to_kds_query() {
+        let tcb = TcbVersion::new(7, 0, 11, 62);
+        assert_eq!(
+            tcb_to_kds_query(&TcbSpl::from_tcb(&tcb, false)),
+            "blSPL=07&teeSPL=00&snpSPL=11&ucodeSPL=62"
+        );
+
+        // Turin's layout: FMC, bootloader, TEE and SNP, then microcode in the top byte
+        let turin_tcb = tcb_from_u64(0x4800_0000_1801_0201);
+        assert_eq!(
+            tcb_to_kds_query(&TcbSpl::from_tcb(&turin_tcb, true)),
+            "fmcSPL=01&blSPL=02&teeSPL=01&snpSPL=24&ucodeSPL=72"
+        );
+    }
+
+    #[test]
+    fn test_check_tcb_minimum() {
+        let report: AttestationReport = serde_json::from_str(SAMPLE_ATTESTATION).unwrap();
+        assert!(check_tcb(&report, &report.reported_tcb).is_ok());
+        assert!(check_tcb(&report, &TcbVersion::default()).is_ok());
+
+        let tcb = report.reported_tcb;
+        let minimum = TcbVersion::new(tcb.bootloader, tcb.tee, tcb.snp + 1, tcb.microcode);
+        assert!(matches!(
+            check_tcb(&report, &minimum),
+            Err(VerifyError::TcbBelowMinimum {
+                component: TcbComponent::Snp,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_check_turin_tcb_minimum() {
+        let report = parse_report_bytes(TEST_TURIN_REPORT).unwrap();
+        assert_eq!(
+            report_tcb_components(&report, &report.reported_tcb),
+            [
+                (TcbComponent::Bootloader, 2),
+                (TcbComponent::Tee, 1),
+                (TcbComponent::Snp, 24),
+                (TcbComponent::Microcode, 72),
+                (TcbComponent::Fmc, 1),
+            ]
+        );
+        assert!(check_tcb(&report, &report.reported_tcb).is_ok());
+
+        // The FMC SPL is in the byte Milan and Genoa use for the bootloader
+        let minimum = tcb_from_u64(0x4800_0000_1801_0202);
+        assert!(matches!(
+            check_tcb(&report, &minimum),
+            Err(VerifyError::TcbBelowMinimum {
+                component: TcbComponent::Fmc,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_check_tcb_rollback() {
+        let mut report: AttestationReport = serde_json::from_str(SAMPLE_ATTESTATION).unwrap();
+        assert!(check_tcb_rollback(&report).is_ok());
+
+        let tcb = report.reported_tcb;
+        report.committed_tcb = TcbVersion::new(tcb.bootloader, tcb.tee, tcb.snp, tcb.microcode + 1);
+        assert!(matches!(
+            check_tcb_rollback(&report),
+            Err(VerifyError::TcbRollback { .. })
+        ));
+        assert!(matches!(
+            check_tcb(&report, &TcbVersion::default()),
+            Err(VerifyError::TcbRollback { .. })
+        ));
+    }
+}
@@ -0,0 +1,33 @@
+//! Logging through the `tracing` crate, compiled out unless the `tracing`
+//! feature is enabled.
+//!
+//! Each macro takes the same arguments as its `tracing` counterpart. Without
+//! the feature, the arguments are not evaluated.
+
+macro_rules! info {
+    ($($arg:tt)+) => {
+        #[cfg(feature = "tracing")]
+        tracing::info!($($arg)+)
+    };
+}
+
+macro_rules! warn {
+    ($($arg:tt)+) => {
+        #[cfg(feature = "tracing")]
+        tracing::warn!($($arg)+)
+    };
+}
+
+macro_rules! debug {
+    ($($arg:tt)+) => {
+        #[cfg(feature = "tracing")]
+        tracing::debug!($($arg)+)
+    };
+}
+
+macro_rules! error {
+    ($($arg:tt)+) => {
+        #[cfg(feature = "tracing")]
+        tracing::error!($($arg)+)
+    };
+}
@@ -0,0 +1,714 @@
+use std::collections::BTreeMap;
+use std::fmt::{self, Write as _};
+use std::io::{Error, ErrorKind};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use openssl::{asn1::Asn1Time, error::ErrorStack, hash::MessageDigest, nid::Nid, x509::X509};
+use serde::Serialize;
+use sev::{
+    certs::snp::{Certificate, Chain},
+    firmware::host::TcbVersion,
+};
+
+use crate::crypto::ct_eq;
+use crate::product::ProductLine;
+use crate::verify_attestation::VerifyError;
+
+/// The DER-encoded OID 1.3.6.1.4.1.3704.1.2 of the productName extension.
+const OID_PRODUCT_NAME: [u8; 9] = [0x2b, 0x06, 0x01, 0x04, 0x01, 0x9c, 0x78, 0x01, 0x02];
+
+/// The DER-encoded OID 1.3.6.1.4.1.3704.1.3, under which AMD places the SPL extensions.
+const OID_SPL_PREFIX: [u8; 9] = [0x2b, 0x06, 0x01, 0x04, 0x01, 0x9c, 0x78, 0x01, 0x03];
+
+/// The DER-encoded OID 1.3.6.1.4.1.3704.1.4 of the hwID extension.
+const OID_HWID: [u8; 9] = [0x2b, 0x06, 0x01, 0x04, 0x01, 0x9c, 0x78, 0x01, 0x04];
+
+/// The last arc of the blSPL (bootloader) extension OID.
+const BL_SPL: u8 = 1;
+
+/// The last arc of the teeSPL extension OID.
+const TEE_SPL: u8 = 2;
+
+/// The last arc of the snpSPL extension OID.
+const SNP_SPL: u8 = 3;
+
+/// The last arc of the ucodeSPL (microcode) extension OID.
+const UCODE_SPL: u8 = 8;
+
+/// The last arc of the fmcSPL (firmware mask ROM) extension OID, present from Turin.
+const FMC_SPL: u8 = 9;
+
+/// The security patch levels (SPLs) of a TCB, as a VCEK is issued for.
+///
+/// The report's TCB_VERSION layout differs between product lines: from Turin, it
+/// also carries an FMC (firmware mask ROM) SPL, and its fields move. This type
+/// holds the patch levels independently of the layout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TcbSpl {
+    /// The PSP bootloader SPL.
+    pub bootloader: u8,
+
+    /// The PSP operating system SPL.
+    pub tee: u8,
+
+    /// The SNP firmware SPL.
+    pub snp: u8,
+
+    /// The microcode SPL.
+    pub microcode: u8,
+
+    /// The FMC SPL, on Turin and later.
+    pub fmc: Option<u8>,
+}
+
+impl TcbSpl {
+    /// Decodes a report's TCB version, in the Turin layout if `turin` is true.
+    pub fn from_tcb(tcb: &TcbVersion, turin: bool) -> Self {
+        if !turin {
+            return Self {
+                bootloader: tcb.bootloader,
+                tee: tcb.tee,
+                snp: tcb.snp,
+                microcode: tcb.microcode,
+                fmc: None,
+            };
+        }
+
+        // The sev crate decodes the pre-Turin layout, so decode the raw bytes instead
+        let bytes = bincode::serialize(tcb).expect("TCB version is serializable");
+        Self {
+            fmc: Some(bytes[0]),
+            bootloader: bytes[1],
+            tee: bytes[2],
+            snp: bytes[3],
+            microcode: bytes[7],
+        }
+    }
+}
+
+impl fmt::Display for TcbSpl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "bootloader {}, tee {}, snp {}, microcode {}",
+            self.bootloader, self.tee, self.snp, self.microcode
+        )?;
+        if let Some(fmc) = self.fmc {
+            write!(f, ", fmc {fmc}")?;
+        }
+        Ok(())
+    }
+}
+
+/// The TCB a VCEK was issued for, from its blSPL, teeSPL, snpSPL and ucodeSPL
+/// (and, from Turin, fmcSPL) extensions.
+pub fn vcek_tcb(vcek: &Certificate) -> Result<TcbSpl, VerifyError> {
+    let der = vcek.to_der().map_err(VerifyError::VcekParse)?;
+    let extensions = extensions(&der).ok_or_else(|| invalid_vcek("malformed extensions"))?;
+
+    let spl = |arc: u8| -> Result<Option<u8>, VerifyError> {
+        let Some(value) = extensions
+            .iter()
+            .find(|(oid, _)| oid.len() == 10 && oid[..9] == OID_SPL_PREFIX && oid[9] == arc)
+            .map(|(_, value)| *value)
+        else {
+            return Ok(None);
+        };
+        parse_spl(value)
+            .map(Some)
+            .ok_or_else(|| invalid_vcek("malformed SPL extension"))
+    };
+    let required = |arc: u8, name: &str| -> Result<u8, VerifyError> {
+        spl(arc)?.ok_or_else(|| invalid_vcek(&format!("missing {name} extension")))
+    };
+
+    Ok(TcbSpl {
+        bootloader: required(BL_SPL, "blSPL")?,
+        tee: required(TEE_SPL, "teeSPL")?,
+        snp: required(SNP_SPL, "snpSPL")?,
+        microcode: required(UCODE_SPL, "ucodeSPL")?,
+        fmc: spl(FMC_SPL)?,
+    })
+}
+
+/// Checks that a VCEK was issued for the TCB a report claims to be signed under.
+///
+/// A VCEK for another TCB of the same chip still chains to the ARK, so without
+/// this check a VCEK for a different (say, older and vulnerable) TCB is accepted.
+pub fn check_vcek_tcb(vcek: &Certificate, reported_tcb: &TcbVersion) -> Result<(), VerifyError> {
+    let vcek_tcb = vcek_tcb(vcek)?;
+    let reported = TcbSpl::from_tcb(reported_tcb, vcek_tcb.fmc.is_some());
+    if vcek_tcb != reported {
+        return Err(VerifyError::VcekTcbMismatch {
+            vcek: vcek_tcb,
+            reported,
+        });
+    }
+
+    Ok(())
+}
+
+/// The hardware ID of the chip a VCEK was issued for, from its hwID extension.
+///
+/// This is the 64-byte chip ID before Turin, and its first 8 bytes from Turin on.
+pub fn vcek_hwid(vcek: &Certificate) -> Result<Vec<u8>, VerifyError> {
+    let der = vcek.to_der().map_err(VerifyError::VcekParse)?;
+    let extensions = extensions(&der).ok_or_else(|| invalid_vcek("malformed extensions"))?;
+
+    extensions
+        .iter()
+        .find(|(oid, _)| *oid == OID_HWID)
+        .map(|(_, value)| value.to_vec())
+        .ok_or_else(|| invalid_vcek("missing hwID extension"))
+}
+
+/// The product line a VCEK was issued for, from the common name of its issuer
+/// (the product's ASK, as "SEV-Genoa" say), or `None` if it names no known product.
+pub fn vcek_product(vcek: &Certificate) -> Option<ProductLine> {
+    let x509 = X509::from(vcek);
+    let common_name = x509.issuer_name().entries_by_nid(Nid::COMMONNAME).next()?;
+    let common_name = common_name.data().as_utf8().ok()?;
+
+    common_name.strip_prefix("SEV-")?.parse().ok()
+}
+
+/// Checks that a VCEK was issued for the chip that produced a report.
+///
+/// Every VCEK chains to the ARK, so without this check a VCEK for any chip is accepted.
+pub fn check_vcek_chip_id(vcek: &Certificate, chip_id: &[u8; 64]) -> Result<(), VerifyError> {
+    let hwid = vcek_hwid(vcek)?;
+
+    // From Turin, the chip ID is the 8-byte hardware ID followed by zeros
+    let matches = match hwid.len() {
+        64 => ct_eq(&hwid, chip_id),
+        8 => ct_eq(&hwid, &chip_id[..8]) & ct_eq(&chip_id[8..], &[0; 56]),
+        _ => false,
+    };
+    if !matches {
+        return Err(VerifyError::ChipIdMismatch {
+            vcek: hwid,
+            report: *chip_id,
+        });
+    }
+
+    Ok(())
+}
+
+/// Checks that a VCEK names the ASK as its issuer, by distinguished name and,
+/// where both certificates have one, by key identifier.
+///
+/// A VCEK issued by another ASK (one AMD has since rotated to, say) would
+/// otherwise fail only as a bad signature, with no hint of which ASK it expects.
+pub fn check_vcek_issuer(vcek: &Certificate, ask: &Certificate) -> Result<(), VerifyError> {
+    let (vcek, ask) = (X509::from(vcek), X509::from(ask));
+    let names_match = vcek
+        .issuer_name()
+        .try_cmp(ask.subject_name())
+        .map_err(|e| VerifyError::ChainBuild(e.into()))?
+        .is_eq();
+    let key_ids_match = match (vcek.authority_key_id(), ask.subject_key_id()) {
+        (Some(authority), Some(subject)) => authority.as_slice() == subject.as_slice(),
+        _ => true,
+    };
+    if !names_match || !key_ids_match {
+        return Err(VerifyError::VcekIssuerMismatch {
+            vcek_issuer: identity(vcek.issuer_name(), vcek.authority_key_id()),
+            ask_subject: identity(ask.subject_name(), ask.subject_key_id()),
+        });
+    }
+
+    Ok(())
+}
+
+/// A certificate name, followed by its key identifier if there is one.
+fn identity(
+    name: &openssl::x509::X509NameRef,
+    key_id: Option<&openssl::asn1::Asn1OctetStringRef>,
+) -> String {
+    match key_id {
+        Some(key_id) => format!(
+            "{} (key ID {})",
+            x509_name(name),
+            hex::encode(key_id.as_slice())
+        ),
+        None => x509_name(name),
+    }
+}
+
+/// The SHA-256 fingerprint of a VCEK's DER encoding.
+pub fn vcek_fingerprint(vcek: &Certificate) -> Result<[u8; 32], VerifyError> {
+    let digest = X509::from(vcek)
+        .digest(MessageDigest::sha256())
+        .map_err(|e| VerifyError::VcekParse(e.into()))?;
+    Ok(digest
+        .as_ref()
+        .try_into()
+        .expect("SHA-256 digest is 32 bytes"))
+}
+
+/// Checks that a VCEK is the pinned one, by its SHA-256 fingerprint.
+///
+/// This rejects any other VCEK, even one that chains to the ARK, so a deployment
+/// can accept reports only from an enumerated set of chips (and TCBs).
+pub fn check_vcek_pin(vcek: &Certificate, pinned: &[u8; 32]) -> Result<(), VerifyError> {
+    let actual = vcek_fingerprint(vcek)?;
+    if !ct_eq(&actual, pinned) {
+        return Err(VerifyError::VcekPinMismatch {
+            expected: *pinned,
+            actual,
+        });
+    }
+
+    Ok(())
+}
+
+/// Checks that a VCEK is valid at the given time, per its notBefore and notAfter fields.
+///
+/// VCEKs are valid for 7 years from issuance, so this mostly catches stale
+/// certificates, such as those kept in a cache.
+pub fn check_vcek_validity(vcek: &Certificate, as_of: SystemTime) -> Result<(), VerifyError> {
+    let vcek = X509::from(vcek);
+    let as_of = asn1_time(as_of).map_err(|e| VerifyError::VcekParse(e.into()))?;
+
+    if vcek.not_before() > as_of {
+        return Err(VerifyError::VcekNotYetValid {
+            not_before: vcek.not_before().to_string(),
+        });
+    }
+    if vcek.not_after() < as_of {
+        return Err(VerifyError::VcekExpired {
+            not_after: vcek.not_after().to_string(),
+        });
+    }
+
+    Ok(())
+}
+
+/// Converts a time to an ASN.1 time, to compare with a certificate's or CRL's.
+pub(crate) fn asn1_time(time: SystemTime) -> Result<Asn1Time, ErrorStack> {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .map(|since_epoch| since_epoch.as_secs())
+        .unwrap_or(0);
+    Asn1Time::from_unix(secs.try_into().unwrap_or(i64::MAX))
+}
+
+/// Renders the fields of a VCEK an operator typically reviews when verification
+/// fails as a readable block, in the style of [`crate::report::describe_report`].
+///
+/// AMD's extensions are decoded: the product name, the hardware ID as hex, and
+/// the TCB the VCEK was issued for, by component.
+pub fn describe_vcek(vcek: &Certificate) -> Result<String, VerifyError> {
+    let der = vcek.to_der().map_err(VerifyError::VcekParse)?;
+    let extensions = extensions(&der).ok_or_else(|| invalid_vcek("malformed extensions"))?;
+    let tcb = vcek_tcb(vcek)?;
+    let x509 = X509::from(vcek);
+    let serial = serial_hex(&x509).map_err(|e| VerifyError::VcekParse(e.into()))?;
+    let product_name = extensions
+        .iter()
+        .find(|(oid, _)| *oid == OID_PRODUCT_NAME)
+        .and_then(|(_, value)| match read_tlv(value)? {
+            (0x16, name, []) => Some(String::from_utf8_lossy(name).into_owned()),
+            _ => None,
+        });
+
+    let mut out = String::new();
+    // Writing to a String cannot fail
+    let mut line = |name: &str, value: &dyn fmt::Display| {
+        writeln!(out, "{name:<18}{value}").unwrap();
+    };
+    line("subject:", &x509_name(x509.subject_name()));
+    line("issuer:", &x509_name(x509.issuer_name()));
+    line("serial:", &serial);
+    line("not_before:", &x509.not_before());
+    line("not_after:", &x509.not_after());
+    line(
+        "product_name:",
+        &product_name.as_deref().unwrap_or("(missing)"),
+    );
+    line("hwid:", &hex::encode(vcek_hwid(vcek)?));
+    line("tcb:", &tcb);
+    line("  bootloader:", &tcb.bootloader);
+    line("  tee:", &tcb.tee);
+    line("  snp:", &tcb.snp);
+    line("  microcode:", &tcb.microcode);
+    if let Some(fmc) = tcb.fmc {
+        line("  fmc:", &fmc);
+    }
+
+    Ok(out)
+}
+
+/// The fields of a certificate an auditor reviews, as serialized by [`chain_to_json`].
+#[derive(Debug, Serialize)]
+struct CertificateSummary {
+    subject: String,
+    issuer: String,
+    serial: String,
+    not_before: String,
+    not_after: String,
+    sha256: String,
+}
+
+impl CertificateSummary {
+    fn new(cert: &Certificate) -> Result<Self, VerifyError> {
+        let x509 = X509::from(cert);
+        let serial = serial_hex(&x509).map_err(|e| VerifyError::ChainBuild(e.into()))?;
+        let sha256 = x509
+            .digest(MessageDigest::sha256())
+            .map_err(|e| VerifyError::ChainBuild(e.into()))?;
+
+        Ok(Self {
+            subject: x509_name(x509.subject_name()),
+            issuer: x509_name(x509.issuer_name()),
+            serial,
+            not_before: x509.not_before().to_string(),
+            not_after: x509.not_after().to_string(),
+            sha256: hex::encode(sha256),
+        })
+    }
+}
+
+/// Serializes the ARK, ASK and VCEK of a chain to JSON, for archiving what was
+/// trusted when a report was verified.
+///
+/// Each certificate is an object of its subject and issuer (as in
+/// [`describe_vcek`]), its serial number as hex, its validity period, and the
+/// SHA-256 fingerprint of its DER encoding:
+///
+/// ```json
+/// {"ark":{"subject":"…","issuer":"…","serial":"…","not_before":"…",
+///   "not_after":"…","sha256":"…"},"ask":{…},"vcek":{…}}
+/// ```
+pub fn chain_to_json(chain: &Chain) -> Result<String, VerifyError> {
+    let summary = BTreeMap::from([
+        ("ark", CertificateSummary::new(&chain.ca.ark)?),
+        ("ask", CertificateSummary::new(&chain.ca.ask)?),
+        ("vcek", CertificateSummary::new(&chain.vcek)?),
+    ]);
+    Ok(serde_json::to_string(&summary).expect("certificate summaries are serializable"))
+}
+
+/// A certificate's serial number as lowercase hex, as in the crate's other hex output.
+pub(crate) fn serial_hex(x509: &openssl::x509::X509Ref) -> Result<String, ErrorStack> {
+    let serial = x509.serial_number().to_bn()?.to_hex_str()?;
+    Ok(serial.to_lowercase())
+}
+
+/// Formats an X509 name as comma-separated `KEY=value` pairs, in certificate order.
+fn x509_name(name: &openssl::x509::X509NameRef) -> String {
+    name.entries()
+        .map(|entry| {
+            let key = entry.object().nid().short_name().unwrap_or("?");
+            let value = entry
+                .data()
+                .as_utf8()
+                .map(|value| value.to_string())
+                .unwrap_or_default();
+            format!("{key}={value}")
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn invalid_vcek(message: &str) -> VerifyError {
+    VerifyError::VcekParse(Error::new(
+        ErrorKind::InvalidData,
+        format!("VCEK has {message}"),
+    ))
+}
+
+/// Parses the value of an SPL extension, a DER INTEGER between 0 and 255.
+fn parse_spl(value: &[u8]) -> Option<u8> {
+    let (0x02, int, []) = read_tlv(value)? else {
+        return None;
+    };
+    match int {
+        [spl] if *spl < 0x80 => Some(*spl),
+        [0, spl] if *spl >= 0x80 => Some(*spl),
+        _ => None,
+    }
+}
+
+/// Finds the extensions of a DER-encoded X509 certificate, as (OID, value) pairs.
+///
+/// The OIDs are in their DER encoding, and the values are the contents of the
+/// extnValue OCTET STRING.
+pub(crate) fn extensions(cert: &[u8]) -> Option<Vec<(&[u8], &[u8])>> {
+    // Certificate ::= SEQUENCE { tbsCertificate, signatureAlgorithm, signature }
+    let (0x30, cert, _) = read_tlv(cert)? else {
+        return None;
+    };
+    let (0x30, mut tbs, _) = read_tlv(cert)? else {
+        return None;
+    };
+
+    // The extensions are the [3] EXPLICIT, last, field of the TBSCertificate
+    let extensions = loop {
+        let (tag, contents, rest) = read_tlv(tbs)?;
+        if tag == 0xa3 {
+            break contents;
+        }
+        tbs = rest;
+    };
+    let (0x30, mut extensions, _) = read_tlv(extensions)? else {
+        return None;
+    };
+
+    // Extension ::= SEQUENCE { extnID, critical BOOLEAN DEFAULT FALSE, extnValue }
+    let mut found = Vec::new();
+    while !extensions.is_empty() {
+        let (0x30, extension, rest) = read_tlv(extensions)? else {
+            return None;
+        };
+        let (0x06, oid, mut extension) = read_tlv(extension)? else {
+            return None;
+        };
+        if let (0x01, _, after_critical) = read_tlv(extension)? {
+            extension = after_critical;
+        }
+        let (0x04, value, _) = read_tlv(extension)? else {
+            return None;
+        };
+
+        found.push((oid, value));
+        extensions = rest;
+    }
+
+    Some(found)
+}
+
+/// Whether the data is a single DER SEQUENCE, as a DER-encoded certificate is.
+///
+/// This is a cheap check, to tell a certificate from (say) an HTML error page
+/// before parsing it.
+pub(crate) fn is_der_sequence(data: &[u8]) -> bool {
+    matches!(read_tlv(data), Some((0x30, _, [])))
+}
+
+/// Reads a DER tag-length-value, returning the tag, the contents and the remaining input.
+fn read_tlv(data: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let (&tag, data) = data.split_first()?;
+    let (&first, mut data) = data.split_first()?;
+
+    let len = if first < 0x80 {
+        first as usize
+    } else {
+        let num_bytes = (first & 0x7f) as usize;
+        if num_bytes == 0 || num_bytes > 4 || data.len() < num_bytes {
+            return None;
+        }
+        let (len_bytes, rest) = data.split_at(num_bytes);
+        data = rest;
+        len_bytes
+            .iter()
+            .fold(0usize, |len, &byte| (len << 8) | byte as usize)
+    };
+
+    if data.len() < len {
+        return None;
+    }
+    let (contents, rest) = data.split_at(len);
+    Some((tag, contents, rest))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::time::Duration;
+
+    use openssl::sha::sha256;
+    use sev::firmware::guest::AttestationReport;
+
+    use crate::report::{parse_report_bytes, SAMPLE_ATTESTATION_MILAN, SAMPLE_VCEK_MILAN};
+    use crate::verify_attestation::{
+        get_cert_chain, SAMPLE_ATTESTATION, SAMPLE_VCEK, SAMPLE_VCEK_TURIN,
+    };
+
+    /// The sample VCEK's notBefore (Sep 19 00:51:30 2023 GMT), as a Unix time.
+    const SAMPLE_VCEK_NOT_BEFORE: u64 = 1695084690;
+
+    #[test]
+    fn test_check_vcek_validity() {
+        let vcek = Certificate::from_der(SAMPLE_VCEK).unwrap();
+        let issued = UNIX_EPOCH + Duration::from_secs(SAMPLE_VCEK_NOT_BEFORE);
+        let year = Duration::from_secs(365 * 24 * 60 * 60);
+
+        assert!(check_vcek_validity(&vcek, issued).is_ok());
+        assert!(check_vcek_validity(&vcek, issued + year).is_ok());
+        assert!(matches!(
+            check_vcek_validity(&vcek, issued - Duration::from_secs(1)),
+            Err(VerifyError::VcekNotYetValid { .. })
+        ));
+        assert!(matches!(
+            check_vcek_validity(&vcek, issued + 8 * year),
+            Err(VerifyError::VcekExpired { not_after }) if not_after == "Sep 19 00:51:30 2030 GMT"
+        ));
+    }
+
+    #[test]
+    fn test_check_vcek_pin() {
+        let vcek = Certificate::from_der(SAMPLE_VCEK).unwrap();
+        let fingerprint = vcek_fingerprint(&vcek).unwrap();
+        assert_eq!(fingerprint, sha256(SAMPLE_VCEK));
+        assert!(check_vcek_pin(&vcek, &fingerprint).is_ok());
+
+        let turin_vcek = Certificate::from_der(SAMPLE_VCEK_TURIN).unwrap();
+        assert!(matches!(
+            check_vcek_pin(&turin_vcek, &fingerprint),
+            Err(VerifyError::VcekPinMismatch { expected, .. }) if expected == fingerprint
+        ));
+    }
+
+    #[test]
+    fn test_vcek_tcb() {
+        let vcek = Certificate::from_der(SAMPLE_VCEK).unwrap();
+        assert_eq!(
+            vcek_tcb(&vcek).unwrap(),
+            TcbSpl {
+                bootloader: 7,
+                tee: 0,
+                snp: 11,
+                microcode: 62,
+                fmc: None
+            }
+        );
+
+        let vcek = Certificate::from_der(SAMPLE_VCEK_TURIN).unwrap();
+        assert_eq!(vcek_tcb(&vcek).unwrap().fmc, Some(0));
+    }
+
+    #[test]
+    fn test_check_vcek_tcb() {
+        let report: AttestationReport = serde_json::from_str(SAMPLE_ATTESTATION).unwrap();
+        let vcek = Certificate::from_der(SAMPLE_VCEK).unwrap();
+        assert!(check_vcek_tcb(&vcek, &report.reported_tcb).is_ok());
+
+        let report = parse_report_bytes(SAMPLE_ATTESTATION_MILAN).unwrap();
+        let milan_vcek = Certificate::from_der(SAMPLE_VCEK_MILAN).unwrap();
+        assert!(check_vcek_tcb(&milan_vcek, &report.reported_tcb).is_ok());
+
+        // A VCEK for another TCB does not match
+        assert!(matches!(
+            check_vcek_tcb(&vcek, &report.reported_tcb),
+            Err(VerifyError::VcekTcbMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_vcek_product() {
+        for (vcek, product) in [
+            (SAMPLE_VCEK_MILAN, ProductLine::Milan),
+            (SAMPLE_VCEK, ProductLine::Genoa),
+            (SAMPLE_VCEK_TURIN, ProductLine::Turin),
+        ] {
+            let vcek = Certificate::from_der(vcek).unwrap();
+            assert_eq!(vcek_product(&vcek), Some(product));
+        }
+    }
+
+    #[test]
+    fn test_check_vcek_chip_id() {
+        let report: AttestationReport = serde_json::from_str(SAMPLE_ATTESTATION).unwrap();
+        let vcek = Certificate::from_der(SAMPLE_VCEK).unwrap();
+        assert_eq!(vcek_hwid(&vcek).unwrap(), report.chip_id);
+        assert!(check_vcek_chip_id(&vcek, &report.chip_id).is_ok());
+
+        let milan_report = parse_report_bytes(SAMPLE_ATTESTATION_MILAN).unwrap();
+        assert!(matches!(
+            check_vcek_chip_id(&vcek, &milan_report.chip_id),
+            Err(VerifyError::ChipIdMismatch { .. })
+        ));
+
+        // A Turin VCEK carries only the first 8 bytes of the chip ID
+        let turin_vcek = Certificate::from_der(SAMPLE_VCEK_TURIN).unwrap();
+        let hwid = vcek_hwid(&turin_vcek).unwrap();
+        assert_eq!(hwid.len(), 8);
+        let mut chip_id = [0; 64];
+        chip_id[..8].copy_from_slice(&hwid);
+        assert!(check_vcek_chip_id(&turin_vcek, &chip_id).is_ok());
+        chip_id[8] = 1;
+        assert!(check_vcek_chip_id(&turin_vcek, &chip_id).is_err());
+    }
+
+    #[test]
+    fn test_tcb_spl_turin_layout() {
+        let tcb = TcbVersion::new(1, 2, 3, 4);
+        let spl = TcbSpl::from_tcb(&tcb, true);
+        // The Turin layout puts the FMC, bootloader, tee and snp SPLs in bytes 0-3
+        assert_eq!(spl.fmc, Some(1));
+        assert_eq!(spl.bootloader, 2);
+        assert_eq!(spl.microcode, 4);
+    }
+
+    #[test]
+    fn test_describe_vcek() {
+        let vcek = Certificate::from_der(SAMPLE_VCEK).unwrap();
+        let description = describe_vcek(&vcek).unwrap();
+
+        assert!(description.contains("issuer:           OU=Engineering, C=US, L=Santa Clara, ST=CA, O=Advanced Micro Devices, CN=SEV-Genoa\n"));
+        assert!(description.contains("not_after:        Sep 19 00:51:30 2030 GMT\n"));
+        assert!(description.contains("product_name:     Genoa\n"));
+        assert!(
+            description.contains("tcb:              bootloader 7, tee 0, snp 11, microcode 62\n")
+        );
+        assert!(description.contains("  microcode:      62\n"));
+        assert!(!description.contains("fmc"));
+
+        let turin_vcek = Certificate::from_der(SAMPLE_VCEK_TURIN).unwrap();
+        assert!(describe_vcek(&turin_vcek)
+            .unwrap()
+            .contains("  fmc:            0\n"));
+    }
+
+    #[test]
+    fn test_chain_to_json() {
+        let chain = Chain {
+            ca: get_cert_chain(ProductLine::Genoa),
+            vcek: Certificate::from_der(SAMPLE_VCEK).unwrap(),
+        };
+        let json = chain_to_json(&chain).unwrap();
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+
+        let vcek = &value["vcek"];
+        assert_eq!(
+            vcek["issuer"],
+            "OU=Engineering, C=US, L=Santa Clara, ST=CA, O=Advanced Micro Devices, CN=SEV-Genoa"
+        );
+        assert_eq!(vcek["not_after"], "Sep 19 00:51:30 2030 GMT");
+        assert_eq!(
+            vcek["sha256"],
+            hex::encode(vcek_fingerprint(&chain.vcek).unwrap())
+        );
+        // The ARK is self-signed, and issued the ASK
+        assert_eq!(value["ark"]["subject"], value["ark"]["issuer"]);
+        assert_eq!(value["ask"]["issuer"], value["ark"]["subject"]);
+        assert_eq!(value["ask"]["subject"], vcek["issuer"]);
+
+        // The serial is written alike in the text description
+        let serial = vcek["serial"].as_str().unwrap();
+        assert!(describe_vcek(&chain.vcek)
+            .unwrap()
+            .contains(&format!("serial:           {serial}\n")));
+        assert_eq!(value["ask"]["serial"], "020002");
+    }
+
+    #[test]
+    fn test_read_tlv() {
+        assert_eq!(
+            read_tlv(&[0x02, 0x01, 0x07]),
+            Some((0x02, &[7][..], &[][..]))
+        );
+        assert_eq!(parse_spl(&[0x02, 0x02, 0x00, 0x80]), Some(0x80));
+        assert_eq!(parse_spl(&[0x02, 0x01, 0x80]), None);
+
+        let long = [&[0x04, 0x81, 0x80][..], &[0; 0x80]].concat();
+        assert_eq!(
+            read_tlv(&long).map(|(_, contents, _)| contents.len()),
+            Some(0x80)
+        );
+        assert_eq!(read_tlv(&long[..10]), None);
+        assert_eq!(read_tlv(&[]), None);
+    }
+}
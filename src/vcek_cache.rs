@@ -0,0 +1,265 @@
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+use sev::firmware::host::TcbVersion;
+
+use crate::kds::KdsClient;
+use crate::product::ProductLine;
+use crate::tcb::tcb_to_string;
+use crate::verify_attestation::VcekError;
+
+/// How long a VCEK remains valid after issuance (7 years).
+pub const VCEK_VALIDITY: Duration = Duration::from_secs(7 * 365 * 24 * 60 * 60);
+
+/// An on-disk cache of VCEKs, keyed by chip ID and TCB.
+///
+/// KDS rate-limits aggressively, so VCEKs fetched once are stored as DER bytes
+/// in a directory and reused until they expire.
+#[derive(Debug)]
+pub struct VcekCache {
+    dir: PathBuf,
+    kds: KdsClient,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl VcekCache {
+    /// Opens a VCEK cache in the given directory, creating it if needed.
+    pub fn new(dir: impl AsRef<Path>) -> std::io::Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        std::fs::create_dir_all(&dir)?;
+
+        Ok(Self {
+            dir,
+            kds: KdsClient::default(),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        })
+    }
+
+    /// Uses the given KDS client to request VCEKs that are not cached.
+    ///
+    /// With an offline client (see [`KdsClient::offline`]), only cached VCEKs are used.
+    pub fn with_kds_client(mut self, kds: KdsClient) -> Self {
+        self.kds = kds;
+        self
+    }
+
+    /// The directory holding the cached VCEKs.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// The number of lookups that were served from the cache.
+    pub fn hits(&self) -> u64 {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    /// The number of lookups that were not in the cache.
+    pub fn misses(&self) -> u64 {
+        self.misses.load(Ordering::Relaxed)
+    }
+
+    /// The cache key for the given chip and TCB.
+    pub fn key(chip_id: &[u8; 64], reported_tcb: &TcbVersion) -> String {
+        format!("{}_{}", hex::encode(chip_id), tcb_to_string(reported_tcb))
+    }
+
+    /// Looks up the cached VCEK for the given chip and TCB.
+    ///
+    /// Expired entries are treated as missing.
+    pub fn get(&self, chip_id: &[u8; 64], reported_tcb: &TcbVersion) -> Option<Vec<u8>> {
+        let path = self.dir.join(Self::key(chip_id, reported_tcb));
+        let vcek = match is_expired(&path, VCEK_VALIDITY) {
+            Ok(false) => std::fs::read(&path).ok(),
+            _ => None,
+        };
+
+        match vcek {
+            Some(_) => self.hits.fetch_add(1, Ordering::Relaxed),
+            None => self.misses.fetch_add(1, Ordering::Relaxed),
+        };
+        vcek
+    }
+
+    /// Stores the VCEK for the given chip and TCB.
+    pub fn insert(
+        &self,
+        chip_id: &[u8; 64],
+        reported_tcb: &TcbVersion,
+        vcek_bytes: &[u8],
+    ) -> std::io::Result<()> {
+        std::fs::write(self.dir.join(Self::key(chip_id, reported_tcb)), vcek_bytes)
+    }
+
+    /// Requests the VCEK for the specified chip and TCB, consulting the cache first.
+    ///
+    /// VCEKs fetched from KDS are written back to the cache. A failure to write
+    /// the cache is not an error, since the VCEK itself was retrieved.
+    pub fn request_vcek(
+        &self,
+        chip_id: [u8; 64],
+        reported_tcb: TcbVersion,
+        product: ProductLine,
+    ) -> Result<Vec<u8>, VcekError> {
+        if let Some(vcek_bytes) = self.get(&chip_id, &reported_tcb) {
+            return Ok(vcek_bytes);
+        }
+
+        let vcek_bytes = self.kds.request_vcek(chip_id, reported_tcb, product)?;
+        let _ = self.insert(&chip_id, &reported_tcb, &vcek_bytes);
+        Ok(vcek_bytes)
+    }
+
+    /// Fetches the VCEKs for the given chips and TCBs into the cache ahead of
+    /// time, returning the outcome for each entry, in order.
+    ///
+    /// At most `concurrency` requests (and at least one) are in flight at once,
+    /// each retried by the KDS client as KDS rate limits. Entries already cached
+    /// are not requested again.
+    pub fn prefetch_vceks(
+        &self,
+        entries: &[([u8; 64], TcbVersion)],
+        product: ProductLine,
+        concurrency: usize,
+    ) -> Vec<Result<(), VcekError>> {
+        let next = AtomicUsize::new(0);
+        let results = Mutex::new((0..entries.len()).map(|_| None).collect::<Vec<_>>());
+        std::thread::scope(|scope| {
+            for _ in 0..concurrency.clamp(1, entries.len().max(1)) {
+                scope.spawn(|| loop {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(&(chip_id, tcb)) = entries.get(index) else {
+                        break;
+                    };
+                    let result = self.request_vcek(chip_id, tcb, product).map(|_| ());
+                    results.lock().unwrap()[index] = Some(result);
+                });
+            }
+        });
+
+        results
+            .into_inner()
+            .unwrap()
+            .into_iter()
+            .map(|result| result.expect("every entry is fetched"))
+            .collect()
+    }
+
+    /// Removes cached VCEKs older than [`VCEK_VALIDITY`], returning how many were removed.
+    pub fn clear_expired(&self) -> std::io::Result<usize> {
+        self.clear_older_than(VCEK_VALIDITY)
+    }
+
+    fn clear_older_than(&self, max_age: Duration) -> std::io::Result<usize> {
+        let mut removed = 0;
+        for entry in std::fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.is_file() && is_expired(&path, max_age)? {
+                std::fs::remove_file(&path)?;
+                removed += 1;
+            }
+        }
+        Ok(removed)
+    }
+}
+
+/// Checks whether the file at `path` was last modified more than `max_age` ago.
+fn is_expired(path: &Path, max_age: Duration) -> std::io::Result<bool> {
+    let modified = std::fs::metadata(path)?.modified()?;
+    let age = SystemTime::now()
+        .duration_since(modified)
+        .unwrap_or(Duration::ZERO);
+    Ok(age >= max_age)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::mock_kds::MockKds;
+    use crate::verify_attestation::{SAMPLE_ATTESTATION, SAMPLE_VCEK};
+    use sev::firmware::guest::AttestationReport;
+
+    fn temp_cache(name: &str) -> VcekCache {
+        let dir =
+            std::env::temp_dir().join(format!("sev_attest_tool_{name}_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        VcekCache::new(dir).unwrap()
+    }
+
+    #[test]
+    fn test_cache_hit_and_miss() {
+        let cache = temp_cache("hit_and_miss");
+        let report: AttestationReport = serde_json::from_str(SAMPLE_ATTESTATION).unwrap();
+
+        assert!(cache.get(&report.chip_id, &report.reported_tcb).is_none());
+        cache
+            .insert(&report.chip_id, &report.reported_tcb, SAMPLE_VCEK)
+            .unwrap();
+
+        // A cached VCEK is served without a network request
+        let vcek_bytes = cache
+            .request_vcek(report.chip_id, report.reported_tcb, ProductLine::Genoa)
+            .unwrap();
+        assert_eq!(vcek_bytes, SAMPLE_VCEK);
+        assert_eq!((cache.hits(), cache.misses()), (1, 1));
+
+        std::fs::remove_dir_all(cache.dir()).unwrap();
+    }
+
+    #[test]
+    fn test_prefetch_vceks() {
+        let cache = temp_cache("prefetch");
+        let kds = MockKds::start();
+        let cache = cache.with_kds_client(KdsClient::new(kds.base_url()));
+        let report: AttestationReport = serde_json::from_str(SAMPLE_ATTESTATION).unwrap();
+        cache
+            .insert(&report.chip_id, &report.reported_tcb, SAMPLE_VCEK)
+            .unwrap();
+
+        let entries: Vec<_> = (0..5)
+            .map(|i| ([i; 64], report.reported_tcb))
+            .chain([(report.chip_id, report.reported_tcb)])
+            .collect();
+        let results = cache.prefetch_vceks(&entries, ProductLine::Genoa, 3);
+        assert_eq!(results.len(), entries.len());
+        assert!(results.iter().all(Result::is_ok));
+        for (chip_id, tcb) in &entries {
+            assert_eq!(cache.get(chip_id, tcb).unwrap(), SAMPLE_VCEK);
+        }
+        // The cached entry was not requested again
+        assert_eq!(kds.paths().len(), 5);
+
+        // Failures are reported for each entry
+        let uncached = [
+            ([8; 64], report.reported_tcb),
+            ([9; 64], report.reported_tcb),
+        ];
+        let results = cache.prefetch_vceks(&uncached, ProductLine::Milan, 0);
+        assert_eq!(results.len(), 2);
+        assert!(results
+            .iter()
+            .all(|result| matches!(result, Err(VcekError::Status { .. }))));
+        assert!(cache.prefetch_vceks(&[], ProductLine::Genoa, 4).is_empty());
+
+        std::fs::remove_dir_all(cache.dir()).unwrap();
+    }
+
+    #[test]
+    fn test_cache_clear_expired() {
+        let cache = temp_cache("clear_expired");
+        let report: AttestationReport = serde_json::from_str(SAMPLE_ATTESTATION).unwrap();
+        cache
+            .insert(&report.chip_id, &report.reported_tcb, SAMPLE_VCEK)
+            .unwrap();
+
+        assert_eq!(cache.clear_expired().unwrap(), 0);
+        assert_eq!(cache.clear_older_than(Duration::ZERO).unwrap(), 1);
+        assert!(cache.get(&report.chip_id, &report.reported_tcb).is_none());
+
+        std::fs::remove_dir_all(cache.dir()).unwrap();
+    }
+}
@@ -0,0 +1,90 @@
+use serde::{Serialize, Serializer};
+use sev::firmware::host::TcbVersion;
+
+use crate::policy::DecodedPolicy;
+use crate::product::ProductLine;
+use crate::tcb::SerializableTcb;
+
+/// The outcome of a single verification step.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "status", content = "reason", rename_all = "snake_case")]
+pub enum StepOutcome {
+    /// The step ran and succeeded.
+    Passed,
+
+    /// The step did not run, either because it was not configured or because
+    /// an earlier step failed.
+    Skipped,
+
+    /// The step ran and failed, for the given reason.
+    Failed(String),
+}
+
+/// A named verification step and its outcome.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct VerificationStep {
+    /// The name of the step, such as "report_signature".
+    pub name: &'static str,
+
+    /// The outcome of the step.
+    pub outcome: StepOutcome,
+}
+
+/// An auditable record of what was checked when verifying an attestation report.
+#[derive(Debug, Clone, Serialize)]
+pub struct VerificationReport {
+    /// The product line whose certificate chain was used.
+    pub product: ProductLine,
+
+    /// Every verification step, in the order they run.
+    pub steps: Vec<VerificationStep>,
+
+    /// The TCB the report claims to have been signed under.
+    #[serde(serialize_with = "serialize_tcb")]
+    pub reported_tcb: TcbVersion,
+
+    /// The TCB the platform has committed to, below which it cannot roll back.
+    #[serde(serialize_with = "serialize_tcb")]
+    pub committed_tcb: TcbVersion,
+
+    /// The TCB of the platform when the guest was launched.
+    #[serde(serialize_with = "serialize_tcb")]
+    pub launch_tcb: TcbVersion,
+
+    /// The launch measurement of the guest, serialized as hex.
+    #[serde(serialize_with = "serialize_hex")]
+    pub measurement: [u8; 48],
+
+    /// The decoded guest policy of the report.
+    pub policy: DecodedPolicy,
+
+    /// Caveats on the verification that did not fail it, such as checks skipped
+    /// for a report of an unsupported version.
+    pub warnings: Vec<String>,
+}
+
+fn serialize_tcb<S: Serializer>(tcb: &TcbVersion, serializer: S) -> Result<S::Ok, S::Error> {
+    SerializableTcb::from(*tcb).serialize(serializer)
+}
+
+fn serialize_hex<S: Serializer>(bytes: &[u8; 48], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&hex::encode(bytes))
+}
+
+impl VerificationReport {
+    /// Whether every step either passed or was not configured to run.
+    pub fn passed(&self) -> bool {
+        !self
+            .steps
+            .iter()
+            .any(|step| matches!(step.outcome, StepOutcome::Failed(_)))
+    }
+
+    /// The outcome of the step with the given name, if there is one.
+    pub fn outcome(&self, name: &str) -> Option<&StepOutcome> {
+        self.steps
+            .iter()
+            .find(|step| step.name == name)
+            .map(|step| &step.outcome)
+    }
+}
@@ -0,0 +1,780 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use serde::Serialize;
+use sev::{
+    certs::snp::{ca, Certificate},
+    firmware::guest::AttestationReport,
+    firmware::host::TcbVersion,
+};
+
+use crate::chain_cache::ChainCache;
+use crate::crl::Crl;
+use crate::extended_report::parse_report_with_certs;
+use crate::id_block::IdAuth;
+use crate::kds::{KdsClient, NetworkMode};
+use crate::policy::{
+    Freshness, PolicyRequirements, SigningKeyAllowlist, UnknownVersionPolicy, VerifyPolicy,
+    NO_MIGRATION_AGENT,
+};
+use crate::product::ProductLine;
+use crate::report::{check_report_format, check_signature_algo, is_future_report_version};
+use crate::verification_report::VerificationReport;
+use crate::verify_attestation::{ca_chain_from_file, verify_recording, VerifyError};
+
+/// A configured attestation report verifier.
+///
+/// Build one with [`Verifier::new`], adding only the checks you need, as in
+/// `Verifier::new(ProductLine::Genoa).require_nonce(nonce).offline(true).build()`.
+#[derive(Debug, Clone)]
+pub struct Verifier {
+    product: ProductLine,
+    policy: VerifyPolicy,
+    kds: KdsClient,
+    custom_root: Option<ca::Chain>,
+}
+
+impl Verifier {
+    /// Starts building a verifier for reports from the given product line.
+    ///
+    /// With no further options, a verifier checks only that the report is signed
+    /// by a VCEK that chains to the AMD root key.
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new(product: ProductLine) -> VerifierBuilder {
+        VerifierBuilder {
+            verifier: Verifier {
+                product,
+                policy: VerifyPolicy::default(),
+                kds: KdsClient::default(),
+                custom_root: None,
+            },
+        }
+    }
+
+    /// The product line whose certificate chain reports are verified against.
+    pub fn product(&self) -> ProductLine {
+        self.product
+    }
+
+    /// The checks applied to a report once its signature is verified.
+    pub fn policy(&self) -> &VerifyPolicy {
+        &self.policy
+    }
+
+    /// The ARK/ASK chain trusted in place of AMD's, if one was given with
+    /// [`VerifierBuilder::custom_root`].
+    pub fn custom_root(&self) -> Option<&ca::Chain> {
+        self.custom_root.as_ref()
+    }
+
+    /// Describes the steps this verifier runs, and which of them may make a
+    /// network request, without verifying anything.
+    ///
+    /// This shows whether the verifier is configured as intended, say that a
+    /// critical check has not been left out. The plan is of
+    /// [`Verifier::fetch_and_verify`]; [`Verifier::verify`] skips `vcek_request`.
+    pub fn plan(&self) -> VerifyPlan {
+        let online = self.kds.mode() == NetworkMode::Online;
+        let policy = &self.policy;
+        let step = |name, enabled, network| PlannedStep {
+            name,
+            enabled,
+            network,
+        };
+
+        let mut steps = vec![
+            step("vcek_request", online, true),
+            step("ark_self_signed", true, false),
+            step("ask_signed_by_ark", true, false),
+            step("vcek_signed_by_ask", true, false),
+            step("vcek_pin", policy.pinned_vcek_sha256.is_some(), false),
+            step("revocation", policy.crl.is_some(), false),
+            step("vcek_validity", true, false),
+            step("report_format", true, false),
+            step("reserved_fields", true, false),
+            step("vcek_tcb", true, false),
+            step("vcek_chip_id", true, false),
+            step("report_signature", true, false),
+        ];
+        steps.extend(
+            policy
+                .configured_checks()
+                .map(|(name, enabled)| step(name, enabled, false)),
+        );
+
+        VerifyPlan {
+            product: self.product,
+            online,
+            steps,
+        }
+    }
+
+    /// Verifies an attestation report signed by the given VCEK.
+    ///
+    /// On success, the returned report records every step that ran.
+    pub fn verify(
+        &self,
+        report: &AttestationReport,
+        vcek: &Certificate,
+    ) -> Result<VerificationReport, VerifyError> {
+        let (verification, result) = verify_recording(
+            report,
+            vcek,
+            self.product,
+            self.custom_root.as_ref(),
+            &self.policy,
+        );
+        result.map(|()| verification)
+    }
+
+    /// Verifies an attestation report, requesting its VCEK from KDS.
+    ///
+    /// This fails with [`crate::verify_attestation::VcekError::Offline`] if the
+    /// verifier is offline.
+    pub fn fetch_and_verify(
+        &self,
+        report: &AttestationReport,
+    ) -> Result<VerificationReport, VerifyError> {
+        let vcek_bytes =
+            self.kds
+                .request_vcek(report.chip_id, report.reported_tcb, self.product)?;
+        let vcek = Certificate::from_der(&vcek_bytes).map_err(VerifyError::VcekParse)?;
+
+        self.verify(report, &vcek)
+    }
+}
+
+/// A verification step a [`Verifier`] would run, as described by [`Verifier::plan`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PlannedStep {
+    /// The name of the step, as in [`VerificationReport::steps`].
+    pub name: &'static str,
+
+    /// Whether the step runs, rather than being skipped as not configured.
+    pub enabled: bool,
+
+    /// Whether the step may make a network request.
+    pub network: bool,
+}
+
+/// The steps a [`Verifier`] runs, in order, as described by [`Verifier::plan`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct VerifyPlan {
+    /// The product line whose certificate chain reports are verified against.
+    pub product: ProductLine,
+
+    /// Whether the verifier may make network requests.
+    pub online: bool,
+
+    /// Every step, whether or not it is enabled.
+    pub steps: Vec<PlannedStep>,
+}
+
+impl VerifyPlan {
+    /// The planned step with the given name, if there is one.
+    pub fn step(&self, name: &str) -> Option<&PlannedStep> {
+        self.steps.iter().find(|step| step.name == name)
+    }
+
+    /// Whether any enabled step may make a network request.
+    pub fn needs_network(&self) -> bool {
+        self.steps.iter().any(|step| step.enabled && step.network)
+    }
+}
+
+impl fmt::Display for VerifyPlan {
+    /// Lists the steps one per line, as "run" or "skip", noting those that may
+    /// make a network request.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mode = if self.online { "online" } else { "offline" };
+        writeln!(f, "Verification plan for {} ({mode}):", self.product)?;
+        for step in &self.steps {
+            let action = if step.enabled { "run" } else { "skip" };
+            if step.network {
+                writeln!(f, "  {action:<6}{:<24}network", step.name)?;
+            } else {
+                writeln!(f, "  {action:<6}{}", step.name)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Verifies every attestation report in a directory, in order of file name.
+///
+/// Each report may be in any format [`crate::report::ReportFormat`] detects. A
+/// binary report followed by a certificate table (see [`crate::extended_report`])
+/// is verified with the VCEK from the table; other reports have their VCEK
+/// requested by [`Verifier::fetch_and_verify`].
+///
+/// Files that are not attestation reports (those that cannot be parsed as one,
+/// or whose version or signature algorithm is unknown) and subdirectories are
+/// skipped. Reports of a future version are verified if the verifier verifies
+/// them on a best-effort basis (see [`UnknownVersionPolicy`]).
+/// A file that cannot be read is yielded with its I/O error.
+pub fn verify_directory<'a>(
+    dir: &Path,
+    verifier: &'a Verifier,
+) -> Result<
+    impl Iterator<Item = (PathBuf, Result<VerificationReport, VerifyError>)> + 'a,
+    VerifyError,
+> {
+    let mut paths: Vec<PathBuf> = std::fs::read_dir(dir)
+        .and_then(|entries| {
+            entries
+                .map(|entry| entry.map(|entry| entry.path()))
+                .collect()
+        })
+        .map_err(VerifyError::Io)?;
+    paths.sort();
+
+    Ok(paths.into_iter().filter_map(move |path| {
+        if path.is_dir() {
+            return None;
+        }
+        let data = match std::fs::read(&path) {
+            Ok(data) => data,
+            Err(e) => return Some((path, Err(VerifyError::Io(e)))),
+        };
+        let extended = match parse_report_with_certs(&data, None, verifier.product) {
+            Ok(extended) if is_verifiable(verifier, &extended.report) => extended,
+            _ => {
+                debug!(path = %path.display(), "skipping a file that is not a report");
+                return None;
+            }
+        };
+
+        let result = match extended.certs {
+            Some(bundle) => verifier.verify(&extended.report, &bundle.vcek),
+            None => verifier.fetch_and_verify(&extended.report),
+        };
+        Some((path, result))
+    }))
+}
+
+/// Whether a report has a version and signature algorithm the verifier accepts.
+fn is_verifiable(verifier: &Verifier, report: &AttestationReport) -> bool {
+    match verifier.policy.unknown_version {
+        UnknownVersionPolicy::BestEffort if is_future_report_version(report) => {
+            check_signature_algo(report).is_ok()
+        }
+        _ => check_report_format(report).is_ok(),
+    }
+}
+
+/// A builder for a [`Verifier`].
+#[derive(Debug, Clone)]
+pub struct VerifierBuilder {
+    verifier: Verifier,
+}
+
+impl VerifierBuilder {
+    /// Requires the report's launch measurement to be the given one.
+    pub fn expect_measurement(mut self, measurement: [u8; 48]) -> Self {
+        self.verifier.policy.expected_measurement = Some(measurement);
+        self
+    }
+
+    /// Requires the report's launch measurement to be one of the given ones.
+    pub fn allow_measurements(mut self, allowed: &[[u8; 48]]) -> Self {
+        self.verifier.policy.allowed_measurements = Some(allowed.to_vec());
+        self
+    }
+
+    /// Requires the report's report data to start with the given nonce.
+    ///
+    /// An empty nonce would be a prefix of every report's report data, so no
+    /// report passes with one.
+    pub fn require_nonce(mut self, nonce: impl Into<Vec<u8>>) -> Self {
+        self.verifier.policy.expected_report_data = Some(nonce.into());
+        self
+    }
+
+    /// Requires the report's host data to be the given one.
+    pub fn expect_host_data(mut self, host_data: [u8; 32]) -> Self {
+        self.verifier.policy.expected_host_data = Some(host_data);
+        self
+    }
+
+    /// Requires the report's family ID to be the given one.
+    pub fn expect_family_id(mut self, family_id: [u8; 16]) -> Self {
+        self.verifier.policy.expected_family_id = Some(family_id);
+        self
+    }
+
+    /// Requires the report's image ID to be the given one.
+    pub fn expect_image_id(mut self, image_id: [u8; 16]) -> Self {
+        self.verifier.policy.expected_image_id = Some(image_id);
+        self
+    }
+
+    /// Requires the report's report ID to be the given one, so that it comes
+    /// from a guest attested before.
+    pub fn expect_report_id(mut self, report_id: [u8; 32]) -> Self {
+        self.verifier.policy.expected_report_id = Some(report_id);
+        self
+    }
+
+    /// Requires the guest's migration agent to be the one with the given report ID.
+    pub fn expect_migration_agent(mut self, report_id_ma: [u8; 32]) -> Self {
+        self.verifier.policy.expected_report_id_ma = Some(report_id_ma);
+        self
+    }
+
+    /// Requires the guest to have no migration agent, so that it cannot have
+    /// been migrated with the help of one (see [`NO_MIGRATION_AGENT`]).
+    pub fn require_no_migration_agent(mut self) -> Self {
+        self.verifier.policy.expected_report_id_ma = Some(NO_MIGRATION_AGENT);
+        self
+    }
+
+    /// Requires the report to have been requested from the given VMPL.
+    ///
+    /// See [`VerifyPolicy::required_vmpl`] for when this matters.
+    pub fn require_vmpl(mut self, vmpl: u32) -> Self {
+        self.verifier.policy.required_vmpl = Some(vmpl);
+        self
+    }
+
+    /// Requires the guest's ID block to be signed by a key on the allowlist.
+    pub fn allow_signing_keys(mut self, allowlist: SigningKeyAllowlist) -> Self {
+        self.verifier.policy.allowed_signing_keys = Some(allowlist);
+        self
+    }
+
+    /// Requires the guest to have been launched with the given ID authentication
+    /// information, so that its ID block is signed by the guest owner's keys.
+    pub fn id_auth(mut self, id_auth: IdAuth) -> Self {
+        self.verifier.policy.id_auth = Some(id_auth);
+        self
+    }
+
+    /// Requires the report to be at most `max_age` old, measured from when its
+    /// nonce was issued.
+    ///
+    /// Reports carry no trustworthy timestamp, so this relies on `issued_at`, the
+    /// time the caller issued the nonce passed to [`VerifierBuilder::require_nonce`].
+    pub fn max_age(mut self, max_age: Duration, issued_at: SystemTime) -> Self {
+        self.verifier.policy.freshness = Some(Freshness { issued_at, max_age });
+        self
+    }
+
+    /// Requires the report's reported TCB to meet the given minimum.
+    pub fn min_tcb(mut self, minimum: TcbVersion) -> Self {
+        self.verifier.policy.min_tcb = Some(minimum);
+        self
+    }
+
+    /// Requires the report's guest policy flags to have the given values.
+    pub fn guest_policy(mut self, requirements: PolicyRequirements) -> Self {
+        self.verifier.policy.guest_policy = requirements;
+        self
+    }
+
+    /// Requires the VCEK to be the one with the given SHA-256 fingerprint (of its
+    /// DER encoding), rejecting any other VCEK even if it chains to the ARK.
+    pub fn pin_vcek_sha256(mut self, fingerprint: [u8; 32]) -> Self {
+        self.verifier.policy.pinned_vcek_sha256 = Some(fingerprint);
+        self
+    }
+
+    /// Checks that neither the ASK nor the VCEK is revoked by the given CRL.
+    pub fn crl(mut self, crl: Crl) -> Self {
+        self.verifier.policy.crl = Some(crl);
+        self
+    }
+
+    /// Remembers VCEKs whose certificate chain has verified in the given cache,
+    /// so that later reports signed by them need only their signature checked.
+    ///
+    /// The VCEK pin, revocation and validity period are still checked for every report.
+    pub fn chain_cache(mut self, cache: ChainCache) -> Self {
+        self.verifier.policy.chain_cache = Some(cache);
+        self
+    }
+
+    /// Checks the VCEK's validity at the given time, rather than the current time.
+    pub fn as_of(mut self, time: SystemTime) -> Self {
+        self.verifier.policy.as_of = Some(time);
+        self
+    }
+
+    /// Sets how reports of a version newer than this crate supports are treated:
+    /// rejected (the default), or verified on a best-effort basis.
+    pub fn unknown_version(mut self, unknown_version: UnknownVersionPolicy) -> Self {
+        self.verifier.policy.unknown_version = unknown_version;
+        self
+    }
+
+    /// Replaces every check with those of the given policy, such as one loaded
+    /// with [`crate::policy_file::policy_from_json`] or
+    /// [`crate::policy_file::policy_from_toml`].
+    ///
+    /// Checks set before this call are discarded; those set after are added to it.
+    pub fn policy(mut self, policy: VerifyPolicy) -> Self {
+        self.verifier.policy = policy;
+        self
+    }
+
+    /// Uses the given client to request VCEKs from KDS.
+    pub fn kds_client(mut self, kds: KdsClient) -> Self {
+        self.verifier.kds = kds;
+        self
+    }
+
+    /// Forbids (if `offline` is true) any network request.
+    pub fn offline(mut self, offline: bool) -> Self {
+        let mode = if offline {
+            NetworkMode::Offline
+        } else {
+            NetworkMode::Online
+        };
+        self.verifier.kds = self.verifier.kds.with_mode(mode);
+        self
+    }
+
+    /// Trusts the given ARK/ASK chain in place of AMD's, such as the root of a
+    /// staging environment or of pre-release hardware.
+    ///
+    /// The chain must still be well-formed, with a self-signed ARK that signed
+    /// the ASK, or every report fails verification. As its ARK is not AMD's, a
+    /// warning is logged, and such a verifier must never be used in production.
+    pub fn custom_root(mut self, ca: ca::Chain) -> Self {
+        warn!(
+            product = %self.verifier.product,
+            ark_fingerprint = crate::verify_attestation::ark_fingerprint(&ca)
+                .map(hex::encode)
+                .unwrap_or_default(),
+            "trusting a custom root instead of the AMD ARK; do not use this in production"
+        );
+        self.verifier.custom_root = Some(ca);
+        self
+    }
+
+    /// Trusts the ARK/ASK chain in the given PEM file in place of AMD's, as
+    /// [`VerifierBuilder::custom_root`] does.
+    ///
+    /// The file holds the ASK, then the ARK, as read by [`ca_chain_from_file`],
+    /// which checks that the ASK is signed by the ARK.
+    pub fn custom_root_file(self, path: impl AsRef<Path>) -> Result<Self, VerifyError> {
+        Ok(self.custom_root(ca_chain_from_file(path)?))
+    }
+
+    /// Builds the verifier.
+    pub fn build(self) -> Verifier {
+        self.verifier
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::verification_report::StepOutcome;
+    use crate::verify_attestation::{get_cert_chain, VcekError, SAMPLE_ATTESTATION, SAMPLE_VCEK};
+
+    #[test]
+    fn test_verifier() {
+        let report: AttestationReport = serde_json::from_str(SAMPLE_ATTESTATION).unwrap();
+        let vcek = Certificate::from_der(SAMPLE_VCEK).unwrap();
+
+        let verification = Verifier::new(ProductLine::Genoa)
+            .build()
+            .verify(&report, &vcek)
+            .unwrap();
+        assert!(verification.passed());
+
+        let verifier = Verifier::new(ProductLine::Genoa)
+            .expect_measurement(report.measurement)
+            .allow_measurements(&[[0; 48], report.measurement])
+            .require_nonce([7; 32])
+            .expect_host_data(report.host_data)
+            .expect_family_id(report.family_id)
+            .expect_image_id(report.image_id)
+            .expect_report_id(report.report_id)
+            .require_no_migration_agent()
+            .require_vmpl(report.vmpl)
+            .min_tcb(report.reported_tcb)
+            .guest_policy(PolicyRequirements {
+                debug: Some(false),
+                ..Default::default()
+            })
+            .build();
+        let verification = verifier.verify(&report, &vcek).unwrap();
+        for step in [
+            "measurement",
+            "measurement_allowlist",
+            "report_data",
+            "host_data",
+            "family_id",
+            "image_id",
+            "report_id",
+            "report_id_ma",
+            "vmpl",
+            "tcb",
+            "guest_policy",
+        ] {
+            assert_eq!(verification.outcome(step), Some(&StepOutcome::Passed));
+        }
+
+        let stale = Verifier::new(ProductLine::Genoa)
+            .require_nonce([7; 32])
+            .max_age(
+                Duration::from_secs(60),
+                SystemTime::now() - Duration::from_secs(120),
+            )
+            .build();
+        assert!(matches!(
+            stale.verify(&report, &vcek),
+            Err(VerifyError::ReportTooOld { .. })
+        ));
+
+        // The sample report was launched without an ID block
+        let id_auth = IdAuth::from_bytes(include_bytes!("../data/test_id_auth.bin")).unwrap();
+        let verifier = Verifier::new(ProductLine::Genoa).id_auth(id_auth).build();
+        assert!(matches!(
+            verifier.verify(&report, &vcek),
+            Err(VerifyError::IdKeyDigestMismatch { .. })
+        ));
+
+        for nonce in [vec![0; 32], vec![]] {
+            let verifier = Verifier::new(ProductLine::Genoa)
+                .require_nonce(nonce)
+                .build();
+            assert!(matches!(
+                verifier.verify(&report, &vcek),
+                Err(VerifyError::ReportDataMismatch { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn test_pinned_vcek() {
+        let report: AttestationReport = serde_json::from_str(SAMPLE_ATTESTATION).unwrap();
+        let vcek = Certificate::from_der(SAMPLE_VCEK).unwrap();
+
+        let verifier = Verifier::new(ProductLine::Genoa)
+            .pin_vcek_sha256(openssl::sha::sha256(SAMPLE_VCEK))
+            .build();
+        let verification = verifier.verify(&report, &vcek).unwrap();
+        assert_eq!(verification.outcome("vcek_pin"), Some(&StepOutcome::Passed));
+
+        let verifier = Verifier::new(ProductLine::Genoa)
+            .pin_vcek_sha256([0; 32])
+            .build();
+        assert!(matches!(
+            verifier.verify(&report, &vcek),
+            Err(VerifyError::VcekPinMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_chain_cache() {
+        let report: AttestationReport = serde_json::from_str(SAMPLE_ATTESTATION).unwrap();
+        let vcek = Certificate::from_der(SAMPLE_VCEK).unwrap();
+        let cache = ChainCache::default();
+
+        let verifier = Verifier::new(ProductLine::Genoa)
+            .chain_cache(cache.clone())
+            .build();
+        assert!(verifier.verify(&report, &vcek).is_ok());
+        assert_eq!(cache.len(), 1);
+        assert!(verifier.verify(&report, &vcek).is_ok());
+        assert_eq!(cache.len(), 1);
+
+        // A cached chain does not skip the VCEK's validity period
+        let expired = Verifier::new(ProductLine::Genoa)
+            .chain_cache(cache.clone())
+            .as_of(SystemTime::UNIX_EPOCH)
+            .build();
+        assert!(matches!(
+            expired.verify(&report, &vcek),
+            Err(VerifyError::VcekNotYetValid { .. })
+        ));
+
+        // Nor is a VCEK cached under one product's chain trusted under another's
+        let milan = Verifier::new(ProductLine::Milan)
+            .chain_cache(cache.clone())
+            .build();
+        assert!(matches!(
+            milan.verify(&report, &vcek),
+            Err(VerifyError::VcekIssuerMismatch { .. })
+        ));
+        assert_eq!(cache.len(), 1);
+    }
+
+    #[test]
+    fn test_custom_root() {
+        let report: AttestationReport = serde_json::from_str(SAMPLE_ATTESTATION).unwrap();
+        let vcek = Certificate::from_der(SAMPLE_VCEK).unwrap();
+
+        // A Milan verifier rejects the Genoa VCEK, unless told to trust the Genoa root
+        let milan = Verifier::new(ProductLine::Milan).build();
+        assert!(milan.verify(&report, &vcek).is_err());
+        let custom = Verifier::new(ProductLine::Milan)
+            .custom_root_file("data/Genoa.pem")
+            .unwrap()
+            .build();
+        assert!(custom.custom_root().is_some());
+        assert!(custom.verify(&report, &vcek).unwrap().passed());
+
+        // The chain must be consistent, with its ASK signed by its ARK
+        let milan = get_cert_chain(ProductLine::Milan);
+        let genoa = get_cert_chain(ProductLine::Genoa);
+        let mut pem = milan.ask.to_pem().unwrap();
+        pem.extend(genoa.ark.to_pem().unwrap());
+        let path =
+            std::env::temp_dir().join(format!("sev_attest_tool_{}_root", std::process::id()));
+        std::fs::write(&path, pem).unwrap();
+        let result = Verifier::new(ProductLine::Milan).custom_root_file(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(result, Err(VerifyError::MalformedCaChain { .. })));
+
+        let inconsistent = Verifier::new(ProductLine::Milan)
+            .custom_root(ca::Chain {
+                ark: genoa.ark,
+                ask: milan.ask,
+            })
+            .build();
+        assert!(inconsistent.verify(&report, &vcek).is_err());
+    }
+
+    #[test]
+    fn test_unknown_version_policy() {
+        let report: AttestationReport = serde_json::from_str(SAMPLE_ATTESTATION).unwrap();
+        let vcek = Certificate::from_der(SAMPLE_VCEK).unwrap();
+        let verification = Verifier::new(ProductLine::Genoa)
+            .unknown_version(UnknownVersionPolicy::BestEffort)
+            .build()
+            .verify(&report, &vcek)
+            .unwrap();
+        assert!(verification.warnings.is_empty());
+
+        // The version is signed, so a bumped version no longer verifies, but
+        // shows which steps best-effort verification runs
+        let mut future = report;
+        future.version = 9;
+        let (strict, _) = verify_recording(
+            &future,
+            &vcek,
+            ProductLine::Genoa,
+            None,
+            &VerifyPolicy::default(),
+        );
+        assert!(matches!(
+            strict.outcome("report_format"),
+            Some(StepOutcome::Failed(_))
+        ));
+        assert!(strict.warnings.is_empty());
+
+        let policy = VerifyPolicy {
+            unknown_version: UnknownVersionPolicy::BestEffort,
+            ..Default::default()
+        };
+        let (lenient, _) = verify_recording(&future, &vcek, ProductLine::Genoa, None, &policy);
+        assert_eq!(lenient.outcome("report_format"), Some(&StepOutcome::Passed));
+        assert_eq!(
+            lenient.outcome("reserved_fields"),
+            Some(&StepOutcome::Skipped)
+        );
+        assert_eq!(lenient.outcome("vcek_tcb"), Some(&StepOutcome::Passed));
+        assert!(matches!(
+            lenient.outcome("report_signature"),
+            Some(StepOutcome::Failed(_))
+        ));
+        assert_eq!(lenient.warnings.len(), 1);
+        assert!(lenient.warnings[0].contains("report version 9"));
+
+        // Versions before the supported ones are still rejected
+        let mut past = report;
+        past.version = 1;
+        let (lenient, _) = verify_recording(&past, &vcek, ProductLine::Genoa, None, &policy);
+        assert!(matches!(
+            lenient.outcome("report_format"),
+            Some(StepOutcome::Failed(_))
+        ));
+    }
+
+    #[test]
+    fn test_offline_verifier_does_not_fetch() {
+        let report: AttestationReport = serde_json::from_str(SAMPLE_ATTESTATION).unwrap();
+        let verifier = Verifier::new(ProductLine::Genoa).offline(true).build();
+
+        assert!(matches!(
+            verifier.fetch_and_verify(&report),
+            Err(VerifyError::Vcek(VcekError::Offline))
+        ));
+    }
+
+    #[test]
+    fn test_verify_directory() {
+        use crate::extended_report::{build_extended_report, VCEK_GUID};
+        use crate::verify_attestation::tamper_report;
+
+        let report: AttestationReport = serde_json::from_str(SAMPLE_ATTESTATION).unwrap();
+        let mut tampered = report;
+        tamper_report(&mut tampered);
+        let dir =
+            std::env::temp_dir().join(format!("sev_attest_tool_{}_reports", std::process::id()));
+        std::fs::create_dir_all(dir.join("archive")).unwrap();
+        let certs: &[(&str, &[u8])] = &[(VCEK_GUID, SAMPLE_VCEK)];
+        std::fs::write(dir.join("a.bin"), build_extended_report(&report, certs)).unwrap();
+        std::fs::write(dir.join("b.bin"), build_extended_report(&tampered, certs)).unwrap();
+        std::fs::write(dir.join("c.json"), SAMPLE_ATTESTATION).unwrap();
+        std::fs::write(dir.join("README.md"), "reports collected from the fleet\n").unwrap();
+
+        let verifier = Verifier::new(ProductLine::Genoa).offline(true).build();
+        let results: Vec<_> = verify_directory(&dir, &verifier).unwrap().collect();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let names: Vec<_> = results
+            .iter()
+            .map(|(path, _)| path.file_name().unwrap().to_str().unwrap())
+            .collect();
+        assert_eq!(names, ["a.bin", "b.bin", "c.json"]);
+        assert!(results[0].1.is_ok());
+        assert!(matches!(results[1].1, Err(VerifyError::ReportSignature(_))));
+        // Without a certificate table, the VCEK must be requested
+        assert!(matches!(
+            results[2].1,
+            Err(VerifyError::Vcek(VcekError::Offline))
+        ));
+
+        assert!(matches!(
+            verify_directory(&dir, &verifier),
+            Err(VerifyError::Io(_))
+        ));
+    }
+
+    #[test]
+    fn test_plan() {
+        let report: AttestationReport = serde_json::from_str(SAMPLE_ATTESTATION).unwrap();
+        let vcek = Certificate::from_der(SAMPLE_VCEK).unwrap();
+
+        let verifier = Verifier::new(ProductLine::Genoa)
+            .require_nonce([7; 32])
+            .build();
+        let plan = verifier.plan();
+        assert!(plan.online && plan.needs_network());
+        assert!(plan.step("report_data").unwrap().enabled);
+        assert!(!plan.step("measurement").unwrap().enabled);
+        assert!(!plan.step("revocation").unwrap().enabled);
+
+        // The plan names every step verification records, in the same order
+        let verification = verifier.verify(&report, &vcek).unwrap();
+        let planned: Vec<_> = plan.steps.iter().map(|step| step.name).collect();
+        let recorded: Vec<_> = verification.steps.iter().map(|step| step.name).collect();
+        assert_eq!(planned[0], "vcek_request");
+        assert_eq!(planned[1..], recorded[..]);
+
+        let plan = Verifier::new(ProductLine::Genoa)
+            .offline(true)
+            .build()
+            .plan();
+        assert!(!plan.needs_network());
+        assert!(plan
+            .to_string()
+            .starts_with("Verification plan for Genoa (offline):\n  skip  vcek_request"));
+    }
+}
@@ -1,14 +1,47 @@
-use openssl::x509::X509;
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+use std::sync::OnceLock;
+use std::time::SystemTime;
+
+use openssl::{
+    ecdsa::EcdsaSig,
+    hash::{hash, MessageDigest},
+    x509::X509,
+};
 use sev::{
-    certs::snp::{ca, Certificate, Chain, Verifiable},
+    certs::snp::{ca, Certificate, Verifiable},
     firmware::{guest::AttestationReport, host::TcbVersion},
 };
 
+use crate::chain_cache::ChainKey;
+use crate::crypto::{ct_eq, is_pem_certificate, CertBackend, DefaultBackend};
+use crate::envelope::Envelope;
+use crate::extended_report::parse_extended_report;
+use crate::kds::KdsClient;
+use crate::policy::{
+    DecodedPolicy, PolicyFlag, SigningKeyDigest, UnknownVersionPolicy, VerifyPolicy,
+    NO_MIGRATION_AGENT,
+};
+use crate::product::ProductLine;
+use crate::report::{
+    check_report_format, check_reserved_fields, check_signature_algo, is_future_report_version,
+    parse_report_bytes, report_signing_key_id, report_to_array, ReportFormat, SIGNED_REGION,
+};
+use crate::tcb::{tcb_to_string, TcbComponent};
+use crate::vcek::{
+    check_vcek_chip_id, check_vcek_issuer, check_vcek_pin, check_vcek_tcb, check_vcek_validity,
+    TcbSpl,
+};
+use crate::verification_report::{StepOutcome, VerificationReport, VerificationStep};
+
 /// The AMD Genoa ARK and ASK certificates.
-pub const GENOA_PEM: &'static [u8] = include_bytes!("../data/Genoa.pem");
+pub const GENOA_PEM: &[u8] = include_bytes!("../data/Genoa.pem");
 
-/// The AMD SEV-SNP product name for Genoa.
-pub const SEV_PROD_NAME: &str = "Genoa";
+/// The AMD Milan ARK and ASK certificates.
+pub const MILAN_PEM: &[u8] = include_bytes!("../data/Milan.pem");
+
+/// The AMD Turin ARK and ASK certificates.
+pub const TURIN_PEM: &[u8] = include_bytes!("../data/Turin.pem");
 
 /// The AMD Key Distribution Service (KDS) URL.
 pub const KDS_CERT_SITE: &str = "https://kdsintf.amd.com";
@@ -16,35 +49,313 @@ pub const KDS_CERT_SITE: &str = "https://kdsintf.amd.com";
 /// The AMD Key Distribution Service (KDS) VCEK endpoint.
 pub const KDS_VCEK: &str = "/vcek/v1";
 
-const _KDS_CERT_CHAIN: &str = "cert_chain";
+/// The AMD Key Distribution Service (KDS) VLEK endpoint.
+pub const KDS_VLEK: &str = "/vlek/v1";
+
+/// The AMD Key Distribution Service (KDS) ARK/ASK certificate chain endpoint.
+pub const KDS_CERT_CHAIN: &str = "cert_chain";
+
+/// The AMD Key Distribution Service (KDS) certificate revocation list (CRL) endpoint.
+pub const KDS_CRL: &str = "crl";
 
 /// A sample attestation report, as a JSON string.
-pub const SAMPLE_ATTESTATION: &'static str = include_str!("../data/sample_attestation_report.json");
+pub const SAMPLE_ATTESTATION: &str = include_str!("../data/sample_attestation_report.json");
 
 /// A sample VCEK, as bytes for a DER-encoded X509 certificate.
-pub const SAMPLE_VCEK: &'static [u8] = include_bytes!("../data/sample_vcek.crt");
+pub const SAMPLE_VCEK: &[u8] = include_bytes!("../data/sample_vcek.crt");
+
+/// A sample Turin VCEK, as bytes for a DER-encoded X509 certificate.
+pub const SAMPLE_VCEK_TURIN: &[u8] = include_bytes!("../data/sample_vcek_turin.crt");
+
+/// A Turin report, signed by [`TEST_TURIN_VCEK`].
+///
+/// No report from Turin hardware is public, so the VCEK and its chain are
+/// issued by a test ARK and ASK rather than AMD's. The report's TCB versions
+/// are FMC 1, bootloader 2, TEE 1, SNP 24 and microcode 72, in Turin's layout.
+#[cfg(test)]
+pub(crate) const TEST_TURIN_REPORT: &[u8] = include_bytes!("../data/test_turin_report.bin");
+
+#[cfg(test)]
+pub(crate) const TEST_TURIN_VCEK: &[u8] = include_bytes!("../data/test_turin_vcek.crt");
+
+/// The test Turin ASK, then the test Turin ARK, as in KDS's `cert_chain`.
+#[cfg(test)]
+pub(crate) const TEST_TURIN_CERT_CHAIN: &[u8] = include_bytes!("../data/test_turin_cert_chain.pem");
 
 /// Requests the main AMD SEV-SNP certificate chain.
 ///
 /// This is the chain of certificates up to the AMD Root Key (ARK).
 /// The order is (chip) -> (vcek) -> (ask) -> (ark).
 /// These may be used to verify the downloaded VCEK is authentic.
-pub fn get_cert_chain(sev_prod_name: &str) -> ca::Chain {
-    // The chain can be retrieved at "https://kdsintf.amd.com/vcek/v1/{SEV_PROD_NAME}/cert_chain"
-    // let url = format!("{KDS_CERT_SITE}{KDS_VCEK}/{sev_prod_name}/{KDS_CERT_CHAIN}");
-    // let pem = reqwest::blocking::get(&url).unwrap().bytes().unwrap().to_vec();
+///
+/// The chain is built from the certificates embedded in this crate, so no network
+/// request is made. See [`get_cert_chain_remote`] to fetch the chain from KDS instead.
+///
+/// # Panics
+///
+/// Panics if the embedded ARK is not the one pinned by [`ProductLine::ark_sha256`],
+/// as when the crate was built with a substituted certificate file. See
+/// [`try_get_cert_chain`] to handle this as an error.
+pub fn get_cert_chain(product: ProductLine) -> ca::Chain {
+    try_get_cert_chain(product).expect("embedded ARK is the pinned ARK")
+}
+
+/// Returns the main AMD SEV-SNP certificate chain, as [`get_cert_chain`] does, or
+/// [`VerifyError::UntrustedRoot`] if the embedded ARK is not the pinned one.
+pub fn try_get_cert_chain(product: ProductLine) -> Result<ca::Chain, VerifyError> {
+    embedded_cert_chain(product).cloned()
+}
+
+/// The chain built from the embedded certificates for the product, if its ARK
+/// has the pinned fingerprint.
+///
+/// Each product's PEM is parsed on first use only, as every verification needs
+/// the chain. Cloning it only takes references to the certificates.
+fn embedded_cert_chain(product: ProductLine) -> Result<&'static ca::Chain, VerifyError> {
+    static MILAN: OnceLock<Option<ca::Chain>> = OnceLock::new();
+    static GENOA: OnceLock<Option<ca::Chain>> = OnceLock::new();
+    static TURIN: OnceLock<Option<ca::Chain>> = OnceLock::new();
+
+    let chain = match product {
+        ProductLine::Milan => &MILAN,
+        ProductLine::Genoa => &GENOA,
+        ProductLine::Turin => &TURIN,
+    };
+    chain
+        .get_or_init(|| {
+            let chain = ca_chain_from_pem(product.embedded_pem())
+                .expect("embedded certificate chain is valid");
+            let pinned = ark_fingerprint(&chain)
+                .is_ok_and(|fingerprint| ct_eq(&fingerprint, &product.ark_sha256()));
+            if !pinned {
+                error!(%product, "embedded ARK does not match the pinned fingerprint");
+            }
+            pinned.then_some(chain)
+        })
+        .as_ref()
+        .ok_or(VerifyError::UntrustedRoot)
+}
+
+/// Requests the AMD SEV-SNP certificate chain from the AMD Key Distribution Service (KDS).
+///
+/// The downloaded ARK must match the embedded ARK for the product, so a compromised
+/// or impersonated KDS cannot swap the root of trust; the downloaded ASK must be
+/// signed by that ARK. If KDS is unreachable, [`get_cert_chain`] provides the same
+/// chain from the embedded certificates.
+///
+/// To use a KDS mirror, see [`KdsClient::get_cert_chain`].
+pub fn get_cert_chain_remote(product: ProductLine) -> Result<ca::Chain, VerifyError> {
+    KdsClient::default().get_cert_chain(product)
+}
+
+/// Builds an ARK/ASK certificate chain from a downloaded PEM stack, checking it
+/// against the embedded ARK for the product.
+pub(crate) fn ca_chain_from_remote_pem(
+    product: ProductLine,
+    pem: &[u8],
+) -> Result<ca::Chain, VerifyError> {
+    let chain = ca_chain_from_pem(pem)?;
+    check_ark_is_embedded(product, &chain)?;
+
+    Ok(chain)
+}
 
-    if sev_prod_name != SEV_PROD_NAME {
-        panic!("Only Genoa is supported at this time");
+/// Checks that the ARK of a chain obtained elsewhere is the embedded ARK for the
+/// product, by its pinned fingerprint.
+pub(crate) fn check_ark_is_embedded(
+    product: ProductLine,
+    chain: &ca::Chain,
+) -> Result<(), VerifyError> {
+    let fingerprint = ark_fingerprint(chain)?;
+    debug!(%product, ark_fingerprint = hex::encode(&fingerprint), "checking certificate chain");
+    if !ct_eq(&fingerprint, &product.ark_sha256()) {
+        error!(%product, "ARK does not match the embedded ARK");
+        return Err(VerifyError::UntrustedRoot);
     }
 
-    let chain = X509::stack_from_pem(&GENOA_PEM).unwrap();
+    Ok(())
+}
+
+/// The SHA-256 fingerprint of the ARK in the given chain.
+pub(crate) fn ark_fingerprint(chain: &ca::Chain) -> Result<Vec<u8>, VerifyError> {
+    let ark = X509::from(&chain.ark);
+    let digest = ark
+        .digest(MessageDigest::sha256())
+        .map_err(|e| VerifyError::ChainBuild(e.into()))?;
+    Ok(digest.to_vec())
+}
+
+/// Builds an ARK/ASK certificate chain from a PEM stack ordered (ask) -> (ark).
+///
+/// The stack must hold exactly those two certificates, and must be a valid chain
+/// (see [`validate_ca_chain`]); this does not check that the ARK is AMD's.
+pub fn ca_chain_from_pem(pem: &[u8]) -> Result<ca::Chain, VerifyError> {
+    let chain = DefaultBackend::default()
+        .pem_to_der_stack(pem)
+        .map_err(VerifyError::ChainBuild)?;
+    let [ask, ark] = chain.as_slice() else {
+        return Err(VerifyError::MalformedCaChain {
+            reason: format!(
+                "expected 2 certificates (ASK, then ARK), found {}",
+                chain.len()
+            ),
+        });
+    };
+
+    ca_chain_from_der(ask, ark)
+}
 
+/// Reads an ARK/ASK certificate chain from a PEM file ordered (ask) -> (ark), as
+/// [`ca_chain_from_pem`] parses it.
+///
+/// This is the format of KDS's `cert_chain` file, and suits the roots of test or
+/// staging environments, which this crate does not embed.
+pub fn ca_chain_from_file(path: impl AsRef<Path>) -> Result<ca::Chain, VerifyError> {
+    let pem = std::fs::read(path).map_err(VerifyError::Io)?;
+    ca_chain_from_pem(&pem)
+}
+
+/// Builds an ARK/ASK certificate chain from DER-encoded certificates, checking
+/// that it is a valid chain (see [`validate_ca_chain`]).
+pub(crate) fn ca_chain_from_der(ask: &[u8], ark: &[u8]) -> Result<ca::Chain, VerifyError> {
     // Create a certificate chain with the ARK and ASK
-    let (ark, ask) = (&chain[1].to_pem().unwrap(), &chain[0].to_pem().unwrap());
-    let cert_chain = ca::Chain::from_pem(ark, ask).unwrap();
+    let chain = ca::Chain {
+        ark: Certificate::from_der(ark).map_err(VerifyError::ChainBuild)?,
+        ask: Certificate::from_der(ask).map_err(VerifyError::ChainBuild)?,
+    };
+    validate_ca_chain(&chain)?;
+
+    Ok(chain)
+}
+
+/// A VCEK, along with the ASK and ARK it was distributed with, if any.
+#[derive(Debug, Clone)]
+pub struct VcekBundle {
+    /// The VCEK.
+    pub vcek: Certificate,
+
+    /// The ARK/ASK chain bundled with the VCEK, whose ARK is AMD's.
+    pub ca: Option<ca::Chain>,
+}
+
+/// Parses a VCEK, either DER or PEM encoded, or as a PEM chain ordered
+/// (vcek) -> (ask) -> (ark), as some tools distribute it.
+///
+/// A bundled chain must be valid, and its ARK must be the embedded ARK for the
+/// product, so a bundle cannot substitute its own root of trust.
+pub fn parse_vcek_bundle(data: &[u8], product: ProductLine) -> Result<VcekBundle, VerifyError> {
+    if !is_pem_certificate(data) {
+        let vcek = Certificate::from_der(data).map_err(VerifyError::VcekParse)?;
+        return Ok(VcekBundle { vcek, ca: None });
+    }
+
+    let certs = DefaultBackend::default()
+        .pem_to_der_stack(data)
+        .map_err(VerifyError::VcekParse)?;
+    let (vcek, ca) = match certs.as_slice() {
+        [vcek] => (vcek, None),
+        [vcek, ask, ark] => {
+            let ca = ca_chain_from_der(ask, ark)?;
+            check_ark_is_embedded(product, &ca)?;
+            (vcek, Some(ca))
+        }
+        _ => {
+            return Err(VerifyError::MalformedCaChain {
+                reason: format!(
+                    "expected 1 certificate (VCEK) or 3 (VCEK, ASK, then ARK), found {}",
+                    certs.len()
+                ),
+            })
+        }
+    };
+    let vcek = Certificate::from_der(vcek).map_err(VerifyError::VcekParse)?;
+
+    Ok(VcekBundle { vcek, ca })
+}
+
+/// An error encountered while requesting a VCEK (or other certificates) from the
+/// AMD Key Distribution Service (KDS).
+#[derive(Debug)]
+pub enum VcekError {
+    /// The HTTP request to KDS failed.
+    Http(reqwest::Error),
+
+    /// KDS responded with a non-success status code.
+    Status {
+        status: reqwest::StatusCode,
+        body: String,
+    },
+
+    /// KDS responded successfully, but with an empty body.
+    EmptyBody,
 
-    cert_chain
+    /// KDS responded successfully, but with something other than a DER-encoded
+    /// certificate, such as an HTML error page. `snippet` is the start of the body.
+    UnexpectedResponse { snippet: String },
+
+    /// KDS kept responding with a retryable status (such as 429) until the
+    /// retries ran out. `attempts` counts every request, including the first.
+    Exhausted {
+        attempts: u32,
+        status: reqwest::StatusCode,
+    },
+
+    /// A request to KDS was needed, but the client is offline.
+    Offline,
+
+    /// A request to KDS did not complete within the client's timeout.
+    Timeout { timeout: std::time::Duration },
+
+    /// KDS responded with a body larger than the client's maximum response size.
+    ResponseTooLarge { limit: usize },
+
+    /// KDS responded with a compressed body that could not be decompressed.
+    Decompress {
+        encoding: &'static str,
+        reason: String,
+    },
+}
+
+impl std::fmt::Display for VcekError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            VcekError::Http(e) => write!(f, "KDS request failed: {e}"),
+            VcekError::Status { status, body } => {
+                write!(f, "KDS responded with status {status}: {body}")
+            }
+            VcekError::EmptyBody => write!(f, "KDS responded with an empty body"),
+            VcekError::UnexpectedResponse { snippet } => write!(
+                f,
+                "KDS responded with something other than a certificate: {snippet}"
+            ),
+            VcekError::Exhausted { attempts, status } => write!(
+                f,
+                "KDS responded with status {status} after {attempts} attempts"
+            ),
+            VcekError::Offline => write!(
+                f,
+                "a request to KDS was needed, but network access is disabled (offline mode)"
+            ),
+            VcekError::Timeout { timeout } => {
+                write!(f, "KDS request timed out after {timeout:?}")
+            }
+            VcekError::ResponseTooLarge { limit } => {
+                write!(f, "KDS response exceeds the maximum size of {limit} bytes")
+            }
+            VcekError::Decompress { encoding, reason } => {
+                write!(f, "could not decompress {encoding} KDS response: {reason}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for VcekError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            VcekError::Http(e) => Some(e),
+            _ => None,
+        }
+    }
 }
 
 /// Requests the VCEK for the specified chip and TCP.
@@ -57,90 +368,1317 @@ pub fn get_cert_chain(sev_prod_name: &str) -> ca::Chain {
 /// valid for 7 years from issuance.
 ///
 /// This function returns the VCEK as a DER-encoded X509 certificate.
-pub fn request_vcek(chip_id: [u8; 64], reported_tcb: TcbVersion, sev_prod_name: &str) -> Vec<u8> {
-    let hw_id = hex::encode(&chip_id);
-    let url = format!(
-    "{KDS_CERT_SITE}{KDS_VCEK}/{sev_prod_name}/{hw_id}?blSPL={:02}&teeSPL={:02}&snpSPL={:02}&ucodeSPL={:02}",
-        reported_tcb.bootloader,
-        reported_tcb.tee,
-        reported_tcb.snp,
-        reported_tcb.microcode,
-        );
-    // println!("Requesting VCEK from: {url}\n");
-    let rsp_bytes = reqwest::blocking::get(&url)
-        .unwrap()
-        .bytes()
-        .unwrap()
-        .to_vec();
-    rsp_bytes
-}
-
-/// Verifies an attestation report, using the provided file paths and options.
-pub fn verify_attestation_report_cli(
-    report_path: &str,
-    vcek_path: Option<&str>,
-    fail_on_purpose: bool,
-) {
-    let report_json_str = std::fs::read_to_string(report_path).unwrap();
-    let vcek_bytes = if let Some(vcek_path) = vcek_path {
-        std::fs::read(vcek_path).unwrap()
-    } else {
-        let report: AttestationReport = serde_json::from_str(&report_json_str).unwrap();
-        let vcek = request_vcek(report.chip_id, report.reported_tcb, SEV_PROD_NAME);
-        vcek
-    };
+/// To use a KDS mirror, see [`KdsClient::request_vcek`].
+pub fn request_vcek(
+    chip_id: [u8; 64],
+    reported_tcb: TcbVersion,
+    product: ProductLine,
+) -> Result<Vec<u8>, VcekError> {
+    KdsClient::default().request_vcek(chip_id, reported_tcb, product)
+}
 
-    verify_attestation_report(&report_json_str, &vcek_bytes, fail_on_purpose);
+/// Requests the VCEK for the specified chip and TCB together with the ARK/ASK
+/// chain that issued it, checking that the VCEK chains to it.
+///
+/// See [`KdsClient::request_vcek_bundle`].
+pub fn request_vcek_bundle(
+    chip_id: [u8; 64],
+    reported_tcb: TcbVersion,
+    product: ProductLine,
+) -> Result<VcekBundle, VerifyError> {
+    KdsClient::default().request_vcek_bundle(chip_id, reported_tcb, product)
+}
+
+/// Checks that the AMD Key Distribution Service (KDS) is reachable, returning the
+/// latency of a request for the product's certificate chain.
+///
+/// Services can call this at startup to tell an unreachable KDS apart from
+/// reports that fail verification. To check a KDS mirror, see [`KdsClient::health_check`].
+pub fn kds_health_check(product: ProductLine) -> Result<std::time::Duration, VcekError> {
+    KdsClient::default().health_check(product)
+}
+
+/// Requests the VCEK for the specified chip and TCB, and writes it to `out` as
+/// a DER-encoded X509 certificate.
+///
+/// The VCEK is checked to chain to the product's ARK before it is written. It
+/// can then be passed to [`verify_attestation_report`] to verify reports from
+/// that chip offline, until its TCB changes.
+/// To use a KDS mirror, see [`KdsClient::save_vcek`].
+pub fn save_vcek(
+    chip_id: [u8; 64],
+    reported_tcb: TcbVersion,
+    product: ProductLine,
+    out: &Path,
+) -> Result<(), VerifyError> {
+    KdsClient::default().save_vcek(chip_id, reported_tcb, product, out)
+}
+
+/// Requests the VCEK for the specified chip and TCB, without blocking.
+///
+/// This is the async equivalent of [`request_vcek`], for use inside an async runtime.
+#[cfg(feature = "async")]
+pub async fn request_vcek_async(
+    chip_id: [u8; 64],
+    reported_tcb: TcbVersion,
+    product: ProductLine,
+) -> Result<Vec<u8>, VcekError> {
+    KdsClient::default()
+        .request_vcek_async(chip_id, reported_tcb, product)
+        .await
+}
+
+/// An error encountered while verifying an attestation report.
+#[derive(Debug)]
+pub enum VerifyError {
+    /// A report or VCEK file could not be read.
+    Io(std::io::Error),
+
+    /// The VCEK could not be requested from KDS.
+    Vcek(VcekError),
+
+    /// The attestation report could not be parsed.
+    ReportParse(serde_json::Error),
+
+    /// The binary attestation report is not the expected size.
+    ReportLength { expected: usize, actual: usize },
+
+    /// The binary attestation report could not be decoded.
+    ReportDecode(bincode::Error),
+
+    /// The VCEK could not be parsed as a DER-encoded X509 certificate.
+    VcekParse(std::io::Error),
+
+    /// The ARK/ASK certificate chain could not be constructed.
+    ChainBuild(std::io::Error),
+
+    /// The VCEK -> ASK -> ARK certificate chain did not verify.
+    ChainVerify(std::io::Error),
+
+    /// The attestation report was not signed by the VCEK.
+    ReportSignature(std::io::Error),
+
+    /// The ARK does not match the trusted AMD root key.
+    UntrustedRoot,
+
+    /// The report's launch measurement is not the expected one.
+    MeasurementMismatch {
+        expected: [u8; 48],
+        actual: [u8; 48],
+    },
+
+    /// The report's launch measurement is not on the allowlist.
+    MeasurementNotAllowed { actual: [u8; 48] },
+
+    /// The report's guest-provided report data is not the expected one.
+    ReportDataMismatch { expected: Vec<u8>, actual: [u8; 64] },
+
+    /// A flag of the report's guest policy does not have the required value.
+    PolicyViolation {
+        flag: PolicyFlag,
+        required: bool,
+        actual: bool,
+    },
+
+    /// A component of the report's reported TCB is below the required minimum.
+    TcbBelowMinimum {
+        component: TcbComponent,
+        minimum: u8,
+        actual: u8,
+    },
+
+    /// The report's reported TCB is lower than its committed TCB.
+    TcbRollback {
+        reported: TcbVersion,
+        committed: TcbVersion,
+    },
+
+    /// The report was not signed by the kind of endorsement key provided.
+    SigningKeyMismatch { expected: &'static str, actual: u32 },
+
+    /// The VCEK expired before the time it was checked at.
+    VcekExpired { not_after: String },
+
+    /// The VCEK was not yet valid at the time it was checked at.
+    VcekNotYetValid { not_before: String },
+
+    /// The VCEK is not the pinned one.
+    VcekPinMismatch {
+        expected: [u8; 32],
+        actual: [u8; 32],
+    },
+
+    /// The VCEK was issued for a TCB other than the report's reported TCB.
+    VcekTcbMismatch { vcek: TcbSpl, reported: TcbSpl },
+
+    /// The VCEK names an issuer other than the ASK it is checked against, as
+    /// when it was issued by a newer ASK than the one trusted.
+    VcekIssuerMismatch {
+        vcek_issuer: String,
+        ask_subject: String,
+    },
+
+    /// The VCEK was issued for a chip other than the one that produced the report.
+    ChipIdMismatch { vcek: Vec<u8>, report: [u8; 64] },
+
+    /// The report's hypervisor-provided host data is not the expected one.
+    HostDataMismatch {
+        expected: [u8; 32],
+        actual: [u8; 32],
+    },
+
+    /// The report's family ID is not the expected one.
+    FamilyIdMismatch {
+        expected: [u8; 16],
+        actual: [u8; 16],
+    },
+
+    /// The report's image ID is not the expected one.
+    ImageIdMismatch {
+        expected: [u8; 16],
+        actual: [u8; 16],
+    },
+
+    /// The report's report ID is not the expected one.
+    ReportIdMismatch {
+        expected: [u8; 32],
+        actual: [u8; 32],
+    },
+
+    /// The report's migration agent is not the expected one, or the guest has a
+    /// migration agent where none is allowed (or none where one is expected).
+    UnexpectedMigrationAgent {
+        expected: [u8; 32],
+        actual: [u8; 32],
+    },
+
+    /// The report was requested from a different VMPL than the policy requires.
+    UnexpectedVmpl { expected: u32, actual: u32 },
+
+    /// The guest's ID block was signed by a key that is not on the allowlist.
+    UnexpectedSigningKey {
+        key: SigningKeyDigest,
+        digest: [u8; 48],
+    },
+
+    /// The ID authentication information could not be parsed.
+    IdAuthInvalid(std::io::Error),
+
+    /// The report's ID key (or author key) digest is not that of the supplied key.
+    IdKeyDigestMismatch {
+        key: SigningKeyDigest,
+        expected: [u8; 48],
+        actual: [u8; 48],
+    },
+
+    /// The ID block (or, for the author key, the ID key) is not signed by the key.
+    IdAuthSignature { signer: SigningKeyDigest },
+
+    /// The report's version is not one whose layout is supported.
+    UnsupportedReportVersion { version: u32 },
+
+    /// The report is not signed with ECDSA P-384 with SHA-384.
+    UnsupportedSignatureAlgo { algo: u32 },
+
+    /// A reserved field of the report, which must be zero, is not.
+    NonZeroReserved { offset: usize },
+
+    /// The report is older than the policy's maximum age.
+    ReportTooOld {
+        age: std::time::Duration,
+        max_age: std::time::Duration,
+    },
+
+    /// The ARK/ASK certificate chain is not made up of a self-signed ARK and an
+    /// ASK signed by it.
+    MalformedCaChain { reason: String },
+
+    /// The encoded attestation report could not be decoded.
+    ReportEncoding {
+        format: ReportFormat,
+        reason: String,
+    },
+
+    /// The certificate table following an extended attestation report is malformed.
+    CertTableInvalid { reason: String },
+
+    /// The cloud attestation envelope wrapping the report is malformed.
+    EnvelopeInvalid { envelope: Envelope, reason: String },
+
+    /// The certificate revocation list could not be parsed, or is not signed by the ARK.
+    CrlInvalid(std::io::Error),
+
+    /// The certificate revocation list was superseded before the time it was checked at.
+    CrlExpired { next_update: String },
+
+    /// The certificate revocation list was not yet issued at the time it was checked at.
+    CrlNotYetValid { last_update: String },
+
+    /// A certificate in the chain has been revoked.
+    CertificateRevoked {
+        certificate: &'static str,
+        serial: String,
+    },
+}
+
+impl std::fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            VerifyError::Io(e) => write!(f, "could not read input: {e}"),
+            VerifyError::Vcek(e) => write!(f, "could not request VCEK: {e}"),
+            VerifyError::ReportParse(e) => write!(f, "could not parse attestation report: {e}"),
+            VerifyError::ReportLength { expected, actual } => write!(
+                f,
+                "attestation report must be {expected} bytes, found {actual}"
+            ),
+            VerifyError::ReportDecode(e) => write!(f, "could not decode attestation report: {e}"),
+            VerifyError::VcekParse(e) => write!(f, "could not parse VCEK: {e}"),
+            VerifyError::ChainBuild(e) => write!(f, "could not build certificate chain: {e}"),
+            VerifyError::ChainVerify(e) => write!(f, "certificate chain did not verify: {e}"),
+            VerifyError::ReportSignature(e) => {
+                write!(f, "attestation report signature did not verify: {e}")
+            }
+            VerifyError::UntrustedRoot => {
+                write!(f, "ARK does not match the trusted AMD root key")
+            }
+            VerifyError::MeasurementMismatch { expected, actual } => write!(
+                f,
+                "measurement mismatch: expected {}, found {}",
+                hex::encode(expected),
+                hex::encode(actual)
+            ),
+            VerifyError::MeasurementNotAllowed { actual } => write!(
+                f,
+                "measurement {} is not on the allowlist",
+                hex::encode(actual)
+            ),
+            VerifyError::ReportDataMismatch { expected, actual } => write!(
+                f,
+                "report data mismatch: expected {}, found {}",
+                hex::encode(expected),
+                hex::encode(actual)
+            ),
+            VerifyError::PolicyViolation {
+                flag,
+                required,
+                actual,
+            } => write!(
+                f,
+                "guest policy violation: '{flag}' must be {required}, found {actual}"
+            ),
+            VerifyError::TcbBelowMinimum {
+                component,
+                minimum,
+                actual,
+            } => write!(
+                f,
+                "reported TCB {component} version {actual} is below the minimum {minimum}"
+            ),
+            VerifyError::TcbRollback {
+                reported,
+                committed,
+            } => write!(
+                f,
+                "reported TCB {} is lower than committed TCB {}",
+                tcb_to_string(reported),
+                tcb_to_string(committed)
+            ),
+            VerifyError::SigningKeyMismatch { expected, actual } => write!(
+                f,
+                "report was not signed by a {expected} (SIGNING_KEY is {actual})"
+            ),
+            VerifyError::VcekExpired { not_after } => {
+                write!(f, "VCEK expired (not valid after {not_after})")
+            }
+            VerifyError::VcekNotYetValid { not_before } => {
+                write!(f, "VCEK is not yet valid (not valid before {not_before})")
+            }
+            VerifyError::VcekPinMismatch { expected, actual } => write!(
+                f,
+                "VCEK is not the pinned one: expected SHA-256 fingerprint {}, found {}",
+                hex::encode(expected),
+                hex::encode(actual)
+            ),
+            VerifyError::VcekTcbMismatch { vcek, reported } => write!(
+                f,
+                "VCEK was issued for TCB ({vcek}), but the report's TCB is ({reported})"
+            ),
+            VerifyError::VcekIssuerMismatch {
+                vcek_issuer,
+                ask_subject,
+            } => write!(
+                f,
+                "VCEK was issued by {vcek_issuer}, not by the ASK {ask_subject}"
+            ),
+            VerifyError::ChipIdMismatch { vcek, report } => write!(
+                f,
+                "VCEK was issued for chip {}, but the report is from chip {}",
+                hex::encode(vcek),
+                hex::encode(report)
+            ),
+            VerifyError::HostDataMismatch { expected, actual } => write!(
+                f,
+                "host data mismatch: expected {}, found {}",
+                hex::encode(expected),
+                hex::encode(actual)
+            ),
+            VerifyError::FamilyIdMismatch { expected, actual } => write!(
+                f,
+                "family ID mismatch: expected {}, found {}",
+                hex::encode(expected),
+                hex::encode(actual)
+            ),
+            VerifyError::ImageIdMismatch { expected, actual } => write!(
+                f,
+                "image ID mismatch: expected {}, found {}",
+                hex::encode(expected),
+                hex::encode(actual)
+            ),
+            VerifyError::ReportIdMismatch { expected, actual } => write!(
+                f,
+                "report ID mismatch: expected {}, found {}",
+                hex::encode(expected),
+                hex::encode(actual)
+            ),
+            VerifyError::UnexpectedMigrationAgent { expected, actual } => {
+                if *expected == NO_MIGRATION_AGENT {
+                    write!(
+                        f,
+                        "guest has migration agent {}, but none is allowed",
+                        hex::encode(actual)
+                    )
+                } else if *actual == NO_MIGRATION_AGENT {
+                    write!(
+                        f,
+                        "guest has no migration agent, but {} is expected",
+                        hex::encode(expected)
+                    )
+                } else {
+                    write!(
+                        f,
+                        "migration agent report ID mismatch: expected {}, found {}",
+                        hex::encode(expected),
+                        hex::encode(actual)
+                    )
+                }
+            }
+            VerifyError::UnexpectedVmpl { expected, actual } => write!(
+                f,
+                "report was requested from VMPL {actual}, but VMPL {expected} is required"
+            ),
+            VerifyError::UnexpectedSigningKey { key, digest } => write!(
+                f,
+                "{key} digest {} is not on the allowlist",
+                hex::encode(digest)
+            ),
+            VerifyError::IdAuthInvalid(e) => {
+                write!(f, "invalid ID authentication information: {e}")
+            }
+            VerifyError::IdKeyDigestMismatch {
+                key,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "{key} digest mismatch: the report has {}, the supplied key's is {}",
+                hex::encode(expected),
+                hex::encode(actual)
+            ),
+            VerifyError::IdAuthSignature { signer } => {
+                let signed = match signer {
+                    SigningKeyDigest::IdKey => "ID block",
+                    SigningKeyDigest::AuthorKey => "ID key",
+                };
+                write!(f, "the {signed} is not signed by the {signer}")
+            }
+            VerifyError::UnsupportedReportVersion { version } => {
+                write!(f, "unsupported attestation report version {version}")
+            }
+            VerifyError::UnsupportedSignatureAlgo { algo } => {
+                write!(
+                    f,
+                    "unsupported attestation report signature algorithm {algo}"
+                )
+            }
+            VerifyError::NonZeroReserved { offset } => write!(
+                f,
+                "reserved field at offset {offset:#x} of the attestation report is not zero"
+            ),
+            VerifyError::ReportTooOld { age, max_age } => write!(
+                f,
+                "report is {}s old, but at most {}s is allowed",
+                age.as_secs(),
+                max_age.as_secs()
+            ),
+            VerifyError::MalformedCaChain { reason } => {
+                write!(f, "malformed certificate chain: {reason}")
+            }
+            VerifyError::ReportEncoding { format, reason } => {
+                write!(f, "could not decode {format} attestation report: {reason}")
+            }
+            VerifyError::CertTableInvalid { reason } => {
+                write!(f, "extended report certificate table is invalid: {reason}")
+            }
+            VerifyError::EnvelopeInvalid { envelope, reason } => {
+                write!(f, "{envelope} report envelope is invalid: {reason}")
+            }
+            VerifyError::CrlInvalid(e) => {
+                write!(f, "certificate revocation list is invalid: {e}")
+            }
+            VerifyError::CrlExpired { next_update } => write!(
+                f,
+                "certificate revocation list is out of date (next update was due {next_update})"
+            ),
+            VerifyError::CrlNotYetValid { last_update } => write!(
+                f,
+                "certificate revocation list is not yet valid (issued {last_update})"
+            ),
+            VerifyError::CertificateRevoked {
+                certificate,
+                serial,
+            } => write!(f, "{certificate} (serial {serial}) has been revoked"),
+        }
+    }
+}
+
+impl std::error::Error for VerifyError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            VerifyError::Io(e)
+            | VerifyError::VcekParse(e)
+            | VerifyError::ChainBuild(e)
+            | VerifyError::ChainVerify(e)
+            | VerifyError::ReportSignature(e)
+            | VerifyError::IdAuthInvalid(e)
+            | VerifyError::CrlInvalid(e) => Some(e),
+            VerifyError::Vcek(e) => Some(e),
+            VerifyError::ReportParse(e) => Some(e),
+            VerifyError::ReportDecode(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<VcekError> for VerifyError {
+    fn from(e: VcekError) -> Self {
+        VerifyError::Vcek(e)
+    }
+}
+
+/// Verifies that a VCEK is signed by the AMD certificate chain for the product.
+///
+/// This checks only the VCEK -> ASK -> ARK path, not any attestation report, so a
+/// separately fetched VCEK can be validated (say, before caching it).
+pub fn verify_vcek(vcek: &Certificate, product: ProductLine) -> Result<(), VerifyError> {
+    verify_vcek_with_ca(vcek, embedded_cert_chain(product)?)
+}
+
+/// Verifies that a VCEK is signed by the given ARK/ASK chain.
+pub(crate) fn verify_vcek_with_ca(
+    vcek: &Certificate,
+    cert_chain: &ca::Chain,
+) -> Result<(), VerifyError> {
+    let ask = cert_chain
+        .verify()
+        .map_err(chain_link_error("ARK/ASK chain"))?;
+    check_vcek_issuer(vcek, ask)?;
+    (ask, vcek)
+        .verify()
+        .map_err(chain_link_error("VCEK is not signed by the ASK"))
+}
+
+/// Wraps an error from the sev crate's chain verification with the link that failed,
+/// as its own message does not say which certificate was at fault.
+fn chain_link_error(link: &'static str) -> impl Fn(Error) -> VerifyError {
+    move |e| VerifyError::ChainVerify(Error::new(e.kind(), format!("{link}: {e}")))
 }
 
 /// Verifies an attestation report, using the provided report JSON string and VCEK bytes.
 ///
-/// Verification intentionally fails if `fail_on_purpose` is true.
-pub fn verify_attestation_report(report_json: &str, vcek_bytes: &[u8], fail_on_purpose: bool) {
-    let report: AttestationReport = serde_json::from_str(report_json).unwrap();
-    let vcek = Certificate::from_der(vcek_bytes).unwrap().into();
+/// The VCEK may also be bundled with its ASK and ARK (see [`parse_vcek_bundle`]),
+/// in which case it is verified against the bundled ASK.
+pub fn verify_attestation_report(
+    report_json: &str,
+    vcek_bytes: &[u8],
+    product: ProductLine,
+    policy: &VerifyPolicy,
+) -> Result<(), VerifyError> {
+    let report: AttestationReport =
+        serde_json::from_str(report_json).map_err(VerifyError::ReportParse)?;
+    let bundle = parse_vcek_bundle(vcek_bytes, product)?;
+    let cert_chain = match bundle.ca {
+        Some(ca) => ca,
+        None => try_get_cert_chain(product)?,
+    };
+
+    verify_report_with_ca(
+        &report,
+        &EndorsementKey::Vcek(bundle.vcek),
+        cert_chain,
+        policy,
+    )
+}
+
+/// Verifies an attestation report captured with the certificate table of an
+/// extended report request (see [`crate::extended_report`]).
+///
+/// The VCEK (and chain) from the table is used if there is one, so that the
+/// report is verified with no network request; otherwise the VCEK is requested
+/// from KDS.
+pub fn verify_extended_report(
+    data: &[u8],
+    product: ProductLine,
+    policy: &VerifyPolicy,
+) -> Result<(), VerifyError> {
+    let extended = parse_extended_report(data, product)?;
+    let bundle = match extended.certs {
+        Some(bundle) => bundle,
+        None => {
+            let report = &extended.report;
+            let vcek_bytes = request_vcek(report.chip_id, report.reported_tcb, product)?;
+            parse_vcek_bundle(&vcek_bytes, product)?
+        }
+    };
+    let cert_chain = match bundle.ca {
+        Some(ca) => ca,
+        None => try_get_cert_chain(product)?,
+    };
+
+    verify_report_with_ca(
+        &extended.report,
+        &EndorsementKey::Vcek(bundle.vcek),
+        cert_chain,
+        policy,
+    )
+}
+
+/// Verifies an attestation report, using the provided report JSON string and a
+/// PEM-encoded VCEK, as KDS or openssl may provide it.
+///
+/// As with [`verify_attestation_report`], the VCEK may be followed by its ASK and ARK.
+pub fn verify_attestation_report_pem(
+    report_json: &str,
+    vcek_pem: &[u8],
+    product: ProductLine,
+    policy: &VerifyPolicy,
+) -> Result<(), VerifyError> {
+    if !is_pem_certificate(vcek_pem) {
+        return Err(VerifyError::VcekParse(Error::new(
+            ErrorKind::InvalidData,
+            "VCEK is not a PEM-encoded certificate",
+        )));
+    }
+
+    verify_attestation_report(report_json, vcek_pem, product, policy)
+}
+
+/// Verifies an attestation report, using the provided raw binary report and VCEK bytes.
+///
+/// The report is in the 1184-byte layout returned by `/dev/sev-guest`
+/// (see [`parse_report_bytes`]), and the VCEK is a DER-encoded X509 certificate.
+///
+/// This is the entry point for memory-constrained verifiers: both are borrowed
+/// and parsed in place, with no PEM or JSON round trip, and the steps are not
+/// recorded. A verification allocates about 2 KiB of Rust heap at its peak, in
+/// addition to openssl's copy of the parsed VCEK.
+pub fn verify_attestation_report_bytes(
+    report_bytes: &[u8],
+    vcek_bytes: &[u8],
+    product: ProductLine,
+    policy: &VerifyPolicy,
+) -> Result<(), VerifyError> {
+    let report = parse_report_bytes(report_bytes)?;
+    let vcek = Certificate::from_der(vcek_bytes).map_err(VerifyError::VcekParse)?;
 
-    verify_attestation_report_raw(report, vcek, fail_on_purpose);
+    verify_report_with_ca(
+        &report,
+        &EndorsementKey::Vcek(vcek),
+        try_get_cert_chain(product)?,
+        policy,
+    )
 }
 
 /// Verifies an attestation report, using the provided report and VCEK.
 ///
-/// Verification intentionally fails if `fail_on_purpose` is true.
+/// Once the report signature is verified, the report is checked against `policy`.
+/// See [`verify_report`] to verify a borrowed report.
 pub fn verify_attestation_report_raw(
-    mut report: AttestationReport,
+    report: AttestationReport,
     vcek: Certificate,
-    fail_on_purpose: bool,
+    product: ProductLine,
+    policy: &VerifyPolicy,
+) -> Result<(), VerifyError> {
+    // Get the ARK and ASK certificates
+    let cert_chain = try_get_cert_chain(product)?;
+
+    verify_report_with_ca(&report, &EndorsementKey::Vcek(vcek), cert_chain, policy)
+}
+
+/// Verifies a borrowed attestation report with the given VCEK, as
+/// [`verify_attestation_report_raw`] does.
+///
+/// Neither the report nor the VCEK is consumed or modified, so the same report
+/// can be verified under several policies without copying it.
+pub fn verify_report(
+    report: &AttestationReport,
+    vcek: &Certificate,
+    product: ProductLine,
+    policy: &VerifyPolicy,
+) -> Result<(), VerifyError> {
+    let cert_chain = try_get_cert_chain(product)?;
+
+    verify_report_with_ca(
+        report,
+        &EndorsementKey::Vcek(vcek.clone()),
+        cert_chain,
+        policy,
+    )
+}
+
+/// Verifies an attestation report against a caller-provided ARK/ASK chain,
+/// rather than the chain embedded for a product line.
+///
+/// This supports roots of trust this crate does not embed, such as those of
+/// test or staging environments. The chain is validated (see
+/// [`validate_ca_chain`]) before it is used.
+pub fn verify_with_ca_chain(
+    report: &AttestationReport,
+    vcek: &Certificate,
+    ca: ca::Chain,
+    policy: &VerifyPolicy,
+) -> Result<(), VerifyError> {
+    validate_ca_chain(&ca)?;
+
+    verify_report_with_ca(report, &EndorsementKey::Vcek(vcek.clone()), ca, policy)
+}
+
+/// Verifies an attestation report with no I/O at all, given every certificate as bytes.
+///
+/// The report is in its raw binary layout (see [`parse_report_bytes`]), the VCEK
+/// is DER-encoded, and the ARK and ASK are each a single PEM-encoded certificate.
+/// The ARK is trusted as given; callers should pin it (say, to the ARK in
+/// [`ProductLine::embedded_pem`]).
+pub fn verify_fully_offline(
+    report_bytes: &[u8],
+    vcek_der: &[u8],
+    ark_pem: &[u8],
+    ask_pem: &[u8],
+    policy: &VerifyPolicy,
+) -> Result<(), VerifyError> {
+    let report = parse_report_bytes(report_bytes)?;
+    let vcek = Certificate::from_der(vcek_der).map_err(VerifyError::VcekParse)?;
+    let ca = ca::Chain {
+        ark: certificate_from_pem(ark_pem)?,
+        ask: certificate_from_pem(ask_pem)?,
+    };
+
+    verify_with_ca_chain(&report, &vcek, ca, policy)
+}
+
+/// Parses a PEM stack holding exactly one certificate.
+fn certificate_from_pem(pem: &[u8]) -> Result<Certificate, VerifyError> {
+    let certs = DefaultBackend::default()
+        .pem_to_der_stack(pem)
+        .map_err(VerifyError::ChainBuild)?;
+    match certs.as_slice() {
+        [der] => Certificate::from_der(der).map_err(VerifyError::ChainBuild),
+        _ => Err(VerifyError::MalformedCaChain {
+            reason: format!("expected 1 certificate, found {}", certs.len()),
+        }),
+    }
+}
+
+/// Checks that an ARK/ASK chain is well-formed: the ARK is self-signed, and the
+/// ASK is signed by the ARK.
+///
+/// This does not check that the ARK is AMD's.
+pub fn validate_ca_chain(ca: &ca::Chain) -> Result<(), VerifyError> {
+    let malformed = |msg: &str, e: std::io::Error| VerifyError::MalformedCaChain {
+        reason: format!("{msg}: {e}"),
+    };
+
+    (&ca.ark, &ca.ark)
+        .verify()
+        .map_err(|e| malformed("ARK is not self-signed", e))?;
+    (&ca.ark, &ca.ask)
+        .verify()
+        .map_err(|e| malformed("ASK is not signed by the ARK", e))
+}
+
+/// Modifies a report so that its signature no longer verifies.
+///
+/// This is a testing hook, used to check that verification fails as it should.
+#[doc(hidden)]
+pub fn tamper_report(report: &mut AttestationReport) {
+    tamper_report_field(report, TamperTarget::Measurement);
+}
+
+/// A field of a report that [`tamper_report_field`] can corrupt.
+#[doc(hidden)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TamperTarget {
+    /// The launch measurement, which only the report signature covers.
+    Measurement,
+
+    /// The guest-provided report data, which only the report signature covers.
+    ReportData,
+
+    /// The report signature itself.
+    Signature,
+
+    /// The chip ID, which must also match the VCEK's hardware ID.
+    ChipId,
+
+    /// The reported TCB, which must also match the TCB the VCEK was issued for.
+    Tcb,
+}
+
+impl TamperTarget {
+    /// Every field that can be tampered with.
+    pub const ALL: [TamperTarget; 5] = [
+        TamperTarget::Measurement,
+        TamperTarget::ReportData,
+        TamperTarget::Signature,
+        TamperTarget::ChipId,
+        TamperTarget::Tcb,
+    ];
+}
+
+/// Modifies the given field of a report, so that verification must fail.
+///
+/// This is a testing hook, used to check that tampering with each part of a
+/// report is detected.
+#[doc(hidden)]
+pub fn tamper_report_field(report: &mut AttestationReport, target: TamperTarget) {
+    match target {
+        TamperTarget::Measurement => report.measurement[0] = report.measurement[0].wrapping_add(1),
+        TamperTarget::ReportData => report.report_data[0] = report.report_data[0].wrapping_add(1),
+        TamperTarget::Signature => {
+            // The sev crate keeps the signature private, so modify the first byte of R
+            let mut bytes = report_to_array(report);
+            bytes[0x2a0] = bytes[0x2a0].wrapping_add(1);
+            *report = parse_report_bytes(&bytes).expect("report layout is unchanged");
+        }
+        TamperTarget::ChipId => report.chip_id[0] = report.chip_id[0].wrapping_add(1),
+        TamperTarget::Tcb => {
+            report.reported_tcb.bootloader = report.reported_tcb.bootloader.wrapping_add(1)
+        }
+    }
+}
+
+/// An endorsement key that signs attestation reports.
+#[derive(Debug, Clone)]
+pub enum EndorsementKey {
+    /// A "Versioned Chip Endorsement Key", unique to a chip and TCB,
+    /// signed by the AMD SEV Key (ASK).
+    Vcek(Certificate),
+
+    /// A "Versioned Loaded Endorsement Key", provisioned by a cloud provider,
+    /// signed by the AMD SEV-VLEK Key (ASVK).
+    Vlek(Certificate),
+}
+
+impl EndorsementKey {
+    /// The endorsement key certificate.
+    pub fn certificate(&self) -> &Certificate {
+        match self {
+            EndorsementKey::Vcek(cert) | EndorsementKey::Vlek(cert) => cert,
+        }
+    }
+
+    /// The name of the kind of endorsement key.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            EndorsementKey::Vcek(_) => "VCEK",
+            EndorsementKey::Vlek(_) => "VLEK",
+        }
+    }
+
+    /// The value of the report's SIGNING_KEY field for this kind of key.
+    fn signing_key_id(&self) -> u32 {
+        match self {
+            EndorsementKey::Vcek(_) => 0,
+            EndorsementKey::Vlek(_) => 1,
+        }
+    }
+}
+
+/// Requests the AMD SEV-SNP VLEK certificate chain from the AMD Key Distribution Service (KDS).
+///
+/// VLEKs are provisioned to cloud providers rather than served per chip, so KDS
+/// only provides the chain that signs them: the ASVK and the ARK. The VLEK
+/// itself comes from the cloud provider, which typically supplies it to the guest
+/// alongside the report. As with [`get_cert_chain_remote`], the downloaded ARK
+/// must match the embedded ARK.
+pub fn get_vlek_cert_chain_remote(product: ProductLine) -> Result<ca::Chain, VerifyError> {
+    KdsClient::default().get_vlek_cert_chain(product)
+}
+
+/// Verifies an attestation report signed by either a VCEK or a VLEK.
+///
+/// The report must declare that it was signed by the kind of key provided.
+/// Verifying a VLEK-signed report requests the VLEK certificate chain from KDS.
+pub fn verify_attestation_report_with_key(
+    report: &AttestationReport,
+    key: EndorsementKey,
+    product: ProductLine,
+    policy: &VerifyPolicy,
+) -> Result<(), VerifyError> {
+    check_signing_key(report, &key)?;
+    let cert_chain = match key {
+        EndorsementKey::Vcek(_) => try_get_cert_chain(product)?,
+        EndorsementKey::Vlek(_) => get_vlek_cert_chain_remote(product)?,
+    };
+    verify_report_with_ca(report, &key, cert_chain, policy)
+}
+
+/// Verifies an attestation report signed by either a VCEK or a VLEK, whose
+/// certificate chain ends in the given ARK and ASK (or ASVK).
+///
+/// As with [`ca_chain_from_pem`], whose output this takes, the ARK is not
+/// checked to be AMD's, which suits test and staging environments.
+pub fn verify_attestation_report_with_key_and_ca(
+    report: &AttestationReport,
+    key: EndorsementKey,
+    cert_chain: ca::Chain,
+    policy: &VerifyPolicy,
+) -> Result<(), VerifyError> {
+    check_signing_key(report, &key)?;
+    verify_report_with_ca(report, &key, cert_chain, policy)
+}
+
+/// Checks that a report declares that it was signed by the kind of key provided.
+fn check_signing_key(report: &AttestationReport, key: &EndorsementKey) -> Result<(), VerifyError> {
+    let actual = report_signing_key_id(report);
+    if actual != key.signing_key_id() {
+        return Err(VerifyError::SigningKeyMismatch {
+            expected: key.kind(),
+            actual,
+        });
+    }
+
+    Ok(())
+}
+
+/// Verifies an attestation report signed by `key`, which in turn must be signed
+/// by the given ARK/ASK chain.
+fn verify_report_with_ca(
+    report: &AttestationReport,
+    key: &EndorsementKey,
+    cert_chain: ca::Chain,
+    policy: &VerifyPolicy,
+) -> Result<(), VerifyError> {
+    let mut steps = StepRecorder::unrecorded();
+    verify_steps(&mut steps, report, key, &cert_chain, policy);
+    steps.finish();
+    steps.result
+}
+
+/// Verifies an attestation report, recording the outcome of every step.
+///
+/// Rather than stopping at the first failure with an error, this returns a
+/// [`VerificationReport`] listing which certificate chain links passed, whether
+/// the report signature verified, and which of the `policy` checks ran. Steps
+/// that depend on a failed step are recorded as skipped.
+pub fn verify_detailed(
+    report: &AttestationReport,
+    vcek: &Certificate,
+    product: ProductLine,
+    policy: &VerifyPolicy,
+) -> VerificationReport {
+    verify_recording(report, vcek, product, None, policy).0
+}
+
+/// Verifies an attestation report, returning both the record of every step and
+/// the first error encountered.
+///
+/// The VCEK is checked against `ca` if given, and otherwise against the embedded
+/// chain for the product.
+pub(crate) fn verify_recording(
+    report: &AttestationReport,
+    vcek: &Certificate,
+    product: ProductLine,
+    ca: Option<&ca::Chain>,
+    policy: &VerifyPolicy,
+) -> (VerificationReport, Result<(), VerifyError>) {
+    let key = EndorsementKey::Vcek(vcek.clone());
+    let mut steps = StepRecorder::new();
+    match ca.map_or_else(|| embedded_cert_chain(product), Ok) {
+        Ok(cert_chain) => verify_steps(&mut steps, report, &key, cert_chain, policy),
+        Err(e) => steps.record("embedded_ark", Err(e)),
+    }
+    steps.finish();
+
+    let verification = VerificationReport {
+        product,
+        reported_tcb: report.reported_tcb,
+        committed_tcb: report.committed_tcb,
+        launch_tcb: report.launch_tcb,
+        measurement: report.measurement,
+        policy: DecodedPolicy::from(report.policy),
+        steps: steps.steps,
+        warnings: steps.warnings,
+    };
+    (verification, steps.result)
+}
+
+/// Runs every verification step, recording each step's outcome along with the
+/// first error encountered.
+fn verify_steps(
+    steps: &mut StepRecorder,
+    report: &AttestationReport,
+    key: &EndorsementKey,
+    cert_chain: &ca::Chain,
+    policy: &VerifyPolicy,
 ) {
-    if fail_on_purpose {
-        report.measurement[0] = report.measurement[0].wrapping_add(1);
+    verify_chain_steps(steps, key, cert_chain, policy);
+    verify_report_steps(steps, report, key, policy);
+}
+
+/// Runs the steps that depend only on the endorsement key and its chain, and
+/// not on any report.
+fn verify_chain_steps(
+    steps: &mut StepRecorder,
+    key: &EndorsementKey,
+    cert_chain: &ca::Chain,
+    policy: &VerifyPolicy,
+) {
+    let vcek = key.certificate();
+    debug!(
+        kind = key.kind(),
+        fingerprint = X509::from(vcek)
+            .digest(MessageDigest::sha256())
+            .map(hex::encode)
+            .unwrap_or_default(),
+        "verifying endorsement key"
+    );
+
+    // A chain already verified (with the same ARK and ASK) need not be again
+    let cached = policy
+        .chain_cache
+        .as_ref()
+        .and_then(|cache| Some((cache, ChainKey::new(cert_chain, vcek)?)));
+    match cached {
+        Some((cache, key)) if cache.contains(&key) => {
+            debug!("certificate chain already verified");
+            for name in ["ark_self_signed", "ask_signed_by_ark", "vcek_signed_by_ask"] {
+                steps.record(name, Ok(()));
+            }
+        }
+        _ => {
+            // Verify the certificate chain (VCEK -> ASK -> ARK) link by link first,
+            // so that a bad chain is not reported as a bad report signature.
+            steps.run("ark_self_signed", || {
+                (&cert_chain.ark, &cert_chain.ark)
+                    .verify()
+                    .map_err(chain_link_error("ARK is not self-signed"))
+            });
+            steps.run("ask_signed_by_ark", || {
+                (&cert_chain.ark, &cert_chain.ask)
+                    .verify()
+                    .map_err(chain_link_error("ASK is not signed by the ARK"))
+            });
+            steps.run("vcek_signed_by_ask", || {
+                check_vcek_issuer(vcek, &cert_chain.ask)?;
+                (&cert_chain.ask, vcek)
+                    .verify()
+                    .map_err(chain_link_error("VCEK is not signed by the ASK"))
+            });
+            if let (Some((cache, key)), Ok(())) = (cached, &steps.result) {
+                cache.insert(key);
+            }
+        }
     }
 
-    // Get the ARK and ASK certificates
-    let cert_chain = get_cert_chain(SEV_PROD_NAME);
+    match &policy.pinned_vcek_sha256 {
+        Some(pinned) => steps.run("vcek_pin", || check_vcek_pin(vcek, pinned)),
+        None => steps.skip("vcek_pin"),
+    }
+
+    match &policy.crl {
+        Some(crl) => steps.run("revocation", || {
+            crl.check(
+                cert_chain,
+                vcek,
+                policy.as_of.unwrap_or_else(SystemTime::now),
+            )
+        }),
+        None => steps.skip("revocation"),
+    }
+
+    steps.run("vcek_validity", || {
+        check_vcek_validity(vcek, policy.as_of.unwrap_or_else(SystemTime::now))
+    });
+}
+
+/// Runs the steps checking a report against an endorsement key whose chain has
+/// already been verified.
+fn verify_report_steps(
+    steps: &mut StepRecorder,
+    report: &AttestationReport,
+    key: &EndorsementKey,
+    policy: &VerifyPolicy,
+) {
+    let vcek = key.certificate();
+
+    // Check the report's layout first, as every later step depends on it.
+    if is_future_report_version(report)
+        && policy.unknown_version == UnknownVersionPolicy::BestEffort
+    {
+        warn!(
+            version = report.version,
+            "verifying a report of an unsupported version"
+        );
+        steps.warn(|| {
+            format!(
+                "report version {} is newer than the supported versions; \
+                 version-specific checks were skipped",
+                report.version
+            )
+        });
+        steps.run("report_format", || check_signature_algo(report));
+        steps.skip("reserved_fields");
+    } else {
+        steps.run("report_format", || check_report_format(report));
+        steps.run("reserved_fields", || check_reserved_fields(report));
+    }
+    steps.run("vcek_tcb", || check_vcek_tcb(vcek, &report.reported_tcb));
+
+    // A VLEK is provisioned to a cloud provider rather than to a chip, so it has no chip ID.
+    match key {
+        EndorsementKey::Vcek(_) => {
+            steps.run("vcek_chip_id", || check_vcek_chip_id(vcek, &report.chip_id))
+        }
+        EndorsementKey::Vlek(_) => steps.skip("vcek_chip_id"),
+    }
+
+    // Check that the attestation report is signed by the VCEK.
+    steps.run("report_signature", || verify_report_signature(report, vcek));
 
-    // Create the full certificate chain
-    let full_cert_chain = Chain {
-        ca: cert_chain,
-        vcek,
+    // Check that the (now authentic) report describes the expected guest.
+    // These checks are independent of each other, so all of them are recorded.
+    let authentic = steps.result.is_ok();
+    for (name, result) in policy.check_steps(report) {
+        match result {
+            Some(result) if authentic => steps.record(name, result),
+            _ => steps.skip(name),
+        }
+    }
+}
+
+/// Verifies that a report is signed by the given endorsement key certificate.
+///
+/// Unlike verifying against a full [`sev::certs::snp::Chain`], this does not verify the
+/// certificate chain again.
+fn verify_report_signature(
+    report: &AttestationReport,
+    vcek: &Certificate,
+) -> Result<(), VerifyError> {
+    let signature_error = |e: &dyn std::fmt::Display| {
+        VerifyError::ReportSignature(Error::new(ErrorKind::Other, e.to_string()))
     };
 
-    // Verify the full certificate chain (VCEK -> ASK -> ARK), and then
-    // check that the attestation report is signed by the VCEK.
-    let verification_result = (&full_cert_chain, &report).verify();
+    let sig = EcdsaSig::try_from(&report.signature).map_err(VerifyError::ReportSignature)?;
+    let report_bytes = report_to_array(report);
+    let digest = hash(MessageDigest::sha384(), &report_bytes[SIGNED_REGION])
+        .map_err(|e| signature_error(&e))?;
+    let key = X509::from(vcek)
+        .public_key()
+        .and_then(|key| key.ec_key())
+        .map_err(|e| signature_error(&e))?;
+
+    match sig.verify(&digest, &key) {
+        Ok(true) => Ok(()),
+        Ok(false) => Err(signature_error(
+            &"VCEK does not sign the attestation report",
+        )),
+        Err(e) => Err(signature_error(&e)),
+    }
+}
+
+/// Verifies many attestation reports signed by the same VCEK.
+///
+/// Reports from one chip at one TCB all share a VCEK, so the VCEK and its
+/// certificate chain are verified once, and only each report's signature and
+/// `policy` checks are run per report. The results are in the order of `reports`.
+///
+/// With the `parallel` feature, reports are verified in parallel.
+pub fn verify_batch(
+    reports: &[AttestationReport],
+    vcek: &Certificate,
+    product: ProductLine,
+    policy: &VerifyPolicy,
+) -> Vec<Result<(), VerifyError>> {
+    let key = EndorsementKey::Vcek(vcek.clone());
+    let Ok(cert_chain) = embedded_cert_chain(product) else {
+        return reports
+            .iter()
+            .map(|_| Err(VerifyError::UntrustedRoot))
+            .collect();
+    };
+
+    let mut chain_steps = StepRecorder::unrecorded();
+    verify_chain_steps(&mut chain_steps, &key, cert_chain, policy);
+    let chain_verified = chain_steps.result.is_ok();
+
+    let verify_one = |report: &AttestationReport| {
+        if chain_verified {
+            let mut steps = StepRecorder::unrecorded();
+            verify_report_steps(&mut steps, report, &key, policy);
+            steps.finish();
+            steps.result
+        } else {
+            // Errors cannot be cloned, so each report gets its own
+            let mut steps = StepRecorder::unrecorded();
+            verify_steps(&mut steps, report, &key, cert_chain, policy);
+            steps.finish();
+            steps.result
+        }
+    };
+
+    #[cfg(feature = "parallel")]
+    {
+        use rayon::prelude::*;
+        reports.par_iter().map(verify_one).collect()
+    }
+    #[cfg(not(feature = "parallel"))]
+    {
+        reports.iter().map(verify_one).collect()
+    }
+}
+
+/// Records the outcome of verification steps, keeping the first error.
+struct StepRecorder {
+    steps: Vec<VerificationStep>,
+    warnings: Vec<String>,
+    result: Result<(), VerifyError>,
+    // Whether each step's outcome is kept in `steps`, or only the first error
+    recording: bool,
+    #[cfg(feature = "metrics")]
+    failed_step: Option<&'static str>,
+}
+
+impl StepRecorder {
+    fn new() -> Self {
+        Self {
+            steps: Vec::new(),
+            warnings: Vec::new(),
+            result: Ok(()),
+            recording: true,
+            #[cfg(feature = "metrics")]
+            failed_step: None,
+        }
+    }
+
+    /// A recorder keeping only the first error, for callers that do not report
+    /// each step, so that verifying allocates as little as possible.
+    fn unrecorded() -> Self {
+        Self {
+            recording: false,
+            ..Self::new()
+        }
+    }
+
+    /// Runs a step, unless an earlier step failed.
+    fn run(&mut self, name: &'static str, step: impl FnOnce() -> Result<(), VerifyError>) {
+        if self.result.is_err() {
+            self.skip(name);
+        } else {
+            #[cfg(feature = "tracing")]
+            let _span = tracing::debug_span!("verification_step", step = name).entered();
+            self.record(name, step());
+        }
+    }
+
+    /// Records the result of a step that has already run.
+    fn record(&mut self, name: &'static str, result: Result<(), VerifyError>) {
+        let outcome = match result {
+            Ok(()) => {
+                debug!(step = name, "verification step passed");
+                #[cfg(feature = "metrics")]
+                crate::metrics::record_step(name, "passed");
+                StepOutcome::Passed
+            }
+            Err(e) => {
+                error!(step = name, error = %e, "verification step failed");
+                #[cfg(feature = "metrics")]
+                crate::metrics::record_step(name, "failed");
+                // The reason is only formatted if it is kept
+                let reason = if self.recording {
+                    e.to_string()
+                } else {
+                    String::new()
+                };
+                if self.result.is_ok() {
+                    self.result = Err(e);
+                    #[cfg(feature = "metrics")]
+                    {
+                        self.failed_step = Some(name);
+                    }
+                }
+                StepOutcome::Failed(reason)
+            }
+        };
+        if self.recording {
+            self.steps.push(VerificationStep { name, outcome });
+        }
+    }
+
+    /// Records a caveat on the verification that is not a failure.
+    fn warn(&mut self, warning: impl FnOnce() -> String) {
+        if self.recording {
+            self.warnings.push(warning());
+        }
+    }
+
+    /// Marks the end of a verification, counting its outcome in the metrics.
+    fn finish(&self) {
+        #[cfg(feature = "metrics")]
+        crate::metrics::record_verification(self.failed_step);
+    }
 
-    // Panic with detailed error if failed
-    verification_result.unwrap();
+    /// Records a step that did not run.
+    fn skip(&mut self, name: &'static str) {
+        debug!(step = name, "verification step skipped");
+        #[cfg(feature = "metrics")]
+        crate::metrics::record_step(name, "skipped");
+        if !self.recording {
+            return;
+        }
+        self.steps.push(VerificationStep {
+            name,
+            outcome: StepOutcome::Skipped,
+        });
+    }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
 
+    use sev::certs::snp::Chain;
+
+    use crate::mock_kds::MockKds;
+    use crate::product::detect_product;
+    use crate::tcb::tcb_from_u64;
+
+    /// The sample report, marked as VLEK-signed and signed by [`TEST_VLEK`].
+    ///
+    /// No VLEK-signed report from real hardware is public, so the VLEK and its
+    /// chain are issued by a test ARK and ASVK rather than AMD's. The VLEK has
+    /// the sample VCEK's TCB extensions.
+    const TEST_VLEK_REPORT: &[u8] = include_bytes!("../data/test_vlek_report.bin");
+
+    const TEST_VLEK: &[u8] = include_bytes!("../data/test_vlek.crt");
+
+    /// The test ASVK, then the test ARK, as in KDS's VLEK `cert_chain`.
+    const TEST_VLEK_CERT_CHAIN: &[u8] = include_bytes!("../data/test_vlek_cert_chain.pem");
+
     #[test]
     fn test_sample_attestation_verifies() {
         let report: AttestationReport = serde_json::from_str(SAMPLE_ATTESTATION).unwrap();
-        let vcek = Certificate::from_der(SAMPLE_VCEK).unwrap().into();
-        let cert_chain = get_cert_chain(SEV_PROD_NAME);
+        let vcek = Certificate::from_der(SAMPLE_VCEK).unwrap();
+        let cert_chain = get_cert_chain(ProductLine::Genoa);
         let full_cert_chain = Chain {
             ca: cert_chain,
             vcek,
@@ -150,13 +1688,116 @@ mod test {
         assert!(verification_result.is_ok());
     }
 
+    #[test]
+    fn test_vlek_attestation_verifies() {
+        let report = parse_report_bytes(TEST_VLEK_REPORT).unwrap();
+        assert_eq!(report_signing_key_id(&report), 1);
+        let vlek = Certificate::from_der(TEST_VLEK).unwrap();
+        let cert_chain = ca_chain_from_pem(TEST_VLEK_CERT_CHAIN).unwrap();
+        let policy = VerifyPolicy::default();
+
+        let result = verify_attestation_report_with_key_and_ca(
+            &report,
+            EndorsementKey::Vlek(vlek.clone()),
+            cert_chain.clone(),
+            &policy,
+        );
+        assert!(result.is_ok());
+
+        // The report is VLEK-signed, so it is not accepted as VCEK-signed
+        let result = verify_attestation_report_with_key_and_ca(
+            &report,
+            EndorsementKey::Vcek(vlek.clone()),
+            cert_chain.clone(),
+            &policy,
+        );
+        assert!(matches!(
+            result,
+            Err(VerifyError::SigningKeyMismatch {
+                expected: "VCEK",
+                actual: 1
+            })
+        ));
+
+        // Nor does a VLEK chain to AMD's Genoa ASK
+        let result = verify_attestation_report_with_key_and_ca(
+            &report,
+            EndorsementKey::Vlek(vlek.clone()),
+            get_cert_chain(ProductLine::Genoa),
+            &policy,
+        );
+        assert!(matches!(
+            result,
+            Err(VerifyError::VcekIssuerMismatch { .. })
+        ));
+
+        let mut tampered = report;
+        tamper_report(&mut tampered);
+        let result = verify_attestation_report_with_key_and_ca(
+            &tampered,
+            EndorsementKey::Vlek(vlek),
+            cert_chain,
+            &policy,
+        );
+        assert!(matches!(result, Err(VerifyError::ReportSignature(_))));
+    }
+
+    #[test]
+    fn test_turin_attestation_verifies() {
+        let report = parse_report_bytes(TEST_TURIN_REPORT).unwrap();
+        assert_eq!(detect_product(&report, None), Some(ProductLine::Turin));
+        let vcek = Certificate::from_der(TEST_TURIN_VCEK).unwrap();
+        let cert_chain = ca_chain_from_pem(TEST_TURIN_CERT_CHAIN).unwrap();
+
+        // Read in Milan's layout, Turin's TCB would claim bootloader 1, SNP 1 and microcode 72
+        let policy = VerifyPolicy {
+            min_tcb: Some(tcb_from_u64(0x4800_0000_1801_0201)),
+            ..Default::default()
+        };
+        let result = verify_attestation_report_with_key_and_ca(
+            &report,
+            EndorsementKey::Vcek(vcek.clone()),
+            cert_chain.clone(),
+            &policy,
+        );
+        assert!(result.is_ok());
+
+        let policy = VerifyPolicy {
+            min_tcb: Some(tcb_from_u64(0x4800_0000_1901_0201)),
+            ..Default::default()
+        };
+        let result = verify_attestation_report_with_key_and_ca(
+            &report,
+            EndorsementKey::Vcek(vcek.clone()),
+            cert_chain.clone(),
+            &policy,
+        );
+        assert!(matches!(
+            result,
+            Err(VerifyError::TcbBelowMinimum {
+                component: TcbComponent::Snp,
+                ..
+            })
+        ));
+
+        let mut tampered = report;
+        tamper_report(&mut tampered);
+        let result = verify_attestation_report_with_key_and_ca(
+            &tampered,
+            EndorsementKey::Vcek(vcek),
+            cert_chain,
+            &VerifyPolicy::default(),
+        );
+        assert!(matches!(result, Err(VerifyError::ReportSignature(_))));
+    }
+
     #[test]
     fn test_tampered_sample_attestation_fails_verification() {
         let mut report: AttestationReport = serde_json::from_str(SAMPLE_ATTESTATION).unwrap();
         report.measurement[0] = report.measurement[0].wrapping_add(1);
 
-        let vcek = Certificate::from_der(SAMPLE_VCEK).unwrap().into();
-        let cert_chain = get_cert_chain(SEV_PROD_NAME);
+        let vcek = Certificate::from_der(SAMPLE_VCEK).unwrap();
+        let cert_chain = get_cert_chain(ProductLine::Genoa);
         let full_cert_chain = Chain {
             ca: cert_chain,
             vcek,
@@ -166,16 +1807,664 @@ mod test {
         assert!(verification_result.is_err());
     }
 
+    #[test]
+    fn test_milan_cert_chain() {
+        assert_eq!(X509::stack_from_pem(MILAN_PEM).unwrap().len(), 2);
+
+        let cert_chain = get_cert_chain(ProductLine::Milan);
+        assert!((&cert_chain).verify().is_ok());
+    }
+
+    #[test]
+    fn test_embedded_cert_chain_is_parsed_once() {
+        for product in [ProductLine::Milan, ProductLine::Genoa, ProductLine::Turin] {
+            let embedded = embedded_cert_chain(product).unwrap();
+            assert!(std::ptr::eq(
+                embedded,
+                embedded_cert_chain(product).unwrap()
+            ));
+            let chain = get_cert_chain(product);
+            assert_eq!(chain.ark, embedded.ark);
+            assert_eq!(chain.ask, embedded.ask);
+        }
+        assert_ne!(
+            get_cert_chain(ProductLine::Milan).ark,
+            get_cert_chain(ProductLine::Genoa).ark
+        );
+    }
+
+    #[test]
+    fn test_embedded_arks_are_pinned() {
+        for product in [ProductLine::Milan, ProductLine::Genoa, ProductLine::Turin] {
+            let chain = try_get_cert_chain(product).unwrap();
+            assert_eq!(ark_fingerprint(&chain).unwrap(), product.ark_sha256());
+            assert!(check_ark_is_embedded(product, &chain).is_ok());
+        }
+
+        let milan = get_cert_chain(ProductLine::Milan);
+        assert!(matches!(
+            check_ark_is_embedded(ProductLine::Genoa, &milan),
+            Err(VerifyError::UntrustedRoot)
+        ));
+    }
+
+    #[test]
+    fn test_sample_turin_vcek_verifies() {
+        assert_eq!(X509::stack_from_pem(TURIN_PEM).unwrap().len(), 2);
+
+        let vcek = Certificate::from_der(SAMPLE_VCEK_TURIN).unwrap();
+        let cert_chain = get_cert_chain(ProductLine::Turin);
+        let full_cert_chain = Chain {
+            ca: cert_chain,
+            vcek,
+        };
+
+        assert!((&full_cert_chain).verify().is_ok());
+
+        // The Turin VCEK must not chain to the Genoa root
+        let vcek = Certificate::from_der(SAMPLE_VCEK_TURIN).unwrap();
+        let full_cert_chain = Chain {
+            ca: get_cert_chain(ProductLine::Genoa),
+            vcek,
+        };
+        assert!((&full_cert_chain).verify().is_err());
+    }
+
+    #[test]
+    fn test_remote_cert_chain_must_match_embedded_ark() {
+        assert!(ca_chain_from_remote_pem(ProductLine::Genoa, GENOA_PEM).is_ok());
+
+        let result = ca_chain_from_remote_pem(ProductLine::Genoa, MILAN_PEM);
+        assert!(matches!(result, Err(VerifyError::UntrustedRoot)));
+
+        let result = ca_chain_from_remote_pem(ProductLine::Genoa, b"not a pem");
+        assert!(matches!(result, Err(VerifyError::MalformedCaChain { .. })));
+    }
+
+    #[test]
+    fn test_ca_chain_from_pem() {
+        for product in ProductLine::ALL {
+            assert!(ca_chain_from_pem(product.embedded_pem()).is_ok());
+        }
+
+        // The certificates in the wrong order
+        let genoa = get_cert_chain(ProductLine::Genoa);
+        let mut reversed = genoa.ark.to_pem().unwrap();
+        reversed.extend(genoa.ask.to_pem().unwrap());
+        match ca_chain_from_pem(&reversed) {
+            Err(VerifyError::MalformedCaChain { reason }) => {
+                assert!(reason.starts_with("ARK is not self-signed"))
+            }
+            result => panic!("unexpected result {result:?}"),
+        }
+
+        let mut three = GENOA_PEM.to_vec();
+        three.extend(genoa.ask.to_pem().unwrap());
+        match ca_chain_from_pem(&three) {
+            Err(VerifyError::MalformedCaChain { reason }) => {
+                assert_eq!(reason, "expected 2 certificates (ASK, then ARK), found 3")
+            }
+            result => panic!("unexpected result {result:?}"),
+        }
+    }
+
+    #[test]
+    fn test_verify_attestation_report_pem() {
+        let vcek_pem = Certificate::from_der(SAMPLE_VCEK)
+            .unwrap()
+            .to_pem()
+            .unwrap();
+        let policy = VerifyPolicy::default();
+
+        assert!(verify_attestation_report_pem(
+            SAMPLE_ATTESTATION,
+            &vcek_pem,
+            ProductLine::Genoa,
+            &policy
+        )
+        .is_ok());
+        assert!(matches!(
+            verify_attestation_report_pem(
+                SAMPLE_ATTESTATION,
+                SAMPLE_VCEK,
+                ProductLine::Genoa,
+                &policy
+            ),
+            Err(VerifyError::VcekParse(_))
+        ));
+    }
+
+    #[test]
+    fn test_parse_vcek_bundle() {
+        let vcek_pem = Certificate::from_der(SAMPLE_VCEK)
+            .unwrap()
+            .to_pem()
+            .unwrap();
+        let mut bundle = vcek_pem.clone();
+        bundle.extend_from_slice(GENOA_PEM);
+
+        let parsed = parse_vcek_bundle(&bundle, ProductLine::Genoa).unwrap();
+        assert_eq!(parsed.vcek.to_der().unwrap(), SAMPLE_VCEK);
+        assert!(parsed.ca.is_some());
+        assert!(verify_attestation_report(
+            SAMPLE_ATTESTATION,
+            &bundle,
+            ProductLine::Genoa,
+            &VerifyPolicy::default()
+        )
+        .is_ok());
+
+        for vcek in [SAMPLE_VCEK, &vcek_pem] {
+            let parsed = parse_vcek_bundle(vcek, ProductLine::Genoa).unwrap();
+            assert_eq!(parsed.vcek.to_der().unwrap(), SAMPLE_VCEK);
+            assert!(parsed.ca.is_none());
+        }
+
+        // A bundle cannot bring its own root
+        assert!(matches!(
+            parse_vcek_bundle(&bundle, ProductLine::Milan),
+            Err(VerifyError::UntrustedRoot)
+        ));
+
+        let mut two = vcek_pem;
+        two.extend(get_cert_chain(ProductLine::Genoa).ask.to_pem().unwrap());
+        assert!(matches!(
+            parse_vcek_bundle(&two, ProductLine::Genoa),
+            Err(VerifyError::MalformedCaChain { .. })
+        ));
+    }
+
+    #[test]
+    fn test_verify_vcek() {
+        let vcek = Certificate::from_der(SAMPLE_VCEK).unwrap();
+        assert!(verify_vcek(&vcek, ProductLine::Genoa).is_ok());
+
+        let vcek = Certificate::from_der(SAMPLE_VCEK_TURIN).unwrap();
+        assert!(verify_vcek(&vcek, ProductLine::Turin).is_ok());
+        // The VCEK names the Turin ASK, so the mismatch is reported as such
+        // rather than as a bad signature
+        match verify_vcek(&vcek, ProductLine::Genoa) {
+            Err(VerifyError::VcekIssuerMismatch {
+                vcek_issuer,
+                ask_subject,
+            }) => {
+                assert!(vcek_issuer.ends_with("CN=SEV-Turin"));
+                assert!(ask_subject.contains("CN=SEV-Genoa (key ID b2c67ba435bb"));
+            }
+            other => panic!("expected an issuer mismatch, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_verify_attestation_report_with_key() {
+        let report: AttestationReport = serde_json::from_str(SAMPLE_ATTESTATION).unwrap();
+        assert_eq!(report_signing_key_id(&report), 0);
+
+        let vcek = Certificate::from_der(SAMPLE_VCEK).unwrap();
+        let result = verify_attestation_report_with_key(
+            &report,
+            EndorsementKey::Vcek(vcek.clone()),
+            ProductLine::Genoa,
+            &VerifyPolicy::default(),
+        );
+        assert!(result.is_ok());
+
+        // The sample report is VCEK-signed, so it is rejected before any VLEK chain is fetched
+        let result = verify_attestation_report_with_key(
+            &report,
+            EndorsementKey::Vlek(vcek),
+            ProductLine::Genoa,
+            &VerifyPolicy::default(),
+        );
+        assert!(matches!(
+            result,
+            Err(VerifyError::SigningKeyMismatch {
+                expected: "VLEK",
+                actual: 0
+            })
+        ));
+    }
+
+    #[test]
+    fn test_verify_attestation_report_bytes() {
+        use crate::report::{report_to_bytes, SAMPLE_ATTESTATION_MILAN, SAMPLE_VCEK_MILAN};
+
+        let policy = VerifyPolicy::default();
+        let result = verify_attestation_report_bytes(
+            SAMPLE_ATTESTATION_MILAN,
+            SAMPLE_VCEK_MILAN,
+            ProductLine::Milan,
+            &policy,
+        );
+        assert!(result.is_ok());
+
+        let report: AttestationReport = serde_json::from_str(SAMPLE_ATTESTATION).unwrap();
+        let report_bytes = report_to_bytes(&report);
+        let result = verify_attestation_report_bytes(
+            &report_bytes,
+            SAMPLE_VCEK,
+            ProductLine::Genoa,
+            &policy,
+        );
+        assert!(result.is_ok());
+
+        let result = verify_attestation_report_bytes(
+            &report_bytes[..100],
+            SAMPLE_VCEK,
+            ProductLine::Genoa,
+            &policy,
+        );
+        assert!(matches!(result, Err(VerifyError::ReportLength { .. })));
+    }
+
+    #[test]
+    fn test_verify_detailed() {
+        let report: AttestationReport = serde_json::from_str(SAMPLE_ATTESTATION).unwrap();
+        let vcek = Certificate::from_der(SAMPLE_VCEK).unwrap();
+        let policy = VerifyPolicy {
+            expected_measurement: Some(report.measurement),
+            ..Default::default()
+        };
+
+        let verification = verify_detailed(&report, &vcek, ProductLine::Genoa, &policy);
+        assert!(verification.passed());
+        assert_eq!(
+            verification.outcome("report_signature"),
+            Some(&StepOutcome::Passed)
+        );
+        assert_eq!(
+            verification.outcome("measurement"),
+            Some(&StepOutcome::Passed)
+        );
+        assert_eq!(
+            verification.outcome("report_data"),
+            Some(&StepOutcome::Skipped)
+        );
+
+        assert_eq!(verification.measurement, report.measurement);
+        assert_eq!(verification.launch_tcb, report.launch_tcb);
+
+        let json = serde_json::to_value(&verification).unwrap();
+        assert_eq!(json["product"], "Genoa");
+        assert_eq!(json["measurement"], hex::encode(report.measurement));
+        assert_eq!(
+            json["committed_tcb"]["microcode"],
+            report.committed_tcb.microcode
+        );
+        assert_eq!(json["steps"][0]["outcome"]["status"], "passed");
+
+        // A VCEK from another product fails the chain, and everything after it is skipped
+        let vcek = Certificate::from_der(SAMPLE_VCEK_TURIN).unwrap();
+        let verification = verify_detailed(&report, &vcek, ProductLine::Genoa, &policy);
+        assert!(!verification.passed());
+        assert!(matches!(
+            verification.outcome("vcek_signed_by_ask"),
+            Some(StepOutcome::Failed(_))
+        ));
+        assert_eq!(
+            verification.outcome("report_signature"),
+            Some(&StepOutcome::Skipped)
+        );
+        assert_eq!(
+            verification.outcome("measurement"),
+            Some(&StepOutcome::Skipped)
+        );
+    }
+
     #[test]
     fn test_verify_attestation_report() {
-        verify_attestation_report(SAMPLE_ATTESTATION, SAMPLE_VCEK, false);
+        assert!(verify_attestation_report(
+            SAMPLE_ATTESTATION,
+            SAMPLE_VCEK,
+            ProductLine::Genoa,
+            &VerifyPolicy::default()
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn test_verify_attestation_report_tampered() {
+        let mut report: AttestationReport = serde_json::from_str(SAMPLE_ATTESTATION).unwrap();
+        tamper_report(&mut report);
+        let result = verify_attestation_report_raw(
+            report,
+            Certificate::from_der(SAMPLE_VCEK).unwrap(),
+            ProductLine::Genoa,
+            &VerifyPolicy::default(),
+        );
+        assert!(matches!(result, Err(VerifyError::ReportSignature(_))));
+    }
+
+    #[test]
+    fn test_verify_report_by_reference() {
+        let report: AttestationReport = serde_json::from_str(SAMPLE_ATTESTATION).unwrap();
+        let vcek = Certificate::from_der(SAMPLE_VCEK).unwrap();
+        let before = report_to_array(&report);
+
+        // The same report, verified under several policies
+        assert!(
+            verify_report(&report, &vcek, ProductLine::Genoa, &VerifyPolicy::default()).is_ok()
+        );
+        let policy = VerifyPolicy {
+            expected_measurement: Some([0; 48]),
+            ..Default::default()
+        };
+        assert!(matches!(
+            verify_report(&report, &vcek, ProductLine::Genoa, &policy),
+            Err(VerifyError::MeasurementMismatch { .. })
+        ));
+        assert_eq!(report_to_array(&report), before);
+    }
+
+    #[test]
+    fn test_verify_extended_report() {
+        use crate::extended_report::{build_extended_report, VCEK_GUID};
+
+        let report: AttestationReport = serde_json::from_str(SAMPLE_ATTESTATION).unwrap();
+        let data = build_extended_report(&report, &[(VCEK_GUID, SAMPLE_VCEK)]);
+        assert!(
+            verify_extended_report(&data, ProductLine::Genoa, &VerifyPolicy::default()).is_ok()
+        );
+
+        let mut tampered = report;
+        tamper_report(&mut tampered);
+        let data = build_extended_report(&tampered, &[(VCEK_GUID, SAMPLE_VCEK)]);
+        assert!(matches!(
+            verify_extended_report(&data, ProductLine::Genoa, &VerifyPolicy::default()),
+            Err(VerifyError::ReportSignature(_))
+        ));
+    }
+
+    #[test]
+    fn test_tampering_with_each_field_is_detected() {
+        let report: AttestationReport = serde_json::from_str(SAMPLE_ATTESTATION).unwrap();
+        let vcek = Certificate::from_der(SAMPLE_VCEK).unwrap();
+
+        for target in TamperTarget::ALL {
+            let mut tampered = report;
+            tamper_report_field(&mut tampered, target);
+            assert_ne!(report_to_array(&tampered), report_to_array(&report));
+
+            let verification = verify_detailed(
+                &tampered,
+                &vcek,
+                ProductLine::Genoa,
+                &VerifyPolicy::default(),
+            );
+            // The step that first rejects each kind of tampering
+            let step = match target {
+                TamperTarget::Measurement | TamperTarget::ReportData | TamperTarget::Signature => {
+                    "report_signature"
+                }
+                TamperTarget::ChipId => "vcek_chip_id",
+                TamperTarget::Tcb => "vcek_tcb",
+            };
+            assert!(
+                matches!(verification.outcome(step), Some(StepOutcome::Failed(_))),
+                "tampering with {target:?} was not rejected by {step}"
+            );
+            assert!(!verification.passed());
+        }
+    }
+
+    #[test]
+    fn test_verify_attestation_report_expected_measurement() {
+        let policy = VerifyPolicy {
+            expected_measurement: Some([0; 48]),
+            ..Default::default()
+        };
+        let result =
+            verify_attestation_report(SAMPLE_ATTESTATION, SAMPLE_VCEK, ProductLine::Genoa, &policy);
+        assert!(matches!(
+            result,
+            Err(VerifyError::MeasurementMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_verify_attestation_report_expired_vcek() {
+        // The sample VCEK is valid for 7 years from September 2023
+        let after_expiry = SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(2_000_000_000);
+        let policy = VerifyPolicy {
+            as_of: Some(after_expiry),
+            ..Default::default()
+        };
+        let result =
+            verify_attestation_report(SAMPLE_ATTESTATION, SAMPLE_VCEK, ProductLine::Genoa, &policy);
+        assert!(matches!(result, Err(VerifyError::VcekExpired { .. })));
+    }
+
+    #[test]
+    fn test_verify_attestation_report_bad_inputs() {
+        let result = verify_attestation_report(
+            "{}",
+            SAMPLE_VCEK,
+            ProductLine::Genoa,
+            &VerifyPolicy::default(),
+        );
+        assert!(matches!(result, Err(VerifyError::ReportParse(_))));
+
+        let result = verify_attestation_report(
+            SAMPLE_ATTESTATION,
+            &SAMPLE_VCEK[1..],
+            ProductLine::Genoa,
+            &VerifyPolicy::default(),
+        );
+        assert!(matches!(result, Err(VerifyError::VcekParse(_))));
+    }
+
+    #[test]
+    fn test_error_messages_and_sources() {
+        use std::error::Error as _;
+
+        let status = VcekError::Status {
+            status: reqwest::StatusCode::NOT_FOUND,
+            body: "no such VCEK".to_string(),
+        };
+        assert_eq!(
+            status.to_string(),
+            "KDS responded with status 404 Not Found: no such VCEK"
+        );
+        let exhausted = VcekError::Exhausted {
+            attempts: 4,
+            status: reqwest::StatusCode::TOO_MANY_REQUESTS,
+        };
+        assert!(exhausted.to_string().contains("429"));
+        assert!(exhausted.to_string().contains("4 attempts"));
+
+        // Wrapped errors keep their context, and are reachable as the source
+        let e = VerifyError::from(status);
+        assert!(e.to_string().contains("404"));
+        assert!(matches!(
+            e.source().and_then(|e| e.downcast_ref::<VcekError>()),
+            Some(VcekError::Status { .. })
+        ));
+        let e = VerifyError::ReportParse(serde_json::from_str::<u32>("{").unwrap_err());
+        assert!(e
+            .source()
+            .is_some_and(|e| e.downcast_ref::<serde_json::Error>().is_some()));
+        let e = VerifyError::VcekParse(Error::new(ErrorKind::InvalidData, "not DER"));
+        assert_eq!(e.source().unwrap().to_string(), "not DER");
+
+        let e = VerifyError::NonZeroReserved { offset: 0x4c };
+        assert!(e.to_string().contains("offset 0x4c"));
+        assert!(e.source().is_none());
+        let e = VerifyError::PolicyViolation {
+            flag: PolicyFlag::Debug,
+            required: false,
+            actual: true,
+        };
+        assert!(e.to_string().contains("'debug allowed'"));
+        let e = VerifyError::UnexpectedVmpl {
+            expected: 0,
+            actual: 1,
+        };
+        assert!(e.to_string().contains("VMPL 1"));
+    }
+
+    #[test]
+    fn test_verify_report_signature() {
+        let mut report: AttestationReport = serde_json::from_str(SAMPLE_ATTESTATION).unwrap();
+        let vcek = Certificate::from_der(SAMPLE_VCEK).unwrap();
+        verify_report_signature(&report, &vcek).unwrap();
+
+        tamper_report(&mut report);
+        assert!(matches!(
+            verify_report_signature(&report, &vcek),
+            Err(VerifyError::ReportSignature(_))
+        ));
+    }
+
+    #[test]
+    fn test_verify_batch() {
+        let report: AttestationReport = serde_json::from_str(SAMPLE_ATTESTATION).unwrap();
+        let vcek = Certificate::from_der(SAMPLE_VCEK).unwrap();
+        let mut tampered = report;
+        tamper_report(&mut tampered);
+
+        let reports = [report, tampered, report];
+        let results = verify_batch(
+            &reports,
+            &vcek,
+            ProductLine::Genoa,
+            &VerifyPolicy::default(),
+        );
+        assert_eq!(results.len(), 3);
+        assert!(results[0].is_ok());
+        assert!(matches!(results[1], Err(VerifyError::ReportSignature(_))));
+        assert!(results[2].is_ok());
+
+        let policy = VerifyPolicy {
+            expected_measurement: Some(tampered.measurement),
+            ..Default::default()
+        };
+        let results = verify_batch(&reports, &vcek, ProductLine::Genoa, &policy);
+        assert!(matches!(
+            results[0],
+            Err(VerifyError::MeasurementMismatch { .. })
+        ));
+        assert!(matches!(results[1], Err(VerifyError::ReportSignature(_))));
+
+        // A bad chain fails every report
+        let results = verify_batch(
+            &reports,
+            &vcek,
+            ProductLine::Milan,
+            &VerifyPolicy::default(),
+        );
+        assert!(results
+            .iter()
+            .all(|result| matches!(result, Err(VerifyError::VcekIssuerMismatch { .. }))));
+
+        assert!(verify_batch(&[], &vcek, ProductLine::Genoa, &VerifyPolicy::default()).is_empty());
+    }
+
+    #[test]
+    fn test_verify_with_ca_chain() {
+        let report: AttestationReport = serde_json::from_str(SAMPLE_ATTESTATION).unwrap();
+        let vcek = Certificate::from_der(SAMPLE_VCEK).unwrap();
+        let policy = VerifyPolicy::default();
+
+        let genoa = get_cert_chain(ProductLine::Genoa);
+        assert!(verify_with_ca_chain(&report, &vcek, genoa.clone(), &policy).is_ok());
+
+        let milan = get_cert_chain(ProductLine::Milan);
+        assert!(matches!(
+            verify_with_ca_chain(&report, &vcek, milan.clone(), &policy),
+            Err(VerifyError::VcekIssuerMismatch { .. })
+        ));
+
+        // An ASK from another product is not signed by the ARK
+        let mismatched = ca::Chain {
+            ark: milan.ark,
+            ask: genoa.ask,
+        };
+        match validate_ca_chain(&mismatched) {
+            Err(VerifyError::MalformedCaChain { reason }) => {
+                assert!(reason.starts_with("ASK is not signed by the ARK"))
+            }
+            result => panic!("unexpected result {result:?}"),
+        }
+        assert!(verify_with_ca_chain(&report, &vcek, mismatched, &policy).is_err());
+    }
+
+    #[test]
+    fn test_verify_fully_offline() {
+        use crate::report::report_to_bytes;
+
+        let report: AttestationReport = serde_json::from_str(SAMPLE_ATTESTATION).unwrap();
+        let report_bytes = report_to_bytes(&report);
+        let genoa = get_cert_chain(ProductLine::Genoa);
+        let ark_pem = genoa.ark.to_pem().unwrap();
+        let ask_pem = genoa.ask.to_pem().unwrap();
+        let policy = VerifyPolicy::default();
+
+        verify_fully_offline(&report_bytes, SAMPLE_VCEK, &ark_pem, &ask_pem, &policy).unwrap();
+
+        assert!(matches!(
+            verify_fully_offline(&report_bytes, SAMPLE_VCEK, &ask_pem, &ark_pem, &policy),
+            Err(VerifyError::MalformedCaChain { .. })
+        ));
+        assert!(matches!(
+            verify_fully_offline(&report_bytes, SAMPLE_VCEK, GENOA_PEM, &ask_pem, &policy),
+            Err(VerifyError::MalformedCaChain { .. })
+        ));
+        assert!(matches!(
+            verify_fully_offline(
+                &report_bytes,
+                &SAMPLE_VCEK[1..],
+                &ark_pem,
+                &ask_pem,
+                &policy
+            ),
+            Err(VerifyError::VcekParse(_))
+        ));
+
+        let mut tampered = report;
+        tamper_report(&mut tampered);
+        assert!(matches!(
+            verify_fully_offline(
+                &report_to_bytes(&tampered),
+                SAMPLE_VCEK,
+                &ark_pem,
+                &ask_pem,
+                &policy
+            ),
+            Err(VerifyError::ReportSignature(_))
+        ));
     }
 
     #[test]
     fn test_verify_attestation_report_fetch_vcek() {
-        // NB: this test makes a web request
         let report: AttestationReport = serde_json::from_str(SAMPLE_ATTESTATION).unwrap();
-        let vcek_bytes = request_vcek(report.chip_id, report.reported_tcb, SEV_PROD_NAME);
-        verify_attestation_report(SAMPLE_ATTESTATION, &vcek_bytes, false);
+        let kds = MockKds::start();
+        let client = KdsClient::new(kds.base_url());
+
+        let vcek_bytes = client
+            .request_vcek(report.chip_id, report.reported_tcb, ProductLine::Genoa)
+            .unwrap();
+        let cert_chain = client.get_cert_chain(ProductLine::Genoa).unwrap();
+        let vcek = Certificate::from_der(&vcek_bytes).unwrap();
+        assert!(verify_with_ca_chain(&report, &vcek, cert_chain, &VerifyPolicy::default()).is_ok());
+
+        let paths = kds.paths();
+        assert_eq!(paths.len(), 2);
+        assert!(paths[0].starts_with(&format!("/vcek/v1/Genoa/{}?", hex::encode(report.chip_id))));
+        assert_eq!(paths[1], "/vcek/v1/Genoa/cert_chain");
+    }
+
+    #[test]
+    #[ignore = "requests the VCEK from the live KDS"]
+    fn test_verify_attestation_report_fetch_vcek_live() {
+        let report: AttestationReport = serde_json::from_str(SAMPLE_ATTESTATION).unwrap();
+        let vcek_bytes =
+            request_vcek(report.chip_id, report.reported_tcb, ProductLine::Genoa).unwrap();
+        assert!(verify_attestation_report(
+            SAMPLE_ATTESTATION,
+            &vcek_bytes,
+            ProductLine::Genoa,
+            &VerifyPolicy::default()
+        )
+        .is_ok());
     }
 }
@@ -0,0 +1,62 @@
+//! Measures the Rust heap used to verify a report from borrowed slices.
+//!
+//! Only allocations made through the Rust allocator are counted: openssl's own
+//! buffers (for the parsed VCEK, say) are allocated with `malloc` and are not.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use sev_attest_tool::policy::VerifyPolicy;
+use sev_attest_tool::product::ProductLine;
+use sev_attest_tool::report::report_to_bytes;
+use sev_attest_tool::verify_attestation::{
+    verify_attestation_report_bytes, SAMPLE_ATTESTATION, SAMPLE_VCEK,
+};
+
+/// The system allocator, tracking the bytes in use and their peak.
+struct CountingAllocator;
+
+static IN_USE: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let in_use = IN_USE.fetch_add(layout.size(), Ordering::SeqCst) + layout.size();
+        PEAK.fetch_max(in_use, Ordering::SeqCst);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        IN_USE.fetch_sub(layout.size(), Ordering::SeqCst);
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+#[test]
+fn test_peak_allocation_of_verify_from_slices() {
+    let report = serde_json::from_str(SAMPLE_ATTESTATION).unwrap();
+    let report_bytes = report_to_bytes(&report);
+    let policy = VerifyPolicy::default();
+
+    // The first verification parses the embedded certificate chain, once per process
+    verify_attestation_report_bytes(&report_bytes, SAMPLE_VCEK, ProductLine::Genoa, &policy)
+        .unwrap();
+
+    let before = IN_USE.load(Ordering::SeqCst);
+    PEAK.store(before, Ordering::SeqCst);
+    verify_attestation_report_bytes(&report_bytes, SAMPLE_VCEK, ProductLine::Genoa, &policy)
+        .unwrap();
+    let peak = PEAK.load(Ordering::SeqCst) - before;
+    assert_eq!(IN_USE.load(Ordering::SeqCst), before, "allocations leaked");
+
+    // About 2 KiB: the policy checks' results, and the VCEK's DER encoding as
+    // its extensions are read
+    println!("peak allocation: {peak} bytes");
+    assert!(
+        peak <= 3 * 1024,
+        "peak allocation {peak} bytes exceeds 3 KiB"
+    );
+}
@@ -0,0 +1,56 @@
+//! Loads the shared library and calls the C interface, as a non-Rust caller would.
+#![cfg(all(feature = "ffi", unix))]
+
+use std::ffi::{c_char, c_int, CStr, CString};
+use std::path::PathBuf;
+
+use libloading::{Library, Symbol};
+
+const SAMPLE_ATTESTATION: &str = include_str!("../data/sample_attestation_report.json");
+const SAMPLE_VCEK: &[u8] = include_bytes!("../data/sample_vcek.crt");
+
+type VerifyReport = unsafe extern "C" fn(*const c_char, *const u8, usize) -> c_int;
+type LastErrorMessage = unsafe extern "C" fn() -> *const c_char;
+
+/// The shared library, which cargo builds next to the test binary.
+fn library_path() -> PathBuf {
+    let deps = std::env::current_exe()
+        .unwrap()
+        .parent()
+        .unwrap()
+        .to_path_buf();
+    let name = format!(
+        "{}sev_attest_tool{}",
+        std::env::consts::DLL_PREFIX,
+        std::env::consts::DLL_SUFFIX
+    );
+    [deps.join(&name), deps.parent().unwrap().join(&name)]
+        .into_iter()
+        .find(|path| path.exists())
+        .expect("shared library is built")
+}
+
+#[test]
+fn test_dlopen_verify_report() {
+    unsafe {
+        let library = Library::new(library_path()).unwrap();
+        let verify: Symbol<VerifyReport> = library.get(b"sev_verify_report\0").unwrap();
+        let last_error: Symbol<LastErrorMessage> =
+            library.get(b"sev_last_error_message\0").unwrap();
+
+        let report = CString::new(SAMPLE_ATTESTATION).unwrap();
+        assert_eq!(
+            verify(report.as_ptr(), SAMPLE_VCEK.as_ptr(), SAMPLE_VCEK.len()),
+            0
+        );
+        assert!(last_error().is_null());
+
+        let report = CString::new("not json").unwrap();
+        assert_eq!(
+            verify(report.as_ptr(), SAMPLE_VCEK.as_ptr(), SAMPLE_VCEK.len()),
+            3
+        );
+        let message = CStr::from_ptr(last_error()).to_str().unwrap();
+        assert!(message.contains("could not parse attestation report"));
+    }
+}
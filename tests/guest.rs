@@ -0,0 +1,24 @@
+//! Requests a report from the SEV-SNP guest device and verifies it, as a guest
+//! attesting itself would.
+#![cfg(all(feature = "guest", target_os = "linux"))]
+
+use sev_attest_tool::guest::request_report;
+use sev_attest_tool::product::detect_product;
+use sev_attest_tool::verifier::Verifier;
+
+#[test]
+#[ignore = "requires an SEV-SNP guest with /dev/sev-guest, and requests the VCEK from KDS"]
+fn test_request_and_verify_report() {
+    let mut nonce = [0; 64];
+    openssl::rand::rand_bytes(&mut nonce).unwrap();
+    let report = request_report(nonce).unwrap();
+    assert_eq!(report.report_data, nonce);
+
+    let product = detect_product(&report, None).expect("report identifies its product line");
+    let verification = Verifier::new(product)
+        .require_nonce(nonce)
+        .build()
+        .fetch_and_verify(&report)
+        .unwrap();
+    assert!(verification.passed());
+}